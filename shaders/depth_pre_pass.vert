@@ -3,10 +3,41 @@
 layout (location = 10) uniform mat4 uModelMat4;
 layout (location = 11) uniform mat4 uViewMat4;
 layout (location = 12) uniform mat4 uProjMat4;
+layout (location = 27) uniform uint uSkinnedMeshUint;
+layout (location = 48) uniform mat4 uJointMat4[56];
+layout (location = 41) uniform float uMorphWeightsVec1f[4];
 
 layout (location = 0) in vec3 aPosition;
+layout (location = 5) in vec4 aJointIndices;
+layout (location = 6) in vec4 aJointWeights;
+layout (location = 7) in vec3 aMorphPosition0;
+layout (location = 8) in vec3 aMorphPosition1;
+layout (location = 9) in vec3 aMorphPosition2;
+layout (location = 10) in vec3 aMorphPosition3;
+
+vec3 calculateMorphedPosition()
+{
+    return aPosition +
+           uMorphWeightsVec1f[0] * aMorphPosition0 +
+           uMorphWeightsVec1f[1] * aMorphPosition1 +
+           uMorphWeightsVec1f[2] * aMorphPosition2 +
+           uMorphWeightsVec1f[3] * aMorphPosition3;
+}
+
+mat4 calculateSkinMatrix()
+{
+    if (uSkinnedMeshUint == 0)
+    {
+        return mat4(1.0);
+    }
+
+    return aJointWeights.x * uJointMat4[int(aJointIndices.x)] +
+           aJointWeights.y * uJointMat4[int(aJointIndices.y)] +
+           aJointWeights.z * uJointMat4[int(aJointIndices.z)] +
+           aJointWeights.w * uJointMat4[int(aJointIndices.w)];
+}
 
 void main()
 {
-    gl_Position = uProjMat4 * uViewMat4 * uModelMat4 * vec4(aPosition, 1);
+    gl_Position = uProjMat4 * uViewMat4 * uModelMat4 * calculateSkinMatrix() * vec4(calculateMorphedPosition(), 1);
 }
\ No newline at end of file
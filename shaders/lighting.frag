@@ -20,6 +20,14 @@ layout (location = 18) uniform uint uNormalMapAvailableUint;
 layout (location = 19) uniform uint uBumpMapAvailableUint;
 layout (location = 20) uniform uint uMetallicAvailableUint;
 layout (location = 21) uniform uint uRoughnessAvailableUint;
+layout (location = 22) uniform uint uOcclusionMapAvailableUint;
+layout (location = 23) uniform uint uEmissiveMapAvailableUint;
+
+layout (location = 24) uniform vec3 uScalarEmissiveVec3f;
+
+layout (location = 104) uniform uint uOcclusionUVSetUint;
+layout (location = 105) uniform uint uLightMapAvailableUint;
+layout (location = 106) uniform uint uLightMapUVSetUint;
 
 layout (binding = 0, location = 30) uniform sampler2D uAlbedoMapSampler2D;
 layout (binding = 1, location = 31) uniform sampler2D uNormalMapSampler2D;
@@ -34,11 +42,59 @@ layout (binding = 5, location = 35) uniform samplerCube uDiffuseSamplerCube;
 layout (binding = 6, location = 36) uniform samplerCube uEnvMapSamplerCube;
 layout (binding = 7, location = 37) uniform sampler2D uBrdfLUTSampler2D;
 
+// Irradiance divided by pi like the cube, the cube is sampled when SH is off
+layout (location = 214) uniform uint uShIrradianceUint;
+layout (location = 215) uniform vec3 uIrradianceShVec3f[9];
+
+// Applied to every environment lookup, the rotation is in radians around the vertical axis
+layout (location = 224) uniform float uEnvironmentRotationVec1f;
+layout (location = 225) uniform float uEnvironmentIntensityVec1f;
+
+// Box projected local reflections, unused samplers are bound to the environment
+#define MAX_REFLECTION_PROBE_COUNT 4
+
+layout (binding = 12, location = 226) uniform samplerCube uReflectionProbe0SamplerCube;
+layout (binding = 13, location = 227) uniform samplerCube uReflectionProbe1SamplerCube;
+layout (binding = 14, location = 228) uniform samplerCube uReflectionProbe2SamplerCube;
+layout (binding = 15, location = 229) uniform samplerCube uReflectionProbe3SamplerCube;
+layout (location = 230) uniform uint uReflectionProbeCountUint;
+layout (location = 231) uniform vec3 uReflectionProbePositionsVec3f[MAX_REFLECTION_PROBE_COUNT];
+layout (location = 235) uniform vec3 uReflectionProbeBoxMinVec3f[MAX_REFLECTION_PROBE_COUNT];
+layout (location = 239) uniform vec3 uReflectionProbeBoxMaxVec3f[MAX_REFLECTION_PROBE_COUNT];
+layout (location = 243) uniform float uReflectionProbeBlendDistanceVec1f[MAX_REFLECTION_PROBE_COUNT];
+
 ///////////////////////////////////////////////////////////
 // Parallax Occlusion Mapping
 ///////////////////////////////////////////////////////////
 layout (binding = 8, location = 38) uniform sampler2D uDepthMapSampler2D;
 
+///////////////////////////////////////////////////////////
+// Occlusion & Emission
+///////////////////////////////////////////////////////////
+layout (binding = 9, location = 39) uniform sampler2D uOcclusionMapSampler2D;
+layout (binding = 10, location = 40) uniform sampler2D uEmissiveMapSampler2D;
+
+///////////////////////////////////////////////////////////
+// Baked Lighting
+///////////////////////////////////////////////////////////
+layout (binding = 11, location = 107) uniform sampler2D uLightMapSampler2D;
+
+///////////////////////////////////////////////////////////
+// Punctual Lights
+///////////////////////////////////////////////////////////
+#define MAX_DIRECT_LIGHT_COUNT 4
+#define MAX_POINT_LIGHT_COUNT 8
+
+layout (location = 176) uniform uint uDirectLightCountUint;
+layout (location = 177) uniform vec3 uDirectLightDirectionsVec3f[MAX_DIRECT_LIGHT_COUNT];
+layout (location = 181) uniform vec3 uDirectLightColorsVec3f[MAX_DIRECT_LIGHT_COUNT];
+layout (location = 185) uniform float uDirectLightRadianceVec1f[MAX_DIRECT_LIGHT_COUNT];
+
+layout (location = 189) uniform uint uPointLightCountUint;
+layout (location = 190) uniform vec3 uPointLightPositionsVec3f[MAX_POINT_LIGHT_COUNT];
+layout (location = 198) uniform vec3 uPointLightColorsVec3f[MAX_POINT_LIGHT_COUNT];
+layout (location = 206) uniform float uPointLightRadianceVec1f[MAX_POINT_LIGHT_COUNT];
+
 ///////////////////////////////////////////////////////////
 // Input
 ///////////////////////////////////////////////////////////
@@ -47,6 +103,8 @@ layout (location = 1) in vec3 normalWorld;
 layout (location = 2) in vec3 positionWorld;
 layout (location = 3) in vec3 cameraPositionWorld;
 layout (location = 4) in mat3 mTBN;
+layout (location = 7) in vec2 inUV1;
+layout (location = 8) in vec4 inColor;
 
 ///////////////////////////////////////////////////////////
 // Output
@@ -58,36 +116,6 @@ layout (location = 0) out vec4 outColor;
 ///////////////////////////////////////////////////////////
 #define M_PI 3.1415926535897932384626433832795
 #define EPSILON 1e-5
-#define DIRECT_LIGHT_COUNT 1
-#define POINT_LIGHT_COUNT 5
-
-vec3 g_directLights[DIRECT_LIGHT_COUNT] = {
-    vec3(0, 1.f, 0)
-};
-vec3 g_directLightColors[DIRECT_LIGHT_COUNT] = {
-    vec3(1.f, 1.f, 1.f)
-};
-float g_directLightRadiance[DIRECT_LIGHT_COUNT] = {
-    1.
-};
-
-vec3 g_pointLights[POINT_LIGHT_COUNT] = {
-    vec3( -50.0f,  50.0f, -20.0f),
-    vec3(  50.0f,  50.0f, -20.0f),
-    vec3( -50.0f, -50.0f, -20.0f),
-    vec3(  50.0f, -50.0f, -20.0f),
-    vec3(    .0f,   0.0f, -20.0f),
-};
-vec3 g_pointLightColors[POINT_LIGHT_COUNT] = {
-    vec3(1.f, 1.f, 1.f),
-    vec3(1.f, 1.f, 1.f),
-    vec3(1.f, 1.f, 1.f),
-    vec3(1.f, 1.f, 1.f),
-    vec3(1.f, 1.f, 1.f)
-};
-float g_pointLightRadiance[POINT_LIGHT_COUNT] = {
-    1000., 1000., 1000., 1000., 1000.
-};
 
 float ClampPunctualLightRadiance(float r, float radiance)
 {
@@ -377,9 +405,9 @@ vec3 CalculatePointLights(
 {
     vec3 Lo = vec3(0);
 
-    for (int i = 0; i < POINT_LIGHT_COUNT; ++i)
+    for (uint i = 0; i < min(uPointLightCountUint, uint(MAX_POINT_LIGHT_COUNT)); ++i)
     {
-        vec3 pointLightTBN = TBN * g_pointLights[i];
+        vec3 pointLightTBN = TBN * uPointLightPositionsVec3f[i];
         vec3 l = normalize(pointLightTBN - positionTBN);
         vec3 h = normalize(l + v);
 
@@ -387,8 +415,8 @@ vec3 CalculatePointLights(
         vec3 kD = (1.0 - kS) * (1.0 - metalness);
 
         // float visibility = GetParallaxSelfShadow(inUV, l, pomResult.depth);
-        vec3 radiance = g_pointLightColors[i] * ClampPunctualLightRadiance(
-            length(pointLightTBN - positionTBN), g_pointLightRadiance[i]);
+        vec3 radiance = uPointLightColorsVec3f[i] * ClampPunctualLightRadiance(
+            length(pointLightTBN - positionTBN), uPointLightRadianceVec1f[i]);
 
         Lo += (
             kD * HammonDiffuse(n, l, v, h, roughness, F0, albedo)
@@ -411,15 +439,15 @@ vec3 CalculateDirectLights(
 {
     vec3 Lo = vec3(0);
 
-    for (int i = 0; i < DIRECT_LIGHT_COUNT; ++i)
+    for (uint i = 0; i < min(uDirectLightCountUint, uint(MAX_DIRECT_LIGHT_COUNT)); ++i)
     {
-        vec3 l = normalize(TBN * g_directLights[i]);
+        vec3 l = normalize(TBN * uDirectLightDirectionsVec3f[i]);
         vec3 h = normalize(l + v);
 
         vec3 kS = FresnelSchlick(max(dot(h, v), 0.0), F0);
         vec3 kD = (1.0 - kS) * (1.0 - metalness);
 
-        vec3 radiance = g_directLightColors[i] * g_directLightRadiance[i];
+        vec3 radiance = uDirectLightColorsVec3f[i] * uDirectLightRadianceVec1f[i];
         // float visibility = GetParallaxSelfShadow(inUV, l, pomResult.depth);
 
         Lo += (
@@ -431,6 +459,89 @@ vec3 CalculateDirectLights(
     return Lo;
 }
 
+vec3 RotateEnvironment(const vec3 v)
+{
+    float c = cos(uEnvironmentRotationVec1f);
+    float s = sin(uEnvironmentRotationVec1f);
+    return vec3(c * v.x - s * v.z, v.y, s * v.x + c * v.z);
+}
+
+vec3 EvaluateShIrradiance(const vec3 n)
+{
+    return uIrradianceShVec3f[0] * 0.282095
+        + uIrradianceShVec3f[1] * 0.488603 * n.y
+        + uIrradianceShVec3f[2] * 0.488603 * n.z
+        + uIrradianceShVec3f[3] * 0.488603 * n.x
+        + uIrradianceShVec3f[4] * 1.092548 * n.x * n.y
+        + uIrradianceShVec3f[5] * 1.092548 * n.y * n.z
+        + uIrradianceShVec3f[6] * 0.315392 * (3.0 * n.z * n.z - 1.0)
+        + uIrradianceShVec3f[7] * 1.092548 * n.x * n.z
+        + uIrradianceShVec3f[8] * 0.546274 * (n.x * n.x - n.y * n.y);
+}
+
+vec3 SampleReflectionProbe(const uint i, const vec3 direction, const float lod)
+{
+    switch (i)
+    {
+    case 0u: return textureLod(uReflectionProbe0SamplerCube, direction, lod).rgb;
+    case 1u: return textureLod(uReflectionProbe1SamplerCube, direction, lod).rgb;
+    case 2u: return textureLod(uReflectionProbe2SamplerCube, direction, lod).rgb;
+    default: return textureLod(uReflectionProbe3SamplerCube, direction, lod).rgb;
+    }
+}
+
+// Zero outside the box, ramps from zero at the faces to one over the blend distance inside
+float CalculateReflectionProbeWeight(const uint i, const vec3 position)
+{
+    vec3 insideDistances = min(
+        position - uReflectionProbeBoxMinVec3f[i],
+        uReflectionProbeBoxMaxVec3f[i] - position);
+    float insideDistance = min(min(insideDistances.x, insideDistances.y), insideDistances.z);
+    if (insideDistance < 0.0)
+    {
+        return 0.0;
+    }
+    float blendDistance = uReflectionProbeBlendDistanceVec1f[i];
+    return blendDistance > 0.0 ? clamp(insideDistance / blendDistance, 0.0, 1.0) : 1.0;
+}
+
+// Intersects the reflected ray with the box and looks the hit point up from the probe
+vec3 CalculateBoxProjectedDirection(const uint i, const vec3 position, const vec3 r)
+{
+    vec3 firstPlanes = (uReflectionProbeBoxMaxVec3f[i] - position) / r;
+    vec3 secondPlanes = (uReflectionProbeBoxMinVec3f[i] - position) / r;
+    vec3 furthestPlanes = max(firstPlanes, secondPlanes);
+    float distance = min(min(furthestPlanes.x, furthestPlanes.y), furthestPlanes.z);
+    return position + r * distance - uReflectionProbePositionsVec3f[i];
+}
+
+// Overlapping probes are averaged by weight, the environment fills what they leave uncovered,
+// r points away from the surface
+vec3 CalculatePrefilteredColor(const vec3 position, const vec3 r, const float lod)
+{
+    vec3 probeColor = vec3(0.0);
+    float totalWeight = 0.0;
+    for (uint i = 0; i < min(uReflectionProbeCountUint, uint(MAX_REFLECTION_PROBE_COUNT)); ++i)
+    {
+        float weight = CalculateReflectionProbeWeight(i, position);
+        if (weight > 0.0)
+        {
+            // Probes are captured like the environment, which is looked up along the negated direction
+            vec3 direction = CalculateBoxProjectedDirection(i, position, r);
+            probeColor += SampleReflectionProbe(i, -direction, lod) * weight;
+            totalWeight += weight;
+        }
+    }
+    if (totalWeight > 1.0)
+    {
+        probeColor /= totalWeight;
+        totalWeight = 1.0;
+    }
+
+    vec3 environmentColor = textureLod(uEnvMapSamplerCube, RotateEnvironment(-r), lod).rgb;
+    return probeColor + environmentColor * uEnvironmentIntensityVec1f * (1.0 - totalWeight);
+}
+
 vec3 CalculateIblLight(
     in vec3 albedo,
     in float metalness,
@@ -442,12 +553,15 @@ vec3 CalculateIblLight(
 {
     vec3 kS = FresnelSchlickRoughness(max(dot(n, v), 0.0), F0, roughness);
     vec3 kD = (1.0 - kS) * (1.0 - metalness);
-    vec3 irradiance = texture(uDiffuseSamplerCube, -n).rgb;
-    vec3 diffuse = irradiance * albedo;
+    vec3 environmentN = RotateEnvironment(n);
+    vec3 irradiance = uShIrradianceUint != 0
+        ? max(EvaluateShIrradiance(environmentN), vec3(0.0))
+        : texture(uDiffuseSamplerCube, -environmentN).rgb;
+    vec3 diffuse = irradiance * albedo * uEnvironmentIntensityVec1f;
 
     const float MAX_REFLECTION_LOD = 7.0;
     vec3 worldR = normalize(reflect(cameraPositionWorld - positionWorld, normalWorld));
-    vec3 prefilteredColor = textureLod(uEnvMapSamplerCube, worldR, roughness * MAX_REFLECTION_LOD).rgb;
+    vec3 prefilteredColor = CalculatePrefilteredColor(positionWorld, -worldR, roughness * MAX_REFLECTION_LOD);
     vec3 F = FresnelSchlickRoughness(max(dot(n, v), 0.0), F0, roughness);
     vec2 envBRDF  = texture(uBrdfLUTSampler2D, vec2(max(dot(n, v), 0.0), roughness)).rg;
     vec3 specular = prefilteredColor * (F * envBRDF.x + envBRDF.y);
@@ -461,15 +575,31 @@ struct PbrData {
     vec3 albedo;
     float roughness;
     float metalness;
+    float occlusion;
+    vec3 emissive;
+    vec3 bakedIrradiance;
 };
 
+vec2 SelectUVSet(in vec2 uv, in uint uvSet)
+{
+    return uvSet == 1 ? inUV1 : uv;
+}
+
+// Normal maps are stored as RG, z is rebuilt from the unit length
+vec3 ReconstructNormal(in vec2 rg)
+{
+    vec2 xy = rg * 2.f - 1.f;
+    return normalize(vec3(xy, sqrt(max(0.f, 1.f - dot(xy, xy)))));
+}
+
 PbrData GetPbrData(in vec2 uv)
 {
     PbrData pbr;
 
-    pbr.albedo = bool(uAlbedoMapAvailableUint)
+    // Albedo and emissive factors scale their maps like in glTF
+    pbr.albedo = inColor.rgb * uScalarAlbedoVec3f * (bool(uAlbedoMapAvailableUint)
         ? texture(uAlbedoMapSampler2D, uv).rgb
-        : uScalarAlbedoVec3f;
+        : vec3(1));
     pbr.metalness = bool(uMetallicAvailableUint)
         ? texture(uMetallicSampler2D, uv).r
         : uScalarMetalnessVec1f;
@@ -477,6 +607,15 @@ PbrData GetPbrData(in vec2 uv)
         ? texture(uRoughnessSampler2D, uv).r
         : uScalarRoughnessVec1f;
     pbr.roughness = clamp(pbr.roughness, 0.04f, 1.f);
+    pbr.occlusion = bool(uOcclusionMapAvailableUint)
+        ? texture(uOcclusionMapSampler2D, SelectUVSet(uv, uOcclusionUVSetUint)).r
+        : 1.f;
+    pbr.emissive = uScalarEmissiveVec3f * (bool(uEmissiveMapAvailableUint)
+        ? texture(uEmissiveMapSampler2D, uv).rgb
+        : vec3(1));
+    pbr.bakedIrradiance = bool(uLightMapAvailableUint)
+        ? texture(uLightMapSampler2D, SelectUVSet(uv, uLightMapUVSetUint)).rgb
+        : vec3(0);
 
     return pbr;
 }
@@ -492,7 +631,7 @@ void main()
     POM pom = ParallaxOcclusionMapping(inUV, v);
     vec2 uv = clamp(pom.uv, 0, 1);
     vec3 n = bool(uNormalMapAvailableUint)
-        ? normalize(normalize(texture(uNormalMapSampler2D, uv).rgb) * 2.f - 1.f)
+        ? ReconstructNormal(texture(uNormalMapSampler2D, uv).rg)
         : TBN * normalWorld;
 
     PbrData pbr = GetPbrData(uv);
@@ -501,7 +640,10 @@ void main()
     vec3 Lo =
           CalculatePointLights(pbr.albedo, pbr.metalness, pbr.roughness, F0, v, n, TBN, positionTBN)
         + CalculateDirectLights(pbr.albedo, pbr.metalness, pbr.roughness, F0, v, n, TBN)
-        + CalculateIblLight(pbr.albedo, pbr.metalness, pbr.roughness, F0, v, n);
+        + CalculateIblLight(pbr.albedo, pbr.metalness, pbr.roughness, F0, v, n) * pbr.occlusion
+        + pbr.bakedIrradiance * pbr.albedo * (1.0 - pbr.metalness) * pbr.occlusion
+        + pbr.emissive;
 
-    outColor = vec4(Lo, 1);
+    // Blending is never enabled, every material is drawn opaque
+    outColor = vec4(Lo, 1.0);
 }
\ No newline at end of file
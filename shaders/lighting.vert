@@ -4,29 +4,102 @@ layout (location = 10) uniform mat4 uModelMat4;
 layout (location = 11) uniform mat4 uViewMat4;
 layout (location = 12) uniform mat4 uProjMat4;
 layout (location = 13) uniform vec3 uCameraPosVec3;
+layout (location = 26) uniform uint uOctahedralDirectionsUint;
+layout (location = 27) uniform uint uSkinnedMeshUint;
+layout (location = 28) uniform uint uUV1AvailableUint;
+layout (location = 29) uniform uint uVertexColorAvailableUint;
+layout (location = 48) uniform mat4 uJointMat4[56];
+layout (location = 41) uniform float uMorphWeightsVec1f[4];
 
 layout (location = 0) in vec3 aPosition;
 layout (location = 1) in vec3 aNormal;
-layout (location = 2) in vec3 aTangent;
-layout (location = 3) in vec3 aBitangent;
-layout (location = 4) in vec2 aUV;
+// Bitangent handedness in w
+layout (location = 2) in vec4 aTangent;
+layout (location = 3) in vec2 aUV;
+layout (location = 4) in vec2 aUV1;
+layout (location = 5) in vec4 aJointIndices;
+layout (location = 6) in vec4 aJointWeights;
+layout (location = 7) in vec3 aMorphPosition0;
+layout (location = 8) in vec3 aMorphPosition1;
+layout (location = 9) in vec3 aMorphPosition2;
+layout (location = 10) in vec3 aMorphPosition3;
+layout (location = 11) in vec3 aMorphNormal0;
+layout (location = 12) in vec3 aMorphNormal1;
+layout (location = 13) in vec3 aMorphNormal2;
+layout (location = 14) in vec3 aMorphNormal3;
+layout (location = 15) in vec4 aColor;
 
 layout (location = 0) out vec2 uv;
 layout (location = 1) out vec3 normalWorld;
 layout (location = 2) out vec3 positionWorld;
 layout (location = 3) out vec3 cameraPositionWorld;
 layout (location = 4) out mat3 mTBN;
+layout (location = 7) out vec2 uv1;
+layout (location = 8) out vec4 color;
+
+vec3 decodeDirection(vec3 direction)
+{
+    if (uOctahedralDirectionsUint == 0)
+    {
+        return direction;
+    }
+
+    vec3 n = vec3(direction.xy, 1.0 - abs(direction.x) - abs(direction.y));
+    if (n.z < 0)
+    {
+        n.xy = (1.0 - abs(n.yx)) * vec2(n.x >= 0 ? 1.0 : -1.0, n.y >= 0 ? 1.0 : -1.0);
+    }
+    return normalize(n);
+}
+
+vec3 calculateMorphedPosition()
+{
+    return aPosition +
+           uMorphWeightsVec1f[0] * aMorphPosition0 +
+           uMorphWeightsVec1f[1] * aMorphPosition1 +
+           uMorphWeightsVec1f[2] * aMorphPosition2 +
+           uMorphWeightsVec1f[3] * aMorphPosition3;
+}
+
+vec3 calculateMorphedNormal()
+{
+    return decodeDirection(aNormal) +
+           uMorphWeightsVec1f[0] * aMorphNormal0 +
+           uMorphWeightsVec1f[1] * aMorphNormal1 +
+           uMorphWeightsVec1f[2] * aMorphNormal2 +
+           uMorphWeightsVec1f[3] * aMorphNormal3;
+}
+
+mat4 calculateSkinMatrix()
+{
+    if (uSkinnedMeshUint == 0)
+    {
+        return mat4(1.0);
+    }
+
+    return aJointWeights.x * uJointMat4[int(aJointIndices.x)] +
+           aJointWeights.y * uJointMat4[int(aJointIndices.y)] +
+           aJointWeights.z * uJointMat4[int(aJointIndices.z)] +
+           aJointWeights.w * uJointMat4[int(aJointIndices.w)];
+}
 
 void main()
 {
+    mat4 modelMat4 = uModelMat4 * calculateSkinMatrix();
+    vec3 position = calculateMorphedPosition();
+
     uv = aUV;
-    normalWorld = normalize(vec3(uModelMat4 * vec4(aNormal, 0.0)));
-    positionWorld = (uModelMat4 * vec4(aPosition, 1)).xyz;
+    uv1 = bool(uUV1AvailableUint) ? aUV1 : aUV;
+    color = bool(uVertexColorAvailableUint) ? aColor : vec4(1.0);
+    normalWorld = normalize(vec3(modelMat4 * vec4(calculateMorphedNormal(), 0.0)));
+    positionWorld = (modelMat4 * vec4(position, 1)).xyz;
     cameraPositionWorld = uCameraPosVec3;
 
-    vec3 t = normalize(uModelMat4 * vec4(aTangent, 0)).rgb;
-    vec3 b = normalize(uModelMat4 * vec4(aBitangent, 0)).rgb;
+    vec3 tangent = decodeDirection(aTangent.xyz);
+    vec3 bitangent = cross(decodeDirection(aNormal), tangent) * aTangent.w;
+    vec3 t = normalize(modelMat4 * vec4(tangent, 0)).rgb;
+    vec3 b = normalize(modelMat4 * vec4(bitangent, 0)).rgb;
     mTBN = mat3(t, b, normalWorld);
 
-    gl_Position = uProjMat4 * uViewMat4 * uModelMat4 * vec4(aPosition, 1);
+    gl_Position = uProjMat4 * uViewMat4 * modelMat4 * vec4(position, 1);
 }
\ No newline at end of file
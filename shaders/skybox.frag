@@ -1,12 +1,22 @@
 #version 460 core
 
 layout (binding = 0, location = 30) uniform samplerCube uSkyboxSamplerCube;
+// Same environment transform as lighting.frag
+layout (location = 31) uniform float uEnvironmentRotationVec1f;
+layout (location = 32) uniform float uEnvironmentIntensityVec1f;
 layout (location = 0) in vec3 texCoord;
 layout (location = 0) out vec4 outColor;
 
+vec3 RotateEnvironment(const vec3 v)
+{
+    float c = cos(uEnvironmentRotationVec1f);
+    float s = sin(uEnvironmentRotationVec1f);
+    return vec3(c * v.x - s * v.z, v.y, s * v.x + c * v.z);
+}
+
 void main()
 {
     // vec3 color = texture(uSkyboxSamplerCube, -texCoord, 0).rgb;
-    vec3 color = textureLod(uSkyboxSamplerCube, -texCoord, 3).rgb;
-    outColor = vec4(color, 1);
+    vec3 color = textureLod(uSkyboxSamplerCube, -RotateEnvironment(texCoord), 3).rgb;
+    outColor = vec4(color * uEnvironmentIntensityVec1f, 1);
 }
\ No newline at end of file
@@ -26,7 +26,12 @@ vec3 acesFilm(const vec3 x) {
     return clamp((x * (a * x + b)) / (x * (c * x + d ) + e), 0.0, 1.0);
 }
 
+// Materials are lit in linear space, the default framebuffer expects sRGB
+vec3 linearToSrgb(const vec3 x) {
+    return mix(x * 12.92, 1.055 * pow(x, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, x));
+}
+
 void main()
 {
-    outColor = vec4(acesFilm(texture(uColorSampler2D, uv, 0).rgb), 1);
+    outColor = vec4(linearToSrgb(acesFilm(texture(uColorSampler2D, uv, 0).rgb)), 1);
 }
\ No newline at end of file
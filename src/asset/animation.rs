@@ -0,0 +1,221 @@
+use crate::asset::model;
+use crate::math;
+use std::ops::{Add, Mul};
+
+// Must match the size of uJointMat4 in the skinning shaders. 56 matrices leave room for the other
+// vertex uniforms within the 256 uniform vectors every GL implementation has to provide.
+pub const MAX_JOINT_COUNT: usize = 56;
+
+#[derive(Clone)]
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>,
+    // Transform of the non-joint nodes between the parent joint (or the root) and this joint
+    pub parent_transform: math::Mat4x4f,
+    pub inverse_bind_matrix: math::Mat4x4f,
+    pub translation: math::Vec3f,
+    pub rotation: math::Vec4f,
+    pub scale: math::Vec3f,
+}
+
+// Joints are sorted so that parents always come before their children
+#[derive(Clone)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    // Every keyframe stores an in-tangent, a value and an out-tangent
+    CubicSpline,
+}
+
+#[derive(Clone)]
+pub enum ChannelValues {
+    Translations(Vec<math::Vec3f>),
+    Rotations(Vec<math::Vec4f>),
+    Scales(Vec<math::Vec3f>),
+}
+
+#[derive(Clone)]
+pub struct Channel {
+    pub joint_index: usize,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub values: ChannelValues,
+}
+
+#[derive(Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+#[derive(Clone)]
+pub struct Pose {
+    pub translations: Vec<math::Vec3f>,
+    pub rotations: Vec<math::Vec4f>,
+    pub scales: Vec<math::Vec3f>,
+}
+
+#[derive(Clone)]
+pub struct AnimationState {
+    pub clip_index: usize,
+    pub time: f32,
+    pub speed: f32,
+    pub playing: bool,
+    // Per mesh, so instances of the same model can use different weights
+    pub morph_weights: Vec<Vec<math::Vec1f>>,
+}
+
+impl Skeleton {
+    pub fn rest_pose(&self) -> Pose {
+        Pose {
+            translations: self.joints.iter().map(|x| x.translation).collect(),
+            rotations: self.joints.iter().map(|x| x.rotation).collect(),
+            scales: self.joints.iter().map(|x| x.scale).collect(),
+        }
+    }
+}
+
+impl AnimationState {
+    pub fn new(model: &model::DeviceModel) -> AnimationState {
+        AnimationState {
+            clip_index: 0,
+            time: 0.,
+            speed: 1.,
+            playing: true,
+            morph_weights: model
+                .meshes
+                .iter()
+                .map(|x| x.default_morph_weights.clone())
+                .collect(),
+        }
+    }
+
+    pub fn advance(&mut self, clip: &AnimationClip, delta_time: f32) {
+        if !self.playing || clip.duration <= 0. {
+            return;
+        }
+
+        self.time = (self.time + delta_time * self.speed).rem_euclid(clip.duration);
+    }
+}
+
+pub fn sample_animation_clip(skeleton: &Skeleton, clip: &AnimationClip, time: f32) -> Pose {
+    let mut pose = skeleton.rest_pose();
+
+    for channel in &clip.channels {
+        match &channel.values {
+            ChannelValues::Translations(values) => {
+                pose.translations[channel.joint_index] =
+                    sample_keyframes(channel, values, time, lerp, |x| x);
+            }
+            ChannelValues::Rotations(values) => {
+                pose.rotations[channel.joint_index] = sample_keyframes(
+                    channel,
+                    values,
+                    time,
+                    math::slerp_quat,
+                    math::normalize_vec4,
+                );
+            }
+            ChannelValues::Scales(values) => {
+                pose.scales[channel.joint_index] =
+                    sample_keyframes(channel, values, time, lerp, |x| x);
+            }
+        }
+    }
+
+    pose
+}
+
+// Joint palette that moves bind pose vertices to the posed model space
+pub fn calculate_joint_matrices(skeleton: &Skeleton, pose: &Pose) -> Vec<math::Mat4x4f> {
+    let mut global_transforms: Vec<math::Mat4x4f> = Vec::with_capacity(skeleton.joints.len());
+
+    for (i, joint) in skeleton.joints.iter().enumerate() {
+        let local_transform = joint.parent_transform
+            * math::create_trs_mat4x4(pose.translations[i], pose.rotations[i], pose.scales[i]);
+
+        global_transforms.push(match joint.parent {
+            Some(parent) => global_transforms[parent] * local_transform,
+            None => local_transform,
+        });
+    }
+
+    global_transforms
+        .iter()
+        .zip(skeleton.joints.iter())
+        .map(|(global_transform, joint)| *global_transform * joint.inverse_bind_matrix)
+        .collect()
+}
+
+fn lerp<T>(a: T, b: T, t: f32) -> T
+where
+    T: Add<Output = T> + Mul<f32, Output = T>,
+{
+    a * (1. - t) + b * t
+}
+
+fn sample_keyframes<T, I, N>(
+    channel: &Channel,
+    values: &[T],
+    time: f32,
+    interpolate: I,
+    normalize: N,
+) -> T
+where
+    T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+    I: Fn(T, T, f32) -> T,
+    N: Fn(T) -> T,
+{
+    let times = &channel.times;
+    let value = |k: usize| {
+        if channel.interpolation == Interpolation::CubicSpline {
+            values[k * 3 + 1]
+        } else {
+            values[k]
+        }
+    };
+
+    let last = times.len() - 1;
+    if time <= times[0] {
+        return value(0);
+    }
+    if time >= times[last] {
+        return value(last);
+    }
+
+    let k = times
+        .iter()
+        .rposition(|&x| x <= time)
+        .unwrap_or(0)
+        .min(last - 1);
+    let delta = times[k + 1] - times[k];
+    let t = if delta > 0. {
+        (time - times[k]) / delta
+    } else {
+        0.
+    };
+
+    match channel.interpolation {
+        Interpolation::Step => value(k),
+        Interpolation::Linear => interpolate(value(k), value(k + 1), t),
+        Interpolation::CubicSpline => {
+            let (t2, t3) = (t * t, t * t * t);
+            let out_tangent = values[k * 3 + 2] * delta;
+            let in_tangent = values[(k + 1) * 3] * delta;
+
+            normalize(
+                value(k) * (2. * t3 - 3. * t2 + 1.)
+                    + out_tangent * (t3 - 2. * t2 + t)
+                    + value(k + 1) * (-2. * t3 + 3. * t2)
+                    + in_tangent * (t3 - t2),
+            )
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::gl::tex;
+use crate::helpers::log;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::{self, Rc};
+use std::sync::{Arc, Mutex, Weak};
+
+#[derive(Copy, Clone, Default)]
+pub struct CacheStatistics {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+    pub entries: usize,
+}
+
+struct HostTextureCache {
+    textures: HashMap<String, Weak<tex::HostTexture>>,
+    statistics: CacheStatistics,
+}
+
+struct DeviceTextureCache {
+    textures: HashMap<String, rc::Weak<tex::DeviceTexture>>,
+    statistics: CacheStatistics,
+}
+
+// Host textures are loaded on worker threads, device textures only live on the GL thread
+static HOST_TEXTURE_CACHE: Mutex<Option<HostTextureCache>> = Mutex::new(None);
+
+thread_local! {
+    static DEVICE_TEXTURE_CACHE: RefCell<DeviceTextureCache> = RefCell::new(DeviceTextureCache {
+        textures: HashMap::new(),
+        statistics: CacheStatistics::default(),
+    });
+}
+
+pub fn create_texture_key(path: &Path, parameters: &str) -> String {
+    let path = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+    let path = path.to_str().unwrap_or_default();
+
+    if parameters.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}|{}", path, parameters)
+    }
+}
+
+fn with_host_texture_cache<R>(f: impl FnOnce(&mut HostTextureCache) -> R) -> R {
+    let mut cache = HOST_TEXTURE_CACHE.lock().unwrap();
+    f(cache.get_or_insert_with(|| HostTextureCache {
+        textures: HashMap::new(),
+        statistics: CacheStatistics::default(),
+    }))
+}
+
+pub fn get_or_load_host_texture<F>(key: &str, load: F) -> Result<Arc<tex::HostTexture>, String>
+where
+    F: FnOnce() -> Result<tex::HostTexture, String>,
+{
+    let cached = with_host_texture_cache(|cache| {
+        let texture = cache.textures.get(key).and_then(|x| x.upgrade());
+        if texture.is_some() {
+            cache.statistics.hits += 1;
+        } else {
+            cache.statistics.misses += 1;
+        }
+        texture
+    });
+    if let Some(texture) = cached {
+        return Ok(texture);
+    }
+
+    // The lock is not held while loading, so textures still load in parallel
+    let mut texture = load()?;
+    texture.name = key.to_string();
+    let texture = Arc::new(texture);
+
+    with_host_texture_cache(|cache| {
+        evict_host_textures(cache);
+        cache
+            .textures
+            .insert(key.to_string(), Arc::downgrade(&texture));
+    });
+
+    Ok(texture)
+}
+
+pub fn get_or_create_device_texture(
+    host_texture: &tex::HostTexture,
+    sampler_format: tex::SamplerFormat,
+) -> Rc<tex::DeviceTexture> {
+    let create = || {
+        tex::DeviceTexture::new(
+            host_texture,
+            &tex::Descriptor::new(tex::DescriptorType::Color(host_texture, sampler_format)),
+        )
+    };
+
+    if host_texture.name.is_empty() {
+        return create();
+    }
+
+    // The same image can be bound by slots with different device formats
+    let key = format!("{}|{:?}", host_texture.name, sampler_format);
+
+    DEVICE_TEXTURE_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+
+        if let Some(texture) = cache.textures.get(&key).and_then(|x| x.upgrade()) {
+            cache.statistics.hits += 1;
+            return texture;
+        }
+
+        cache.statistics.misses += 1;
+        evict_device_textures(&mut cache);
+
+        let texture = create();
+        cache.textures.insert(key, Rc::downgrade(&texture));
+        texture
+    })
+}
+
+fn evict_host_textures(cache: &mut HostTextureCache) {
+    let count = cache.textures.len();
+    cache.textures.retain(|_, x| x.strong_count() > 0);
+    cache.statistics.evictions += count - cache.textures.len();
+}
+
+fn evict_device_textures(cache: &mut DeviceTextureCache) {
+    let count = cache.textures.len();
+    cache.textures.retain(|_, x| x.strong_count() > 0);
+    cache.statistics.evictions += count - cache.textures.len();
+}
+
+pub fn evict_unused_textures() {
+    with_host_texture_cache(evict_host_textures);
+    DEVICE_TEXTURE_CACHE.with(|cache| evict_device_textures(&mut cache.borrow_mut()));
+}
+
+pub fn get_host_texture_statistics() -> CacheStatistics {
+    with_host_texture_cache(|cache| CacheStatistics {
+        entries: cache.textures.len(),
+        ..cache.statistics
+    })
+}
+
+pub fn get_device_texture_statistics() -> CacheStatistics {
+    DEVICE_TEXTURE_CACHE.with(|cache| {
+        let cache = cache.borrow();
+        CacheStatistics {
+            entries: cache.textures.len(),
+            ..cache.statistics
+        }
+    })
+}
+
+pub fn log_texture_statistics() {
+    for (name, statistics) in &[
+        ("Host", get_host_texture_statistics()),
+        ("Device", get_device_texture_statistics()),
+    ] {
+        log::log_info(format!(
+            "{} texture cache: {} hits, {} misses, {} evictions, {} entries",
+            name, statistics.hits, statistics.misses, statistics.evictions, statistics.entries
+        ));
+    }
+}
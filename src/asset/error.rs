@@ -0,0 +1,22 @@
+use std::fmt;
+
+#[derive(Clone, Debug)]
+pub enum LoaderError {
+    Io(String),
+    Parse(String),
+    Validation(String),
+    MissingTexture(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for LoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoaderError::Io(msg) => write!(f, "I/O error: {}", msg),
+            LoaderError::Parse(msg) => write!(f, "Parse error: {}", msg),
+            LoaderError::Validation(msg) => write!(f, "Validation error: {}", msg),
+            LoaderError::MissingTexture(msg) => write!(f, "Missing texture: {}", msg),
+            LoaderError::Unsupported(msg) => write!(f, "Unsupported: {}", msg),
+        }
+    }
+}
@@ -1,5 +1,7 @@
+use crate::asset::cache;
 use crate::gl::{shader, tex, uniform};
 use crate::math;
+use std::sync::Arc;
 use std::vec::Vec;
 
 #[derive(Clone)]
@@ -8,13 +10,19 @@ pub struct Property<T> {
     pub value: T,
 }
 
+#[derive(Clone)]
+pub struct HostSampler {
+    pub texture: Arc<tex::HostTexture>,
+    pub format: tex::SamplerFormat,
+}
+
 #[derive(Clone)]
 pub struct HostMaterial {
     pub name: String,
     pub properties_1u: Vec<Property<math::Vec1u>>,
     pub properties_1f: Vec<Property<math::Vec1f>>,
     pub properties_3f: Vec<Property<math::Vec3f>>,
-    pub properties_samplers: Vec<Property<tex::HostTexture>>,
+    pub properties_samplers: Vec<Property<HostSampler>>,
 }
 
 #[derive(Clone)]
@@ -26,6 +34,15 @@ pub struct DeviceMaterial {
     pub properties_samplers: Vec<Property<uniform::TextureSampler>>,
 }
 
+impl HostSampler {
+    pub fn new(name: &str, texture: Arc<tex::HostTexture>) -> HostSampler {
+        HostSampler {
+            texture,
+            format: get_default_sampler_format(name),
+        }
+    }
+}
+
 impl HostMaterial {
     pub fn empty() -> HostMaterial {
         HostMaterial {
@@ -87,9 +104,9 @@ impl DeviceMaterial {
                     name: property.name.clone(),
                     value: uniform::TextureSampler::new(
                         &property.name,
-                        tex::DeviceTexture::new(
-                            &property.value,
-                            &tex::Descriptor::new(tex::DescriptorType::Color(&property.value)),
+                        cache::get_or_create_device_texture(
+                            &property.value.texture,
+                            property.value.format,
                         ),
                     ),
                 })
@@ -184,3 +201,19 @@ impl DeviceMaterial {
         }
     }
 }
+
+// Color slots are authored in sRGB, data slots only keep the channels the shader reads
+pub fn get_default_sampler_format(name: &str) -> tex::SamplerFormat {
+    match name {
+        "uAlbedoMapSampler2D" => tex::SamplerFormat::new(tex::ColorSpace::Srgb, 4),
+        "uEmissiveMapSampler2D" | "uLightMapSampler2D" => {
+            tex::SamplerFormat::new(tex::ColorSpace::Srgb, 3)
+        }
+        "uNormalMapSampler2D" => tex::SamplerFormat::new(tex::ColorSpace::Linear, 2),
+        "uBumpMapSampler2D"
+        | "uMetallicSampler2D"
+        | "uRoughnessSampler2D"
+        | "uOcclusionMapSampler2D" => tex::SamplerFormat::new(tex::ColorSpace::Linear, 1),
+        _ => tex::SamplerFormat::new(tex::ColorSpace::Linear, 4),
+    }
+}
@@ -1,7 +1,7 @@
+use crate::core::camera;
 use crate::gl::{buffer, shader};
 use crate::math;
 use std::mem::size_of;
-use std::ptr::null;
 
 pub type Indices = Vec<math::Vec1u>;
 pub type Vertices = Vec<math::Vec3f>;
@@ -9,19 +9,89 @@ pub type Normals = Vec<math::Vec3f>;
 pub type Tangents = Vec<math::Vec3f>;
 pub type Bitangents = Vec<math::Vec3f>;
 pub type UVs = Vec<math::Vec2f>;
+pub type Colors = Vec<math::Vec4f>;
+pub type JointIndices = Vec<math::Vec4u>;
+pub type JointWeights = Vec<math::Vec4f>;
 
 pub const VERTEX_ATTRIBUTE_NAME: &str = "aPosition";
 pub const NORMAL_ATTRIBUTE_NAME: &str = "aNormal";
 pub const TANGENT_ATTRIBUTE_NAME: &str = "aTangent";
-pub const BITANGENT_ATTRIBUTE_NAME: &str = "aBitangent";
 pub const UV_ATTRIBUTE_NAME: &str = "aUV";
+pub const UV1_ATTRIBUTE_NAME: &str = "aUV1";
+pub const COLOR_ATTRIBUTE_NAME: &str = "aColor";
+pub const JOINT_INDICES_ATTRIBUTE_NAME: &str = "aJointIndices";
+pub const JOINT_WEIGHTS_ATTRIBUTE_NAME: &str = "aJointWeights";
+
+// Must match the size of uMorphWeightsVec1f and the morph attributes in the shaders.
+// Limited by the 16 vertex attributes every GL implementation has to support, bitangents are
+// rebuilt from the normal and the tangent handedness in aTangent.w to make room for four targets.
+pub const MAX_MORPH_TARGET_COUNT: usize = 4;
+pub const MORPH_POSITION_ATTRIBUTE_NAMES: [&str; MAX_MORPH_TARGET_COUNT] = [
+    "aMorphPosition0",
+    "aMorphPosition1",
+    "aMorphPosition2",
+    "aMorphPosition3",
+];
+pub const MORPH_NORMAL_ATTRIBUTE_NAMES: [&str; MAX_MORPH_TARGET_COUNT] = [
+    "aMorphNormal0",
+    "aMorphNormal1",
+    "aMorphNormal2",
+    "aMorphNormal3",
+];
+
+// Shaders may read these even if a mesh does not provide them
+pub const OPTIONAL_ATTRIBUTE_NAMES: [&str; 4 + 2 * MAX_MORPH_TARGET_COUNT] = [
+    UV1_ATTRIBUTE_NAME,
+    COLOR_ATTRIBUTE_NAME,
+    JOINT_INDICES_ATTRIBUTE_NAME,
+    JOINT_WEIGHTS_ATTRIBUTE_NAME,
+    MORPH_POSITION_ATTRIBUTE_NAMES[0],
+    MORPH_POSITION_ATTRIBUTE_NAMES[1],
+    MORPH_POSITION_ATTRIBUTE_NAMES[2],
+    MORPH_POSITION_ATTRIBUTE_NAMES[3],
+    MORPH_NORMAL_ATTRIBUTE_NAMES[0],
+    MORPH_NORMAL_ATTRIBUTE_NAMES[1],
+    MORPH_NORMAL_ATTRIBUTE_NAMES[2],
+    MORPH_NORMAL_ATTRIBUTE_NAMES[3],
+];
+
+// Largest allowed LOD error in pixels
+pub const LOD_SCREEN_SPACE_ERROR_THRESHOLD: f32 = 1.;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum VertexLayout {
+    // One f32 buffer per attribute
+    Separate,
+    // One f32 buffer with all attributes
+    Interleaved,
+    // Interleaved with octahedral snorm16 directions, half-float uvs, unorm8 colors and 16-bit
+    // indices if possible
+    Compressed,
+}
 
 #[derive(Clone)]
 pub struct Attribute {
     pub name: String,
     pub dimensions: i32,
     pub stride: i32,
+    pub offset: i32,
     pub data_type: u32,
+    pub normalized: bool,
+    pub buffer_index: usize,
+}
+
+#[derive(Clone)]
+pub struct MeshLod {
+    pub indices: Indices,
+    pub error: f32,
+}
+
+// Dense per-vertex deltas, normal deltas are optional
+#[derive(Clone)]
+pub struct MorphTarget {
+    pub name: String,
+    pub position_deltas: Vertices,
+    pub normal_deltas: Normals,
 }
 
 #[derive(Clone)]
@@ -34,16 +104,39 @@ pub struct HostMesh {
     pub tangents: Tangents,
     pub bitangents: Bitangents,
     pub uvs: UVs,
+    pub uvs1: UVs,
+    pub colors: Colors,
+    pub joint_indices: JointIndices,
+    pub joint_weights: JointWeights,
+    pub morph_targets: Vec<MorphTarget>,
+    pub morph_weights: Vec<f32>,
     pub indices: Indices,
+    pub lods: Vec<MeshLod>,
+}
+
+#[derive(Copy, Clone)]
+pub struct DeviceMeshLod {
+    pub index_offset: u32,
+    pub index_count: u32,
+    pub error: f32,
 }
 
 pub struct DeviceMesh {
     pub name: String,
     pub vao: u32,
-    pub index_count: u32,
+    pub lods: Vec<DeviceMeshLod>,
+    pub bounds_center: math::Vec3f,
+    pub bounds_radius: f32,
+    pub layout: VertexLayout,
+    pub skinned: bool,
+    pub morph_target_names: Vec<String>,
+    // Weights from the file, always MAX_MORPH_TARGET_COUNT long with zero for unused targets.
+    // Every entity edits its own copy in its AnimationState.
+    pub default_morph_weights: Vec<math::Vec1f>,
     pub attributes: Vec<Attribute>,
     pub vbos: Vec<u32>,
     pub indices: u32,
+    pub index_type: u32,
     pub material_index: usize,
 }
 
@@ -67,7 +160,10 @@ impl Attribute {
             name: name.to_string(),
             dimensions: T::DIMENSIONS as i32,
             stride: size_of::<T>() as i32,
+            offset: 0,
             data_type: <T as math::VecGLTypeTrait>::GL_TYPE,
+            normalized: false,
+            buffer_index: 0,
         }
     }
 }
@@ -83,22 +179,70 @@ impl HostMesh {
         uvs: UVs,
         indices: Indices,
     ) -> HostMesh {
-        HostMesh {
+        let mut host_mesh = HostMesh {
             name,
-            attributes: create_mesh_attributes(&vertices, &normals, &tangents, &bitangents, &uvs),
+            attributes: Vec::new(),
             material_index,
             vertices,
             normals,
             tangents,
             bitangents,
             uvs,
+            uvs1: UVs::new(),
+            colors: Colors::new(),
+            joint_indices: JointIndices::new(),
+            joint_weights: JointWeights::new(),
+            morph_targets: Vec::new(),
+            morph_weights: Vec::new(),
             indices,
-        }
+            lods: Vec::new(),
+        };
+        host_mesh.attributes = create_mesh_attributes(&host_mesh);
+        host_mesh
+    }
+
+    pub fn with_uvs1(mut self, uvs1: UVs) -> HostMesh {
+        self.uvs1 = uvs1;
+        self.attributes = create_mesh_attributes(&self);
+        self
+    }
+
+    pub fn with_colors(mut self, colors: Colors) -> HostMesh {
+        self.colors = colors;
+        self.attributes = create_mesh_attributes(&self);
+        self
+    }
+
+    pub fn with_joints(
+        mut self,
+        joint_indices: JointIndices,
+        joint_weights: JointWeights,
+    ) -> HostMesh {
+        self.joint_indices = joint_indices;
+        self.joint_weights = joint_weights;
+        self.attributes = create_mesh_attributes(&self);
+        self
+    }
+
+    pub fn with_morph_targets(
+        mut self,
+        morph_targets: Vec<MorphTarget>,
+        morph_weights: Vec<f32>,
+    ) -> HostMesh {
+        assert!(
+            morph_targets.len() <= MAX_MORPH_TARGET_COUNT
+                && morph_weights.len() == morph_targets.len(),
+            "Every morph target must have a weight."
+        );
+        self.morph_targets = morph_targets;
+        self.morph_weights = morph_weights;
+        self.attributes = create_mesh_attributes(&self);
+        self
     }
 }
 
 impl DeviceMesh {
-    pub fn new(mesh: &HostMesh) -> DeviceMesh {
+    pub fn new(mesh: &HostMesh, layout: VertexLayout) -> DeviceMesh {
         let mut vao: u32 = 0;
         unsafe {
             gl::GenVertexArrays(1, &mut vao as *mut u32);
@@ -106,35 +250,145 @@ impl DeviceMesh {
         };
 
         assert!(
-            (mesh.indices.len() % 3) == 0,
+            (mesh.indices.len() % 3) == 0 && mesh.lods.iter().all(|x| (x.indices.len() % 3) == 0),
             "Index count should be multiple of 3 to render triangles.",
         );
 
+        // All LODs share one index buffer, LOD 0 comes first
+        let mut indices = mesh.indices.clone();
+        let mut lods = vec![DeviceMeshLod {
+            index_offset: 0,
+            index_count: mesh.indices.len() as u32,
+            error: 0.,
+        }];
+        for lod in &mesh.lods {
+            lods.push(DeviceMeshLod {
+                index_offset: indices.len() as u32,
+                index_count: lod.indices.len() as u32,
+                error: lod.error,
+            });
+            indices.extend_from_slice(&lod.indices);
+        }
+
+        let (bounds_center, bounds_radius) = math::calculate_bounding_sphere(&mesh.vertices);
+
+        let (vbos, attributes) = match layout {
+            VertexLayout::Separate => (create_device_mesh_vbos(mesh), mesh.attributes.clone()),
+            VertexLayout::Interleaved => create_device_mesh_interleaved_vbo(mesh, false),
+            VertexLayout::Compressed => create_device_mesh_interleaved_vbo(mesh, true),
+        };
+
+        let use_short_indices =
+            layout == VertexLayout::Compressed && mesh.vertices.len() <= u16::MAX as usize + 1;
+        let (indices, index_type) = if use_short_indices {
+            let indices: Vec<u16> = indices.iter().map(|i| i.x as u16).collect();
+            (
+                buffer::create_buffer(&buffer::create_buffer_descriptor(
+                    &indices,
+                    gl::ELEMENT_ARRAY_BUFFER,
+                )),
+                gl::UNSIGNED_SHORT,
+            )
+        } else {
+            (
+                buffer::create_buffer(&buffer::create_buffer_descriptor(
+                    &indices,
+                    gl::ELEMENT_ARRAY_BUFFER,
+                )),
+                gl::UNSIGNED_INT,
+            )
+        };
+
         DeviceMesh {
             name: mesh.name.clone(),
             vao,
-            index_count: mesh.indices.len() as u32 / 3,
-            attributes: mesh.attributes.clone(),
-            vbos: create_device_mesh_vbos(mesh),
-            indices: buffer::create_buffer(&buffer::create_buffer_descriptor(
-                &mesh.indices,
-                gl::ELEMENT_ARRAY_BUFFER,
-            ))
-            .expect("Failed to create index buffer."),
+            lods,
+            bounds_center,
+            bounds_radius,
+            layout,
+            skinned: !mesh.joint_indices.is_empty(),
+            morph_target_names: mesh.morph_targets.iter().map(|x| x.name.clone()).collect(),
+            default_morph_weights: (0..MAX_MORPH_TARGET_COUNT)
+                .map(|i| math::Vec1f::new(mesh.morph_weights.get(i).copied().unwrap_or(0.)))
+                .collect(),
+            attributes,
+            vbos,
+            indices: indices.expect("Failed to create index buffer."),
+            index_type,
             material_index: mesh.material_index,
         }
     }
 
+    pub fn draw(&self, lod_index: usize) {
+        let lod = &self.lods[lod_index.min(self.lods.len() - 1)];
+        let index_size = if self.index_type == gl::UNSIGNED_SHORT {
+            size_of::<u16>()
+        } else {
+            size_of::<u32>()
+        };
+
+        unsafe {
+            gl::DrawElements(
+                gl::TRIANGLES,
+                lod.index_count as i32,
+                self.index_type,
+                (lod.index_offset as usize * index_size) as *const _,
+            );
+        }
+    }
+
+    // Picks the coarsest LOD whose error projects to less than the threshold in pixels
+    pub fn select_lod(
+        &self,
+        camera: &camera::Camera,
+        transform: &math::Mat4x4f,
+        viewport_height: u32,
+    ) -> usize {
+        let center = *transform
+            * math::Vec4f::new(
+                self.bounds_center.x,
+                self.bounds_center.y,
+                self.bounds_center.z,
+                1.,
+            );
+        let scale = math::calculate_max_scale_mat4x4(transform);
+
+        let distance =
+            math::length_vec3(math::Vec3f::new(center.x, center.y, center.z) - camera.pos)
+                - self.bounds_radius * scale;
+
+        let mut lod_index = 0;
+        for (i, lod) in self.lods.iter().enumerate().skip(1) {
+            let error = camera::calculate_screen_space_error(
+                camera,
+                lod.error * scale,
+                distance,
+                viewport_height,
+            );
+            if error > LOD_SCREEN_SPACE_ERROR_THRESHOLD {
+                break;
+            }
+            lod_index = i;
+        }
+        lod_index
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attributes.iter().any(|x| x.name == name)
+    }
+
     pub fn bind_shader_program(&self, program: &shader::ShaderProgram) {
         assert!(
-            self.vbos.len() == self.attributes.len(),
-            "DeviceModel is invalid! There must be Attribute for each VBO.",
+            self.attributes
+                .iter()
+                .all(|x| x.buffer_index < self.vbos.len()),
+            "DeviceModel is invalid! Every Attribute must reference a VBO.",
         );
 
         for program_attribute in &program.attributes {
-            for (i, device_mesh_attribute) in self.attributes.iter().enumerate() {
+            for device_mesh_attribute in &self.attributes {
                 if device_mesh_attribute.name == program_attribute.name {
-                    let vbo = self.vbos[i];
+                    let vbo = self.vbos[device_mesh_attribute.buffer_index];
                     unsafe {
                         gl::BindVertexArray(self.vao);
                         gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
@@ -143,9 +397,13 @@ impl DeviceMesh {
                             program_attribute.location,
                             device_mesh_attribute.dimensions,
                             device_mesh_attribute.data_type,
-                            gl::FALSE,
+                            if device_mesh_attribute.normalized {
+                                gl::TRUE
+                            } else {
+                                gl::FALSE
+                            },
                             device_mesh_attribute.stride,
-                            null(),
+                            device_mesh_attribute.offset as usize as *const _,
                         );
                     }
                 }
@@ -159,31 +417,81 @@ impl DeviceMesh {
     }
 }
 
-fn create_mesh_attributes(
-    vertices: &Vertices,
-    normals: &Normals,
-    tangents: &Tangents,
-    bitangents: &Bitangents,
-    uvs: &UVs,
-) -> Vec<Attribute> {
-    assert!(!vertices.is_empty(), "Model must always have vertices.");
+// Handedness of the bitangent in w, the shader rebuilds it from the normal and the tangent
+fn create_signed_tangents(mesh: &HostMesh) -> Vec<math::Vec4f> {
+    mesh.tangents
+        .iter()
+        .enumerate()
+        .map(|(i, &tangent)| {
+            let sign = match (mesh.normals.get(i), mesh.bitangents.get(i)) {
+                (Some(&normal), Some(&bitangent))
+                    if math::dot_vec3(math::cross(normal, tangent), bitangent) < 0. =>
+                {
+                    -1.
+                }
+                _ => 1.,
+            };
+            math::Vec4f::new(tangent.x, tangent.y, tangent.z, sign)
+        })
+        .collect()
+}
+
+fn create_mesh_attributes(mesh: &HostMesh) -> Vec<Attribute> {
+    assert!(
+        !mesh.vertices.is_empty(),
+        "Model must always have vertices."
+    );
 
     let mut attributes: Vec<Attribute> = Vec::new();
 
-    if !vertices.is_empty() {
-        attributes.push(Attribute::new(&vertices, VERTEX_ATTRIBUTE_NAME));
+    if !mesh.vertices.is_empty() {
+        attributes.push(Attribute::new(&mesh.vertices, VERTEX_ATTRIBUTE_NAME));
+    }
+    if !mesh.normals.is_empty() {
+        attributes.push(Attribute::new(&mesh.normals, NORMAL_ATTRIBUTE_NAME));
+    }
+    if !mesh.tangents.is_empty() {
+        attributes.push(Attribute::new(
+            &create_signed_tangents(mesh),
+            TANGENT_ATTRIBUTE_NAME,
+        ));
+    }
+    if !mesh.uvs.is_empty() {
+        attributes.push(Attribute::new(&mesh.uvs, UV_ATTRIBUTE_NAME));
     }
-    if !normals.is_empty() {
-        attributes.push(Attribute::new(&normals, NORMAL_ATTRIBUTE_NAME));
+    if !mesh.uvs1.is_empty() {
+        attributes.push(Attribute::new(&mesh.uvs1, UV1_ATTRIBUTE_NAME));
     }
-    if !tangents.is_empty() {
-        attributes.push(Attribute::new(&tangents, TANGENT_ATTRIBUTE_NAME));
+    if !mesh.colors.is_empty() {
+        attributes.push(Attribute::new(&mesh.colors, COLOR_ATTRIBUTE_NAME));
     }
-    if !bitangents.is_empty() {
-        attributes.push(Attribute::new(&bitangents, BITANGENT_ATTRIBUTE_NAME));
+    if !mesh.joint_indices.is_empty() {
+        attributes.push(Attribute::new(
+            &mesh.joint_indices,
+            JOINT_INDICES_ATTRIBUTE_NAME,
+        ));
     }
-    if !uvs.is_empty() {
-        attributes.push(Attribute::new(&uvs, UV_ATTRIBUTE_NAME));
+    if !mesh.joint_weights.is_empty() {
+        attributes.push(Attribute::new(
+            &mesh.joint_weights,
+            JOINT_WEIGHTS_ATTRIBUTE_NAME,
+        ));
+    }
+    for (i, target) in mesh.morph_targets.iter().enumerate() {
+        attributes.push(Attribute::new(
+            &target.position_deltas,
+            MORPH_POSITION_ATTRIBUTE_NAMES[i],
+        ));
+        if !target.normal_deltas.is_empty() {
+            attributes.push(Attribute::new(
+                &target.normal_deltas,
+                MORPH_NORMAL_ATTRIBUTE_NAMES[i],
+            ));
+        }
+    }
+
+    for (i, attribute) in attributes.iter_mut().enumerate() {
+        attribute.buffer_index = i;
     }
 
     attributes
@@ -215,32 +523,274 @@ fn create_device_mesh_vbos(mesh: &HostMesh) -> Vec<u32> {
     if !mesh.tangents.is_empty() {
         vbos.push(
             buffer::create_buffer(&buffer::create_buffer_descriptor(
-                &mesh.tangents,
+                &create_signed_tangents(mesh),
                 gl::ARRAY_BUFFER,
             ))
             .expect("Failed to create tangent buffer."),
         );
     }
 
-    if !mesh.bitangents.is_empty() {
+    if !mesh.uvs.is_empty() {
         vbos.push(
             buffer::create_buffer(&buffer::create_buffer_descriptor(
-                &mesh.bitangents,
+                &mesh.uvs,
                 gl::ARRAY_BUFFER,
             ))
-            .expect("Failed to create bitangent buffer."),
+            .expect("Failed to create uv buffer."),
         );
     }
 
-    if !mesh.uvs.is_empty() {
+    if !mesh.uvs1.is_empty() {
         vbos.push(
             buffer::create_buffer(&buffer::create_buffer_descriptor(
-                &mesh.uvs,
+                &mesh.uvs1,
                 gl::ARRAY_BUFFER,
             ))
-            .expect("Failed to create uv buffer."),
+            .expect("Failed to create uv1 buffer."),
+        );
+    }
+
+    if !mesh.colors.is_empty() {
+        vbos.push(
+            buffer::create_buffer(&buffer::create_buffer_descriptor(
+                &mesh.colors,
+                gl::ARRAY_BUFFER,
+            ))
+            .expect("Failed to create color buffer."),
+        );
+    }
+
+    if !mesh.joint_indices.is_empty() {
+        vbos.push(
+            buffer::create_buffer(&buffer::create_buffer_descriptor(
+                &mesh.joint_indices,
+                gl::ARRAY_BUFFER,
+            ))
+            .expect("Failed to create joint index buffer."),
+        );
+    }
+
+    if !mesh.joint_weights.is_empty() {
+        vbos.push(
+            buffer::create_buffer(&buffer::create_buffer_descriptor(
+                &mesh.joint_weights,
+                gl::ARRAY_BUFFER,
+            ))
+            .expect("Failed to create joint weight buffer."),
+        );
+    }
+
+    for target in &mesh.morph_targets {
+        vbos.push(
+            buffer::create_buffer(&buffer::create_buffer_descriptor(
+                &target.position_deltas,
+                gl::ARRAY_BUFFER,
+            ))
+            .expect("Failed to create morph position buffer."),
         );
+        if !target.normal_deltas.is_empty() {
+            vbos.push(
+                buffer::create_buffer(&buffer::create_buffer_descriptor(
+                    &target.normal_deltas,
+                    gl::ARRAY_BUFFER,
+                ))
+                .expect("Failed to create morph normal buffer."),
+            );
+        }
     }
 
     vbos
 }
+
+fn create_device_mesh_interleaved_vbo(
+    mesh: &HostMesh,
+    compressed: bool,
+) -> (Vec<u32>, Vec<Attribute>) {
+    // (dimensions, data type, normalized, size in bytes)
+    let position_format = (3, gl::FLOAT, false, size_of::<math::Vec3f>());
+    let (
+        direction_format,
+        tangent_format,
+        uv_format,
+        color_format,
+        joint_index_format,
+        joint_weight_format,
+        delta_format,
+    ) = if compressed {
+        (
+            (2, gl::SHORT, true, 2 * size_of::<i16>()),
+            // Octahedral direction, padding and handedness
+            (4, gl::SHORT, true, 4 * size_of::<i16>()),
+            (2, gl::HALF_FLOAT, false, 2 * size_of::<u16>()),
+            (4, gl::UNSIGNED_BYTE, true, 4 * size_of::<u8>()),
+            (4, gl::UNSIGNED_SHORT, false, 4 * size_of::<u16>()),
+            (4, gl::UNSIGNED_SHORT, true, 4 * size_of::<u16>()),
+            // Padded to 8 bytes to keep the following attributes aligned
+            (3, gl::HALF_FLOAT, false, 4 * size_of::<u16>()),
+        )
+    } else {
+        (
+            (3, gl::FLOAT, false, size_of::<math::Vec3f>()),
+            (4, gl::FLOAT, false, size_of::<math::Vec4f>()),
+            (2, gl::FLOAT, false, size_of::<math::Vec2f>()),
+            (4, gl::FLOAT, false, size_of::<math::Vec4f>()),
+            (4, gl::UNSIGNED_INT, false, size_of::<math::Vec4u>()),
+            (4, gl::FLOAT, false, size_of::<math::Vec4f>()),
+            (3, gl::FLOAT, false, size_of::<math::Vec3f>()),
+        )
+    };
+
+    let mut attributes: Vec<Attribute> = Vec::new();
+    let mut stride: usize = 0;
+    let mut formats = vec![
+        (VERTEX_ATTRIBUTE_NAME, true, position_format),
+        (
+            NORMAL_ATTRIBUTE_NAME,
+            !mesh.normals.is_empty(),
+            direction_format,
+        ),
+        (
+            TANGENT_ATTRIBUTE_NAME,
+            !mesh.tangents.is_empty(),
+            tangent_format,
+        ),
+        (UV_ATTRIBUTE_NAME, !mesh.uvs.is_empty(), uv_format),
+        (UV1_ATTRIBUTE_NAME, !mesh.uvs1.is_empty(), uv_format),
+        (COLOR_ATTRIBUTE_NAME, !mesh.colors.is_empty(), color_format),
+        (
+            JOINT_INDICES_ATTRIBUTE_NAME,
+            !mesh.joint_indices.is_empty(),
+            joint_index_format,
+        ),
+        (
+            JOINT_WEIGHTS_ATTRIBUTE_NAME,
+            !mesh.joint_weights.is_empty(),
+            joint_weight_format,
+        ),
+    ];
+    for (i, target) in mesh.morph_targets.iter().enumerate() {
+        formats.push((MORPH_POSITION_ATTRIBUTE_NAMES[i], true, delta_format));
+        formats.push((
+            MORPH_NORMAL_ATTRIBUTE_NAMES[i],
+            !target.normal_deltas.is_empty(),
+            delta_format,
+        ));
+    }
+    for &(name, available, (dimensions, data_type, normalized, size)) in &formats {
+        if available {
+            attributes.push(Attribute {
+                name: name.to_string(),
+                dimensions,
+                stride: 0,
+                offset: stride as i32,
+                data_type,
+                normalized,
+                buffer_index: 0,
+            });
+            stride += size;
+        }
+    }
+    for attribute in &mut attributes {
+        attribute.stride = stride as i32;
+    }
+
+    let write_f32s = |data: &mut Vec<u8>, values: &[f32]| {
+        for value in values {
+            data.extend_from_slice(&value.to_ne_bytes());
+        }
+    };
+    let write_direction = |data: &mut Vec<u8>, value: math::Vec3f| {
+        if compressed {
+            let encoded = math::encode_octahedral_vec3(value);
+            data.extend_from_slice(&math::convert_f32_to_snorm16(encoded.x).to_ne_bytes());
+            data.extend_from_slice(&math::convert_f32_to_snorm16(encoded.y).to_ne_bytes());
+        } else {
+            write_f32s(data, &[value.x, value.y, value.z]);
+        }
+    };
+
+    let tangents = create_signed_tangents(mesh);
+    let mut data: Vec<u8> = Vec::with_capacity(stride * mesh.vertices.len());
+    for i in 0..mesh.vertices.len() {
+        let vertex = mesh.vertices[i];
+        write_f32s(&mut data, &[vertex.x, vertex.y, vertex.z]);
+
+        if let Some(&normal) = mesh.normals.get(i) {
+            write_direction(&mut data, normal);
+        }
+
+        if let Some(tangent) = tangents.get(i) {
+            write_direction(&mut data, math::Vec3f::new(tangent.x, tangent.y, tangent.z));
+            if compressed {
+                data.extend_from_slice(&0i16.to_ne_bytes());
+                data.extend_from_slice(&math::convert_f32_to_snorm16(tangent.w).to_ne_bytes());
+            } else {
+                write_f32s(&mut data, &[tangent.w]);
+            }
+        }
+
+        for stream in &[&mesh.uvs, &mesh.uvs1] {
+            if let Some(uv) = stream.get(i) {
+                if compressed {
+                    data.extend_from_slice(&math::convert_f32_to_f16(uv.x).to_ne_bytes());
+                    data.extend_from_slice(&math::convert_f32_to_f16(uv.y).to_ne_bytes());
+                } else {
+                    write_f32s(&mut data, &[uv.x, uv.y]);
+                }
+            }
+        }
+
+        if let Some(color) = mesh.colors.get(i) {
+            for &value in &[color.x, color.y, color.z, color.w] {
+                if compressed {
+                    data.push((value.max(0.).min(1.) * u8::MAX as f32).round() as u8);
+                } else {
+                    data.extend_from_slice(&value.to_ne_bytes());
+                }
+            }
+        }
+
+        if let Some(joints) = mesh.joint_indices.get(i) {
+            for &joint in &[joints.x, joints.y, joints.z, joints.w] {
+                if compressed {
+                    data.extend_from_slice(&(joint as u16).to_ne_bytes());
+                } else {
+                    data.extend_from_slice(&joint.to_ne_bytes());
+                }
+            }
+        }
+
+        if let Some(weights) = mesh.joint_weights.get(i) {
+            for &weight in &[weights.x, weights.y, weights.z, weights.w] {
+                if compressed {
+                    let weight = (weight.max(0.).min(1.) * u16::MAX as f32).round() as u16;
+                    data.extend_from_slice(&weight.to_ne_bytes());
+                } else {
+                    data.extend_from_slice(&weight.to_ne_bytes());
+                }
+            }
+        }
+
+        for target in &mesh.morph_targets {
+            for stream in &[&target.position_deltas, &target.normal_deltas] {
+                if let Some(&delta) = stream.get(i) {
+                    for &value in &[delta.x, delta.y, delta.z] {
+                        if compressed {
+                            data.extend_from_slice(&math::convert_f32_to_f16(value).to_ne_bytes());
+                        } else {
+                            data.extend_from_slice(&value.to_ne_bytes());
+                        }
+                    }
+                    if compressed {
+                        data.extend_from_slice(&0u16.to_ne_bytes());
+                    }
+                }
+            }
+        }
+    }
+
+    let vbo = buffer::create_buffer(&buffer::create_buffer_descriptor(&data, gl::ARRAY_BUFFER))
+        .expect("Failed to create interleaved vertex buffer.");
+
+    (vec![vbo], attributes)
+}
@@ -1,3 +1,6 @@
+pub mod animation;
+pub mod cache;
+pub mod error;
 pub mod material;
 pub mod mesh;
 pub mod model;
@@ -1,5 +1,7 @@
-use crate::asset::{material, mesh};
-use crate::core::pass;
+use crate::asset::error::LoaderError;
+use crate::asset::{animation, material, mesh};
+use crate::core::{camera, pass};
+use crate::math;
 use std::sync::Arc;
 use std::vec::Vec;
 
@@ -7,15 +9,21 @@ use std::vec::Vec;
 pub struct HostModel {
     pub meshes: Arc<Vec<mesh::HostMesh>>,
     pub materials: Arc<Vec<material::HostMaterial>>,
+    pub skeleton: Option<Arc<animation::Skeleton>>,
+    pub animations: Arc<Vec<animation::AnimationClip>>,
+    pub warnings: Vec<LoaderError>,
 }
 
 pub struct DeviceModel {
     pub meshes: Vec<mesh::DeviceMesh>,
     pub materials: Vec<material::DeviceMaterial>,
+    pub skeleton: Option<Arc<animation::Skeleton>>,
+    pub animations: Arc<Vec<animation::AnimationClip>>,
+    pub joint_matrices: Vec<math::Mat4x4f>,
 }
 
 impl DeviceModel {
-    pub fn new(host_model: &HostModel) -> DeviceModel {
+    pub fn new(host_model: &HostModel, layout: mesh::VertexLayout) -> DeviceModel {
         let mut materials: Vec<material::DeviceMaterial> = Vec::new();
         for host_material in host_model.materials.iter() {
             materials.push(material::DeviceMaterial::new(host_material));
@@ -23,10 +31,33 @@ impl DeviceModel {
 
         let mut meshes: Vec<mesh::DeviceMesh> = Vec::new();
         for host_mesh in host_model.meshes.iter() {
-            meshes.push(mesh::DeviceMesh::new(host_mesh));
+            meshes.push(mesh::DeviceMesh::new(host_mesh, layout));
         }
 
-        DeviceModel { meshes, materials }
+        let joint_matrices = match &host_model.skeleton {
+            Some(skeleton) => animation::calculate_joint_matrices(skeleton, &skeleton.rest_pose()),
+            None => Vec::new(),
+        };
+
+        DeviceModel {
+            meshes,
+            materials,
+            skeleton: host_model.skeleton.clone(),
+            animations: host_model.animations.clone(),
+            joint_matrices,
+        }
+    }
+
+    pub fn select_lods(
+        &self,
+        camera: &camera::Camera,
+        transform: &math::Mat4x4f,
+        viewport_height: u32,
+    ) -> Vec<usize> {
+        self.meshes
+            .iter()
+            .map(|x| x.select_lod(camera, transform, viewport_height))
+            .collect()
     }
 
     pub fn bind_pass(&mut self, pass: &pass::Pass) {
@@ -44,3 +75,15 @@ impl DeviceModel {
         }
     }
 }
+
+// Passes draw the meshes of all models in order, per mesh uniforms follow that order
+pub fn create_mesh_transforms(
+    models: &[DeviceModel],
+    transforms: &[math::Mat4x4f],
+) -> Vec<math::Mat4x4f> {
+    models
+        .iter()
+        .zip(transforms)
+        .flat_map(|(model, &transform)| vec![transform; model.meshes.len()])
+        .collect()
+}
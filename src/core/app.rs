@@ -19,6 +19,8 @@ pub struct App {
     pub width: u32,
     pub height: u32,
     pub resized: bool,
+    pub time: f64,
+    pub delta_time: f32,
 }
 
 impl App {
@@ -53,6 +55,8 @@ impl App {
         let imgui_glfw = ui::ImguiGLFW::new(&mut imgui, &mut window);
         imgui.io_mut().mouse_draw_cursor = false;
 
+        let time = glfw.get_time();
+
         App {
             glfw,
             imgui,
@@ -62,6 +66,8 @@ impl App {
             width,
             height,
             resized: false,
+            time,
+            delta_time: 0.,
         }
     }
 }
@@ -26,3 +26,16 @@ pub fn create_default_camera(width: u32, height: u32) -> Camera {
         fov: f32::consts::PI / 2. * 0.66,
     }
 }
+
+// Size in pixels of a world space error seen from the given distance
+pub fn calculate_screen_space_error(
+    camera: &Camera,
+    error: f32,
+    distance: f32,
+    viewport_height: u32,
+) -> f32 {
+    let distance = distance.max(camera.near);
+    let pixels_per_unit = viewport_height as f32 / (2. * (camera.fov / 2.).tan());
+
+    error / distance * pixels_per_unit
+}
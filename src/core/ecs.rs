@@ -1,5 +1,7 @@
 use crate::asset;
 use crate::core;
+use crate::helpers;
+use crate::ibl;
 use crate::math;
 use crate::ui;
 
@@ -220,12 +222,17 @@ ecs_define_shared_archetype_storage!(
     techniques: core::tech::TechniqueContainer,
     pipeline: core::pipeline::Pipeline,
     camera: core::camera::Camera,
+    scene: helpers::scene::SceneDescription,
 );
 
 ecs_define_archetype_storage!(
     renderer: Asset => (
         models: asset::model::DeviceModel,
         transforms: math::Mat4x4f,
+        animation_states: asset::animation::AnimationState,
+    ),
+    probes: ReflectionProbes => (
+        reflection_probes: ibl::ReflectionProbe,
     ),
 );
 
@@ -233,7 +240,11 @@ ecs_define_entity_queries!(
     (
         models: asset::model::DeviceModel,
         transforms: math::Mat4x4f,
+        animation_states: asset::animation::AnimationState,
     ) => core::ecs::Asset,
+    (
+        reflection_probes: ibl::ReflectionProbe,
+    ) => core::ecs::ReflectionProbes,
 );
 
 ecs_define_entity_chunks!(
@@ -241,12 +252,24 @@ ecs_define_entity_chunks!(
         (
             &'a Vec<asset::model::DeviceModel>,
             &'a Vec<math::Mat4x4f>,
+            &'a Vec<asset::animation::AnimationState>,
         ),
         (
             &'a mut Vec<asset::model::DeviceModel>,
             &'a mut Vec<math::Mat4x4f>,
+            &'a mut Vec<asset::animation::AnimationState>,
         )
     ) => (
         core::ecs::Asset,
     ),
+    (
+        (
+            &'a Vec<ibl::ReflectionProbe>,
+        ),
+        (
+            &'a mut Vec<ibl::ReflectionProbe>,
+        )
+    ) => (
+        core::ecs::ReflectionProbes,
+    ),
 );
@@ -44,6 +44,10 @@ pub fn update_input(app: &mut app::App, input_data: &mut Data) {
 
     app.glfw.poll_events();
 
+    let time = app.glfw.get_time();
+    app.delta_time = (time - app.time) as f32;
+    app.time = time;
+
     for (_, event) in glfw::flush_messages(&app.events) {
         match event {
             glfw::WindowEvent::MouseButton(mouse_btn, action, _) => {
@@ -170,25 +174,35 @@ pub fn update_camera(camera: &mut camera::Camera, app: &app::App, input_data: &D
 pub fn hot_reload(
     pipeline: &mut pipeline::Pipeline,
     techniques: &mut tech::TechniqueContainer,
-    device_model: &mut model::DeviceModel,
+    device_models: &mut [model::DeviceModel],
     input_data: &Data,
 ) {
     if let Some(Action::Press) = input_data.keys.get(&Key::F5) {
-        pipeline.reload(techniques, device_model);
+        pipeline.reload(techniques, device_models);
     }
 }
 
+pub fn screenshot_requested(input_data: &Data) -> bool {
+    if let Some(Action::Press) = input_data.keys.get(&Key::F12) {
+        return true;
+    }
+    false
+}
+
 pub fn resize(
     pipeline: &mut pipeline::Pipeline,
     techniques: &mut tech::TechniqueContainer,
-    device_model: &mut model::DeviceModel,
+    device_models: &[model::DeviceModel],
     app: &app::App,
 ) {
     if app.resized {
         pipeline::resize_render_pipeline(app, pipeline);
 
-        if let Err(msg) = pipeline::is_render_pipeline_valid(pipeline, &techniques, &device_model) {
-            log::log_error(msg);
+        for device_model in device_models {
+            if let Err(msg) = pipeline::is_render_pipeline_valid(pipeline, techniques, device_model)
+            {
+                log::log_error(msg);
+            }
         }
     }
 }
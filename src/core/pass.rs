@@ -1,11 +1,10 @@
 extern crate gl;
-use crate::asset::{material, model};
+use crate::asset::{material, mesh, model};
 use crate::core::tech;
 use crate::gl::{shader, tex, uniform};
 use crate::helpers::log;
 use crate::math;
 use std::collections::HashMap;
-use std::ptr::null;
 use std::rc::Rc;
 
 pub struct Framebuffer {
@@ -41,15 +40,11 @@ impl Framebuffer {
                     let attachment_index = (gl::COLOR_ATTACHMENT0 as u32 + color_attachment_count)
                         as gl::types::GLenum;
 
-                    unsafe {
-                        gl::FramebufferTexture2D(
-                            gl::FRAMEBUFFER,
-                            attachment_index,
-                            attachment.desc.textarget,
-                            attachment_texture.handle,
-                            attachment.desc.mip_level,
-                        );
-                    }
+                    attach_framebuffer_texture(
+                        attachment_index,
+                        &attachment_texture,
+                        &attachment.desc,
+                    );
                     draw_attachments.push(attachment_index);
 
                     color_attachment_count += 1;
@@ -59,15 +54,11 @@ impl Framebuffer {
                         depth_attachment_count == 0,
                         "There can only be 1 depth attachment"
                     );
-                    unsafe {
-                        gl::FramebufferTexture2D(
-                            gl::FRAMEBUFFER,
-                            gl::DEPTH_ATTACHMENT,
-                            attachment.desc.textarget,
-                            attachment_texture.handle,
-                            attachment.desc.mip_level,
-                        );
-                    }
+                    attach_framebuffer_texture(
+                        gl::DEPTH_ATTACHMENT,
+                        &attachment_texture,
+                        &attachment.desc,
+                    );
                     depth_attachment_count += 1;
                 }
             }
@@ -122,6 +113,8 @@ pub struct PassAttachmentDescriptor {
     pub width: u32,
     pub height: u32,
     pub mip_level: i32,
+    // Array slice, 3D slice or cube array layer-face of layered textures
+    pub layer: i32,
 }
 
 #[derive(Clone)]
@@ -167,6 +160,24 @@ pub struct Pass {
     pub height: u32,
 }
 
+impl PassAttachment {
+    // Reads back the level and layer or cube face this attachment renders to, top row first
+    pub fn download(&self) -> tex::HostTexture {
+        let layer = match self.desc.textarget {
+            gl::TEXTURE_CUBE_MAP_POSITIVE_X..=gl::TEXTURE_CUBE_MAP_NEGATIVE_Z => {
+                (self.desc.textarget - gl::TEXTURE_CUBE_MAP_POSITIVE_X) as usize
+            }
+            _ => self.desc.layer.max(0) as usize,
+        };
+        let mut texture = self
+            .texture
+            .download_level(layer, self.desc.mip_level.max(0) as usize);
+        // Framebuffer rows start at the bottom
+        texture.flip_rows();
+        texture
+    }
+}
+
 impl Pass {
     pub fn new(desc: PassDescriptor) -> Result<Pass, String> {
         let device_program = shader::ShaderProgram::new(&desc.program);
@@ -210,6 +221,17 @@ impl Pass {
     }
 
     pub fn execute(&self, techniques: &tech::TechniqueContainer, model: &model::DeviceModel) {
+        self.execute_models(techniques, std::slice::from_ref(model), &[]);
+    }
+
+    // Meshes are numbered across all models to index the per model uniforms of techniques.
+    // Meshes without an entry in lod_indices draw their full detail LOD
+    pub fn execute_models(
+        &self,
+        techniques: &tech::TechniqueContainer,
+        models: &[model::DeviceModel],
+        lod_indices: &[Vec<usize>],
+    ) {
         let mut clear_mask: gl::types::GLbitfield = 0;
 
         for attachment in &self.fbo.attachments {
@@ -261,7 +283,21 @@ impl Pass {
             }
         }
 
-        for (i, mesh) in model.meshes.iter().enumerate() {
+        let meshes = models.iter().enumerate().flat_map(|(model_index, model)| {
+            model
+                .meshes
+                .iter()
+                .enumerate()
+                .map(move |(mesh_index, mesh)| {
+                    let lod_index = lod_indices
+                        .get(model_index)
+                        .and_then(|x| x.get(mesh_index))
+                        .copied()
+                        .unwrap_or(0);
+                    (model, mesh, lod_index)
+                })
+        });
+        for (i, (model, mesh, lod_index)) in meshes.enumerate() {
             unsafe {
                 gl::BindVertexArray(mesh.vao);
             }
@@ -280,14 +316,7 @@ impl Pass {
                 );
             }
 
-            unsafe {
-                gl::DrawElements(
-                    gl::TRIANGLES,
-                    (mesh.index_count * 3) as i32,
-                    gl::UNSIGNED_INT,
-                    null(),
-                );
-            }
+            mesh.draw(lod_index);
 
             unbind_dependencies(&self.program, &self.dependencies);
             unbind_material(
@@ -449,6 +478,9 @@ pub fn is_render_pass_valid(
     {
         for (i, mesh) in device_model.meshes.iter().enumerate() {
             for shader_attribute in &pass.program.attributes {
+                if mesh::OPTIONAL_ATTRIBUTE_NAMES.contains(&shader_attribute.name.as_str()) {
+                    continue;
+                }
                 if let None = mesh
                     .attributes
                     .iter()
@@ -565,12 +597,30 @@ pub fn blit_framebuffer_to_backbuffer(pass: &Pass) {
     }
 }
 
-pub fn copy_framebuffer_to_texture(fbo: u32, width: u32, height: u32, target: gl::types::GLenum) {
+fn attach_framebuffer_texture(
+    attachment_index: gl::types::GLenum,
+    texture: &tex::DeviceTexture,
+    desc: &PassAttachmentDescriptor,
+) {
     unsafe {
-        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fbo);
-        gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
-        gl::CopyTexSubImage2D(target, 0, 0, 0, 0, 0, width as i32, height as i32);
-        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        match texture.target {
+            gl::TEXTURE_2D_ARRAY | gl::TEXTURE_3D | gl::TEXTURE_CUBE_MAP_ARRAY => {
+                gl::FramebufferTextureLayer(
+                    gl::FRAMEBUFFER,
+                    attachment_index,
+                    texture.handle,
+                    desc.mip_level,
+                    desc.layer,
+                )
+            }
+            _ => gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                attachment_index,
+                desc.textarget,
+                texture.handle,
+                desc.mip_level,
+            ),
+        }
     }
 }
 
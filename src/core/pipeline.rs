@@ -1,8 +1,9 @@
 use crate::asset::model;
 use crate::core::{app, pass, tech};
 use crate::gl::{shader, tex};
-use crate::helpers::{helper, log};
+use crate::helpers::{helper, log, writer};
 use crate::math;
+use std::path::{Path, PathBuf};
 
 pub struct Pipeline {
     pub passes: Vec<pass::Pass>,
@@ -21,7 +22,11 @@ impl Pipeline {
                 vert_shader_file_path: "shaders/depth_pre_pass.vert".to_string(),
                 frag_shader_file_path: "shaders/depth_pre_pass.frag".to_string(),
             },
-            techniques: vec![tech::Techniques::MVP],
+            techniques: vec![
+                tech::Techniques::MVP,
+                tech::Techniques::Skinning,
+                tech::Techniques::MorphTargets,
+            ],
             attachments: vec![pass::PassAttachmentDescriptor {
                 texture_desc: tex::Descriptor::new(tex::DescriptorType::Depth),
                 flavor: pass::PassAttachmentType::Depth(1., gl::LESS),
@@ -32,6 +37,7 @@ impl Pipeline {
                 width: app.width,
                 height: app.height,
                 mip_level: 0,
+                layer: 0,
             }],
             dependencies: Vec::new(),
             width: app.width,
@@ -50,7 +56,12 @@ impl Pipeline {
                 vert_shader_file_path: "shaders/lighting.vert".to_string(),
                 frag_shader_file_path: "shaders/lighting.frag".to_string(),
             },
-            techniques: vec![tech::Techniques::MVP, tech::Techniques::Lighting],
+            techniques: vec![
+                tech::Techniques::MVP,
+                tech::Techniques::Skinning,
+                tech::Techniques::MorphTargets,
+                tech::Techniques::Lighting,
+            ],
             attachments: vec![
                 pass::PassAttachmentDescriptor {
                     texture_desc: tex::Descriptor::new(tex::DescriptorType::Depth),
@@ -66,6 +77,7 @@ impl Pipeline {
                     width: app.width,
                     height: app.height,
                     mip_level: 0,
+                    layer: 0,
                 },
                 pass::PassAttachmentDescriptor {
                     texture_desc: tex::Descriptor::new(tex::DescriptorType::ColorAttachment),
@@ -77,6 +89,7 @@ impl Pipeline {
                     width: app.width,
                     height: app.height,
                     mip_level: 0,
+                    layer: 0,
                 },
             ],
             dependencies: vec![pass::PassDependencyDescriptor {
@@ -119,6 +132,7 @@ impl Pipeline {
                     width: app.width,
                     height: app.height,
                     mip_level: 0,
+                    layer: 0,
                 },
                 pass::PassAttachmentDescriptor {
                     texture_desc: tex::Descriptor::new(tex::DescriptorType::ColorAttachment),
@@ -134,6 +148,7 @@ impl Pipeline {
                     width: app.width,
                     height: app.height,
                     mip_level: 0,
+                    layer: 0,
                 },
             ],
             dependencies: Vec::new(),
@@ -165,6 +180,7 @@ impl Pipeline {
                 width: app.width,
                 height: app.height,
                 mip_level: 0,
+                layer: 0,
             }],
             dependencies: vec![pass::PassDependencyDescriptor {
                 name: "uColorSampler2D".to_string(),
@@ -204,10 +220,12 @@ impl Pipeline {
     pub fn reload(
         &mut self,
         techniques: &mut tech::TechniqueContainer,
-        device_model: &mut model::DeviceModel,
+        device_models: &mut [model::DeviceModel],
     ) {
         techniques.unbind_pipeline(self);
-        self.unbind_model(device_model);
+        for device_model in device_models.iter_mut() {
+            self.unbind_model(device_model);
+        }
 
         if let Err(msg) = reload_render_pipeline(self) {
             log::log_error(format!("Failed to hot reload pipeline:\n{}", msg));
@@ -216,10 +234,12 @@ impl Pipeline {
         }
 
         techniques.bind_pipeline(self);
-        self.bind_model(device_model);
+        for device_model in device_models.iter_mut() {
+            self.bind_model(device_model);
 
-        if let Err(msg) = is_render_pipeline_valid(self, &techniques, &device_model) {
-            log::log_error(msg);
+            if let Err(msg) = is_render_pipeline_valid(self, techniques, device_model) {
+                log::log_error(msg);
+            }
         }
     }
 
@@ -233,14 +253,38 @@ impl Pipeline {
         device_model.unbind_pass(self.passes[1].program.handle);
     }
 
-    pub fn draw(&self, techniques: &tech::TechniqueContainer, device_model: &model::DeviceModel) {
-        self.passes[0].execute(&techniques, &device_model);
-        self.passes[1].execute(&techniques, &device_model);
+    pub fn draw(
+        &self,
+        techniques: &tech::TechniqueContainer,
+        device_models: &[model::DeviceModel],
+        lod_indices: &[Vec<usize>],
+    ) {
+        self.passes[0].execute_models(techniques, device_models, lod_indices);
+        self.passes[1].execute_models(techniques, device_models, lod_indices);
         self.passes[2].execute(&techniques, &self.skybox_model);
         self.passes[3].execute(&techniques, &self.fullsceen_model);
 
         pass::blit_framebuffer_to_backbuffer(&self.passes.last().unwrap());
     }
+
+    // Tone mapped output as PNG and the HDR lighting attachment as EXR, without the UI
+    pub fn save_screenshot(&self, folder_path: &Path) -> Result<Vec<PathBuf>, String> {
+        let timestamp = writer::create_timestamp();
+        let captures = [
+            (&self.passes[3].fbo.attachments[0], "png"),
+            (&self.passes[1].fbo.attachments[1], "exr"),
+        ];
+
+        let mut paths = Vec::new();
+        for (attachment, extension) in captures.iter() {
+            let texture = writer::remove_alpha_channel(&attachment.download());
+            let path = folder_path.join(format!("screenshot_{}.{}", timestamp, extension));
+            writer::write_host_texture(&texture, &path)?;
+            paths.push(path);
+        }
+
+        Ok(paths)
+    }
 }
 
 pub fn is_render_pipeline_valid(
@@ -75,6 +75,8 @@ impl Technique {
 #[derive(Hash, PartialEq, Clone)]
 pub enum Techniques {
     MVP,
+    Skinning,
+    MorphTargets,
     Lighting,
     Skybox,
     IBL,
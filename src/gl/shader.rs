@@ -263,7 +263,7 @@ fn link_shader_program(
 fn create_shader_program_attributes(program: u32, file: &str) -> Vec<ShaderProgramAttribute> {
     let mut attribs: Vec<ShaderProgramAttribute> = Vec::new();
 
-    for attribute_name in find_shader_program_inputs(file, ShaderProgramVariableType::In, 0, 10) {
+    for attribute_name in find_shader_program_inputs(file, ShaderProgramVariableType::In, 0, 16) {
         if let ShaderProgramInputFindResult::In(attribute_name) = attribute_name {
             let c_attribute_name =
                 CString::new(attribute_name.clone()).expect("Failed to create CString.");
@@ -293,7 +293,7 @@ fn create_shader_program_scalar_uniforms(
     let mut i = 0;
     while i < files.len() {
         for uniform_name in
-            find_shader_program_inputs(files[i], ShaderProgramVariableType::Uniform, 10, 50)
+            find_shader_program_inputs(files[i], ShaderProgramVariableType::Uniform, 10, 256)
         {
             if let ShaderProgramInputFindResult::Uniform(uniform_name) = uniform_name {
                 let c_uniform_name =
@@ -420,7 +420,8 @@ fn find_shader_program_inputs(
             let last_index = index + file[index..].find(';').unwrap();
 
             if let Some(attribute_name) = file[index..last_index].split_whitespace().last() {
-                let attribute_name = attribute_name.to_string();
+                // Array uniforms are located by their name without the size suffix
+                let attribute_name = attribute_name.split('[').next().unwrap().to_string();
                 match input_type {
                     ShaderProgramVariableType::In => {
                         inputs.push(ShaderProgramInputFindResult::In(attribute_name))
@@ -2,6 +2,12 @@ use std::ffi::c_void;
 use std::ptr::null;
 use std::rc::Rc;
 
+// S3TC formats come from EXT_texture_compression_s3tc and EXT_texture_sRGB
+const COMPRESSED_RGBA_S3TC_DXT1_EXT: gl::types::GLenum = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT5_EXT: gl::types::GLenum = 0x83F3;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT: gl::types::GLenum = 0x8C4D;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT: gl::types::GLenum = 0x8C4F;
+
 #[derive(Clone)]
 pub struct Descriptor {
     pub target: gl::types::GLenum,
@@ -18,17 +24,45 @@ pub struct Descriptor {
 }
 
 pub enum DescriptorType<'a> {
-    Color(&'a HostTexture),
+    Color(&'a HostTexture, SamplerFormat),
     Depth,
     ColorAttachment,
     SphericalHdri(&'a HostTexture),
     PrefilteredEnvMap,
 }
 
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ColorSpace {
+    Linear,
+    Srgb,
+}
+
+// How a material slot reads its texture, extra source channels are dropped on upload
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SamplerFormat {
+    pub color_space: ColorSpace,
+    pub channel_count: usize,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CompressedFormat {
+    BC1,
+    BC1Srgb,
+    BC3,
+    BC3Srgb,
+    BC4,
+    BC5,
+    BC6H,
+    BC7,
+    BC7Srgb,
+}
+
 #[derive(Clone)]
 pub enum HostTextureData {
     UINT8(Vec<u8>),
     FLOAT32(Vec<f32>),
+    // Mip chain of 4x4 blocks, largest level first
+    COMPRESSED(CompressedFormat, Vec<Vec<u8>>),
 }
 
 #[derive(Clone)]
@@ -38,6 +72,10 @@ pub struct HostTexture {
     pub height: usize,
     pub depth: usize,
     pub data: HostTextureData,
+    // Levels below the base one, the driver generates them when empty
+    pub mips: Vec<HostTextureData>,
+    // Declared by the file like the sRGB block formats, overrides a linear sampler slot
+    pub srgb: bool,
 }
 
 #[derive(Clone)]
@@ -66,10 +104,54 @@ impl HostTexture {
             height,
             depth,
             data: HostTextureData::UINT8(Vec::new()),
+            mips: Vec::new(),
+            srgb: false,
+        }
+    }
+
+    // Only the base level, mips are dropped
+    pub fn flip_rows(&mut self) {
+        let stride = self.width * self.depth;
+        match &mut self.data {
+            HostTextureData::FLOAT32(data) => flip_rows(data, stride),
+            HostTextureData::UINT8(data) => flip_rows(data, stride),
+            HostTextureData::COMPRESSED(..) => {}
+        }
+        self.mips.clear();
+    }
+}
+
+impl SamplerFormat {
+    pub fn new(color_space: ColorSpace, channel_count: usize) -> SamplerFormat {
+        SamplerFormat {
+            color_space,
+            channel_count,
         }
     }
 }
 
+impl CompressedFormat {
+    pub fn block_size(&self) -> usize {
+        match self {
+            CompressedFormat::BC1 | CompressedFormat::BC1Srgb | CompressedFormat::BC4 => 8,
+            _ => 16,
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        match self {
+            CompressedFormat::BC4 => 1,
+            CompressedFormat::BC5 => 2,
+            CompressedFormat::BC6H => 3,
+            _ => 4,
+        }
+    }
+
+    pub fn calculate_level_size(&self, width: usize, height: usize) -> usize {
+        width.div_ceil(4).max(1) * height.div_ceil(4).max(1) * self.block_size()
+    }
+}
+
 impl Drop for DeviceTexture {
     fn drop(&mut self) {
         unsafe { gl::DeleteTextures(1, &self.handle) };
@@ -78,79 +160,441 @@ impl Drop for DeviceTexture {
 
 impl DeviceTexture {
     pub fn new(host_texture: &HostTexture, desc: &Descriptor) -> Rc<DeviceTexture> {
-        assert_eq!(desc.target, gl::TEXTURE_2D);
+        if desc.target != gl::TEXTURE_2D {
+            return DeviceTexture::new_layered(&[host_texture], desc);
+        }
 
-        let mut handle: u32 = 0;
-        unsafe { gl::GenTextures(1, &mut handle as *mut u32) };
-        assert!(handle != 0, "Failed to generate texture");
+        let handle = generate_texture_handle(desc);
 
         unsafe {
-            gl::BindTexture(desc.target, handle);
-            gl::TexParameteri(desc.target, gl::TEXTURE_WRAP_S, desc.s_wrap as i32);
-            gl::TexParameteri(desc.target, gl::TEXTURE_WRAP_T, desc.t_wrap as i32);
-            gl::TexParameteri(desc.target, gl::TEXTURE_WRAP_R, desc.r_wrap as i32);
-            gl::TexParameteri(desc.target, gl::TEXTURE_MAG_FILTER, desc.mag_filter as i32);
-            gl::TexParameteri(desc.target, gl::TEXTURE_MIN_FILTER, desc.min_filter as i32);
-
-            if desc.max_anisotropy > 1. {
-                gl::TexParameterf(
-                    desc.target,
-                    0x84FE as gl::types::GLenum,
-                    desc.max_anisotropy,
+            if let HostTextureData::COMPRESSED(_, levels) = &host_texture.data {
+                // Mips come from the file, compressed formats cannot be regenerated by the driver
+                for (level, data) in levels.iter().enumerate() {
+                    gl::CompressedTexImage2D(
+                        desc.target,
+                        level as i32,
+                        desc.internal_format,
+                        (host_texture.width >> level).max(1) as i32,
+                        (host_texture.height >> level).max(1) as i32,
+                        0,
+                        data.len() as i32,
+                        data.as_ptr() as *const c_void,
+                    );
+                }
+            } else {
+                let levels = std::iter::once(&host_texture.data).chain(host_texture.mips.iter());
+                for (level, data) in levels.enumerate() {
+                    gl::TexImage2D(
+                        desc.target,
+                        level as i32,
+                        desc.internal_format as i32,
+                        (host_texture.width >> level).max(1) as i32,
+                        (host_texture.height >> level).max(1) as i32,
+                        0,
+                        desc.format,
+                        desc.data_type,
+                        get_host_texture_data_pointer(data),
+                    );
+                }
+            }
+        }
+
+        let texture = DeviceTexture {
+            handle,
+            target: desc.target,
+        };
+        texture.set_mip_range(
+            desc,
+            calculate_host_texture_level_count(host_texture),
+            !matches!(host_texture.data, HostTextureData::COMPRESSED(..)),
+        );
+        Rc::new(texture)
+    }
+
+    // Storage for every target, layer_count is the array size, the 3D depth or the cube count
+    pub fn new_empty(
+        desc: &Descriptor,
+        width: usize,
+        height: usize,
+        layer_count: usize,
+    ) -> Rc<DeviceTexture> {
+        let level_count = if desc.use_mipmaps {
+            calculate_mip_count(width, height)
+        } else {
+            1
+        };
+        DeviceTexture::new_storage(desc, width, height, layer_count, level_count)
+    }
+
+    // Layers are array slices, 3D slices or cube faces in +X, -X, +Y, -Y, +Z, -Z order
+    pub fn new_layered(layers: &[&HostTexture], desc: &Descriptor) -> Rc<DeviceTexture> {
+        assert!(
+            !layers.is_empty(),
+            "Layered texture needs at least one layer"
+        );
+
+        let first = layers[0];
+        let layer_count = match desc.target {
+            gl::TEXTURE_CUBE_MAP | gl::TEXTURE_CUBE_MAP_ARRAY => {
+                assert!(layers.len() % 6 == 0, "Cube maps need 6 faces per cube");
+                layers.len() / 6
+            }
+            _ => layers.len(),
+        };
+        let stored_level_count = calculate_host_texture_level_count(first);
+        let level_count = if desc.use_mipmaps && stored_level_count == 1 {
+            calculate_mip_count(first.width, first.height)
+        } else {
+            stored_level_count
+        };
+
+        let texture =
+            DeviceTexture::new_storage(desc, first.width, first.height, layer_count, level_count);
+        for (layer, host_texture) in layers.iter().enumerate() {
+            texture.upload_layer(desc, layer, host_texture);
+        }
+        if level_count > stored_level_count {
+            texture.generate_mipmaps();
+        }
+
+        texture
+    }
+
+    pub fn new_cube_map(host_texture: &HostCubeMapTexture, desc: &Descriptor) -> Rc<DeviceTexture> {
+        DeviceTexture::new_layered(
+            &[
+                &host_texture.px,
+                &host_texture.nx,
+                &host_texture.py,
+                &host_texture.ny,
+                &host_texture.pz,
+                &host_texture.nz,
+            ],
+            &Descriptor {
+                target: gl::TEXTURE_CUBE_MAP,
+                ..desc.clone()
+            },
+        )
+    }
+
+    // Uploads the base level and every stored mip of one layer
+    pub fn upload_layer(&self, desc: &Descriptor, layer: usize, host_texture: &HostTexture) {
+        match &host_texture.data {
+            HostTextureData::COMPRESSED(_, levels) => {
+                for level in 0..levels.len() {
+                    self.upload_level(desc, layer, level, host_texture, &host_texture.data);
+                }
+            }
+            data => {
+                let levels = std::iter::once(data).chain(host_texture.mips.iter());
+                for (level, data) in levels.enumerate() {
+                    self.upload_level(desc, layer, level, host_texture, data);
+                }
+            }
+        }
+    }
+
+    // Level sizes come from the base size of the host texture, compressed data holds every level
+    pub fn upload_level(
+        &self,
+        desc: &Descriptor,
+        layer: usize,
+        level: usize,
+        host_texture: &HostTexture,
+        data: &HostTextureData,
+    ) {
+        let width = (host_texture.width >> level).max(1) as i32;
+        let height = (host_texture.height >> level).max(1) as i32;
+        let level = level as i32;
+
+        unsafe {
+            gl::BindTexture(self.target, self.handle);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+
+            let target_2d = match self.target {
+                gl::TEXTURE_CUBE_MAP => Some(gl::TEXTURE_CUBE_MAP_POSITIVE_X + layer as u32),
+                gl::TEXTURE_2D => Some(gl::TEXTURE_2D),
+                _ => None,
+            };
+            match (data, target_2d) {
+                (HostTextureData::COMPRESSED(_, levels), Some(target)) => {
+                    gl::CompressedTexSubImage2D(
+                        target,
+                        level,
+                        0,
+                        0,
+                        width,
+                        height,
+                        desc.internal_format,
+                        levels[level as usize].len() as i32,
+                        levels[level as usize].as_ptr() as *const c_void,
+                    )
+                }
+                (HostTextureData::COMPRESSED(_, levels), None) => gl::CompressedTexSubImage3D(
+                    self.target,
+                    level,
+                    0,
+                    0,
+                    layer as i32,
+                    width,
+                    height,
+                    1,
+                    desc.internal_format,
+                    levels[level as usize].len() as i32,
+                    levels[level as usize].as_ptr() as *const c_void,
+                ),
+                (data, Some(target)) => gl::TexSubImage2D(
+                    target,
+                    level,
+                    0,
+                    0,
+                    width,
+                    height,
+                    desc.format,
+                    desc.data_type,
+                    get_host_texture_data_pointer(data),
+                ),
+                (data, None) => gl::TexSubImage3D(
+                    self.target,
+                    level,
+                    0,
+                    0,
+                    layer as i32,
+                    width,
+                    height,
+                    1,
+                    desc.format,
+                    desc.data_type,
+                    get_host_texture_data_pointer(data),
+                ),
+            }
+        }
+    }
+
+    pub fn generate_mipmaps(&self) {
+        unsafe {
+            gl::BindTexture(self.target, self.handle);
+            gl::GenerateMipmap(self.target);
+        }
+    }
+
+    // Reads a level back in the row order uploads use, cube faces are layers
+    pub fn download_level(&self, layer: usize, level: usize) -> HostTexture {
+        let query_target = match self.target {
+            gl::TEXTURE_CUBE_MAP => gl::TEXTURE_CUBE_MAP_POSITIVE_X,
+            target => target,
+        };
+        let query = |parameter: gl::types::GLenum| {
+            let mut value: i32 = 0;
+            unsafe {
+                gl::GetTexLevelParameteriv(query_target, level as i32, parameter, &mut value)
+            };
+            value
+        };
+
+        unsafe { gl::BindTexture(self.target, self.handle) };
+        let width = query(gl::TEXTURE_WIDTH).max(0) as usize;
+        let height = query(gl::TEXTURE_HEIGHT).max(0) as usize;
+        let is_depth = query(gl::TEXTURE_DEPTH_SIZE) > 0;
+        let is_float = is_depth || query(gl::TEXTURE_RED_TYPE) != gl::UNSIGNED_NORMALIZED as i32;
+        let depth = if is_depth {
+            1
+        } else {
+            1 + [
+                gl::TEXTURE_GREEN_SIZE,
+                gl::TEXTURE_BLUE_SIZE,
+                gl::TEXTURE_ALPHA_SIZE,
+            ]
+            .iter()
+            .filter(|&&x| query(x) > 0)
+            .count()
+        };
+        let format = if is_depth {
+            gl::DEPTH_COMPONENT
+        } else {
+            convert_image_depth_to_gl_format(depth)
+        };
+
+        let count = width * height * depth;
+        let mut texture = HostTexture::empty(
+            format!("texture_{}_layer_{}_level_{}", self.handle, layer, level),
+            width,
+            height,
+            depth,
+        );
+        texture.data = if is_float {
+            HostTextureData::FLOAT32(vec![0.; count])
+        } else {
+            HostTextureData::UINT8(vec![0; count])
+        };
+
+        let (data_type, size, pointer) = match &mut texture.data {
+            HostTextureData::FLOAT32(data) => {
+                (gl::FLOAT, count * 4, data.as_mut_ptr() as *mut c_void)
+            }
+            HostTextureData::UINT8(data) => {
+                (gl::UNSIGNED_BYTE, count, data.as_mut_ptr() as *mut c_void)
+            }
+            HostTextureData::COMPRESSED(..) => unreachable!(),
+        };
+        if count > 0 {
+            unsafe {
+                gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+                gl::GetTextureSubImage(
+                    self.handle,
+                    level as i32,
+                    0,
+                    0,
+                    layer as i32,
+                    width as i32,
+                    height as i32,
+                    1,
+                    format,
+                    data_type,
+                    size as i32,
+                    pointer,
                 );
             }
+        }
 
-            gl::TexImage2D(
-                desc.target,
-                0,
-                desc.internal_format as i32,
-                host_texture.width as i32,
-                host_texture.height as i32,
-                0,
-                desc.format,
-                desc.data_type,
-                {
-                    match &host_texture.data {
-                        HostTextureData::UINT8(data) => {
-                            if data.is_empty() {
-                                null()
-                            } else {
-                                data.as_ptr() as *const c_void
-                            }
-                        }
-                        HostTextureData::FLOAT32(data) => {
-                            if data.is_empty() {
-                                null()
-                            } else {
-                                data.as_ptr() as *const c_void
-                            }
-                        }
-                    }
-                },
-            );
+        texture
+    }
 
-            if desc.use_mipmaps {
-                gl::TexParameteri(
+    fn new_storage(
+        desc: &Descriptor,
+        width: usize,
+        height: usize,
+        layer_count: usize,
+        level_count: usize,
+    ) -> Rc<DeviceTexture> {
+        let handle = generate_texture_handle(desc);
+        let (width, height, level_count) = (width as i32, height as i32, level_count as i32);
+        let layer_count = layer_count.max(1) as i32;
+
+        unsafe {
+            match desc.target {
+                gl::TEXTURE_2D | gl::TEXTURE_CUBE_MAP => gl::TexStorage2D(
+                    desc.target,
+                    level_count,
+                    desc.internal_format,
+                    width,
+                    height,
+                ),
+                gl::TEXTURE_2D_ARRAY | gl::TEXTURE_3D => gl::TexStorage3D(
                     desc.target,
+                    level_count,
+                    desc.internal_format,
+                    width,
+                    height,
+                    layer_count,
+                ),
+                gl::TEXTURE_CUBE_MAP_ARRAY => gl::TexStorage3D(
+                    desc.target,
+                    level_count,
+                    desc.internal_format,
+                    width,
+                    height,
+                    layer_count * 6,
+                ),
+                _ => panic!("Unsupported texture target"),
+            }
+        }
+
+        let texture = DeviceTexture {
+            handle,
+            target: desc.target,
+        };
+        texture.set_mip_range(desc, level_count as usize, false);
+        Rc::new(texture)
+    }
+
+    fn set_mip_range(&self, desc: &Descriptor, level_count: usize, generate_mipmaps: bool) {
+        unsafe {
+            gl::BindTexture(self.target, self.handle);
+
+            // Textures without stored mips get them from the driver when allowed
+            let generate_mipmaps = desc.use_mipmaps && generate_mipmaps && level_count == 1;
+            if generate_mipmaps {
+                gl::GenerateMipmap(self.target);
+            } else {
+                gl::TexParameteri(self.target, gl::TEXTURE_MAX_LEVEL, level_count as i32 - 1);
+            }
+
+            if desc.use_mipmaps && (generate_mipmaps || level_count > 1) {
+                gl::TexParameteri(
+                    self.target,
                     gl::TEXTURE_MIN_FILTER,
                     gl::LINEAR_MIPMAP_LINEAR as i32,
                 );
-                gl::TexParameteri(desc.target, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-                gl::GenerateMipmap(desc.target);
+                gl::TexParameteri(self.target, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
             }
         }
+    }
+}
+
+fn generate_texture_handle(desc: &Descriptor) -> u32 {
+    let mut handle: u32 = 0;
+    unsafe { gl::GenTextures(1, &mut handle as *mut u32) };
+    assert!(handle != 0, "Failed to generate texture");
+
+    unsafe {
+        gl::BindTexture(desc.target, handle);
+        gl::TexParameteri(desc.target, gl::TEXTURE_WRAP_S, desc.s_wrap as i32);
+        gl::TexParameteri(desc.target, gl::TEXTURE_WRAP_T, desc.t_wrap as i32);
+        gl::TexParameteri(desc.target, gl::TEXTURE_WRAP_R, desc.r_wrap as i32);
+        gl::TexParameteri(desc.target, gl::TEXTURE_MAG_FILTER, desc.mag_filter as i32);
+        gl::TexParameteri(desc.target, gl::TEXTURE_MIN_FILTER, desc.min_filter as i32);
+
+        if desc.max_anisotropy > 1. {
+            gl::TexParameterf(
+                desc.target,
+                0x84FE as gl::types::GLenum,
+                desc.max_anisotropy,
+            );
+        }
 
-        Rc::new(DeviceTexture {
-            handle,
-            target: desc.target,
-        })
+        // Rows of 8-bit RGB data are not padded to 4 bytes
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+    }
+
+    handle
+}
+
+fn get_host_texture_data_pointer(data: &HostTextureData) -> *const c_void {
+    match data {
+        HostTextureData::UINT8(data) if !data.is_empty() => data.as_ptr() as *const c_void,
+        HostTextureData::FLOAT32(data) if !data.is_empty() => data.as_ptr() as *const c_void,
+        _ => null(),
     }
 }
 
+fn calculate_host_texture_level_count(host_texture: &HostTexture) -> usize {
+    match &host_texture.data {
+        HostTextureData::COMPRESSED(_, levels) => levels.len().max(1),
+        _ => host_texture.mips.len() + 1,
+    }
+}
+
+fn flip_rows<T>(data: &mut [T], stride: usize) {
+    if stride == 0 {
+        return;
+    }
+    let height = data.len() / stride;
+    for y in 0..height / 2 {
+        let (top, bottom) = data.split_at_mut((height - 1 - y) * stride);
+        top[y * stride..(y + 1) * stride].swap_with_slice(&mut bottom[..stride]);
+    }
+}
+
+pub fn calculate_mip_count(width: usize, height: usize) -> usize {
+    let size = width.max(height).max(1);
+    (std::mem::size_of::<usize>() * 8) - size.leading_zeros() as usize
+}
+
 impl Descriptor {
     pub fn new(desc_type: DescriptorType) -> Descriptor {
         match desc_type {
-            DescriptorType::Color(host_texture) => Descriptor {
+            DescriptorType::Color(host_texture, sampler_format) => Descriptor {
                 target: gl::TEXTURE_2D,
                 s_wrap: gl::REPEAT,
                 t_wrap: gl::REPEAT,
@@ -158,9 +602,15 @@ impl Descriptor {
                 mag_filter: gl::LINEAR,
                 min_filter: gl::LINEAR,
                 max_anisotropy: 16_f32,
-                internal_format: convert_image_depth_to_gl_internal_format(host_texture.depth),
+                internal_format: convert_host_texture_to_gl_internal_format(
+                    host_texture,
+                    sampler_format,
+                ),
                 format: convert_image_depth_to_gl_format(host_texture.depth),
-                data_type: gl::UNSIGNED_BYTE,
+                data_type: match host_texture.data {
+                    HostTextureData::FLOAT32(_) => gl::FLOAT,
+                    _ => gl::UNSIGNED_BYTE,
+                },
                 use_mipmaps: true,
             },
             DescriptorType::Depth => Descriptor {
@@ -229,6 +679,37 @@ pub fn convert_image_depth_to_gl_internal_format(image_depth: usize) -> gl::type
     }
 }
 
+// sRGB only applies to 8-bit color data, float data is already linear
+pub fn convert_host_texture_to_gl_internal_format(
+    host_texture: &HostTexture,
+    sampler_format: SamplerFormat,
+) -> gl::types::GLenum {
+    let srgb = sampler_format.color_space == ColorSpace::Srgb || host_texture.srgb;
+    let channel_count = sampler_format.channel_count.max(1).min(host_texture.depth);
+
+    match &host_texture.data {
+        HostTextureData::UINT8(_) => match channel_count {
+            1 => gl::R8,
+            2 => gl::RG8,
+            3 if srgb => gl::SRGB8,
+            3 => gl::RGB8,
+            4 if srgb => gl::SRGB8_ALPHA8,
+            4 => gl::RGBA8,
+            _ => panic!("Unsupported image depth"),
+        },
+        HostTextureData::FLOAT32(_) => convert_image_depth_to_gl_internal_format(channel_count),
+        HostTextureData::COMPRESSED(format, _) => {
+            let format = match format {
+                CompressedFormat::BC1 if srgb => CompressedFormat::BC1Srgb,
+                CompressedFormat::BC3 if srgb => CompressedFormat::BC3Srgb,
+                CompressedFormat::BC7 if srgb => CompressedFormat::BC7Srgb,
+                format => *format,
+            };
+            convert_compressed_format_to_gl_internal_format(format)
+        }
+    }
+}
+
 pub fn convert_image_depth_to_gl_format(image_depth: usize) -> gl::types::GLenum {
     match image_depth {
         1 => gl::RED,
@@ -239,6 +720,22 @@ pub fn convert_image_depth_to_gl_format(image_depth: usize) -> gl::types::GLenum
     }
 }
 
+pub fn convert_compressed_format_to_gl_internal_format(
+    format: CompressedFormat,
+) -> gl::types::GLenum {
+    match format {
+        CompressedFormat::BC1 => COMPRESSED_RGBA_S3TC_DXT1_EXT,
+        CompressedFormat::BC1Srgb => COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT,
+        CompressedFormat::BC3 => COMPRESSED_RGBA_S3TC_DXT5_EXT,
+        CompressedFormat::BC3Srgb => COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT,
+        CompressedFormat::BC4 => gl::COMPRESSED_RED_RGTC1,
+        CompressedFormat::BC5 => gl::COMPRESSED_RG_RGTC2,
+        CompressedFormat::BC6H => gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+        CompressedFormat::BC7 => gl::COMPRESSED_RGBA_BPTC_UNORM,
+        CompressedFormat::BC7Srgb => gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+    }
+}
+
 pub fn convert_gl_format_to_image_depth(gl_format: gl::types::GLenum) -> usize {
     match gl_format {
         gl::RED | gl::DEPTH_COMPONENT => 1,
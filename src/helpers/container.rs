@@ -0,0 +1,527 @@
+use crate::gl::tex;
+use crate::helpers::log;
+use std::convert::TryInto;
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const KTX2_HEADER_SIZE: usize = 80;
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const DDS_HEADER_SIZE: usize = 128;
+const DDS_DX10_HEADER_SIZE: usize = 20;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+
+// Pixel layouts shared by both containers, only the first mip of uncompressed data is kept
+#[derive(Copy, Clone)]
+enum PixelFormat {
+    Compressed(tex::CompressedFormat),
+    UInt8(usize, tex::ColorSpace),
+    Float32(usize),
+}
+
+pub fn is_texture_container(data: &[u8]) -> bool {
+    data.starts_with(&KTX2_IDENTIFIER) || data.starts_with(DDS_MAGIC)
+}
+
+pub fn load_host_texture_from_container(
+    data: &[u8],
+    source_name: &str,
+    name: &str,
+) -> Result<tex::HostTexture, String> {
+    let texture = if data.starts_with(&KTX2_IDENTIFIER) {
+        load_host_texture_from_ktx2(data, source_name, name)?
+    } else if data.starts_with(DDS_MAGIC) {
+        load_host_texture_from_dds(data, source_name, name)?
+    } else {
+        return Err(format!(
+            "Failed to load texture: {}\nNot a KTX2 or DDS file",
+            source_name
+        ));
+    };
+
+    log::log_info(format!(
+        "Loaded {} texture: {}",
+        match &texture.data {
+            tex::HostTextureData::UINT8(_) => "8-bit".to_string(),
+            tex::HostTextureData::FLOAT32(_) => "32-bit".to_string(),
+            tex::HostTextureData::COMPRESSED(format, levels) => {
+                format!("{:?} ({} mips)", format, levels.len())
+            }
+        },
+        source_name
+    ));
+
+    Ok(texture)
+}
+
+pub fn load_host_texture_from_ktx2(
+    data: &[u8],
+    source_name: &str,
+    name: &str,
+) -> Result<tex::HostTexture, String> {
+    let error = |msg: &str| format!("Failed to load texture: {}\nKTX2: {}", source_name, msg);
+
+    if !data.starts_with(&KTX2_IDENTIFIER) || data.len() < KTX2_HEADER_SIZE {
+        return Err(error("invalid header"));
+    }
+
+    let vk_format = read_u32(data, 12);
+    let width = read_u32(data, 20) as usize;
+    let height = read_u32(data, 24) as usize;
+    let pixel_depth = read_u32(data, 28);
+    let layer_count = read_u32(data, 32);
+    let face_count = read_u32(data, 36);
+    let level_count = (read_u32(data, 40) as usize).max(1);
+    let supercompression_scheme = read_u32(data, 44);
+
+    if supercompression_scheme != 0 {
+        return Err(error("supercompressed textures are not supported"));
+    }
+    if pixel_depth > 1 || layer_count > 1 || face_count != 1 {
+        return Err(error("only 2D textures are supported"));
+    }
+    let format = convert_vk_format(vk_format)
+        .ok_or_else(|| error(&format!("unsupported VkFormat {}", vk_format)))?;
+    check_level_count(level_count, width, height).map_err(|msg| error(&msg))?;
+
+    let mut levels: Vec<&[u8]> = Vec::with_capacity(level_count);
+    for level in 0..level_count {
+        let index = KTX2_HEADER_SIZE + level * 24;
+        if data.len() < index + 24 {
+            return Err(error("truncated level index"));
+        }
+        let offset = read_u64(data, index) as usize;
+        let length = read_u64(data, index + 8) as usize;
+        levels.push(
+            data.get(offset..offset.saturating_add(length))
+                .ok_or_else(|| error("level data is out of bounds"))?,
+        );
+    }
+
+    create_host_texture(format, width, height, &levels, name).map_err(|msg| error(&msg))
+}
+
+pub fn load_host_texture_from_dds(
+    data: &[u8],
+    source_name: &str,
+    name: &str,
+) -> Result<tex::HostTexture, String> {
+    let error = |msg: &str| format!("Failed to load texture: {}\nDDS: {}", source_name, msg);
+
+    if !data.starts_with(DDS_MAGIC) || data.len() < DDS_HEADER_SIZE || read_u32(data, 4) != 124 {
+        return Err(error("invalid header"));
+    }
+
+    let flags = read_u32(data, 8);
+    let height = read_u32(data, 12) as usize;
+    let width = read_u32(data, 16) as usize;
+    let level_count = if flags & DDSD_MIPMAPCOUNT != 0 {
+        (read_u32(data, 28) as usize).max(1)
+    } else {
+        1
+    };
+    let pixel_flags = read_u32(data, 80);
+    let four_cc = &data[84..88];
+    let caps2 = read_u32(data, 112);
+
+    if caps2 & (DDSCAPS2_CUBEMAP | DDSCAPS2_VOLUME) != 0 {
+        return Err(error("only 2D textures are supported"));
+    }
+
+    let (format, data_offset) = if pixel_flags & DDPF_FOURCC != 0 && four_cc == b"DX10" {
+        if data.len() < DDS_HEADER_SIZE + DDS_DX10_HEADER_SIZE {
+            return Err(error("truncated DX10 header"));
+        }
+        let dxgi_format = read_u32(data, 128);
+        if read_u32(data, 140) > 1 {
+            return Err(error("texture arrays are not supported"));
+        }
+        let format = convert_dxgi_format(dxgi_format)
+            .ok_or_else(|| error(&format!("unsupported DXGI format {}", dxgi_format)))?;
+        (format, DDS_HEADER_SIZE + DDS_DX10_HEADER_SIZE)
+    } else if pixel_flags & DDPF_FOURCC != 0 {
+        let format = match four_cc {
+            b"DXT1" => tex::CompressedFormat::BC1,
+            b"DXT5" => tex::CompressedFormat::BC3,
+            b"ATI1" | b"BC4U" => tex::CompressedFormat::BC4,
+            b"ATI2" | b"BC5U" => tex::CompressedFormat::BC5,
+            _ => {
+                return Err(error(&format!(
+                    "unsupported FourCC '{}'",
+                    String::from_utf8_lossy(four_cc)
+                )))
+            }
+        };
+        (PixelFormat::Compressed(format), DDS_HEADER_SIZE)
+    } else if pixel_flags & (DDPF_RGB | DDPF_LUMINANCE) != 0 {
+        // Legacy uncompressed layouts are described by channel masks
+        let bit_count = read_u32(data, 88) as usize;
+        let mut masks = vec![read_u32(data, 92)];
+        if pixel_flags & DDPF_RGB != 0 {
+            masks.push(read_u32(data, 96));
+            masks.push(read_u32(data, 100));
+        }
+        if pixel_flags & DDPF_ALPHAPIXELS != 0 {
+            masks.push(read_u32(data, 104));
+        }
+        let pixels = width
+            .checked_mul(height)
+            .and_then(|x| x.checked_mul(bit_count / 8))
+            .and_then(|x| x.checked_add(DDS_HEADER_SIZE))
+            .and_then(|end| data.get(DDS_HEADER_SIZE..end))
+            .ok_or_else(|| error("pixel data is out of bounds"))?;
+        return create_host_texture_from_masks(pixels, width, height, bit_count, &masks, name)
+            .map_err(|msg| error(&msg));
+    } else {
+        return Err(error("unsupported pixel format"));
+    };
+    check_level_count(level_count, width, height).map_err(|msg| error(&msg))?;
+
+    let mut levels: Vec<&[u8]> = Vec::with_capacity(level_count);
+    let mut offset = data_offset;
+    for level in 0..level_count {
+        let length =
+            calculate_level_size(format, (width >> level).max(1), (height >> level).max(1))
+                .map_err(|msg| error(&msg))?;
+        let end = offset
+            .checked_add(length)
+            .ok_or_else(|| error("level data is out of bounds"))?;
+        levels.push(
+            data.get(offset..end)
+                .ok_or_else(|| error("level data is out of bounds"))?,
+        );
+        offset = end;
+    }
+
+    create_host_texture(format, width, height, &levels, name).map_err(|msg| error(&msg))
+}
+
+fn create_host_texture(
+    format: PixelFormat,
+    width: usize,
+    height: usize,
+    levels: &[&[u8]],
+    name: &str,
+) -> Result<tex::HostTexture, String> {
+    if width == 0 || height == 0 {
+        return Err("empty texture".to_string());
+    }
+
+    let mut level_sizes: Vec<usize> = Vec::with_capacity(levels.len());
+    for (level, data) in levels.iter().enumerate() {
+        let expected =
+            calculate_level_size(format, (width >> level).max(1), (height >> level).max(1))?;
+        if data.len() < expected {
+            return Err(format!(
+                "level {} has {} bytes, expected {}",
+                level,
+                data.len(),
+                expected
+            ));
+        }
+        level_sizes.push(expected);
+    }
+
+    let (depth, data) = match format {
+        PixelFormat::Compressed(format) => (
+            format.channel_count(),
+            tex::HostTextureData::COMPRESSED(
+                format,
+                levels
+                    .iter()
+                    .zip(&level_sizes)
+                    .map(|(data, &size)| data[..size].to_vec())
+                    .collect(),
+            ),
+        ),
+        PixelFormat::UInt8(depth, _) => (
+            depth,
+            tex::HostTextureData::UINT8(levels[0][..level_sizes[0]].to_vec()),
+        ),
+        PixelFormat::Float32(depth) => (
+            depth,
+            tex::HostTextureData::FLOAT32(
+                levels[0][..level_sizes[0]]
+                    .chunks_exact(4)
+                    .map(|x| f32::from_le_bytes(x.try_into().unwrap()))
+                    .collect(),
+            ),
+        ),
+    };
+
+    Ok(tex::HostTexture {
+        name: name.to_string(),
+        width,
+        height,
+        depth,
+        data,
+        mips: Vec::new(),
+        srgb: matches!(format, PixelFormat::UInt8(_, tex::ColorSpace::Srgb)),
+    })
+}
+
+fn create_host_texture_from_masks(
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+    bit_count: usize,
+    masks: &[u32],
+    name: &str,
+) -> Result<tex::HostTexture, String> {
+    if !bit_count.is_multiple_of(8) || bit_count == 0 || bit_count > 32 {
+        return Err(format!("unsupported bit count {}", bit_count));
+    }
+
+    let pixel_size = bit_count / 8;
+    let mut data: Vec<u8> = Vec::with_capacity(pixels.len() / pixel_size * masks.len());
+    for pixel in pixels.chunks_exact(pixel_size) {
+        let mut value = [0u8; 4];
+        value[..pixel_size].copy_from_slice(pixel);
+        let value = u32::from_le_bytes(value);
+
+        for &mask in masks {
+            if mask == 0 {
+                data.push(0);
+                continue;
+            }
+            let max = mask >> mask.trailing_zeros();
+            let channel = (value & mask) >> mask.trailing_zeros();
+            data.push((channel as u64 * 255 / max as u64) as u8);
+        }
+    }
+
+    Ok(tex::HostTexture {
+        name: name.to_string(),
+        width,
+        height,
+        depth: masks.len(),
+        data: tex::HostTextureData::UINT8(data),
+        mips: Vec::new(),
+        srgb: false,
+    })
+}
+
+// Headers can claim any count, a level past the full chain would have a zero size
+fn check_level_count(level_count: usize, width: usize, height: usize) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err("empty texture".to_string());
+    }
+    let max_level_count = tex::calculate_mip_count(width, height);
+    if level_count > max_level_count {
+        return Err(format!(
+            "{} levels for a {}x{} texture, at most {} are possible",
+            level_count, width, height, max_level_count
+        ));
+    }
+
+    Ok(())
+}
+
+// Header dimensions are untrusted, a size that does not fit in usize is an error
+fn calculate_level_size(format: PixelFormat, width: usize, height: usize) -> Result<usize, String> {
+    let (element_count, element_size) = match format {
+        PixelFormat::Compressed(format) => (
+            width
+                .div_ceil(4)
+                .max(1)
+                .checked_mul(height.div_ceil(4).max(1)),
+            format.block_size(),
+        ),
+        PixelFormat::UInt8(depth, _) => (width.checked_mul(height), depth),
+        PixelFormat::Float32(depth) => (width.checked_mul(height), depth * 4),
+    };
+
+    element_count
+        .and_then(|x| x.checked_mul(element_size))
+        .ok_or_else(|| format!("size of a {}x{} level overflows", width, height))
+}
+
+fn convert_vk_format(vk_format: u32) -> Option<PixelFormat> {
+    Some(match vk_format {
+        9 => PixelFormat::UInt8(1, tex::ColorSpace::Linear),
+        16 => PixelFormat::UInt8(2, tex::ColorSpace::Linear),
+        23 => PixelFormat::UInt8(3, tex::ColorSpace::Linear),
+        37 => PixelFormat::UInt8(4, tex::ColorSpace::Linear),
+        43 => PixelFormat::UInt8(4, tex::ColorSpace::Srgb),
+        100 => PixelFormat::Float32(1),
+        103 => PixelFormat::Float32(2),
+        106 => PixelFormat::Float32(3),
+        109 => PixelFormat::Float32(4),
+        131 | 133 => PixelFormat::Compressed(tex::CompressedFormat::BC1),
+        132 | 134 => PixelFormat::Compressed(tex::CompressedFormat::BC1Srgb),
+        137 => PixelFormat::Compressed(tex::CompressedFormat::BC3),
+        138 => PixelFormat::Compressed(tex::CompressedFormat::BC3Srgb),
+        139 => PixelFormat::Compressed(tex::CompressedFormat::BC4),
+        141 => PixelFormat::Compressed(tex::CompressedFormat::BC5),
+        143 => PixelFormat::Compressed(tex::CompressedFormat::BC6H),
+        145 => PixelFormat::Compressed(tex::CompressedFormat::BC7),
+        146 => PixelFormat::Compressed(tex::CompressedFormat::BC7Srgb),
+        _ => return None,
+    })
+}
+
+fn convert_dxgi_format(dxgi_format: u32) -> Option<PixelFormat> {
+    Some(match dxgi_format {
+        2 => PixelFormat::Float32(4),
+        6 => PixelFormat::Float32(3),
+        16 => PixelFormat::Float32(2),
+        41 => PixelFormat::Float32(1),
+        28 => PixelFormat::UInt8(4, tex::ColorSpace::Linear),
+        29 => PixelFormat::UInt8(4, tex::ColorSpace::Srgb),
+        49 => PixelFormat::UInt8(2, tex::ColorSpace::Linear),
+        61 => PixelFormat::UInt8(1, tex::ColorSpace::Linear),
+        71 => PixelFormat::Compressed(tex::CompressedFormat::BC1),
+        72 => PixelFormat::Compressed(tex::CompressedFormat::BC1Srgb),
+        77 => PixelFormat::Compressed(tex::CompressedFormat::BC3),
+        78 => PixelFormat::Compressed(tex::CompressedFormat::BC3Srgb),
+        80 => PixelFormat::Compressed(tex::CompressedFormat::BC4),
+        83 => PixelFormat::Compressed(tex::CompressedFormat::BC5),
+        95 => PixelFormat::Compressed(tex::CompressedFormat::BC6H),
+        98 => PixelFormat::Compressed(tex::CompressedFormat::BC7),
+        99 => PixelFormat::Compressed(tex::CompressedFormat::BC7Srgb),
+        _ => return None,
+    })
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_ktx2(vk_format: u32, width: u32, height: u32, levels: &[&[u8]]) -> Vec<u8> {
+        let mut data = KTX2_IDENTIFIER.to_vec();
+        for value in &[vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.resize(KTX2_HEADER_SIZE, 0);
+
+        let mut offset = KTX2_HEADER_SIZE + levels.len() * 24;
+        for level in levels {
+            for value in &[offset as u64, level.len() as u64, level.len() as u64] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            offset += level.len();
+        }
+        for level in levels {
+            data.extend_from_slice(level);
+        }
+        data
+    }
+
+    fn create_dds(
+        width: u32,
+        height: u32,
+        level_count: u32,
+        pixel_format: (u32, &[u8; 4], u32, [u32; 4]),
+        pixels: &[u8],
+    ) -> Vec<u8> {
+        let (pixel_flags, four_cc, bit_count, masks) = pixel_format;
+        let mut data = vec![0u8; DDS_HEADER_SIZE];
+        let mut write_u32 = |offset: usize, value: u32| {
+            data[offset..offset + 4].copy_from_slice(&value.to_le_bytes())
+        };
+        write_u32(4, 124);
+        write_u32(8, DDSD_MIPMAPCOUNT);
+        write_u32(12, height);
+        write_u32(16, width);
+        write_u32(28, level_count);
+        write_u32(76, 32);
+        write_u32(80, pixel_flags);
+        write_u32(88, bit_count);
+        for (index, mask) in masks.iter().enumerate() {
+            write_u32(92 + index * 4, *mask);
+        }
+        data[..4].copy_from_slice(DDS_MAGIC);
+        data[84..88].copy_from_slice(four_cc);
+        data.extend_from_slice(pixels);
+        data
+    }
+
+    const DXT1: (u32, &[u8; 4], u32, [u32; 4]) = (DDPF_FOURCC, b"DXT1", 0, [0; 4]);
+    const BGRA8: (u32, &[u8; 4], u32, [u32; 4]) = (
+        DDPF_RGB | DDPF_ALPHAPIXELS,
+        &[0; 4],
+        32,
+        [0xFF0000, 0xFF00, 0xFF, 0xFF000000],
+    );
+
+    #[test]
+    fn ktx2_levels_are_loaded() {
+        let texels: Vec<u8> = (0..16).collect();
+        let data = create_ktx2(37, 2, 2, &[&texels]);
+        let texture = load_host_texture_from_ktx2(&data, "test.ktx2", "test").unwrap();
+        assert_eq!((texture.width, texture.height, texture.depth), (2, 2, 4));
+        assert!(matches!(texture.data, tex::HostTextureData::UINT8(ref x) if *x == texels));
+
+        let data = create_ktx2(131, 8, 8, &[&[0; 32], &[0; 8], &[0; 8], &[0; 8]]);
+        let texture = load_host_texture_from_ktx2(&data, "test.ktx2", "test").unwrap();
+        assert!(matches!(
+            texture.data,
+            tex::HostTextureData::COMPRESSED(tex::CompressedFormat::BC1, ref x) if x.len() == 4
+        ));
+    }
+
+    #[test]
+    fn malformed_ktx2_is_rejected() {
+        let data = create_ktx2(37, 2, 2, &[&[0; 16]]);
+        assert!(load_host_texture_from_ktx2(&data[..40], "test.ktx2", "test").is_err());
+        assert!(load_host_texture_from_ktx2(&data[..90], "test.ktx2", "test").is_err());
+        assert!(load_host_texture_from_ktx2(&data[..110], "test.ktx2", "test").is_err());
+
+        // More levels than a 2x2 texture can have
+        let data = create_ktx2(37, 2, 2, &[&[0; 16], &[0; 4], &[0; 4]]);
+        assert!(load_host_texture_from_ktx2(&data, "test.ktx2", "test").is_err());
+
+        // Level size of the claimed dimensions overflows
+        let data = create_ktx2(109, u32::MAX, u32::MAX, &[&[0; 16]]);
+        assert!(load_host_texture_from_ktx2(&data, "test.ktx2", "test").is_err());
+    }
+
+    #[test]
+    fn dds_levels_are_loaded() {
+        let data = create_dds(8, 8, 4, DXT1, &[0; 56]);
+        let texture = load_host_texture_from_dds(&data, "test.dds", "test").unwrap();
+        assert!(matches!(
+            texture.data,
+            tex::HostTextureData::COMPRESSED(tex::CompressedFormat::BC1, ref x) if x.len() == 4
+        ));
+
+        let data = create_dds(1, 1, 1, BGRA8, &[10, 20, 30, 40]);
+        let texture = load_host_texture_from_dds(&data, "test.dds", "test").unwrap();
+        assert!(matches!(
+            texture.data,
+            tex::HostTextureData::UINT8(ref x) if *x == [30, 20, 10, 40]
+        ));
+    }
+
+    #[test]
+    fn malformed_dds_is_rejected() {
+        let data = create_dds(8, 8, 4, DXT1, &[0; 56]);
+        assert!(load_host_texture_from_dds(&data[..64], "test.dds", "test").is_err());
+        assert!(load_host_texture_from_dds(&data[..150], "test.dds", "test").is_err());
+
+        // More mips than an 8x8 texture can have
+        let data = create_dds(8, 8, 5, DXT1, &[0; 64]);
+        assert!(load_host_texture_from_dds(&data, "test.dds", "test").is_err());
+
+        // Sizes of the claimed dimensions overflow
+        let data = create_dds(u32::MAX, u32::MAX, 1, DXT1, &[0; 8]);
+        assert!(load_host_texture_from_dds(&data, "test.dds", "test").is_err());
+        let data = create_dds(u32::MAX, u32::MAX, 1, BGRA8, &[0; 4]);
+        assert!(load_host_texture_from_dds(&data, "test.dds", "test").is_err());
+    }
+}
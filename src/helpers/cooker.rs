@@ -0,0 +1,1009 @@
+extern crate memmap2;
+use crate::asset::error::LoaderError;
+use crate::asset::{cache, material, mesh, model};
+use crate::gl::tex;
+use crate::helpers::environment;
+use crate::helpers::helper;
+use crate::helpers::loader;
+use crate::helpers::log;
+use crate::math;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use threadpool::ThreadPool;
+
+pub const COOKED_MODEL_EXTENSION: &str = "cooked";
+const COOKED_MODEL_MAGIC: &[u8; 4] = b"SRCM";
+const COOKED_MODEL_VERSION: u32 = 6;
+
+pub const COOKED_ENVIRONMENT_EXTENSION: &str = "ibl";
+const COOKED_ENVIRONMENT_MAGIC: &[u8; 4] = b"SRCE";
+const COOKED_ENVIRONMENT_VERSION: u32 = 2;
+
+// Stable on-disk indices of block compressed formats
+const COMPRESSED_FORMATS: [tex::CompressedFormat; 9] = [
+    tex::CompressedFormat::BC1,
+    tex::CompressedFormat::BC1Srgb,
+    tex::CompressedFormat::BC3,
+    tex::CompressedFormat::BC3Srgb,
+    tex::CompressedFormat::BC4,
+    tex::CompressedFormat::BC5,
+    tex::CompressedFormat::BC6H,
+    tex::CompressedFormat::BC7,
+    tex::CompressedFormat::BC7Srgb,
+];
+
+pub fn get_cooked_model_path(source_path: &Path) -> PathBuf {
+    source_path.with_extension(COOKED_MODEL_EXTENSION)
+}
+
+pub fn cook_folder(
+    folder_path: &Path,
+    options: &loader::LoaderOptions,
+) -> Result<Vec<PathBuf>, LoaderError> {
+    let mut source_paths: Vec<PathBuf> = fs::read_dir(folder_path)
+        .map_err(|error| {
+            LoaderError::Io(format!(
+                "Failed to read folder '{}' '{}'",
+                folder_path.to_str().unwrap_or_default(),
+                error
+            ))
+        })?
+        .filter_map(|entry| entry.ok().map(|x| x.path()))
+        .filter(|path| path.extension().unwrap_or_default() == "obj")
+        .collect();
+    source_paths.sort();
+
+    let mut cooked_paths: Vec<PathBuf> = Vec::new();
+    for source_path in &source_paths {
+        let host_model = loader::load_host_model_from_obj(source_path, options)?;
+        for warning in &host_model.warnings {
+            log::log_warning(warning.to_string());
+        }
+
+        let cooked_path = get_cooked_model_path(source_path);
+        write_cooked_model(
+            &cooked_path,
+            &host_model,
+            calculate_source_hash(source_path, options)?,
+        )?;
+        log::log_info(format!(
+            "Cooked '{}'",
+            cooked_path.to_str().unwrap_or_default()
+        ));
+        cooked_paths.push(cooked_path);
+    }
+
+    Ok(cooked_paths)
+}
+
+pub fn load_host_model_from_obj_cached(
+    source_path: &Path,
+    options: &loader::LoaderOptions,
+) -> Result<model::HostModel, LoaderError> {
+    let source_hash = calculate_source_hash(source_path, options)?;
+    let cooked_path = get_cooked_model_path(source_path);
+
+    if cooked_path.exists() {
+        match load_host_model_from_cooked(&cooked_path, Some(source_hash)) {
+            Ok(host_model) => return Ok(host_model),
+            Err(error) => log::log_info(format!(
+                "Recooking '{}': {}",
+                source_path.to_str().unwrap_or_default(),
+                error
+            )),
+        }
+    }
+
+    let host_model = loader::load_host_model_from_obj(source_path, options)?;
+    if let Err(error) = write_cooked_model(&cooked_path, &host_model, source_hash) {
+        log::log_warning(error.to_string());
+    }
+
+    Ok(host_model)
+}
+
+// The hash covers the OBJ, every MTL next to it and the cooking parameters
+pub fn calculate_source_hash(
+    source_path: &Path,
+    options: &loader::LoaderOptions,
+) -> Result<u64, LoaderError> {
+    let read = |path: &Path| {
+        fs::read(path).map_err(|error| {
+            LoaderError::Io(format!(
+                "Failed to read '{}' '{}'",
+                path.to_str().unwrap_or_default(),
+                error
+            ))
+        })
+    };
+
+    let mut hash = fnv1a_hash(FNV_OFFSET_BASIS, &COOKED_MODEL_VERSION.to_le_bytes());
+    hash = fnv1a_hash(hash, &[options.optimize_meshes as u8]);
+    hash = fnv1a_hash(hash, &[options.generate_lods as u8]);
+    hash = fnv1a_hash(
+        hash,
+        &[options.mip_filter.map_or(0, |filter| filter as u8 + 1)],
+    );
+    hash = match options.normals_generation {
+        helper::NormalsGeneration::Smooth(crease_angle) => {
+            fnv1a_hash(hash, &[&[0u8][..], &crease_angle.to_le_bytes()].concat())
+        }
+        helper::NormalsGeneration::Flat => fnv1a_hash(hash, &[1u8]),
+    };
+    hash = fnv1a_hash(hash, &read(source_path)?);
+
+    if let Some(folder_path) = source_path.parent() {
+        let mut material_paths: Vec<PathBuf> = fs::read_dir(folder_path)
+            .map_err(|error| LoaderError::Io(error.to_string()))?
+            .filter_map(|entry| entry.ok().map(|x| x.path()))
+            .filter(|path| path.extension().unwrap_or_default() == "mtl")
+            .collect();
+        material_paths.sort();
+
+        for material_path in &material_paths {
+            hash = fnv1a_hash(hash, &read(material_path)?);
+        }
+    }
+
+    Ok(hash)
+}
+
+// Bakes on the CPU, the result is the same as the one the renderer cooks on its first run
+pub fn cook_environment(
+    hdri_path: &Path,
+    settings: &environment::EnvironmentSettings,
+) -> Result<PathBuf, LoaderError> {
+    let hdri = loader::load_host_texture_from_file(hdri_path, "uHdriSampler2D")
+        .map_err(LoaderError::MissingTexture)?;
+    let host_environment = environment::bake_host_environment(&hdri, settings);
+
+    let cooked_path = get_cooked_environment_path(hdri_path);
+    write_cooked_environment(
+        &cooked_path,
+        &host_environment,
+        calculate_environment_hash(&hdri, settings),
+    )?;
+    log::log_info(format!(
+        "Cooked '{}'",
+        cooked_path.to_str().unwrap_or_default()
+    ));
+
+    Ok(cooked_path)
+}
+
+// Same cache as ibl::create_environment, a missing or stale one is baked on the CPU
+pub fn load_host_environment_cached(
+    hdri_path: &Path,
+    settings: &environment::EnvironmentSettings,
+) -> Result<environment::HostEnvironment, LoaderError> {
+    let hdri = loader::load_host_texture_from_file(hdri_path, "uHdriSampler2D")
+        .map_err(LoaderError::MissingTexture)?;
+    let source_hash = calculate_environment_hash(&hdri, settings);
+    let cooked_path = get_cooked_environment_path(hdri_path);
+
+    if cooked_path.exists() {
+        match load_host_environment_from_cooked(&cooked_path, Some(source_hash)) {
+            Ok(host_environment) => return Ok(host_environment),
+            Err(error) => log::log_info(format!(
+                "Rebaking '{}': {}",
+                hdri_path.to_str().unwrap_or_default(),
+                error
+            )),
+        }
+    }
+
+    let host_environment = environment::bake_host_environment(&hdri, settings);
+    if let Err(error) = write_cooked_environment(&cooked_path, &host_environment, source_hash) {
+        log::log_warning(error.to_string());
+    }
+
+    Ok(host_environment)
+}
+
+pub fn get_cooked_environment_path(hdri_path: &Path) -> PathBuf {
+    hdri_path.with_extension(COOKED_ENVIRONMENT_EXTENSION)
+}
+
+// The hash covers the decoded HDRI and every bake size
+pub fn calculate_environment_hash(
+    hdri: &tex::HostTexture,
+    settings: &environment::EnvironmentSettings,
+) -> u64 {
+    let mut hash = fnv1a_hash(FNV_OFFSET_BASIS, &COOKED_ENVIRONMENT_VERSION.to_le_bytes());
+    for value in &[
+        settings.cube_map_size,
+        settings.irradiance_size,
+        settings.prefiltered_size,
+        settings.brdf_lut_size,
+        hdri.width,
+        hdri.height,
+        hdri.depth,
+    ] {
+        hash = fnv1a_hash(hash, &(*value as u32).to_le_bytes());
+    }
+
+    match &hdri.data {
+        tex::HostTextureData::UINT8(data) => fnv1a_hash(hash, data),
+        tex::HostTextureData::FLOAT32(data) => data
+            .iter()
+            .fold(hash, |hash, x| fnv1a_hash(hash, &x.to_le_bytes())),
+        tex::HostTextureData::COMPRESSED(_, levels) => levels
+            .iter()
+            .fold(hash, |hash, level| fnv1a_hash(hash, level)),
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv1a_hash(mut hash: u64, data: &[u8]) -> u64 {
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+///////////////////////////////////////////////////////////
+// Writing
+///////////////////////////////////////////////////////////
+
+pub fn write_cooked_model(
+    path: &Path,
+    host_model: &model::HostModel,
+    source_hash: u64,
+) -> Result<(), LoaderError> {
+    let mut writer = CookedWriter { data: Vec::new() };
+
+    writer.data.extend_from_slice(COOKED_MODEL_MAGIC);
+    writer.write_u32(COOKED_MODEL_VERSION);
+    writer.write_u64(source_hash);
+
+    writer.write_u32(host_model.materials.len() as u32);
+    for material in host_model.materials.iter() {
+        writer.write_material(material);
+    }
+
+    writer.write_u32(host_model.meshes.len() as u32);
+    for mesh in host_model.meshes.iter() {
+        writer.write_mesh(mesh);
+    }
+
+    fs::write(path, &writer.data).map_err(|error| {
+        LoaderError::Io(format!(
+            "Failed to write cooked model '{}' '{}'",
+            path.to_str().unwrap_or_default(),
+            error
+        ))
+    })
+}
+
+pub fn write_cooked_environment(
+    path: &Path,
+    host_environment: &environment::HostEnvironment,
+    source_hash: u64,
+) -> Result<(), LoaderError> {
+    let mut writer = CookedWriter { data: Vec::new() };
+
+    writer.data.extend_from_slice(COOKED_ENVIRONMENT_MAGIC);
+    writer.write_u32(COOKED_ENVIRONMENT_VERSION);
+    writer.write_u64(source_hash);
+
+    writer.write_cube_map(&host_environment.cube_map);
+    writer.write_cube_map(&host_environment.irradiance);
+    writer.write_cube_map(&host_environment.prefiltered);
+    writer.write_texture_levels(&host_environment.brdf_lut);
+    writer.write_vec3s(&host_environment.irradiance_sh);
+
+    fs::write(path, &writer.data).map_err(|error| {
+        LoaderError::Io(format!(
+            "Failed to write cooked environment '{}' '{}'",
+            path.to_str().unwrap_or_default(),
+            error
+        ))
+    })
+}
+
+struct CookedWriter {
+    data: Vec<u8>,
+}
+
+impl CookedWriter {
+    fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_f32(&mut self, value: f32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_string(&mut self, value: &str) {
+        self.write_u32(value.len() as u32);
+        self.data.extend_from_slice(value.as_bytes());
+    }
+
+    fn write_f32s(&mut self, values: &[f32]) {
+        self.write_u32(values.len() as u32);
+        for value in values {
+            self.write_f32(*value);
+        }
+    }
+
+    fn write_vec2s(&mut self, values: &[math::Vec2f]) {
+        self.write_u32(values.len() as u32);
+        for value in values {
+            self.write_f32(value.x);
+            self.write_f32(value.y);
+        }
+    }
+
+    fn write_vec3s(&mut self, values: &[math::Vec3f]) {
+        self.write_u32(values.len() as u32);
+        for value in values {
+            self.write_f32(value.x);
+            self.write_f32(value.y);
+            self.write_f32(value.z);
+        }
+    }
+
+    fn write_indices(&mut self, values: &[math::Vec1u]) {
+        self.write_u32(values.len() as u32);
+        for value in values {
+            self.write_u32(value.x);
+        }
+    }
+
+    fn write_mesh(&mut self, mesh: &mesh::HostMesh) {
+        self.write_string(&mesh.name);
+        self.write_u32(mesh.material_index as u32);
+        self.write_vec3s(&mesh.vertices);
+        self.write_vec3s(&mesh.normals);
+        self.write_vec3s(&mesh.tangents);
+        self.write_vec3s(&mesh.bitangents);
+        self.write_vec2s(&mesh.uvs);
+        self.write_indices(&mesh.indices);
+
+        self.write_u32(mesh.lods.len() as u32);
+        for lod in &mesh.lods {
+            self.write_f32(lod.error);
+            self.write_indices(&lod.indices);
+        }
+    }
+
+    fn write_material(&mut self, material: &material::HostMaterial) {
+        self.write_string(&material.name);
+
+        self.write_u32(material.properties_1u.len() as u32);
+        for property in &material.properties_1u {
+            self.write_string(&property.name);
+            self.write_u32(property.value.x);
+        }
+
+        self.write_u32(material.properties_1f.len() as u32);
+        for property in &material.properties_1f {
+            self.write_string(&property.name);
+            self.write_f32(property.value.x);
+        }
+
+        self.write_u32(material.properties_3f.len() as u32);
+        for property in &material.properties_3f {
+            self.write_string(&property.name);
+            self.write_vec3s(std::slice::from_ref(&property.value));
+        }
+
+        self.write_u32(material.properties_samplers.len() as u32);
+        for property in &material.properties_samplers {
+            self.write_string(&property.name);
+            self.write_u8((property.value.format.color_space == tex::ColorSpace::Srgb) as u8);
+            self.write_u8(property.value.format.channel_count as u8);
+            self.write_texture(&property.value.texture);
+        }
+    }
+
+    // Textures backed by a file are referenced by path, derived ones are embedded
+    fn write_texture(&mut self, texture: &tex::HostTexture) {
+        self.write_string(&texture.name);
+
+        if Path::new(&texture.name).is_file() {
+            self.write_u8(0);
+            return;
+        }
+
+        self.write_u8(1);
+        self.write_texture_levels(texture);
+    }
+
+    fn write_texture_levels(&mut self, texture: &tex::HostTexture) {
+        self.write_u32(texture.width as u32);
+        self.write_u32(texture.height as u32);
+        self.write_u32(texture.depth as u32);
+        self.write_texture_data(&texture.data);
+        self.write_u32(texture.mips.len() as u32);
+        for data in &texture.mips {
+            self.write_texture_data(data);
+        }
+    }
+
+    fn write_cube_map(&mut self, cube_map: &tex::HostCubeMapTexture) {
+        for face in environment::get_cube_map_faces(cube_map).iter() {
+            self.write_texture_levels(face);
+        }
+    }
+
+    fn write_texture_data(&mut self, data: &tex::HostTextureData) {
+        match data {
+            tex::HostTextureData::UINT8(data) => {
+                self.write_u8(0);
+                self.write_u32(data.len() as u32);
+                self.data.extend_from_slice(data);
+            }
+            tex::HostTextureData::FLOAT32(data) => {
+                self.write_u8(1);
+                self.write_f32s(data);
+            }
+            tex::HostTextureData::COMPRESSED(format, levels) => {
+                self.write_u8(2);
+                self.write_u8(COMPRESSED_FORMATS.iter().position(|x| x == format).unwrap() as u8);
+                self.write_u32(levels.len() as u32);
+                for level in levels {
+                    self.write_u32(level.len() as u32);
+                    self.data.extend_from_slice(level);
+                }
+            }
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////
+// Reading
+///////////////////////////////////////////////////////////
+
+enum CookedTexture {
+    Reference(String),
+    Embedded(tex::HostTexture),
+}
+
+struct CookedMaterial {
+    material: material::HostMaterial,
+    samplers: Vec<(String, tex::SamplerFormat, CookedTexture)>,
+}
+
+pub fn load_host_model_from_cooked(
+    path: &Path,
+    expected_source_hash: Option<u64>,
+) -> Result<model::HostModel, LoaderError> {
+    let file = fs::File::open(path).map_err(|error| {
+        LoaderError::Io(format!(
+            "Failed to open cooked model '{}' '{}'",
+            path.to_str().unwrap_or_default(),
+            error
+        ))
+    })?;
+    let mmap = unsafe { memmap2::Mmap::map(&file) }.map_err(|error| {
+        LoaderError::Io(format!(
+            "Failed to map cooked model '{}' '{}'",
+            path.to_str().unwrap_or_default(),
+            error
+        ))
+    })?;
+    let mut reader = CookedReader {
+        data: &mmap,
+        offset: 0,
+    };
+
+    if reader.read_bytes(COOKED_MODEL_MAGIC.len())? != COOKED_MODEL_MAGIC {
+        return Err(LoaderError::Parse(format!(
+            "'{}' is not a cooked model",
+            path.to_str().unwrap_or_default()
+        )));
+    }
+    let version = reader.read_u32()?;
+    if version != COOKED_MODEL_VERSION {
+        return Err(LoaderError::Validation(format!(
+            "Cooked model version {} does not match {}",
+            version, COOKED_MODEL_VERSION
+        )));
+    }
+    let source_hash = reader.read_u64()?;
+    if expected_source_hash.is_some_and(|x| x != source_hash) {
+        return Err(LoaderError::Validation(
+            "Cooked model is stale, source hash does not match".to_string(),
+        ));
+    }
+
+    let mut cooked_materials: Vec<CookedMaterial> = Vec::new();
+    for _ in 0..reader.read_u32()? {
+        cooked_materials.push(reader.read_material()?);
+    }
+
+    let mut meshes: Vec<mesh::HostMesh> = Vec::new();
+    for _ in 0..reader.read_u32()? {
+        let mesh = reader.read_mesh()?;
+        if mesh.material_index >= cooked_materials.len() {
+            return Err(LoaderError::Validation(format!(
+                "Cooked mesh '{}' material index is out of range",
+                mesh.name
+            )));
+        }
+        meshes.push(mesh);
+    }
+
+    Ok(model::HostModel {
+        meshes: Arc::new(meshes),
+        materials: Arc::new(resolve_cooked_materials(cooked_materials)?),
+        // Only OBJ files are cooked and they cannot contain skeletons
+        skeleton: None,
+        animations: Arc::new(Vec::new()),
+        warnings: Vec::new(),
+    })
+}
+
+pub fn load_host_environment_from_cooked(
+    path: &Path,
+    expected_source_hash: Option<u64>,
+) -> Result<environment::HostEnvironment, LoaderError> {
+    let file = fs::File::open(path).map_err(|error| {
+        LoaderError::Io(format!(
+            "Failed to open cooked environment '{}' '{}'",
+            path.to_str().unwrap_or_default(),
+            error
+        ))
+    })?;
+    let mmap = unsafe { memmap2::Mmap::map(&file) }.map_err(|error| {
+        LoaderError::Io(format!(
+            "Failed to map cooked environment '{}' '{}'",
+            path.to_str().unwrap_or_default(),
+            error
+        ))
+    })?;
+    let mut reader = CookedReader {
+        data: &mmap,
+        offset: 0,
+    };
+
+    if reader.read_bytes(COOKED_ENVIRONMENT_MAGIC.len())? != COOKED_ENVIRONMENT_MAGIC {
+        return Err(LoaderError::Parse(format!(
+            "'{}' is not a cooked environment",
+            path.to_str().unwrap_or_default()
+        )));
+    }
+    let version = reader.read_u32()?;
+    if version != COOKED_ENVIRONMENT_VERSION {
+        return Err(LoaderError::Validation(format!(
+            "Cooked environment version {} does not match {}",
+            version, COOKED_ENVIRONMENT_VERSION
+        )));
+    }
+    let source_hash = reader.read_u64()?;
+    if expected_source_hash.is_some_and(|x| x != source_hash) {
+        return Err(LoaderError::Validation(
+            "Cooked environment is stale, source hash does not match".to_string(),
+        ));
+    }
+
+    Ok(environment::HostEnvironment {
+        cube_map: reader.read_cube_map("cube_map")?,
+        irradiance: reader.read_cube_map("irradiance")?,
+        prefiltered: reader.read_cube_map("prefiltered")?,
+        brdf_lut: reader.read_texture_levels("brdf_lut".to_string())?,
+        irradiance_sh: reader.read_vec3s()?,
+    })
+}
+
+fn resolve_cooked_materials(
+    cooked_materials: Vec<CookedMaterial>,
+) -> Result<Vec<material::HostMaterial>, LoaderError> {
+    let mut reference_keys: Vec<String> = cooked_materials
+        .iter()
+        .flat_map(|x| x.samplers.iter())
+        .filter_map(|(_, _, texture)| match texture {
+            CookedTexture::Reference(key) => Some(key.clone()),
+            CookedTexture::Embedded(_) => None,
+        })
+        .collect();
+    reference_keys.sort();
+    reference_keys.dedup();
+
+    let pool_size = reference_keys.len();
+    let mut references: HashMap<String, Arc<tex::HostTexture>> = HashMap::new();
+    if pool_size > 0 {
+        let pool = ThreadPool::new(pool_size.min(8));
+        let (sender, receiver) = channel();
+        for key in reference_keys {
+            let sender = sender.clone();
+            pool.execute(move || {
+                let result = cache::get_or_load_host_texture(&key, || {
+                    loader::load_host_texture_from_file(Path::new(&key), "")
+                });
+                sender.send((key, result)).unwrap();
+            });
+        }
+        for (key, result) in receiver.iter().take(pool_size) {
+            references.insert(key, result.map_err(LoaderError::MissingTexture)?);
+        }
+    }
+
+    let mut materials: Vec<material::HostMaterial> = Vec::new();
+    for cooked_material in cooked_materials {
+        let mut material = cooked_material.material;
+        for (name, format, texture) in cooked_material.samplers {
+            let texture = match texture {
+                CookedTexture::Reference(key) => references[&key].clone(),
+                CookedTexture::Embedded(texture) if texture.name.is_empty() => Arc::new(texture),
+                CookedTexture::Embedded(texture) => {
+                    let key = texture.name.clone();
+                    cache::get_or_load_host_texture(&key, || Ok(texture))
+                        .map_err(LoaderError::MissingTexture)?
+                }
+            };
+            material.properties_samplers.push(material::Property {
+                name,
+                value: material::HostSampler { texture, format },
+            });
+        }
+        materials.push(material);
+    }
+
+    Ok(materials)
+}
+
+struct CookedReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> CookedReader<'a> {
+    fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], LoaderError> {
+        if self.offset + count > self.data.len() {
+            return Err(LoaderError::Parse(
+                "Unexpected end of cooked model".to_string(),
+            ));
+        }
+
+        let bytes = &self.data[self.offset..self.offset + count];
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    // Element count of an array, rejected when the remaining data cannot hold it
+    fn read_count(&mut self, element_size: usize) -> Result<usize, LoaderError> {
+        let count = self.read_u32()? as usize;
+        match count.checked_mul(element_size) {
+            Some(size) if size <= self.data.len() - self.offset => Ok(count),
+            _ => Err(LoaderError::Parse(
+                "Unexpected end of cooked model".to_string(),
+            )),
+        }
+    }
+
+    fn read_u8(&mut self) -> Result<u8, LoaderError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, LoaderError> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.read_bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, LoaderError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn read_f32(&mut self) -> Result<f32, LoaderError> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+
+    fn read_string(&mut self) -> Result<String, LoaderError> {
+        let count = self.read_u32()? as usize;
+        String::from_utf8(self.read_bytes(count)?.to_vec())
+            .map_err(|error| LoaderError::Parse(error.to_string()))
+    }
+
+    fn read_f32s(&mut self) -> Result<Vec<f32>, LoaderError> {
+        let count = self.read_count(4)?;
+        Ok(self
+            .read_bytes(count * 4)?
+            .chunks_exact(4)
+            .map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+            .collect())
+    }
+
+    fn read_vec2s(&mut self) -> Result<Vec<math::Vec2f>, LoaderError> {
+        let count = self.read_count(8)?;
+        let mut values = Vec::with_capacity(count);
+        for _ in 0..count {
+            values.push(math::Vec2f::new(self.read_f32()?, self.read_f32()?));
+        }
+        Ok(values)
+    }
+
+    fn read_vec3s(&mut self) -> Result<Vec<math::Vec3f>, LoaderError> {
+        let count = self.read_count(12)?;
+        let mut values = Vec::with_capacity(count);
+        for _ in 0..count {
+            values.push(math::Vec3f::new(
+                self.read_f32()?,
+                self.read_f32()?,
+                self.read_f32()?,
+            ));
+        }
+        Ok(values)
+    }
+
+    fn read_indices(&mut self) -> Result<Vec<math::Vec1u>, LoaderError> {
+        let count = self.read_count(4)?;
+        let mut values = Vec::with_capacity(count);
+        for _ in 0..count {
+            values.push(math::Vec1u::new(self.read_u32()?));
+        }
+        Ok(values)
+    }
+
+    fn read_mesh(&mut self) -> Result<mesh::HostMesh, LoaderError> {
+        let name = self.read_string()?;
+        let material_index = self.read_u32()? as usize;
+        let vertices = self.read_vec3s()?;
+        let normals = self.read_vec3s()?;
+        let tangents = self.read_vec3s()?;
+        let bitangents = self.read_vec3s()?;
+        let uvs = self.read_vec2s()?;
+        let indices = self.read_indices()?;
+
+        // Error and index count of every LOD
+        let lod_count = self.read_count(8)?;
+        let mut lods: Vec<mesh::MeshLod> = Vec::with_capacity(lod_count);
+        for _ in 0..lod_count {
+            let error = self.read_f32()?;
+            lods.push(mesh::MeshLod {
+                error,
+                indices: self.read_indices()?,
+            });
+        }
+
+        if vertices.is_empty()
+            || std::iter::once(&indices)
+                .chain(lods.iter().map(|x| &x.indices))
+                .flatten()
+                .any(|i| i.x as usize >= vertices.len())
+        {
+            return Err(LoaderError::Validation(format!(
+                "Cooked mesh '{}' has invalid vertices or indices",
+                name
+            )));
+        }
+
+        let mut host_mesh = mesh::HostMesh::new(
+            name,
+            material_index,
+            vertices,
+            normals,
+            tangents,
+            bitangents,
+            uvs,
+            indices,
+        );
+        host_mesh.lods = lods;
+
+        // Every optional stream is either absent or has one element per vertex
+        let vertex_count = host_mesh.vertices.len();
+        let stream_lengths = [
+            host_mesh.normals.len(),
+            host_mesh.tangents.len(),
+            host_mesh.bitangents.len(),
+            host_mesh.uvs.len(),
+            host_mesh.uvs1.len(),
+            host_mesh.colors.len(),
+            host_mesh.joint_indices.len(),
+            host_mesh.joint_weights.len(),
+        ];
+        let optional_lengths = stream_lengths.iter().copied().chain(
+            host_mesh
+                .morph_targets
+                .iter()
+                .map(|x| x.normal_deltas.len()),
+        );
+        if optional_lengths
+            .filter(|&x| x != 0)
+            .chain(
+                host_mesh
+                    .morph_targets
+                    .iter()
+                    .map(|x| x.position_deltas.len()),
+            )
+            .any(|x| x != vertex_count)
+        {
+            return Err(LoaderError::Validation(format!(
+                "Cooked mesh '{}' has vertex streams that do not match its {} vertices",
+                host_mesh.name, vertex_count
+            )));
+        }
+
+        Ok(host_mesh)
+    }
+
+    fn read_material(&mut self) -> Result<CookedMaterial, LoaderError> {
+        let mut material = material::HostMaterial::empty();
+        material.name = self.read_string()?;
+
+        for _ in 0..self.read_u32()? {
+            material.properties_1u.push(material::Property {
+                name: self.read_string()?,
+                value: math::Vec1u::new(self.read_u32()?),
+            });
+        }
+
+        for _ in 0..self.read_u32()? {
+            material.properties_1f.push(material::Property {
+                name: self.read_string()?,
+                value: math::Vec1f::new(self.read_f32()?),
+            });
+        }
+
+        for _ in 0..self.read_u32()? {
+            let name = self.read_string()?;
+            let value = self.read_vec3s()?;
+            material.properties_3f.push(material::Property {
+                name,
+                value: *value.first().ok_or(LoaderError::Parse(
+                    "Empty material vector property".to_string(),
+                ))?,
+            });
+        }
+
+        let mut samplers: Vec<(String, tex::SamplerFormat, CookedTexture)> = Vec::new();
+        for _ in 0..self.read_u32()? {
+            let name = self.read_string()?;
+            let color_space = match self.read_u8()? {
+                0 => tex::ColorSpace::Linear,
+                _ => tex::ColorSpace::Srgb,
+            };
+            let format = tex::SamplerFormat::new(color_space, self.read_u8()? as usize);
+            samplers.push((name, format, self.read_texture()?));
+        }
+
+        Ok(CookedMaterial { material, samplers })
+    }
+
+    fn read_texture(&mut self) -> Result<CookedTexture, LoaderError> {
+        let name = self.read_string()?;
+
+        if self.read_u8()? == 0 {
+            return Ok(CookedTexture::Reference(name));
+        }
+
+        Ok(CookedTexture::Embedded(self.read_texture_levels(name)?))
+    }
+
+    fn read_texture_levels(&mut self, name: String) -> Result<tex::HostTexture, LoaderError> {
+        let width = self.read_u32()? as usize;
+        let height = self.read_u32()? as usize;
+        let depth = self.read_u32()? as usize;
+        let data = self.read_texture_data()?;
+        let mut mips = Vec::new();
+        for _ in 0..self.read_u32()? {
+            mips.push(self.read_texture_data()?);
+        }
+
+        Ok(tex::HostTexture {
+            name,
+            width,
+            height,
+            depth,
+            data,
+            mips,
+            srgb: false,
+        })
+    }
+
+    fn read_cube_map(&mut self, name: &str) -> Result<tex::HostCubeMapTexture, LoaderError> {
+        let mut faces = Vec::new();
+        for face_name in &environment::CUBE_MAP_FACE_NAMES {
+            faces.push(self.read_texture_levels(format!("{}_{}", name, face_name))?);
+        }
+        Ok(environment::create_host_cube_map_texture(faces))
+    }
+
+    fn read_texture_data(&mut self) -> Result<tex::HostTextureData, LoaderError> {
+        Ok(match self.read_u8()? {
+            0 => {
+                let count = self.read_u32()? as usize;
+                tex::HostTextureData::UINT8(self.read_bytes(count)?.to_vec())
+            }
+            1 => tex::HostTextureData::FLOAT32(self.read_f32s()?),
+            2 => {
+                let format = *COMPRESSED_FORMATS
+                    .get(self.read_u8()? as usize)
+                    .ok_or_else(|| {
+                        LoaderError::Parse("Unknown compressed texture format".to_string())
+                    })?;
+                let mut levels = Vec::new();
+                for _ in 0..self.read_u32()? {
+                    let count = self.read_u32()? as usize;
+                    levels.push(self.read_bytes(count)?.to_vec());
+                }
+                tex::HostTextureData::COMPRESSED(format, levels)
+            }
+            tag => {
+                return Err(LoaderError::Parse(format!(
+                    "Unknown texture data tag {}",
+                    tag
+                )))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> mesh::HostMesh {
+        mesh::HostMesh::new(
+            "triangle".to_string(),
+            0,
+            vec![
+                math::Vec3f::new(0., 0., 0.),
+                math::Vec3f::new(1., 0., 0.),
+                math::Vec3f::new(0., 1., 0.),
+            ],
+            vec![math::Vec3f::new(0., 0., 1.); 3],
+            Vec::new(),
+            Vec::new(),
+            vec![math::Vec2f::new(0., 0.); 3],
+            vec![
+                math::Vec1u::new(0),
+                math::Vec1u::new(1),
+                math::Vec1u::new(2),
+            ],
+        )
+    }
+
+    fn cook_and_read(host_mesh: &mesh::HostMesh) -> Result<mesh::HostMesh, LoaderError> {
+        let mut writer = CookedWriter { data: Vec::new() };
+        writer.write_mesh(host_mesh);
+        let mut reader = CookedReader {
+            data: &writer.data,
+            offset: 0,
+        };
+        reader.read_mesh()
+    }
+
+    #[test]
+    fn meshes_round_trip() {
+        let host_mesh = cook_and_read(&triangle()).unwrap();
+        assert_eq!(host_mesh.vertices.len(), 3);
+        assert_eq!(host_mesh.normals.len(), 3);
+        assert_eq!(host_mesh.uvs.len(), 3);
+        assert!(host_mesh.tangents.is_empty());
+    }
+
+    #[test]
+    fn mismatched_streams_are_rejected() {
+        let mut host_mesh = triangle();
+        host_mesh.normals.pop();
+        assert!(cook_and_read(&host_mesh).is_err());
+
+        let mut host_mesh = triangle();
+        host_mesh.uvs.push(math::Vec2f::new(1., 1.));
+        assert!(cook_and_read(&host_mesh).is_err());
+
+        let mut host_mesh = triangle();
+        host_mesh.tangents = vec![math::Vec3f::new(1., 0., 0.); 2];
+        host_mesh.bitangents = vec![math::Vec3f::new(0., 1., 0.); 2];
+        assert!(cook_and_read(&host_mesh).is_err());
+    }
+}
@@ -0,0 +1,816 @@
+use crate::gl::tex;
+use crate::math;
+use std::f32::consts::{FRAC_1_PI, PI};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+pub const HDRI_DIRECTORY: &str = "data/materials/hdri";
+
+// The lighting shader reads roughness r from level r * 7
+pub const PREFILTERED_LEVEL_COUNT: usize = 8;
+const IRRADIANCE_SAMPLE_DELTA: f32 = 0.015;
+const GGX_SAMPLE_COUNT: u32 = 1024;
+pub const SH_COEFFICIENT_COUNT: usize = 9;
+pub const CUBE_MAP_FACE_NAMES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct EnvironmentSettings {
+    pub cube_map_size: usize,
+    pub irradiance_size: usize,
+    pub prefiltered_size: usize,
+    pub brdf_lut_size: usize,
+}
+
+pub const DEFAULT_ENVIRONMENT_SETTINGS: EnvironmentSettings = EnvironmentSettings {
+    cube_map_size: 2048,
+    irradiance_size: 32,
+    prefiltered_size: 128,
+    brdf_lut_size: 128,
+};
+
+// Everything image based lighting needs, faces keep the row order of the GPU textures
+#[derive(Clone)]
+pub struct HostEnvironment {
+    pub cube_map: tex::HostCubeMapTexture,
+    pub irradiance: tex::HostCubeMapTexture,
+    // Every face holds PREFILTERED_LEVEL_COUNT levels or as many as its size allows
+    pub prefiltered: tex::HostCubeMapTexture,
+    // NdotV along x, roughness along y, scale and bias in red and green
+    pub brdf_lut: tex::HostTexture,
+    // Same irradiance as the irradiance map sampled at the negated normal
+    pub irradiance_sh: Vec<math::Vec3f>,
+}
+
+// CPU versions of the GPU bake in ibl.rs, same integrals and sample patterns
+pub fn bake_host_environment(
+    hdri: &tex::HostTexture,
+    settings: &EnvironmentSettings,
+) -> HostEnvironment {
+    let cube_map = create_host_cube_map(hdri, settings.cube_map_size);
+    let irradiance = create_host_irradiance_map(&cube_map, settings.irradiance_size);
+    let prefiltered = create_host_prefiltered_environment_map(&cube_map, settings.prefiltered_size);
+
+    HostEnvironment {
+        cube_map,
+        irradiance,
+        prefiltered,
+        brdf_lut: create_host_brdf_lut(settings.brdf_lut_size),
+        irradiance_sh: project_irradiance_sh(hdri),
+    }
+}
+
+pub fn create_host_cube_map(hdri: &tex::HostTexture, size: usize) -> tex::HostCubeMapTexture {
+    let depth = hdri.depth.min(4);
+    let faces = create_host_cube_map_level(size, depth, |direction| {
+        let direction = convert_cube_map_to_hdri_direction(direction);
+
+        // Same mapping as hdri2cube.frag, whose invAtan rounds 1 / (2 pi) and 1 / pi
+        let u = direction.z.atan2(direction.x) * 0.5 * FRAC_1_PI + 0.5;
+        let v = direction.y.clamp(-1., 1.).asin() * FRAC_1_PI + 0.5;
+        sample_host_texture(hdri, u, v)
+    });
+
+    create_host_cube_map_texture(
+        faces
+            .into_iter()
+            .enumerate()
+            .map(|(face, data)| create_face(&hdri.name, face, size, depth, data))
+            .collect(),
+    )
+}
+
+// Riemann sum over the hemisphere, as in diffuse_cube_map_convolution.frag
+pub fn create_host_irradiance_map(
+    cube_map: &tex::HostCubeMapTexture,
+    size: usize,
+) -> tex::HostCubeMapTexture {
+    let faces = create_host_cube_map_level(size, 4, |direction| {
+        let normal = -convert_cube_map_to_hdri_direction(direction);
+        let right = math::normalize_vec3(math::cross(math::Vec3f::new(0., 1., 0.), normal));
+        let up = math::normalize_vec3(math::cross(normal, right));
+
+        let mut irradiance = [0_f32; 3];
+        let mut sample_count = 0;
+        let mut phi = 0_f32;
+        while phi < 2. * PI {
+            let mut theta = 0_f32;
+            while theta < 0.5 * PI {
+                let sample = right * (theta.sin() * phi.cos())
+                    + up * (theta.sin() * phi.sin())
+                    + normal * theta.cos();
+                let color = sample_host_cube_map(cube_map, sample);
+                for channel in 0..3 {
+                    irradiance[channel] += color[channel] * theta.cos() * theta.sin();
+                }
+                sample_count += 1;
+                theta += IRRADIANCE_SAMPLE_DELTA;
+            }
+            phi += IRRADIANCE_SAMPLE_DELTA;
+        }
+
+        let scale = PI / sample_count.max(1) as f32;
+        [
+            irradiance[0] * scale,
+            irradiance[1] * scale,
+            irradiance[2] * scale,
+            1.,
+        ]
+    });
+
+    create_host_cube_map_texture(
+        faces
+            .into_iter()
+            .enumerate()
+            .map(|(face, data)| create_face("irradiance", face, size, 4, data))
+            .collect(),
+    )
+}
+
+// GGX importance sampling with N = V = R, as in prefiltered_environment_map.frag
+pub fn create_host_prefiltered_environment_map(
+    cube_map: &tex::HostCubeMapTexture,
+    size: usize,
+) -> tex::HostCubeMapTexture {
+    let level_count = calculate_prefiltered_level_count(size);
+
+    let mut faces: Vec<tex::HostTexture> = Vec::new();
+    for level in 0..level_count {
+        let roughness = level as f32 / (PREFILTERED_LEVEL_COUNT - 1) as f32;
+        let level_size = (size >> level).max(1);
+
+        let level_faces = create_host_cube_map_level(level_size, 4, |direction| {
+            let normal = convert_cube_map_to_hdri_direction(direction);
+
+            let mut color = [0_f32; 3];
+            let mut total_weight = 0.;
+            for i in 0..GGX_SAMPLE_COUNT {
+                let half = sample_ggx(calculate_hammersley(i, GGX_SAMPLE_COUNT), normal, roughness);
+                let light =
+                    math::normalize_vec3(half * (2. * math::dot_vec3(normal, half)) - normal);
+
+                let n_dot_l = math::dot_vec3(normal, light).max(0.);
+                if n_dot_l > 0. {
+                    let sample = sample_host_cube_map(cube_map, -light);
+                    for channel in 0..3 {
+                        color[channel] += sample[channel] * n_dot_l;
+                    }
+                    total_weight += n_dot_l;
+                }
+            }
+
+            let scale = 1. / total_weight;
+            [color[0] * scale, color[1] * scale, color[2] * scale, 1.]
+        });
+
+        for (face, data) in level_faces.into_iter().enumerate() {
+            if level == 0 {
+                faces.push(create_face("prefiltered", face, size, 4, data));
+            } else {
+                faces[face].mips.push(tex::HostTextureData::FLOAT32(data));
+            }
+        }
+    }
+
+    create_host_cube_map_texture(faces)
+}
+
+// Split sum scale and bias, as in brdf_integration_map.frag
+pub fn create_host_brdf_lut(size: usize) -> tex::HostTexture {
+    let mut data = Vec::with_capacity(size * size * 4);
+    for y in 0..size {
+        for x in 0..size {
+            let (scale, bias) = integrate_brdf(
+                (x as f32 + 0.5) / size as f32,
+                (y as f32 + 0.5) / size as f32,
+            );
+            data.extend_from_slice(&[scale, bias, 0., 1.]);
+        }
+    }
+
+    let mut texture = tex::HostTexture::empty("brdf_lut".to_string(), size, size, 4);
+    texture.data = tex::HostTextureData::FLOAT32(data);
+    texture
+}
+
+// 3rd order projection of the equirect HDRI convolved with the clamped cosine, divided by pi
+pub fn project_irradiance_sh(hdri: &tex::HostTexture) -> Vec<math::Vec3f> {
+    let mut coefficients = [[0_f32; 3]; SH_COEFFICIENT_COUNT];
+    let mut total_weight = 0.;
+
+    for y in 0..hdri.height {
+        // Inverse of the mapping in hdri2cube.frag
+        let latitude = ((y as f32 + 0.5) / hdri.height as f32 - 0.5) * PI;
+        let weight = latitude.cos() * (2. * PI / hdri.width as f32) * (PI / hdri.height as f32);
+
+        for x in 0..hdri.width {
+            let longitude = ((x as f32 + 0.5) / hdri.width as f32 - 0.5) * 2. * PI;
+            let direction = math::Vec3f::new(
+                latitude.cos() * longitude.cos(),
+                latitude.sin(),
+                latitude.cos() * longitude.sin(),
+            );
+            let basis = evaluate_sh_basis(direction);
+
+            let texel = read_texel(hdri, x, y);
+            for (coefficient, value) in coefficients.iter_mut().zip(basis.iter()) {
+                for channel in 0..3 {
+                    coefficient[channel] += texel[channel] * value * weight;
+                }
+            }
+            total_weight += weight;
+        }
+    }
+
+    // Clamped cosine per band, a Lanczos window per band against ringing
+    let band_scales: Vec<f32> = [PI, 2. * PI / 3., PI / 4.]
+        .iter()
+        .enumerate()
+        .map(|(band, cosine)| {
+            let x = PI * band as f32 / 3.;
+            let window = if band == 0 { 1. } else { x.sin() / x };
+            cosine * window / PI
+        })
+        .collect();
+    let normalization = 4. * PI / total_weight.max(f32::MIN_POSITIVE);
+
+    coefficients
+        .iter()
+        .enumerate()
+        .map(|(i, coefficient)| {
+            let band = match i {
+                0 => 0,
+                1..=3 => 1,
+                _ => 2,
+            };
+            let scale = band_scales[band] * normalization;
+            math::Vec3f::new(
+                coefficient[0] * scale,
+                coefficient[1] * scale,
+                coefficient[2] * scale,
+            )
+        })
+        .collect()
+}
+
+// Real SH basis in the order lighting.frag expects
+pub fn evaluate_sh_basis(direction: math::Vec3f) -> [f32; SH_COEFFICIENT_COUNT] {
+    let (x, y, z) = (direction.x, direction.y, direction.z);
+    [
+        0.282_095,
+        0.488_603 * y,
+        0.488_603 * z,
+        0.488_603 * x,
+        1.092_548 * x * y,
+        1.092_548 * y * z,
+        0.315_392 * (3. * z * z - 1.),
+        1.092_548 * x * z,
+        0.546_274 * (x * x - y * y),
+    ]
+}
+
+// Every .hdr below the folder, sorted so the editor list is stable
+pub fn find_hdri_files(folder_path: &Path) -> Vec<PathBuf> {
+    let mut hdri_paths = Vec::new();
+    let entries = match fs::read_dir(folder_path) {
+        Ok(entries) => entries,
+        Err(_) => return hdri_paths,
+    };
+    for path in entries.filter_map(|x| x.ok()).map(|x| x.path()) {
+        if path.is_dir() {
+            hdri_paths.extend(find_hdri_files(&path));
+        } else if path.extension().unwrap_or_default() == "hdr" {
+            hdri_paths.push(path);
+        }
+    }
+    hdri_paths.sort();
+    hdri_paths
+}
+
+// Uniform sky, lights the scene evenly when no HDRI can be loaded. A single texel would
+// give the SH projection a single direction, so a few rows and columns are kept
+pub fn create_constant_hdri(name: &str, radiance: math::Vec3f) -> tex::HostTexture {
+    let (width, height) = (16, 8);
+    tex::HostTexture {
+        name: name.to_string(),
+        width,
+        height,
+        depth: 3,
+        data: tex::HostTextureData::FLOAT32(
+            [radiance.x, radiance.y, radiance.z].repeat(width * height),
+        ),
+        mips: Vec::new(),
+        srgb: false,
+    }
+}
+
+pub fn calculate_prefiltered_level_count(size: usize) -> usize {
+    PREFILTERED_LEVEL_COUNT.min(tex::calculate_mip_count(size, size))
+}
+
+// Faces in the +X, -X, +Y, -Y, +Z, -Z order of GL targets and layers
+pub fn get_cube_map_faces(cube_map: &tex::HostCubeMapTexture) -> [&tex::HostTexture; 6] {
+    [
+        &cube_map.px,
+        &cube_map.nx,
+        &cube_map.py,
+        &cube_map.ny,
+        &cube_map.pz,
+        &cube_map.nz,
+    ]
+}
+
+pub fn create_host_cube_map_texture(faces: Vec<tex::HostTexture>) -> tex::HostCubeMapTexture {
+    assert_eq!(faces.len(), 6, "Cube maps need 6 faces");
+
+    let mut faces = faces.into_iter();
+    let px = faces.next().unwrap();
+    let nx = faces.next().unwrap();
+    let py = faces.next().unwrap();
+    let ny = faces.next().unwrap();
+    let pz = faces.next().unwrap();
+    let nz = faces.next().unwrap();
+
+    tex::HostCubeMapTexture {
+        width: px.width,
+        height: px.height,
+        px,
+        nx,
+        py,
+        ny,
+        pz,
+        nz,
+    }
+}
+
+// GL cube map lookup direction through (s, t) of a face, t follows the stored rows
+pub fn get_cube_map_direction(face: usize, s: f32, t: f32) -> math::Vec3f {
+    let (sc, tc) = (s * 2. - 1., t * 2. - 1.);
+    let direction = match face {
+        0 => math::Vec3f::new(1., -tc, -sc),
+        1 => math::Vec3f::new(-1., -tc, sc),
+        2 => math::Vec3f::new(sc, 1., tc),
+        3 => math::Vec3f::new(sc, -1., -tc),
+        4 => math::Vec3f::new(sc, -tc, 1.),
+        _ => math::Vec3f::new(-sc, -tc, -1.),
+    };
+    math::normalize_vec3(direction)
+}
+
+pub fn get_cube_map_face_coordinates(direction: math::Vec3f) -> (usize, f32, f32) {
+    let (x, y, z) = (direction.x, direction.y, direction.z);
+    let (abs_x, abs_y, abs_z) = (x.abs(), y.abs(), z.abs());
+
+    let (face, major, sc, tc) = if abs_x >= abs_y && abs_x >= abs_z {
+        if x > 0. {
+            (0, abs_x, -z, -y)
+        } else {
+            (1, abs_x, z, -y)
+        }
+    } else if abs_y >= abs_z {
+        if y > 0. {
+            (2, abs_y, x, z)
+        } else {
+            (3, abs_y, x, -z)
+        }
+    } else if z > 0. {
+        (4, abs_z, x, -y)
+    } else {
+        (5, abs_z, -x, -y)
+    };
+
+    let major = major.max(f32::MIN_POSITIVE);
+    (face, (sc / major + 1.) * 0.5, (tc / major + 1.) * 0.5)
+}
+
+// The GPU bake renders every face with a view that amounts to this rotation
+pub fn convert_cube_map_to_hdri_direction(direction: math::Vec3f) -> math::Vec3f {
+    math::Vec3f::new(direction.z, -direction.y, direction.x)
+}
+
+// Bilinear on one face, the GPU also filters across the seams
+pub fn sample_host_cube_map(
+    cube_map: &tex::HostCubeMapTexture,
+    direction: math::Vec3f,
+) -> [f32; 4] {
+    let (face, s, t) = get_cube_map_face_coordinates(direction);
+    sample_host_texture(get_cube_map_faces(cube_map)[face], s, t)
+}
+
+// Bilinear with clamped edges, missing channels read as zero
+pub fn sample_host_texture(texture: &tex::HostTexture, s: f32, t: f32) -> [f32; 4] {
+    let mut result = [0_f32; 4];
+    if texture.width == 0 || texture.height == 0 {
+        return result;
+    }
+
+    let x = s * texture.width as f32 - 0.5;
+    let y = t * texture.height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let clamp = |value: f32, size: usize| value.max(0.).min((size - 1) as f32) as usize;
+    let (x0, x1) = (clamp(x0, texture.width), clamp(x0 + 1., texture.width));
+    let (y0, y1) = (clamp(y0, texture.height), clamp(y0 + 1., texture.height));
+
+    for &(x, y, weight) in &[
+        (x0, y0, (1. - fx) * (1. - fy)),
+        (x1, y0, fx * (1. - fy)),
+        (x0, y1, (1. - fx) * fy),
+        (x1, y1, fx * fy),
+    ] {
+        let texel = read_texel(texture, x, y);
+        for channel in 0..4 {
+            result[channel] += texel[channel] * weight;
+        }
+    }
+
+    result
+}
+
+fn read_texel(texture: &tex::HostTexture, x: usize, y: usize) -> [f32; 4] {
+    let depth = texture.depth.min(4);
+    let index = (y * texture.width + x) * texture.depth;
+
+    let mut texel = [0_f32; 4];
+    match &texture.data {
+        tex::HostTextureData::FLOAT32(data) if data.len() >= index + depth => {
+            texel[..depth].copy_from_slice(&data[index..index + depth]);
+        }
+        tex::HostTextureData::UINT8(data) if data.len() >= index + depth => {
+            for channel in 0..depth {
+                texel[channel] = data[index + channel] as f32 / 255.;
+            }
+        }
+        _ => {}
+    }
+    texel
+}
+
+// One thread per face, texels are evaluated at their GL lookup direction
+fn create_host_cube_map_level<F>(size: usize, depth: usize, evaluate: F) -> Vec<Vec<f32>>
+where
+    F: Fn(math::Vec3f) -> [f32; 4] + Sync,
+{
+    let evaluate = &evaluate;
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..6)
+            .map(|face| {
+                scope.spawn(move || {
+                    let mut data = Vec::with_capacity(size * size * depth);
+                    for y in 0..size {
+                        for x in 0..size {
+                            let direction = get_cube_map_direction(
+                                face,
+                                (x as f32 + 0.5) / size as f32,
+                                (y as f32 + 0.5) / size as f32,
+                            );
+                            data.extend_from_slice(&evaluate(direction)[..depth]);
+                        }
+                    }
+                    data
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("Cube map face thread panicked"))
+            .collect()
+    })
+}
+
+fn create_face(
+    name: &str,
+    face: usize,
+    size: usize,
+    depth: usize,
+    data: Vec<f32>,
+) -> tex::HostTexture {
+    let mut texture = tex::HostTexture::empty(
+        format!("{}_{}", name, CUBE_MAP_FACE_NAMES[face]),
+        size,
+        size,
+        depth,
+    );
+    texture.data = tex::HostTextureData::FLOAT32(data);
+    texture
+}
+
+fn calculate_hammersley(i: u32, count: u32) -> (f32, f32) {
+    (
+        i as f32 / count as f32,
+        i.reverse_bits() as f32 * 2.328_306_4e-10,
+    )
+}
+
+fn sample_ggx(xi: (f32, f32), normal: math::Vec3f, roughness: f32) -> math::Vec3f {
+    let a = roughness * roughness;
+
+    let phi = 2. * PI * xi.0;
+    let cos_theta = ((1. - xi.1) / (1. + (a * a - 1.) * xi.1)).sqrt();
+    let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+
+    let up = if normal.z.abs() < 0.999 {
+        math::Vec3f::new(0., 0., 1.)
+    } else {
+        math::Vec3f::new(1., 0., 0.)
+    };
+    let tangent = math::normalize_vec3(math::cross(up, normal));
+    let bitangent = math::cross(normal, tangent);
+
+    math::normalize_vec3(
+        tangent * (phi.cos() * sin_theta)
+            + bitangent * (phi.sin() * sin_theta)
+            + normal * cos_theta,
+    )
+}
+
+fn calculate_geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.;
+    let schlick_ggx = |n_dot_x: f32| n_dot_x / (n_dot_x * (1. - k) + k);
+    schlick_ggx(n_dot_v) * schlick_ggx(n_dot_l)
+}
+
+fn integrate_brdf(n_dot_v: f32, roughness: f32) -> (f32, f32) {
+    let view = math::Vec3f::new((1. - n_dot_v * n_dot_v).sqrt(), 0., n_dot_v);
+    let normal = math::Vec3f::new(0., 0., 1.);
+
+    let (mut scale, mut bias) = (0., 0.);
+    for i in 0..GGX_SAMPLE_COUNT {
+        let half = sample_ggx(calculate_hammersley(i, GGX_SAMPLE_COUNT), normal, roughness);
+        let light = math::normalize_vec3(half * (2. * math::dot_vec3(view, half)) - view);
+
+        let n_dot_l = light.z.max(0.);
+        let n_dot_h = half.z.max(0.);
+        let v_dot_h = math::dot_vec3(view, half).max(0.);
+        if n_dot_l > 0. {
+            let geometry = calculate_geometry_smith(n_dot_v.max(0.), n_dot_l, roughness);
+            let visibility = geometry * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = (1. - v_dot_h).powi(5);
+
+            scale += (1. - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+
+    (
+        scale / GGX_SAMPLE_COUNT as f32,
+        bias / GGX_SAMPLE_COUNT as f32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::cooker;
+
+    const TEST_SETTINGS: EnvironmentSettings = EnvironmentSettings {
+        cube_map_size: 8,
+        irradiance_size: 2,
+        prefiltered_size: 8,
+        brdf_lut_size: 4,
+    };
+
+    fn get_floats(data: &tex::HostTextureData) -> &[f32] {
+        match data {
+            tex::HostTextureData::FLOAT32(data) => data,
+            _ => panic!("Expected float data"),
+        }
+    }
+
+    fn assert_texels_near(texture: &tex::HostTexture, expected: &[f32; 3], tolerance: f32) {
+        for data in std::iter::once(&texture.data).chain(&texture.mips) {
+            for texel in get_floats(data).chunks_exact(texture.depth) {
+                for channel in 0..3 {
+                    let error = (texel[channel] - expected[channel]).abs();
+                    assert!(error <= tolerance * expected[channel], "{:?}", texel);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn brdf_lut_matches_split_sum() {
+        // A mirror reflects along the normal of the half vector, only Fresnel remains
+        for &n_dot_v in &[0.1, 0.3, 0.5, 0.7, 0.9, 1.] {
+            let (scale, bias) = integrate_brdf(n_dot_v, 0.);
+            let fresnel = (1. - n_dot_v).powi(5);
+            assert!((scale - (1. - fresnel)).abs() < 1e-3);
+            assert!((bias - fresnel).abs() < 1e-3);
+        }
+
+        // Midpoint quadrature of the same integral over every light direction
+        for &n_dot_v in &[0.2_f32, 0.5, 0.8] {
+            for &roughness in &[0.4_f32, 0.7, 1.] {
+                let view = math::Vec3f::new((1. - n_dot_v * n_dot_v).sqrt(), 0., n_dot_v);
+                let a2 = roughness.powi(4);
+                let step_count = 512;
+                let (d_theta, d_phi) = (0.5 * PI / step_count as f32, 2. * PI / step_count as f32);
+
+                let mut expected = (0., 0.);
+                for i in 0..step_count {
+                    let theta = (i as f32 + 0.5) * d_theta;
+                    for j in 0..step_count {
+                        let phi = (j as f32 + 0.5) * d_phi;
+                        let light = math::Vec3f::new(
+                            theta.sin() * phi.cos(),
+                            theta.sin() * phi.sin(),
+                            theta.cos(),
+                        );
+                        let half = math::normalize_vec3(view + light);
+                        let v_dot_h = math::dot_vec3(view, half);
+                        let distribution = a2 / (PI * (half.z * half.z * (a2 - 1.) + 1.).powi(2));
+                        let geometry = calculate_geometry_smith(n_dot_v, light.z, roughness);
+
+                        // Specular BRDF without Fresnel times cos, over the solid angle
+                        let value = distribution * geometry / (4. * n_dot_v)
+                            * theta.sin()
+                            * d_theta
+                            * d_phi;
+                        let fresnel = (1. - v_dot_h).powi(5);
+                        expected.0 += (1. - fresnel) * value;
+                        expected.1 += fresnel * value;
+                    }
+                }
+
+                let (scale, bias) = integrate_brdf(n_dot_v, roughness);
+                assert!((scale - expected.0).abs() < 0.01);
+                assert!((bias - expected.1).abs() < 0.01);
+            }
+        }
+
+        // NdotV along x and roughness along y at texel centers
+        let lut = create_host_brdf_lut(4);
+        let (scale, bias) = integrate_brdf(0.625, 0.375);
+        assert_eq!(
+            get_floats(&lut.data)[(4 + 2) * 4..(4 + 2) * 4 + 4],
+            [scale, bias, 0., 1.]
+        );
+    }
+
+    #[test]
+    fn constant_hdri_gives_constant_lighting() {
+        let radiance = [0.25, 0.5, 2.];
+        let hdri = create_constant_hdri("constant", math::Vec3f::new(0.25, 0.5, 2.));
+        let environment = bake_host_environment(&hdri, &TEST_SETTINGS);
+
+        // Irradiance is divided by pi, so a constant sky gives back its radiance
+        for face in 0..6 {
+            assert_texels_near(
+                get_cube_map_faces(&environment.cube_map)[face],
+                &radiance,
+                1e-6,
+            );
+            assert_texels_near(
+                get_cube_map_faces(&environment.irradiance)[face],
+                &radiance,
+                0.02,
+            );
+            assert_texels_near(
+                get_cube_map_faces(&environment.prefiltered)[face],
+                &radiance,
+                1e-4,
+            );
+        }
+        assert_eq!(
+            environment.prefiltered.px.mips.len() + 1,
+            calculate_prefiltered_level_count(TEST_SETTINGS.prefiltered_size)
+        );
+
+        for &(s, t) in &[(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)] {
+            let basis = evaluate_sh_basis(get_cube_map_direction(2, s, t));
+            let mut irradiance = math::Vec3f::new(0., 0., 0.);
+            for (coefficient, &value) in environment.irradiance_sh.iter().zip(basis.iter()) {
+                irradiance = irradiance + *coefficient * value;
+            }
+            for (&actual, &expected) in [irradiance.x, irradiance.y, irradiance.z]
+                .iter()
+                .zip(&radiance)
+            {
+                assert!((actual - expected).abs() < 1e-2 * expected);
+            }
+        }
+    }
+
+    #[test]
+    fn sh_irradiance_matches_irradiance_map() {
+        // Sky brighter than the ground, radiance (1 + y / 2) * color
+        let color = [1., 0.5, 0.25];
+        let (width, height) = (64, 32);
+        let mut hdri = tex::HostTexture::empty("gradient".to_string(), width, height, 3);
+        hdri.data = tex::HostTextureData::FLOAT32(
+            (0..width * height)
+                .flat_map(|i| {
+                    let latitude = ((i / width) as f32 + 0.5) / height as f32 * PI - 0.5 * PI;
+                    let radiance = 1. + 0.5 * latitude.sin();
+                    color.iter().map(move |x| x * radiance)
+                })
+                .collect(),
+        );
+
+        let cube_map = create_host_cube_map(&hdri, 16);
+        let irradiance = create_host_irradiance_map(&cube_map, 2);
+        let irradiance_sh = project_irradiance_sh(&hdri);
+
+        // The clamped cosine keeps 2 / 3 of the linear band, the window damps it further
+        let window = (PI / 3.).sin() / (PI / 3.);
+        for (face, texture) in get_cube_map_faces(&irradiance).iter().enumerate() {
+            for (i, texel) in get_floats(&texture.data).chunks_exact(4).enumerate() {
+                let direction = get_cube_map_direction(
+                    face,
+                    ((i % 2) as f32 + 0.5) / 2.,
+                    ((i / 2) as f32 + 0.5) / 2.,
+                );
+
+                // The map is sampled at the negated normal
+                let normal = -direction;
+                let basis = evaluate_sh_basis(normal);
+                let mut sh = math::Vec3f::new(0., 0., 0.);
+                for (coefficient, &value) in irradiance_sh.iter().zip(basis.iter()) {
+                    sh = sh + *coefficient * value;
+                }
+
+                let expected = 1. + normal.y / 3.;
+                let expected_sh = 1. + window * normal.y / 3.;
+                for (channel, &actual_sh) in [sh.x, sh.y, sh.z].iter().enumerate() {
+                    let actual = texel[channel] / color[channel];
+                    let actual_sh = actual_sh / color[channel];
+                    assert!((actual - expected).abs() < 0.02, "map {} {}", face, i);
+                    assert!((actual_sh - expected_sh).abs() < 0.02, "sh {} {}", face, i);
+                    assert!(
+                        (actual_sh - actual).abs() <= (1. - window) * normal.y.abs() / 3. + 0.03,
+                        "{} {}",
+                        face,
+                        i
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn face_coordinates_invert_direction() {
+        for face in 0..6 {
+            for &s in &[0.05, 0.3, 0.5, 0.8, 0.95] {
+                for &t in &[0.05, 0.25, 0.5, 0.7, 0.95] {
+                    let direction = get_cube_map_direction(face, s, t);
+                    let (actual_face, actual_s, actual_t) =
+                        get_cube_map_face_coordinates(direction);
+                    assert_eq!(actual_face, face);
+                    assert!((actual_s - s).abs() < 1e-5 && (actual_t - t).abs() < 1e-5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cooked_environment_round_trips() {
+        let data: Vec<f32> = (0..8 * 4 * 3).map(|i| (i % 13) as f32 * 0.3).collect();
+        let mut hdri = tex::HostTexture::empty("gradient".to_string(), 8, 4, 3);
+        hdri.data = tex::HostTextureData::FLOAT32(data);
+        let environment = bake_host_environment(&hdri, &TEST_SETTINGS);
+
+        let path = std::env::temp_dir().join(format!(
+            "environment_test_{}.{}",
+            uuid::Uuid::new_v4(),
+            cooker::COOKED_ENVIRONMENT_EXTENSION
+        ));
+        cooker::write_cooked_environment(&path, &environment, 42).unwrap();
+        let cooked = cooker::load_host_environment_from_cooked(&path, Some(42));
+        let stale = cooker::load_host_environment_from_cooked(&path, Some(43));
+        fs::remove_file(&path).unwrap();
+
+        let cooked = cooked.unwrap();
+        assert!(stale.is_err());
+        let pairs = [
+            (&environment.cube_map, &cooked.cube_map),
+            (&environment.irradiance, &cooked.irradiance),
+            (&environment.prefiltered, &cooked.prefiltered),
+        ];
+        for (expected, actual) in pairs.iter() {
+            let (expected, actual) = (get_cube_map_faces(expected), get_cube_map_faces(actual));
+            for (expected, actual) in expected.iter().zip(&actual) {
+                assert_eq!(
+                    (actual.width, actual.height, actual.depth),
+                    (expected.width, expected.height, expected.depth)
+                );
+                assert_eq!(get_floats(&actual.data), get_floats(&expected.data));
+                assert_eq!(actual.mips.len(), expected.mips.len());
+                for (actual, expected) in actual.mips.iter().zip(&expected.mips) {
+                    assert_eq!(get_floats(actual), get_floats(expected));
+                }
+            }
+        }
+        assert_eq!(
+            get_floats(&cooked.brdf_lut.data),
+            get_floats(&environment.brdf_lut.data)
+        );
+        let get_components = |sh: &[math::Vec3f]| -> Vec<f32> {
+            sh.iter().flat_map(|x| vec![x.x, x.y, x.z]).collect()
+        };
+        assert_eq!(
+            get_components(&cooked.irradiance_sh),
+            get_components(&environment.irradiance_sh)
+        );
+    }
+}
@@ -0,0 +1,1236 @@
+use crate::asset::error::LoaderError;
+use crate::asset::{animation, material, mesh, model};
+use crate::gl::tex;
+use crate::math;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Sampler names and the MTL keys the OBJ loader reads them from
+const OBJ_TEXTURE_KEYS: [(&str, &str); 8] = [
+    ("uAlbedoMapSampler2D", "map_Kd"),
+    ("uNormalMapSampler2D", "norm"),
+    ("uBumpMapSampler2D", "bump"),
+    ("uMetallicSampler2D", "map_Rm"),
+    ("uRoughnessSampler2D", "map_Pr"),
+    ("uOcclusionMapSampler2D", "map_ao"),
+    ("uEmissiveMapSampler2D", "map_Ke"),
+    ("uLightMapSampler2D", "map_Lm"),
+];
+
+const GLTF_FLOAT: u32 = 5126;
+const GLTF_UNSIGNED_SHORT: u32 = 5123;
+const GLTF_UNSIGNED_INT: u32 = 5125;
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+const GLTF_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+struct TextureReference {
+    uri: String,
+    // Derived textures already have the material factors baked in
+    derived: bool,
+}
+
+pub fn export_host_model(
+    file_path: &Path,
+    host_model: &model::HostModel,
+) -> Result<Vec<LoaderError>, LoaderError> {
+    match file_path.extension().and_then(|x| x.to_str()) {
+        Some("obj") => export_host_model_to_obj(file_path, host_model),
+        Some("gltf") => export_host_model_to_gltf(file_path, host_model),
+        _ => Err(LoaderError::Unsupported(format!(
+            "'{}' is not an OBJ or glTF file",
+            file_path.to_str().unwrap_or_default()
+        ))),
+    }
+}
+
+///////////////////////////////////////////////////////////
+// OBJ
+///////////////////////////////////////////////////////////
+
+pub fn export_host_model_to_obj(
+    file_path: &Path,
+    host_model: &model::HostModel,
+) -> Result<Vec<LoaderError>, LoaderError> {
+    let folder_path = create_export_folder(file_path)?;
+    let mut warnings: Vec<LoaderError> = Vec::new();
+
+    if host_model.skeleton.is_some() || !host_model.animations.is_empty() {
+        warnings.push(LoaderError::Unsupported(
+            "OBJ does not support skeletons and animations, they are not exported.".to_string(),
+        ));
+    }
+    for host_mesh in host_model.meshes.iter() {
+        let dropped: Vec<&str> = vec![
+            ("second uv set", !host_mesh.uvs1.is_empty()),
+            ("vertex colors", !host_mesh.colors.is_empty()),
+            ("morph targets", !host_mesh.morph_targets.is_empty()),
+            ("levels of detail", !host_mesh.lods.is_empty()),
+        ]
+        .into_iter()
+        .filter(|x| x.1)
+        .map(|x| x.0)
+        .collect();
+        if !dropped.is_empty() {
+            warnings.push(LoaderError::Unsupported(format!(
+                "Mesh '{}': OBJ does not support {}, they are not exported.",
+                host_mesh.name,
+                dropped.join(", ")
+            )));
+        }
+    }
+
+    let material_names = create_unique_names(
+        host_model
+            .materials
+            .iter()
+            .map(|x| x.name.replace(char::is_whitespace, "_")),
+        "Material",
+    );
+    let mesh_names = create_unique_names(host_model.meshes.iter().map(|x| x.name.clone()), "Mesh");
+    let material_path = file_path.with_extension("mtl");
+
+    let mut obj = String::new();
+    writeln!(
+        obj,
+        "mtllib {}",
+        material_path
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap_or_default()
+    )
+    .unwrap();
+
+    let mut offset = 1;
+    for (host_mesh, name) in host_model.meshes.iter().zip(mesh_names.iter()) {
+        writeln!(obj, "o {}", name).unwrap();
+        for v in &host_mesh.vertices {
+            writeln!(obj, "v {} {} {}", v.x, v.y, v.z).unwrap();
+        }
+        for uv in &host_mesh.uvs {
+            writeln!(obj, "vt {} {}", uv.x, uv.y).unwrap();
+        }
+        for n in &host_mesh.normals {
+            writeln!(obj, "vn {} {} {}", n.x, n.y, n.z).unwrap();
+        }
+
+        if let Some(name) = material_names.get(host_mesh.material_index) {
+            writeln!(obj, "usemtl {}", name).unwrap();
+        }
+        for triangle in host_mesh.indices.chunks_exact(3) {
+            let corners: Vec<String> = triangle
+                .iter()
+                .map(|i| {
+                    let i = i.x as usize + offset;
+                    match (host_mesh.uvs.is_empty(), host_mesh.normals.is_empty()) {
+                        (false, false) => format!("{}/{}/{}", i, i, i),
+                        (true, false) => format!("{}//{}", i, i),
+                        (false, true) => format!("{}/{}", i, i),
+                        (true, true) => format!("{}", i),
+                    }
+                })
+                .collect();
+            writeln!(obj, "f {}", corners.join(" ")).unwrap();
+        }
+
+        offset += host_mesh.vertices.len();
+    }
+
+    let mut mtl = String::new();
+    for (host_material, name) in host_model.materials.iter().zip(material_names.iter()) {
+        let albedo = get_property_3f(host_material, "uScalarAlbedoVec3f", math::zero_vec3());
+        let emissive = get_property_3f(host_material, "uScalarEmissiveVec3f", math::zero_vec3());
+
+        writeln!(mtl, "newmtl {}", name).unwrap();
+        writeln!(mtl, "Kd {} {} {}", albedo.x, albedo.y, albedo.z).unwrap();
+        writeln!(mtl, "Ks 0 0 0").unwrap();
+        writeln!(mtl, "Ke {} {} {}", emissive.x, emissive.y, emissive.z).unwrap();
+        writeln!(
+            mtl,
+            "Pr {}",
+            get_property_1f(host_material, "uScalarRoughnessVec1f", 1.)
+        )
+        .unwrap();
+        writeln!(
+            mtl,
+            "Pm {}",
+            get_property_1f(host_material, "uScalarMetalnessVec1f", 0.)
+        )
+        .unwrap();
+        writeln!(
+            mtl,
+            "d {}",
+            get_property_1f(host_material, "uScalarOpacityVec1f", 1.)
+        )
+        .unwrap();
+        writeln!(
+            mtl,
+            "uv_ao {}",
+            get_property_1u(host_material, "uOcclusionUVSetUint", 0)
+        )
+        .unwrap();
+        writeln!(
+            mtl,
+            "uv_Lm {}",
+            get_property_1u(host_material, "uLightMapUVSetUint", 1)
+        )
+        .unwrap();
+
+        for &(sampler_name, key) in &OBJ_TEXTURE_KEYS {
+            let texture = match get_sampler(host_material, sampler_name) {
+                Some(texture) => texture,
+                None => continue,
+            };
+            match create_texture_reference(texture, &folder_path) {
+                Some(reference) if !reference.derived => {
+                    writeln!(mtl, "{} {}", key, reference.uri).unwrap()
+                }
+                _ => warnings.push(create_texture_warning(host_material, sampler_name)),
+            }
+        }
+        writeln!(mtl).unwrap();
+    }
+
+    write_export_file(file_path, obj.as_bytes())?;
+    write_export_file(&material_path, mtl.as_bytes())?;
+
+    Ok(warnings)
+}
+
+///////////////////////////////////////////////////////////
+// glTF
+///////////////////////////////////////////////////////////
+
+struct GltfBuffer {
+    data: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl GltfBuffer {
+    fn add_accessor(
+        &mut self,
+        data: &[u8],
+        component_type: u32,
+        count: usize,
+        accessor_type: &str,
+        target: Option<u32>,
+        bounds: Option<(Vec<f32>, Vec<f32>)>,
+    ) -> usize {
+        while !self.data.len().is_multiple_of(4) {
+            self.data.push(0);
+        }
+
+        let mut buffer_view = json!({
+            "buffer": 0,
+            "byteOffset": self.data.len(),
+            "byteLength": data.len(),
+        });
+        if let Some(target) = target {
+            buffer_view["target"] = json!(target);
+        }
+        self.data.extend_from_slice(data);
+        self.buffer_views.push(buffer_view);
+
+        let mut accessor = json!({
+            "bufferView": self.buffer_views.len() - 1,
+            "componentType": component_type,
+            "count": count,
+            "type": accessor_type,
+        });
+        if let Some((min, max)) = bounds {
+            accessor["min"] = create_json_f32s(&min);
+            accessor["max"] = create_json_f32s(&max);
+        }
+        self.accessors.push(accessor);
+
+        self.accessors.len() - 1
+    }
+
+    fn add_f32_accessor(
+        &mut self,
+        values: &[f32],
+        component_count: usize,
+        target: Option<u32>,
+        with_bounds: bool,
+    ) -> usize {
+        let bounds = if with_bounds && !values.is_empty() {
+            let mut min = vec![f32::MAX; component_count];
+            let mut max = vec![f32::MIN; component_count];
+            for element in values.chunks_exact(component_count) {
+                for (i, &value) in element.iter().enumerate() {
+                    min[i] = min[i].min(value);
+                    max[i] = max[i].max(value);
+                }
+            }
+            Some((min, max))
+        } else {
+            None
+        };
+
+        let data: Vec<u8> = values
+            .iter()
+            .flat_map(|x| x.to_le_bytes().to_vec())
+            .collect();
+        self.add_accessor(
+            &data,
+            GLTF_FLOAT,
+            values.len() / component_count,
+            get_gltf_accessor_type(component_count),
+            target,
+            bounds,
+        )
+    }
+
+    fn add_vec2_accessor(&mut self, values: &[math::Vec2f]) -> usize {
+        let values: Vec<f32> = values.iter().flat_map(|x| vec![x.x, x.y]).collect();
+        self.add_f32_accessor(&values, 2, Some(GLTF_ARRAY_BUFFER), false)
+    }
+
+    fn add_vec3_accessor(&mut self, values: &[math::Vec3f], with_bounds: bool) -> usize {
+        let values: Vec<f32> = values.iter().flat_map(|x| vec![x.x, x.y, x.z]).collect();
+        self.add_f32_accessor(&values, 3, Some(GLTF_ARRAY_BUFFER), with_bounds)
+    }
+
+    fn add_vec4_accessor(&mut self, values: &[math::Vec4f]) -> usize {
+        let values: Vec<f32> = values
+            .iter()
+            .flat_map(|x| vec![x.x, x.y, x.z, x.w])
+            .collect();
+        self.add_f32_accessor(&values, 4, Some(GLTF_ARRAY_BUFFER), false)
+    }
+
+    fn add_index_accessor(&mut self, indices: &[math::Vec1u]) -> usize {
+        let data: Vec<u8> = indices
+            .iter()
+            .flat_map(|x| x.x.to_le_bytes().to_vec())
+            .collect();
+        self.add_accessor(
+            &data,
+            GLTF_UNSIGNED_INT,
+            indices.len(),
+            "SCALAR",
+            Some(GLTF_ELEMENT_ARRAY_BUFFER),
+            None,
+        )
+    }
+}
+
+pub fn export_host_model_to_gltf(
+    file_path: &Path,
+    host_model: &model::HostModel,
+) -> Result<Vec<LoaderError>, LoaderError> {
+    let folder_path = create_export_folder(file_path)?;
+    let buffer_path = file_path.with_extension("bin");
+    let mut warnings: Vec<LoaderError> = Vec::new();
+
+    let mut buffer = GltfBuffer {
+        data: Vec::new(),
+        buffer_views: Vec::new(),
+        accessors: Vec::new(),
+    };
+    let mut images: Vec<String> = Vec::new();
+    let materials: Vec<Value> = host_model
+        .materials
+        .iter()
+        .map(|host_material| {
+            create_gltf_material(host_material, &folder_path, &mut images, &mut warnings)
+        })
+        .collect();
+
+    let skeleton = host_model.skeleton.as_ref();
+    // glTF meshes of every host mesh, the full detail mesh followed by its LODs
+    let mut meshes: Vec<Value> = Vec::new();
+    let mut mesh_lods: Vec<Vec<usize>> = Vec::new();
+    for host_mesh in host_model.meshes.iter() {
+        if host_mesh.material_index >= host_model.materials.len() {
+            warnings.push(LoaderError::Validation(format!(
+                "Mesh '{}' material index is out of range, it is exported without a material.",
+                host_mesh.name
+            )));
+        }
+        let gltf_meshes = create_gltf_mesh(host_mesh, host_model.materials.len(), &mut buffer);
+        mesh_lods.push((meshes.len()..meshes.len() + gltf_meshes.len()).collect());
+        meshes.extend(gltf_meshes);
+    }
+
+    let create_mesh_node = |host_mesh: &mesh::HostMesh, mesh: usize| {
+        let mut node = json!({ "name": host_mesh.name, "mesh": mesh });
+        if skeleton.is_some() && !host_mesh.joint_indices.is_empty() {
+            node["skin"] = json!(0);
+        }
+        node
+    };
+
+    // Mesh nodes come first, followed by the LOD nodes and the joint hierarchy
+    let mut nodes: Vec<Value> = Vec::new();
+    let mut scene_nodes: Vec<usize> = Vec::new();
+    for (i, (host_mesh, lods)) in host_model.meshes.iter().zip(mesh_lods.iter()).enumerate() {
+        nodes.push(create_mesh_node(host_mesh, lods[0]));
+        scene_nodes.push(i);
+    }
+
+    // MSFT_lod lists the lower detail nodes on the full detail one, they stay out of the scene
+    for (i, (host_mesh, lods)) in host_model.meshes.iter().zip(mesh_lods.iter()).enumerate() {
+        if lods.len() < 2 {
+            continue;
+        }
+        let ids: Vec<usize> = lods[1..]
+            .iter()
+            .map(|&mesh| {
+                nodes.push(create_mesh_node(host_mesh, mesh));
+                nodes.len() - 1
+            })
+            .collect();
+        nodes[i]["extensions"] = json!({ "MSFT_lod": { "ids": ids } });
+    }
+
+    let mut skins: Vec<Value> = Vec::new();
+    let mut joint_nodes: Vec<usize> = Vec::new();
+    if let Some(skeleton) = skeleton {
+        joint_nodes = create_gltf_joint_nodes(skeleton, &mut nodes, &mut scene_nodes);
+
+        let inverse_bind_matrices: Vec<f32> = skeleton
+            .joints
+            .iter()
+            .flat_map(|x| convert_to_gltf_matrix(&x.inverse_bind_matrix).to_vec())
+            .collect();
+        let accessor = buffer.add_f32_accessor(&inverse_bind_matrices, 16, None, false);
+        skins.push(json!({ "joints": joint_nodes, "inverseBindMatrices": accessor }));
+    }
+
+    let animations: Vec<Value> = if joint_nodes.is_empty() {
+        if !host_model.animations.is_empty() {
+            warnings.push(LoaderError::Unsupported(
+                "Animations without a skeleton are not exported.".to_string(),
+            ));
+        }
+        Vec::new()
+    } else {
+        host_model
+            .animations
+            .iter()
+            .map(|clip| create_gltf_animation(clip, &joint_nodes, &mut buffer))
+            .collect()
+    };
+
+    let textures: Vec<Value> = (0..images.len()).map(|i| json!({ "source": i })).collect();
+    let images: Vec<Value> = images.iter().map(|uri| json!({ "uri": uri })).collect();
+
+    let mut gltf = json!({
+        "asset": { "version": "2.0", "generator": "simple_renderer_rust" },
+        "scene": 0,
+        "scenes": [{ "nodes": scene_nodes }],
+    });
+    // Not required, viewers without the extension draw the full detail meshes
+    if mesh_lods.iter().any(|x| x.len() > 1) {
+        gltf["extensionsUsed"] = json!(["MSFT_lod"]);
+    }
+    for (name, items) in vec![
+        ("nodes", nodes),
+        ("meshes", meshes),
+        ("materials", materials),
+        ("textures", textures),
+        ("images", images),
+        ("skins", skins),
+        ("animations", animations),
+        ("accessors", buffer.accessors),
+        ("bufferViews", buffer.buffer_views),
+    ] {
+        if !items.is_empty() {
+            gltf[name] = Value::Array(items);
+        }
+    }
+    // A buffer must not be empty
+    if !buffer.data.is_empty() {
+        gltf["buffers"] = json!([{
+            "uri": buffer_path.file_name().unwrap().to_str().unwrap_or_default(),
+            "byteLength": buffer.data.len(),
+        }]);
+        write_export_file(&buffer_path, &buffer.data)?;
+    }
+
+    let gltf = serde_json::to_string_pretty(&gltf).map_err(|error| {
+        LoaderError::Parse(format!(
+            "Failed to serialize '{}' '{}'",
+            file_path.to_str().unwrap_or_default(),
+            error
+        ))
+    })?;
+    write_export_file(file_path, gltf.as_bytes())?;
+
+    Ok(warnings)
+}
+
+// Returns the full detail mesh followed by one mesh per LOD, the LODs share every
+// vertex accessor and only have their own indices
+fn create_gltf_mesh(
+    host_mesh: &mesh::HostMesh,
+    material_count: usize,
+    buffer: &mut GltfBuffer,
+) -> Vec<Value> {
+    let mut attributes = Map::new();
+    let mut add_attribute = |name: &str, accessor: usize| {
+        attributes.insert(name.to_string(), json!(accessor));
+    };
+
+    add_attribute(
+        "POSITION",
+        buffer.add_vec3_accessor(&host_mesh.vertices, true),
+    );
+    if !host_mesh.normals.is_empty() {
+        add_attribute(
+            "NORMAL",
+            buffer.add_vec3_accessor(&host_mesh.normals, false),
+        );
+    }
+    if !host_mesh.tangents.is_empty() && host_mesh.tangents.len() == host_mesh.normals.len() {
+        // Undoes the bitangent mirroring of the uv flip on load
+        let tangents: Vec<math::Vec4f> = host_mesh
+            .tangents
+            .iter()
+            .zip(host_mesh.normals.iter())
+            .zip(host_mesh.bitangents.iter())
+            .map(|((&t, &n), &b)| {
+                let sign = if math::dot_vec3(math::cross(n, t), b) < 0. {
+                    1.
+                } else {
+                    -1.
+                };
+                math::Vec4f::new(t.x, t.y, t.z, sign)
+            })
+            .collect();
+        add_attribute("TANGENT", buffer.add_vec4_accessor(&tangents));
+    }
+    for (i, uvs) in [&host_mesh.uvs, &host_mesh.uvs1].iter().enumerate() {
+        if !uvs.is_empty() {
+            let uvs: Vec<math::Vec2f> = uvs
+                .iter()
+                .map(|uv| math::Vec2f::new(uv.x, 1. - uv.y))
+                .collect();
+            add_attribute(&format!("TEXCOORD_{}", i), buffer.add_vec2_accessor(&uvs));
+        }
+    }
+    if !host_mesh.colors.is_empty() {
+        add_attribute("COLOR_0", buffer.add_vec4_accessor(&host_mesh.colors));
+    }
+    if !host_mesh.joint_indices.is_empty() {
+        let data: Vec<u8> = host_mesh
+            .joint_indices
+            .iter()
+            .flat_map(|x| vec![x.x, x.y, x.z, x.w])
+            .flat_map(|x| (x as u16).to_le_bytes().to_vec())
+            .collect();
+        add_attribute(
+            "JOINTS_0",
+            buffer.add_accessor(
+                &data,
+                GLTF_UNSIGNED_SHORT,
+                host_mesh.joint_indices.len(),
+                "VEC4",
+                Some(GLTF_ARRAY_BUFFER),
+                None,
+            ),
+        );
+        add_attribute(
+            "WEIGHTS_0",
+            buffer.add_vec4_accessor(&host_mesh.joint_weights),
+        );
+    }
+
+    let targets: Vec<Value> = host_mesh
+        .morph_targets
+        .iter()
+        .map(|target| {
+            let mut target_attributes = json!({
+                "POSITION": buffer.add_vec3_accessor(&target.position_deltas, true),
+            });
+            if !target.normal_deltas.is_empty() {
+                target_attributes["NORMAL"] =
+                    json!(buffer.add_vec3_accessor(&target.normal_deltas, false));
+            }
+            target_attributes
+        })
+        .collect();
+
+    let indices = buffer.add_index_accessor(&host_mesh.indices);
+
+    let mut primitive = json!({ "attributes": attributes, "indices": indices });
+    if host_mesh.material_index < material_count {
+        primitive["material"] = json!(host_mesh.material_index);
+    }
+    if !targets.is_empty() {
+        primitive["targets"] = Value::Array(targets);
+    }
+
+    let mut gltf_mesh = json!({ "name": host_mesh.name, "primitives": [primitive.clone()] });
+    if !host_mesh.morph_targets.is_empty() {
+        let target_names: Vec<&str> = host_mesh
+            .morph_targets
+            .iter()
+            .map(|x| x.name.as_str())
+            .collect();
+        gltf_mesh["weights"] = create_json_f32s(&host_mesh.morph_weights);
+        gltf_mesh["extras"] = json!({ "targetNames": target_names });
+    }
+
+    let mut gltf_meshes = vec![gltf_mesh];
+    for (i, lod) in host_mesh.lods.iter().enumerate() {
+        let mut lod_primitive = primitive.clone();
+        lod_primitive["indices"] = json!(buffer.add_index_accessor(&lod.indices));
+
+        let mut lod_mesh = gltf_meshes[0].clone();
+        lod_mesh["name"] = json!(format!("{}_LOD{}", host_mesh.name, i + 1));
+        lod_mesh["primitives"] = json!([lod_primitive]);
+        lod_mesh["extras"]["lodError"] = create_json_f32(lod.error);
+        gltf_meshes.push(lod_mesh);
+    }
+
+    gltf_meshes
+}
+
+fn create_gltf_material(
+    host_material: &material::HostMaterial,
+    folder_path: &Path,
+    images: &mut Vec<String>,
+    warnings: &mut Vec<LoaderError>,
+) -> Value {
+    let mut add_texture = |sampler_name: &str, warnings: &mut Vec<LoaderError>| {
+        let reference = get_sampler(host_material, sampler_name)
+            .and_then(|texture| create_texture_reference(texture, folder_path));
+        if get_sampler(host_material, sampler_name).is_some() && reference.is_none() {
+            warnings.push(create_texture_warning(host_material, sampler_name));
+        }
+        reference.map(|reference| {
+            let index = images
+                .iter()
+                .position(|x| *x == reference.uri)
+                .unwrap_or_else(|| {
+                    images.push(reference.uri.clone());
+                    images.len() - 1
+                });
+            (index, reference.derived)
+        })
+    };
+
+    let albedo = add_texture("uAlbedoMapSampler2D", warnings);
+    let normal = add_texture("uNormalMapSampler2D", warnings);
+    let occlusion = add_texture("uOcclusionMapSampler2D", warnings);
+    let emissive = add_texture("uEmissiveMapSampler2D", warnings);
+    for &sampler_name in &["uBumpMapSampler2D", "uLightMapSampler2D"] {
+        if get_sampler(host_material, sampler_name).is_some() {
+            warnings.push(create_texture_warning(host_material, sampler_name));
+        }
+    }
+
+    // glTF packs metalness and roughness into one texture, they must share a source image
+    let metallic = add_texture("uMetallicSampler2D", warnings);
+    let roughness = add_texture("uRoughnessSampler2D", warnings);
+    let metallic_roughness = match (metallic, roughness) {
+        (Some(metallic), Some(roughness)) if metallic.0 == roughness.0 => Some(metallic),
+        (None, None) => None,
+        _ => {
+            warnings.push(LoaderError::Unsupported(format!(
+                "Material '{}': metallic and roughness textures must come from the same image in glTF, they are not exported.",
+                host_material.name
+            )));
+            None
+        }
+    };
+
+    // The renderer scales albedo and emissive maps by their factors, while metalness and
+    // roughness maps that are plain files are used as is, so their factors are one
+    let factor = |texture: Option<(usize, bool)>, scalar: f32| match texture {
+        Some((_, false)) => 1.,
+        _ => scalar,
+    };
+    let scalar_albedo = get_property_3f(host_material, "uScalarAlbedoVec3f", math::zero_vec3());
+    let scalar_emissive = get_property_3f(host_material, "uScalarEmissiveVec3f", math::zero_vec3());
+    let opacity = get_property_1f(host_material, "uScalarOpacityVec1f", 1.);
+    let base_color = [scalar_albedo.x, scalar_albedo.y, scalar_albedo.z, opacity];
+    let emissive_factor = [scalar_emissive.x, scalar_emissive.y, scalar_emissive.z];
+
+    let mut pbr = json!({
+        "baseColorFactor": create_json_f32s(&base_color),
+        "metallicFactor": create_json_f32(factor(
+            metallic_roughness,
+            get_property_1f(host_material, "uScalarMetalnessVec1f", 0.)
+        )),
+        "roughnessFactor": create_json_f32(factor(
+            metallic_roughness,
+            get_property_1f(host_material, "uScalarRoughnessVec1f", 1.)
+        )),
+    });
+    if let Some((index, _)) = albedo {
+        pbr["baseColorTexture"] = json!({ "index": index });
+    }
+    if let Some((index, _)) = metallic_roughness {
+        pbr["metallicRoughnessTexture"] = json!({ "index": index });
+    }
+
+    let mut gltf_material = json!({
+        "name": host_material.name,
+        "pbrMetallicRoughness": pbr,
+        "emissiveFactor": create_json_f32s(&emissive_factor),
+    });
+    if let Some((index, _)) = normal {
+        gltf_material["normalTexture"] = json!({ "index": index });
+    }
+    if let Some((index, _)) = occlusion {
+        gltf_material["occlusionTexture"] = json!({
+            "index": index,
+            "texCoord": get_property_1u(host_material, "uOcclusionUVSetUint", 0),
+        });
+    }
+    if let Some((index, _)) = emissive {
+        gltf_material["emissiveTexture"] = json!({ "index": index });
+    }
+    if opacity < 1. {
+        gltf_material["alphaMode"] = json!("BLEND");
+    }
+
+    gltf_material
+}
+
+// Joints whose parent transform is not the identity get an extra node holding it
+fn create_gltf_joint_nodes(
+    skeleton: &animation::Skeleton,
+    nodes: &mut Vec<Value>,
+    scene_nodes: &mut Vec<usize>,
+) -> Vec<usize> {
+    let identity = convert_to_gltf_matrix(&math::Mat4x4f::identity());
+
+    let mut joint_nodes: Vec<usize> = Vec::new();
+    let mut attached_nodes: Vec<usize> = Vec::new();
+    let mut offset_nodes: Vec<Option<usize>> = Vec::new();
+    let mut next_node = nodes.len();
+    for joint in &skeleton.joints {
+        let offset_node = if convert_to_gltf_matrix(&joint.parent_transform) != identity {
+            next_node += 1;
+            Some(next_node - 1)
+        } else {
+            None
+        };
+        joint_nodes.push(next_node);
+        attached_nodes.push(offset_node.unwrap_or(next_node));
+        offset_nodes.push(offset_node);
+        next_node += 1;
+    }
+
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, joint) in skeleton.joints.iter().enumerate() {
+        match joint.parent {
+            Some(parent) => children
+                .entry(joint_nodes[parent])
+                .or_default()
+                .push(attached_nodes[i]),
+            None => scene_nodes.push(attached_nodes[i]),
+        }
+    }
+
+    for (i, joint) in skeleton.joints.iter().enumerate() {
+        if offset_nodes[i].is_some() {
+            nodes.push(json!({
+                "name": format!("{} parent", joint.name),
+                "matrix": create_json_f32s(&convert_to_gltf_matrix(&joint.parent_transform)),
+                "children": [joint_nodes[i]],
+            }));
+        }
+
+        let mut node = json!({
+            "name": joint.name,
+            "translation": create_json_f32s(&[
+                joint.translation.x,
+                joint.translation.y,
+                joint.translation.z
+            ]),
+            "rotation": create_json_f32s(&[
+                joint.rotation.x,
+                joint.rotation.y,
+                joint.rotation.z,
+                joint.rotation.w
+            ]),
+            "scale": create_json_f32s(&[joint.scale.x, joint.scale.y, joint.scale.z]),
+        });
+        if let Some(children) = children.get(&joint_nodes[i]) {
+            node["children"] = json!(children);
+        }
+        nodes.push(node);
+    }
+
+    joint_nodes
+}
+
+fn create_gltf_animation(
+    clip: &animation::AnimationClip,
+    joint_nodes: &[usize],
+    buffer: &mut GltfBuffer,
+) -> Value {
+    let mut samplers: Vec<Value> = Vec::new();
+    let mut channels: Vec<Value> = Vec::new();
+
+    for channel in &clip.channels {
+        let input = buffer.add_f32_accessor(&channel.times, 1, None, true);
+        let (path, output) = match &channel.values {
+            animation::ChannelValues::Translations(values) => (
+                "translation",
+                buffer.add_f32_accessor(
+                    &values
+                        .iter()
+                        .flat_map(|x| vec![x.x, x.y, x.z])
+                        .collect::<Vec<f32>>(),
+                    3,
+                    None,
+                    false,
+                ),
+            ),
+            animation::ChannelValues::Rotations(values) => (
+                "rotation",
+                buffer.add_f32_accessor(
+                    &values
+                        .iter()
+                        .flat_map(|x| vec![x.x, x.y, x.z, x.w])
+                        .collect::<Vec<f32>>(),
+                    4,
+                    None,
+                    false,
+                ),
+            ),
+            animation::ChannelValues::Scales(values) => (
+                "scale",
+                buffer.add_f32_accessor(
+                    &values
+                        .iter()
+                        .flat_map(|x| vec![x.x, x.y, x.z])
+                        .collect::<Vec<f32>>(),
+                    3,
+                    None,
+                    false,
+                ),
+            ),
+        };
+        let interpolation = match channel.interpolation {
+            animation::Interpolation::Step => "STEP",
+            animation::Interpolation::Linear => "LINEAR",
+            animation::Interpolation::CubicSpline => "CUBICSPLINE",
+        };
+
+        samplers.push(json!({
+            "input": input,
+            "output": output,
+            "interpolation": interpolation,
+        }));
+        channels.push(json!({
+            "sampler": samplers.len() - 1,
+            "target": { "node": joint_nodes[channel.joint_index], "path": path },
+        }));
+    }
+
+    json!({ "name": clip.name, "samplers": samplers, "channels": channels })
+}
+
+///////////////////////////////////////////////////////////
+// Helpers
+///////////////////////////////////////////////////////////
+
+// File backed textures are named by their path, derived ones append the derivation
+// parameters. Images embedded in a glTF file cannot be referenced.
+fn create_texture_reference(
+    texture: &tex::HostTexture,
+    folder_path: &Path,
+) -> Option<TextureReference> {
+    let mut parts = texture.name.split('|');
+    let path = PathBuf::from(parts.next()?);
+    let parameters: Vec<&str> = parts.collect();
+
+    if !path.is_file()
+        || !parameters
+            .iter()
+            .all(|x| x.starts_with("channel=") || x.starts_with("scale=") || x.starts_with("mips="))
+    {
+        return None;
+    }
+
+    // Generated mips do not change the source image
+    let uri = create_relative_path(&path, folder_path)?;
+    Some(TextureReference {
+        uri: uri.to_str()?.replace('\\', "/"),
+        derived: parameters.iter().any(|x| !x.starts_with("mips=")),
+    })
+}
+
+// Relative to the exported file, so the export can be moved together with its textures
+fn create_relative_path(path: &Path, folder_path: &Path) -> Option<PathBuf> {
+    let path = fs::canonicalize(path).ok()?;
+    let common_count = path
+        .components()
+        .zip(folder_path.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    // Paths on different drives have no relative form
+    if common_count == 0 {
+        return None;
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in folder_path.components().skip(common_count) {
+        relative.push("..");
+    }
+    for component in path.components().skip(common_count) {
+        relative.push(component);
+    }
+    Some(relative)
+}
+
+fn create_texture_warning(
+    host_material: &material::HostMaterial,
+    sampler_name: &str,
+) -> LoaderError {
+    LoaderError::Unsupported(format!(
+        "Material '{}': texture '{}' has no exportable source image, it is not exported.",
+        host_material.name, sampler_name
+    ))
+}
+
+fn create_export_folder(file_path: &Path) -> Result<PathBuf, LoaderError> {
+    let folder_path = file_path.parent().unwrap_or(Path::new(""));
+    let folder_path = if folder_path.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        folder_path
+    };
+
+    fs::create_dir_all(folder_path)
+        .and_then(|_| fs::canonicalize(folder_path))
+        .map_err(|error| {
+            LoaderError::Io(format!(
+                "Failed to create folder '{}' '{}'",
+                folder_path.to_str().unwrap_or_default(),
+                error
+            ))
+        })
+}
+
+fn write_export_file(path: &Path, data: &[u8]) -> Result<(), LoaderError> {
+    fs::write(path, data).map_err(|error| {
+        LoaderError::Io(format!(
+            "Failed to write '{}' '{}'",
+            path.to_str().unwrap_or_default(),
+            error
+        ))
+    })
+}
+
+fn create_unique_names<I>(names: I, fallback: &str) -> Vec<String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut used: HashSet<String> = HashSet::new();
+    names
+        .enumerate()
+        .map(|(i, name)| {
+            let mut name = if name.trim().is_empty() {
+                format!("{}_{}", fallback, i)
+            } else {
+                name.trim().to_string()
+            };
+            if used.contains(&name) {
+                name = format!("{}_{}", name, i);
+            }
+            used.insert(name.clone());
+            name
+        })
+        .collect()
+}
+
+fn get_property_1u(host_material: &material::HostMaterial, name: &str, default: u32) -> u32 {
+    host_material
+        .properties_1u
+        .iter()
+        .find(|x| x.name == name)
+        .map_or(default, |x| x.value.x)
+}
+
+fn get_property_1f(host_material: &material::HostMaterial, name: &str, default: f32) -> f32 {
+    host_material
+        .properties_1f
+        .iter()
+        .find(|x| x.name == name)
+        .map_or(default, |x| x.value.x)
+}
+
+fn get_property_3f(
+    host_material: &material::HostMaterial,
+    name: &str,
+    default: math::Vec3f,
+) -> math::Vec3f {
+    host_material
+        .properties_3f
+        .iter()
+        .find(|x| x.name == name)
+        .map_or(default, |x| x.value)
+}
+
+fn get_sampler<'a>(
+    host_material: &'a material::HostMaterial,
+    name: &str,
+) -> Option<&'a tex::HostTexture> {
+    host_material
+        .properties_samplers
+        .iter()
+        .find(|x| x.name == name)
+        .map(|x| &*x.value.texture)
+}
+
+fn convert_to_gltf_matrix(m: &math::Mat4x4f) -> [f32; 16] {
+    [
+        m.r1.x, m.r2.x, m.r3.x, m.r4.x, m.r1.y, m.r2.y, m.r3.y, m.r4.y, m.r1.z, m.r2.z, m.r3.z,
+        m.r4.z, m.r1.w, m.r2.w, m.r3.w, m.r4.w,
+    ]
+}
+
+fn get_gltf_accessor_type(component_count: usize) -> &'static str {
+    match component_count {
+        1 => "SCALAR",
+        2 => "VEC2",
+        3 => "VEC3",
+        4 => "VEC4",
+        _ => "MAT4",
+    }
+}
+
+// JSON has no representation for non-finite numbers
+fn create_json_f32(value: f32) -> Value {
+    if value.is_finite() {
+        json!(value)
+    } else {
+        json!(0)
+    }
+}
+
+fn create_json_f32s(values: &[f32]) -> Value {
+    Value::Array(values.iter().map(|&x| create_json_f32(x)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::{helper, loader, primitives, writer};
+    use std::sync::Arc;
+
+    const LOADER_OPTIONS: loader::LoaderOptions = loader::LoaderOptions {
+        normals_generation: helper::NormalsGeneration::Flat,
+        optimize_meshes: false,
+        generate_lods: false,
+        mip_filter: None,
+    };
+
+    fn create_test_folder() -> PathBuf {
+        std::env::temp_dir().join(format!("exporter_test_{}", uuid::Uuid::new_v4()))
+    }
+
+    // Position, uv and normal of every triangle corner, independent of how the loader indexes them
+    fn create_corners(host_mesh: &mesh::HostMesh) -> Vec<[f32; 8]> {
+        host_mesh
+            .indices
+            .iter()
+            .map(|i| {
+                let (v, uv, n) = (
+                    host_mesh.vertices[i.x as usize],
+                    host_mesh.uvs[i.x as usize],
+                    host_mesh.normals[i.x as usize],
+                );
+                [v.x, v.y, v.z, uv.x, uv.y, n.x, n.y, n.z]
+            })
+            .collect()
+    }
+
+    fn assert_round_trip(exported: &model::HostModel, imported: &model::HostModel) {
+        assert_eq!(imported.meshes.len(), exported.meshes.len());
+        for (a, b) in exported.meshes.iter().zip(imported.meshes.iter()) {
+            assert_eq!(a.indices.len(), b.indices.len());
+            assert_eq!(a.material_index, b.material_index);
+            for (a, b) in create_corners(a).iter().zip(create_corners(b).iter()) {
+                for (a, b) in a.iter().zip(b.iter()) {
+                    assert!((a - b).abs() < 1e-5, "{:?} != {:?}", a, b);
+                }
+            }
+        }
+
+        assert_eq!(imported.materials.len(), exported.materials.len());
+        for (a, b) in exported.materials.iter().zip(imported.materials.iter()) {
+            let albedo = (
+                get_property_3f(a, "uScalarAlbedoVec3f", math::zero_vec3()),
+                get_property_3f(b, "uScalarAlbedoVec3f", math::zero_vec3()),
+            );
+            assert!(math::length_vec3(albedo.0 - albedo.1) < 1e-5);
+            for name in &[
+                "uScalarRoughnessVec1f",
+                "uScalarMetalnessVec1f",
+                "uScalarOpacityVec1f",
+            ] {
+                let (a, b) = (get_property_1f(a, name, -1.), get_property_1f(b, name, -1.));
+                assert!((a - b).abs() < 1e-5, "{}: {} != {}", name, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn obj_round_trip() {
+        let folder_path = create_test_folder();
+        let file_path = folder_path.join("model.obj");
+        for &primitive in &[
+            primitives::Primitive::Cube,
+            primitives::Primitive::Icosphere,
+        ] {
+            let exported = primitives::create_primitive_host_model(primitive, 16);
+            let warnings = export_host_model(&file_path, &exported).unwrap();
+            assert!(warnings.is_empty());
+            assert!(file_path.with_extension("mtl").is_file());
+
+            let imported = loader::load_host_model_from_obj(&file_path, &LOADER_OPTIONS).unwrap();
+            assert_round_trip(&exported, &imported);
+        }
+        fs::remove_dir_all(&folder_path).unwrap();
+    }
+
+    #[test]
+    fn gltf_round_trip() {
+        let folder_path = create_test_folder();
+        let file_path = folder_path.join("model.gltf");
+        for &primitive in &[
+            primitives::Primitive::Cube,
+            primitives::Primitive::Icosphere,
+        ] {
+            let exported = primitives::create_primitive_host_model(primitive, 16);
+            let warnings = export_host_model(&file_path, &exported).unwrap();
+            assert!(warnings.is_empty());
+
+            let imported = loader::load_host_model_from_gltf(&file_path, &LOADER_OPTIONS).unwrap();
+            assert_round_trip(&exported, &imported);
+        }
+        fs::remove_dir_all(&folder_path).unwrap();
+    }
+
+    #[test]
+    fn gltf_without_meshes_has_no_buffer() {
+        let folder_path = create_test_folder();
+        let file_path = folder_path.join("empty.gltf");
+        let mut host_model =
+            primitives::create_primitive_host_model(primitives::Primitive::Cube, 16);
+        host_model.meshes = std::sync::Arc::new(Vec::new());
+        export_host_model(&file_path, &host_model).unwrap();
+
+        let gltf: Value = serde_json::from_slice(&fs::read(&file_path).unwrap()).unwrap();
+        assert!(gltf.get("buffers").is_none());
+        assert!(!file_path.with_extension("bin").exists());
+        fs::remove_dir_all(&folder_path).unwrap();
+    }
+
+    #[test]
+    fn textured_round_trip() {
+        let folder_path = create_test_folder();
+        let texture_path = folder_path.join("textures").join("albedo.png");
+        let texture = tex::HostTexture {
+            name: texture_path.to_str().unwrap().to_string(),
+            width: 2,
+            height: 2,
+            depth: 4,
+            data: tex::HostTextureData::UINT8((0..16).map(|x| x * 16).collect()),
+            mips: Vec::new(),
+            srgb: false,
+        };
+        writer::write_host_texture(&texture, &texture_path).unwrap();
+
+        // The albedo factor stays a material scalar, so both loaders keep the file as is
+        let mut exported = primitives::create_primitive_host_model(primitives::Primitive::Cube, 16);
+        let mut host_material =
+            primitives::create_lookdev_host_material(math::Vec3f::new(0.5, 0.25, 1.), 0.5, 0.);
+        host_material.properties_samplers.push(material::Property {
+            name: "uAlbedoMapSampler2D".to_string(),
+            value: material::HostSampler::new("uAlbedoMapSampler2D", Arc::new(texture)),
+        });
+        exported.materials = Arc::new(vec![host_material]);
+
+        let texture_path = fs::canonicalize(&texture_path).unwrap();
+        for file_name in &["model.obj", "model.gltf"] {
+            let file_path = folder_path.join("export").join(file_name);
+            let warnings = export_host_model(&file_path, &exported).unwrap();
+            assert!(warnings.is_empty());
+
+            let imported = if file_path.extension().unwrap() == "obj" {
+                loader::load_host_model_from_obj(&file_path, &LOADER_OPTIONS).unwrap()
+            } else {
+                loader::load_host_model_from_gltf(&file_path, &LOADER_OPTIONS).unwrap()
+            };
+            assert_round_trip(&exported, &imported);
+            let texture = get_sampler(&imported.materials[0], "uAlbedoMapSampler2D").unwrap();
+            assert_eq!(
+                fs::canonicalize(texture.name.split('|').next().unwrap()).unwrap(),
+                texture_path
+            );
+            assert_eq!((texture.width, texture.height), (2, 2));
+        }
+        fs::remove_dir_all(&folder_path).unwrap();
+    }
+
+    #[test]
+    fn gltf_lods_are_exported() {
+        let folder_path = create_test_folder();
+        let file_path = folder_path.join("model.gltf");
+        let mut exported = primitives::create_primitive_host_model(primitives::Primitive::Cube, 16);
+        let mut meshes = (*exported.meshes).clone();
+        let lod_indices = meshes[0].indices[..6].to_vec();
+        meshes[0].lods = vec![mesh::MeshLod {
+            indices: lod_indices,
+            error: 0.5,
+        }];
+        exported.meshes = Arc::new(meshes);
+
+        let warnings = export_host_model(&file_path, &exported).unwrap();
+        assert!(warnings.is_empty());
+        let gltf: Value = serde_json::from_slice(&fs::read(&file_path).unwrap()).unwrap();
+        assert_eq!(gltf["extensionsUsed"], json!(["MSFT_lod"]));
+        assert_eq!(gltf["scenes"][0]["nodes"], json!([0]));
+
+        let lod_node = gltf["nodes"][0]["extensions"]["MSFT_lod"]["ids"][0]
+            .as_u64()
+            .unwrap() as usize;
+        let lod_mesh = &gltf["meshes"][gltf["nodes"][lod_node]["mesh"].as_u64().unwrap() as usize];
+        let lod_primitive = &lod_mesh["primitives"][0];
+        assert_eq!(
+            lod_primitive["attributes"],
+            gltf["meshes"][0]["primitives"][0]["attributes"]
+        );
+        let lod_accessor = lod_primitive["indices"].as_u64().unwrap() as usize;
+        assert_eq!(gltf["accessors"][lod_accessor]["count"], json!(6));
+
+        // The full detail mesh is what comes back
+        let imported = loader::load_host_model_from_gltf(&file_path, &LOADER_OPTIONS).unwrap();
+        assert_round_trip(&exported, &imported);
+
+        let warnings = export_host_model(&file_path.with_extension("obj"), &exported).unwrap();
+        assert_eq!(warnings.len(), 1);
+        fs::remove_dir_all(&folder_path).unwrap();
+    }
+
+    #[test]
+    fn texture_paths_are_relative() {
+        let folder_path = fs::canonicalize(std::env::temp_dir()).unwrap();
+        let texture_path = folder_path.join(format!("exporter_test_{}.png", uuid::Uuid::new_v4()));
+        fs::write(&texture_path, [0u8]).unwrap();
+
+        let export_path = folder_path.join("a").join("b");
+        let relative = create_relative_path(&texture_path, &export_path).unwrap();
+        assert_eq!(
+            relative,
+            Path::new("..")
+                .join("..")
+                .join(texture_path.file_name().unwrap())
+        );
+        fs::remove_file(&texture_path).unwrap();
+    }
+}
@@ -1,65 +1,275 @@
 use crate::asset::{material, mesh, model};
-use crate::gl::tex;
-use crate::helpers::loader;
+use crate::helpers::primitives;
 use crate::math;
-use std::path::Path;
+use std::collections::HashMap;
 use std::sync::Arc;
 
+// MikkTSpace tangents, the frame baked normal maps expect. Corners of a vertex that end up in
+// different tangent spaces, like the two sides of a mirrored uv seam, get their own vertex.
+// Returns the new indices and the source vertex of every vertex like calculate_normals.
 pub fn calculate_tangents_and_bitangents(
     indices: &mesh::Indices,
     vertices: &mesh::Vertices,
+    normals: &mesh::Normals,
     uvs: &mesh::UVs,
-) -> (mesh::Tangents, mesh::Bitangents) {
-    let mut tangents = mesh::Tangents::new();
-    let mut bitangents = mesh::Bitangents::new();
-
-    for i in (0..indices.len()).step_by(3) {
-        let vert0 = &vertices[indices[i + 0].x as usize];
-        let vert1 = &vertices[indices[i + 1].x as usize];
-        let vert2 = &vertices[indices[i + 2].x as usize];
-
-        let uv0 = &uvs[indices[i + 0].x as usize];
-        let uv1 = &uvs[indices[i + 1].x as usize];
-        let uv2 = &uvs[indices[i + 2].x as usize];
-
-        let delta_pos1 = *vert1 - *vert0;
-        let delta_pos2 = *vert2 - *vert0;
-
-        let delta_uv1 = *uv1 - *uv0;
-        let delta_uv2 = *uv2 - *uv0;
-
-        let r = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
-        let tangent =
-            math::normalize_vec3((delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r);
-        let bitangent =
-            math::normalize_vec3((delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * r);
-
-        // if tangent.x.is_nan() || tangent.y.is_nan() || tangent.z.is_nan() {
-        //     let pass = 0;
-        // }
-
-        // if bitangent.x.is_nan() || bitangent.y.is_nan() || bitangent.z.is_nan() {
-        //     let pass = 0;
-        // }
-
-        // assert!(
-        //     !tangent.x.is_nan() && !tangent.y.is_nan() && !tangent.z.is_nan(),
-        //     "Nan tangent!"
-        // );
-        // assert!(
-        //     !bitangent.x.is_nan() && !bitangent.y.is_nan() && !bitangent.z.is_nan(),
-        //     "Nan bitangent!"
-        // );
-
-        tangents.push(tangent);
-        tangents.push(tangent);
-        tangents.push(tangent);
-        bitangents.push(bitangent);
-        bitangents.push(bitangent);
-        bitangents.push(bitangent);
+) -> Result<(mesh::Tangents, mesh::Bitangents, mesh::Indices, Vec<usize>), String> {
+    if uvs.is_empty() {
+        return Ok((
+            mesh::Tangents::new(),
+            mesh::Bitangents::new(),
+            indices.clone(),
+            (0..vertices.len()).collect(),
+        ));
     }
+    if uvs.len() != vertices.len() {
+        return Err("Every vertex must have a uv to calculate tangents.".to_string());
+    }
+    if normals.len() != vertices.len() {
+        return Err("Every vertex must have a normal to calculate tangents.".to_string());
+    }
+    if !indices.len().is_multiple_of(3) || indices.iter().any(|i| i.x as usize >= vertices.len()) {
+        return Err("Indices must form triangles of existing vertices.".to_string());
+    }
+
+    let mut geometry = TangentGeometry {
+        indices,
+        vertices,
+        normals,
+        uvs,
+        corner_tangents: vec![[0.; 4]; indices.len()],
+    };
+    if !mikktspace::generate_tangents(&mut geometry) {
+        return Err("Failed to generate MikkTSpace tangents.".to_string());
+    }
+
+    // The first frame of a vertex keeps its index, other frames are appended
+    let mut tangents: mesh::Tangents = normals
+        .iter()
+        .map(|&x| calculate_orthogonal_vec3(x))
+        .collect();
+    let mut bitangents: mesh::Bitangents = normals
+        .iter()
+        .zip(&tangents)
+        .map(|(&normal, &tangent)| math::cross(normal, tangent))
+        .collect();
+    let mut new_indices = mesh::Indices::with_capacity(indices.len());
+    let mut remap: Vec<usize> = (0..vertices.len()).collect();
+    let mut vertex_frames: Vec<Option<([u32; 3], bool)>> = vec![None; vertices.len()];
+    let mut split_vertices: HashMap<(u32, [u32; 3], bool), u32> = HashMap::new();
+
+    for (corner, tangent) in indices.iter().zip(&geometry.corner_tangents) {
+        let vertex = corner.x;
+        let normal = normals[vertex as usize];
+
+        // Degenerate triangles without any neighbours get an arbitrary frame
+        let tangent_xyz = math::Vec3f::new(tangent[0], tangent[1], tangent[2]);
+        let tangent_xyz = tangent_xyz - normal * math::dot_vec3(normal, tangent_xyz);
+        let tangent_xyz = if math::length_squared_vec3(tangent_xyz) > f32::EPSILON {
+            math::normalize_vec3(tangent_xyz)
+        } else {
+            calculate_orthogonal_vec3(normal)
+        };
+        // Mirrored uvs flip the handedness of the tangent frame
+        let mirrored = tangent[3] < 0.;
+        let bitangent = math::cross(normal, tangent_xyz) * if mirrored { -1. } else { 1. };
+
+        let frame = (position_key(tangent_xyz), mirrored);
+        let new_vertex = match vertex_frames[vertex as usize] {
+            None => {
+                vertex_frames[vertex as usize] = Some(frame);
+                tangents[vertex as usize] = tangent_xyz;
+                bitangents[vertex as usize] = bitangent;
+                vertex
+            }
+            Some(first_frame) if first_frame == frame => vertex,
+            Some(_) => *split_vertices
+                .entry((vertex, frame.0, frame.1))
+                .or_insert_with(|| {
+                    tangents.push(tangent_xyz);
+                    bitangents.push(bitangent);
+                    remap.push(vertex as usize);
+                    remap.len() as u32 - 1
+                }),
+        };
+        new_indices.push(math::Vec1u::new(new_vertex));
+    }
+
+    Ok((tangents, bitangents, new_indices, remap))
+}
+
+struct TangentGeometry<'a> {
+    indices: &'a mesh::Indices,
+    vertices: &'a mesh::Vertices,
+    normals: &'a mesh::Normals,
+    uvs: &'a mesh::UVs,
+    corner_tangents: Vec<[f32; 4]>,
+}
 
-    return (tangents, bitangents);
+impl<'a> TangentGeometry<'a> {
+    fn get_vertex(&self, face: usize, vert: usize) -> usize {
+        self.indices[face * 3 + vert].x as usize
+    }
+}
+
+impl<'a> mikktspace::Geometry for TangentGeometry<'a> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        let position = self.vertices[self.get_vertex(face, vert)];
+        [position.x, position.y, position.z]
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        let normal = self.normals[self.get_vertex(face, vert)];
+        [normal.x, normal.y, normal.z]
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let uv = self.uvs[self.get_vertex(face, vert)];
+        [uv.x, uv.y]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.corner_tangents[face * 3 + vert] = tangent;
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum NormalsGeneration {
+    Smooth(f32),
+    Flat,
+}
+
+pub fn calculate_normals(
+    indices: &mesh::Indices,
+    vertices: &mesh::Vertices,
+    generation: NormalsGeneration,
+) -> (mesh::Normals, mesh::Indices, Vec<usize>) {
+    let face_normals: Vec<math::Vec3f> = indices
+        .chunks_exact(3)
+        .map(|triangle| {
+            let face_normal = math::cross(
+                vertices[triangle[1].x as usize] - vertices[triangle[0].x as usize],
+                vertices[triangle[2].x as usize] - vertices[triangle[0].x as usize],
+            );
+            if math::length_squared_vec3(face_normal) > f32::EPSILON {
+                math::normalize_vec3(face_normal)
+            } else {
+                math::zero_vec3()
+            }
+        })
+        .collect();
+
+    // Vertices may already be split by uvs, smoothing works on positions
+    let mut position_faces: HashMap<[u32; 3], Vec<(usize, f32)>> = HashMap::new();
+    if let NormalsGeneration::Smooth(_) = generation {
+        for (face, triangle) in indices.chunks_exact(3).enumerate() {
+            for k in 0..3 {
+                let vertex = vertices[triangle[k].x as usize];
+                let angle = calculate_corner_angle(
+                    vertex,
+                    vertices[triangle[(k + 1) % 3].x as usize],
+                    vertices[triangle[(k + 2) % 3].x as usize],
+                );
+                position_faces
+                    .entry(position_key(vertex))
+                    .or_default()
+                    .push((face, angle));
+            }
+        }
+    }
+
+    let mut normals = mesh::Normals::new();
+    let mut new_indices = mesh::Indices::with_capacity(indices.len());
+    let mut remap: Vec<usize> = Vec::new();
+    let mut corner_vertices: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+
+    for (face, triangle) in indices.chunks_exact(3).enumerate() {
+        for corner in triangle {
+            let vertex = corner.x;
+            let normal = match generation {
+                NormalsGeneration::Flat => face_normals[face],
+                NormalsGeneration::Smooth(crease_angle) => {
+                    let crease_cos = crease_angle.cos();
+                    let mut normal_sum = math::zero_vec3();
+                    for &(other_face, angle) in
+                        &position_faces[&position_key(vertices[vertex as usize])]
+                    {
+                        let other_normal = face_normals[other_face];
+                        if other_face == face
+                            || math::dot_vec3(face_normals[face], other_normal) >= crease_cos
+                        {
+                            normal_sum = normal_sum + other_normal * angle;
+                        }
+                    }
+                    normal_sum
+                }
+            };
+            let normal = if math::length_squared_vec3(normal) > f32::EPSILON {
+                math::normalize_vec3(normal)
+            } else {
+                math::Vec3f::new(0., 0., 1.)
+            };
+
+            // Corners sharing a vertex and a normal are welded back together
+            let new_vertex = *corner_vertices
+                .entry((vertex, position_key(normal)))
+                .or_insert_with(|| {
+                    normals.push(normal);
+                    remap.push(vertex as usize);
+                    remap.len() as u32 - 1
+                });
+            new_indices.push(math::Vec1u::new(new_vertex));
+        }
+    }
+
+    (normals, new_indices, remap)
+}
+
+pub fn remap_vertex_attribute<T: Copy>(data: &Vec<T>, remap: &Vec<usize>) -> Vec<T> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+
+    remap.iter().map(|&i| data[i]).collect()
+}
+
+pub fn position_key(position: math::Vec3f) -> [u32; 3] {
+    [
+        position.x.to_bits(),
+        position.y.to_bits(),
+        position.z.to_bits(),
+    ]
+}
+
+fn calculate_corner_angle(corner: math::Vec3f, next: math::Vec3f, prev: math::Vec3f) -> f32 {
+    let edge1 = next - corner;
+    let edge2 = prev - corner;
+    let length = math::length_vec3(edge1) * math::length_vec3(edge2);
+
+    if length < f32::EPSILON {
+        return 0.;
+    }
+
+    (math::dot_vec3(edge1, edge2) / length)
+        .clamp(-1., 1.)
+        .acos()
+}
+
+fn calculate_orthogonal_vec3(normal: math::Vec3f) -> math::Vec3f {
+    let axis = if normal.x.abs() < 0.9 {
+        math::Vec3f::new(1., 0., 0.)
+    } else {
+        math::Vec3f::new(0., 1., 0.)
+    };
+
+    math::normalize_vec3(axis - normal * math::dot_vec3(normal, axis))
 }
 
 #[allow(dead_code)]
@@ -85,43 +295,12 @@ pub fn create_host_triangle_model() -> mesh::HostMesh {
         math::Vec1u::new(2),
     ];
 
-    let (tangents, bitangents) = calculate_tangents_and_bitangents(&indices, &vertices, &uvs);
-
-    mesh::HostMesh::new(
-        "Triangle".to_string(),
-        0,
-        vertices,
-        normals,
-        tangents,
-        bitangents,
-        uvs,
-        indices,
-    )
-}
-
-pub fn create_full_screen_triangle_host_mesh() -> mesh::HostMesh {
-    let vertices: Vec<math::Vec3f> = vec![
-        math::Vec3::new(-1.0, -1.0, 0.5),
-        math::Vec3::new(3.0, -1.0, 0.5),
-        math::Vec3::new(-1.0, 3.0, 0.5),
-    ];
-    let normals = vec![
-        math::Vec3::new(0.0, 0.0, 1.0),
-        math::Vec3::new(0.0, 0.0, 1.0),
-        math::Vec3::new(0.0, 0.0, 1.0),
-    ];
-    let uvs = vec![
-        math::Vec2::new(0.0, 0.0),
-        math::Vec2::new(2.0, 0.0),
-        math::Vec2::new(0.0, 2.0),
-    ];
-    let indices = vec![
-        math::Vec1u { x: 0u32 },
-        math::Vec1u { x: 1u32 },
-        math::Vec1u { x: 2u32 },
-    ];
-
-    let (tangents, bitangents) = calculate_tangents_and_bitangents(&indices, &vertices, &uvs);
+    let (tangents, bitangents, indices, remap) =
+        calculate_tangents_and_bitangents(&indices, &vertices, &normals, &uvs)
+            .expect("The triangle has a uv and a normal for every vertex.");
+    let vertices = remap_vertex_attribute(&vertices, &remap);
+    let normals = remap_vertex_attribute(&normals, &remap);
+    let uvs = remap_vertex_attribute(&uvs, &remap);
 
     mesh::HostMesh::new(
         "Triangle".to_string(),
@@ -136,177 +315,169 @@ pub fn create_full_screen_triangle_host_mesh() -> mesh::HostMesh {
 }
 
 pub fn create_full_screen_triangle_model() -> model::DeviceModel {
-    model::DeviceModel::new(&model::HostModel {
-        meshes: Arc::new(vec![create_full_screen_triangle_host_mesh()]),
-        materials: Arc::new(vec![material::HostMaterial::empty()]),
-    })
-}
-
-#[allow(dead_code)]
-pub fn load_wall() -> model::DeviceModel {
-    let mut device_model =
-        loader::load_device_model_from_obj(Path::new("data/models/quad/quad.obj"));
-    device_model.materials[0]
-        .set_svec1f("uScalarRoughnessVec1f", math::Vec1f::new(1.))
-        .unwrap();
-    device_model.materials[0]
-        .set_svec1f("uScalarMetalnessVec1f", math::Vec1f::new(0.))
-        .unwrap();
-    device_model.materials[0]
-        .set_svec3f("uScalarAlbedoVec3f", math::Vec3f::new(1., 1., 1.))
-        .unwrap();
-
-    device_model
-}
-
-#[allow(dead_code)]
-pub fn load_studio() -> (model::DeviceModel, Vec<math::Mat4x4f>) {
-    let mut device_model =
-        loader::load_device_model_from_obj(Path::new("data/models/studio/studio.obj"));
-    device_model.materials[0]
-        .set_svec1f("uScalarRoughnessVec1f", math::Vec1f::new(1.))
-        .unwrap();
-    device_model.materials[0]
-        .set_svec1f("uScalarMetalnessVec1f", math::Vec1f::new(0.))
-        .unwrap();
-    device_model.materials[0]
-        .set_svec3f("uScalarAlbedoVec3f", math::Vec3f::new(1., 1., 1.))
-        .unwrap();
-    let trasforms = vec![
-        math::x_rotation_mat4x4(3.14_f32),
-        math::x_rotation_mat4x4(3.14_f32),
-    ];
-
-    (device_model, trasforms)
-}
-
-#[allow(dead_code)]
-pub fn load_well() -> (model::DeviceModel, Vec<math::Mat4x4f>) {
-    let mut device_model =
-        loader::load_device_model_from_obj(Path::new("data/models/well/well.obj"));
-    device_model.materials[0]
-        .set_svec1f("uScalarRoughnessVec1f", math::Vec1f::new(1.))
-        .unwrap();
-    device_model.materials[0]
-        .set_svec1f("uScalarMetalnessVec1f", math::Vec1f::new(0.))
-        .unwrap();
-    device_model.materials[0]
-        .set_svec3f("uScalarAlbedoVec3f", math::Vec3f::new(0., 0., 0.))
-        .unwrap();
-    let trasforms = vec![math::Mat4x4f::identity()];
-
-    (device_model, trasforms)
+    model::DeviceModel::new(
+        &model::HostModel {
+            meshes: Arc::new(vec![primitives::create_full_screen_triangle_host_mesh()]),
+            materials: Arc::new(vec![material::HostMaterial::empty()]),
+            skeleton: None,
+            animations: Arc::new(Vec::new()),
+            warnings: Vec::new(),
+        },
+        mesh::VertexLayout::Separate,
+    )
 }
 
 #[allow(dead_code)]
-pub fn load_cylinder() -> (model::DeviceModel, Vec<math::Mat4x4f>) {
-    let mut device_model =
-        loader::load_device_model_from_obj(Path::new("data/models/cylinder/cylinder.obj"));
-    device_model.materials[0]
-        .set_svec1f("uScalarRoughnessVec1f", math::Vec1f::new(0.))
-        .unwrap();
-    device_model.materials[0]
-        .set_svec1f("uScalarMetalnessVec1f", math::Vec1f::new(0.))
-        .unwrap();
-    device_model.materials[0]
-        .set_svec3f("uScalarAlbedoVec3f", math::Vec3f::new(0., 0., 0.))
-        .unwrap();
-    let trasforms = vec![
-        math::tranlation_mat4x4(math::Vec3f::new(0., 0., 0.))
-            * math::scale_mat4x4(math::Vec3f::new(100., 100., 100.)),
-    ];
-
-    (device_model, trasforms)
+pub fn load_skybox() -> model::DeviceModel {
+    model::DeviceModel::new(
+        &model::HostModel {
+            meshes: Arc::new(vec![primitives::create_cube_host_mesh(2., 1)]),
+            materials: Arc::new(vec![material::HostMaterial::empty()]),
+            skeleton: None,
+            animations: Arc::new(Vec::new()),
+            warnings: Vec::new(),
+        },
+        mesh::VertexLayout::Separate,
+    )
 }
 
-// #[allow(dead_code)]
-// pub fn load_pbr_sphere() -> (model::DeviceModel, Vec<math::Mat4x4f>) {
-//     let mut device_model =
-//         loader::load_device_model_from_obj(Path::new("data/models/pbr-sphere/sphere.obj"));
-
-//     let mesh = device_model.meshes[0].clone();
-//     let mut material = device_model.materials[0].clone();
-//     material
-//         .set_svec3f("uScalarAlbedoVec3f", math::Vec3f::new(1., 1., 1.))
-//         .unwrap();
-//     material.properties_3f[0].value.data_location.data[0] = math::Vec3f::new(1., 1., 1.);
-//     let mut transforms = Vec::new();
-
-//     device_model.meshes.clear();
-//     device_model.materials.clear();
-
-//     for x in (-10..12).step_by(2) {
-//         for y in (-10..12).step_by(2) {
-//             let mut material = material.clone();
-//             let roughness = (x + 10) as f32 / 20.;
-//             let metalness = (y + 10) as f32 / 20.;
-
-//             material
-//                 .set_svec1f("uScalarRoughnessVec1f", math::Vec1f::new(roughness))
-//                 .unwrap();
-//             material
-//                 .set_svec1f("uScalarMetalnessVec1f", math::Vec1f::new(metalness))
-//                 .unwrap();
-//             device_model.materials.push(material);
-
-//             let mut mesh = mesh.clone();
-//             mesh.material_index = device_model.materials.len() - 1;
-//             device_model.meshes.push(mesh.clone());
-
-//             transforms.push(math::tranlation_mat4x4(math::Vec3f {
-//                 x: x as f32,
-//                 y: y as f32,
-//                 z: 0.,
-//             }));
-//         }
-//     }
-
-//     (device_model, transforms)
-// }
-
-#[allow(dead_code)]
-pub fn load_pbr_spheres() -> (model::DeviceModel, Vec<math::Mat4x4f>) {
-    let device_model =
-        loader::load_device_model_from_obj(Path::new("data/models/pbr-spheres/spheres.obj"));
-    let transforms = vec![
-        math::tranlation_mat4x4(math::Vec3f::new(-25., -25., -65.));
-        device_model.meshes.len()
-    ];
-
-    (device_model, transforms)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: math::Vec3f, expected: math::Vec3f) {
+        assert!(
+            math::length_vec3(actual - expected) < 1e-4,
+            "({}, {}, {}) != ({}, {}, {})",
+            actual.x,
+            actual.y,
+            actual.z,
+            expected.x,
+            expected.y,
+            expected.z
+        );
+    }
 
-#[allow(dead_code)]
-pub fn load_skybox() -> model::DeviceModel {
-    let mut box_model = loader::load_device_model_from_obj(Path::new("data/models/box/box.obj"));
-    box_model.materials = vec![material::DeviceMaterial::empty()];
+    fn assert_orthonormal(normals: &mesh::Normals, tangents: &mesh::Tangents, remap: &[usize]) {
+        for (tangent, &source) in tangents.iter().zip(remap) {
+            assert!((math::length_vec3(*tangent) - 1.).abs() < 1e-4);
+            assert!(math::dot_vec3(*tangent, normals[source]).abs() < 1e-4);
+        }
+    }
 
-    box_model
-}
+    fn create_indices(indices: &[u32]) -> mesh::Indices {
+        indices.iter().map(|&i| math::Vec1u::new(i)).collect()
+    }
 
-#[allow(dead_code)]
-pub fn load_hdri_texture() -> tex::HostTexture {
-    loader::load_host_texture_from_file(
-        &Path::new("data/materials/hdri/quattro_canti/quattro_canti_8k.hdr"),
-        "uHdriSampler2D",
-    )
-    .unwrap()
-}
+    // Two quads side by side in the xy plane, sharing the edge at x = 1
+    fn create_quads(us: [f32; 3]) -> (mesh::Indices, mesh::Vertices, mesh::Normals, mesh::UVs) {
+        let mut vertices = mesh::Vertices::new();
+        let mut uvs = mesh::UVs::new();
+        for &y in &[0., 1.] {
+            for (x, &u) in us.iter().enumerate() {
+                vertices.push(math::Vec3f::new(x as f32, y, 0.));
+                uvs.push(math::Vec2f::new(u, y));
+            }
+        }
+        let normals = vec![math::Vec3f::new(0., 0., 1.); vertices.len()];
+        let indices = create_indices(&[0, 1, 4, 0, 4, 3, 1, 2, 5, 1, 5, 4]);
+        (indices, vertices, normals, uvs)
+    }
 
-#[allow(dead_code)]
-pub fn load_sponza() -> (model::DeviceModel, Vec<math::Mat4x4f>) {
-    let device_model =
-        loader::load_device_model_from_obj(Path::new("data/models/Sponza/sponza.obj"));
-    let transforms = vec![math::Mat4x4f::identity(); device_model.meshes.len()];
+    #[test]
+    fn tangents_follow_uvs() {
+        let (indices, vertices, normals, uvs) = create_quads([0., 0.5, 1.]);
+        let (tangents, bitangents, new_indices, remap) =
+            calculate_tangents_and_bitangents(&indices, &vertices, &normals, &uvs).unwrap();
+
+        // Nothing is mirrored, so no vertex is split
+        assert_eq!(remap, (0..vertices.len()).collect::<Vec<_>>());
+        assert_eq!(new_indices.len(), indices.len());
+        for (tangent, bitangent) in tangents.iter().zip(&bitangents) {
+            assert_near(*tangent, math::Vec3f::new(1., 0., 0.));
+            assert_near(*bitangent, math::Vec3f::new(0., 1., 0.));
+        }
+    }
 
-    (device_model, transforms)
-}
+    #[test]
+    fn mirrored_uvs_split_shared_vertices() {
+        // u runs back from the shared edge, the right quad mirrors the left one
+        let (indices, vertices, normals, uvs) = create_quads([0., 1., 0.]);
+        let (tangents, bitangents, new_indices, remap) =
+            calculate_tangents_and_bitangents(&indices, &vertices, &normals, &uvs).unwrap();
+
+        // Both vertices on the shared edge get one copy per side
+        assert_eq!(tangents.len(), vertices.len() + 2);
+        assert_eq!(remap.iter().filter(|&&i| i == 1 || i == 4).count(), 4);
+        assert_orthonormal(&normals, &tangents, &remap);
+
+        for (corner, (new_corner, old_corner)) in new_indices.iter().zip(&indices).enumerate() {
+            let vertex = new_corner.x as usize;
+            assert_eq!(remap[vertex], old_corner.x as usize);
+
+            let u_direction = if corner < 6 { 1. } else { -1. };
+            assert_near(tangents[vertex], math::Vec3f::new(u_direction, 0., 0.));
+            assert_near(bitangents[vertex], math::Vec3f::new(0., 1., 0.));
+        }
+    }
 
-#[allow(dead_code)]
-pub fn load_bistro_exterior() -> (model::DeviceModel, Vec<math::Mat4x4f>) {
-    let device_model =
-        loader::load_device_model_from_obj(Path::new("data/models/bistro/bistro.obj"));
-    let transforms = vec![math::Mat4x4f::identity(); device_model.meshes.len()];
+    #[test]
+    fn degenerate_triangles_get_orthonormal_frames() {
+        let vertices = vec![
+            math::Vec3f::new(0., 0., 0.),
+            math::Vec3f::new(1., 0., 0.),
+            math::Vec3f::new(0., 1., 0.),
+            // Collinear with the first two
+            math::Vec3f::new(2., 0., 0.),
+        ];
+        let normals = vec![math::Vec3f::new(0., 0., 1.); vertices.len()];
+        // Every uv is the same, there is no uv direction at all
+        let uvs = vec![math::Vec2f::new(0.5, 0.5); vertices.len()];
+        let indices = create_indices(&[0, 1, 2, 0, 1, 3]);
+
+        let (tangents, bitangents, new_indices, remap) =
+            calculate_tangents_and_bitangents(&indices, &vertices, &normals, &uvs).unwrap();
+
+        assert_eq!(new_indices.len(), indices.len());
+        assert_orthonormal(&normals, &tangents, &remap);
+        for ((tangent, bitangent), &source) in tangents.iter().zip(&bitangents).zip(&remap) {
+            let cosine = math::dot_vec3(*bitangent, math::cross(normals[source], *tangent));
+            assert!((cosine.abs() - 1.).abs() < 1e-4);
+        }
+    }
 
-    (device_model, transforms)
+    #[test]
+    fn mismatched_streams_are_errors() {
+        let (indices, vertices, normals, uvs) = create_quads([0., 0.5, 1.]);
+
+        assert!(calculate_tangents_and_bitangents(
+            &indices,
+            &vertices,
+            &normals,
+            &uvs[1..].to_vec()
+        )
+        .is_err());
+        assert!(calculate_tangents_and_bitangents(
+            &indices,
+            &vertices,
+            &mesh::Normals::new(),
+            &uvs
+        )
+        .is_err());
+        assert!(calculate_tangents_and_bitangents(
+            &create_indices(&[0, 1, 6]),
+            &vertices,
+            &normals,
+            &uvs
+        )
+        .is_err());
+
+        // Meshes without uvs have no tangents and keep their vertices
+        let (tangents, bitangents, new_indices, remap) =
+            calculate_tangents_and_bitangents(&indices, &vertices, &normals, &mesh::UVs::new())
+                .unwrap();
+        assert!(tangents.is_empty() && bitangents.is_empty());
+        assert_eq!(new_indices.len(), indices.len());
+        assert_eq!(remap.len(), vertices.len());
+    }
 }
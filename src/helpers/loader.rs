@@ -1,11 +1,18 @@
+extern crate gltf;
 extern crate stb_image;
 extern crate tobj;
-use crate::asset::{material, mesh, model};
+use crate::asset::error::LoaderError;
+use crate::asset::{animation, cache, material, mesh, model};
 use crate::gl::{shader, tex};
+use crate::helpers::container;
 use crate::helpers::helper;
 use crate::helpers::log;
+use crate::helpers::mipmap;
+use crate::helpers::optimizer;
+use crate::helpers::simplifier;
 use crate::math;
 use stb_image::image;
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
@@ -53,20 +60,48 @@ pub fn load_host_shader_program(
     })
 }
 
-pub fn load_device_model_from_obj(path: &Path) -> model::DeviceModel {
-    let host_model = load_host_model_from_obj(path);
-    let device_model = model::DeviceModel::new(&host_model);
+pub fn load_host_texture_from_file(path: &Path, name: &str) -> Result<tex::HostTexture, String> {
+    let source_name = path.to_str().unwrap_or_default();
+    let extension = path
+        .extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    // Block compressed containers keep their mips and bypass stb_image
+    if extension == "ktx2" || extension == "dds" {
+        let data = fs::read(path).map_err(|error| {
+            format!(
+                "Failed to load texture: {}\nI/O error: {}",
+                source_name, error
+            )
+        })?;
+        return container::load_host_texture_from_container(&data, source_name, name);
+    }
 
-    device_model
+    create_host_texture_from_stb_image(image::load(path), source_name, name)
 }
 
-pub fn load_host_texture_from_file(path: &Path, name: &str) -> Result<tex::HostTexture, String> {
-    match image::load(path) {
+pub fn load_host_texture_from_memory(
+    data: &[u8],
+    source_name: &str,
+    name: &str,
+) -> Result<tex::HostTexture, String> {
+    if container::is_texture_container(data) {
+        return container::load_host_texture_from_container(data, source_name, name);
+    }
+
+    create_host_texture_from_stb_image(image::load_from_memory(data), source_name, name)
+}
+
+fn create_host_texture_from_stb_image(
+    load_result: image::LoadResult,
+    source_name: &str,
+    name: &str,
+) -> Result<tex::HostTexture, String> {
+    match load_result {
         image::LoadResult::ImageU8(image) => {
-            log::log_info(format!(
-                "Loaded 8-bit texture: {}",
-                path.to_str().unwrap_or_default()
-            ));
+            log::log_info(format!("Loaded 8-bit texture: {}", source_name));
 
             Ok(tex::HostTexture {
                 name: name.to_string(),
@@ -74,13 +109,12 @@ pub fn load_host_texture_from_file(path: &Path, name: &str) -> Result<tex::HostT
                 height: image.height,
                 depth: image.depth,
                 data: tex::HostTextureData::UINT8(image.data),
+                mips: Vec::new(),
+                srgb: false,
             })
         }
         image::LoadResult::ImageF32(image) => {
-            log::log_info(format!(
-                "Loaded 32-bit texture: {}",
-                path.to_str().unwrap_or_default()
-            ));
+            log::log_info(format!("Loaded 32-bit texture: {}", source_name));
 
             Ok(tex::HostTexture {
                 name: name.to_string(),
@@ -88,81 +122,143 @@ pub fn load_host_texture_from_file(path: &Path, name: &str) -> Result<tex::HostT
                 height: image.height,
                 depth: image.depth,
                 data: tex::HostTextureData::FLOAT32(image.data),
+                mips: Vec::new(),
+                srgb: false,
             })
         }
         image::LoadResult::Error(msg) => {
             let msg = format!(
                 "Failed to load texture: {}\nSTB message: {}",
-                path.to_str().unwrap_or_default(),
-                msg
+                source_name, msg
             );
             Err(msg)
         }
     }
 }
 
-pub fn load_host_model_from_obj(file_path: &Path) -> model::HostModel {
-    assert!(
-        file_path.extension().unwrap() == "obj",
-        "This function shall only load OBJ files.",
-    );
-    assert!(
-        file_path.exists(),
-        format!("File does not exist '{}'", file_path.to_str().unwrap())
-    );
+#[derive(Copy, Clone)]
+pub struct LoaderOptions {
+    pub normals_generation: helper::NormalsGeneration,
+    pub optimize_meshes: bool,
+    pub generate_lods: bool,
+    // Material texture mips are left to the driver when unset
+    pub mip_filter: Option<mipmap::MipFilter>,
+}
 
-    let load_result = tobj::load_obj(&file_path);
-    if let Err(error_type) = &load_result {
-        panic!(format!(
-            "Failed to load obj file '{}' '{}'",
-            file_path.to_str().unwrap(),
-            error_type
-        ));
+pub const DEFAULT_LOADER_OPTIONS: LoaderOptions = LoaderOptions {
+    normals_generation: helper::NormalsGeneration::Smooth(std::f32::consts::FRAC_PI_3),
+    optimize_meshes: true,
+    generate_lods: false,
+    mip_filter: None,
+};
+
+pub fn load_host_model_from_obj(
+    file_path: &Path,
+    options: &LoaderOptions,
+) -> Result<model::HostModel, LoaderError> {
+    if file_path.extension().unwrap_or_default() != "obj" {
+        return Err(LoaderError::Unsupported(format!(
+            "'{}' is not an OBJ file",
+            file_path.to_str().unwrap_or_default()
+        )));
     }
-    let (raw_models, raw_materials) = load_result.unwrap();
+    if !file_path.exists() {
+        return Err(LoaderError::Io(format!(
+            "File does not exist '{}'",
+            file_path.to_str().unwrap_or_default()
+        )));
+    }
+    let folder = file_path.parent().ok_or(LoaderError::Io(format!(
+        "Invalid folder path of '{}'",
+        file_path.to_str().unwrap_or_default()
+    )))?;
+
+    let (raw_models, raw_materials) = tobj::load_obj(&file_path).map_err(|error| {
+        LoaderError::Parse(format!(
+            "Failed to load obj file '{}' '{}'",
+            file_path.to_str().unwrap_or_default(),
+            error
+        ))
+    })?;
 
+    let mut warnings: Vec<LoaderError> = Vec::new();
     let mut materials: Vec<material::HostMaterial> = Vec::new();
     for raw_material in &raw_materials {
-        if let Some(folder) = file_path.parent() {
-            materials.push(create_host_material_from_tobj_material(
-                &folder,
-                raw_material,
-            ));
-        } else {
-            panic!("Failed to load model, folder path was invalid!");
-        }
+        materials.push(create_host_material_from_tobj_material(
+            &folder,
+            raw_material,
+            options.mip_filter,
+            &mut warnings,
+        ));
     }
 
     let mut meshes: Vec<mesh::HostMesh> = Vec::new();
     for raw_model in &raw_models {
-        meshes.push(create_host_mesh_from_tobj_mesh(raw_model));
+        meshes.push(create_host_mesh_from_tobj_mesh(
+            raw_model,
+            materials.len(),
+            options.normals_generation,
+        )?);
     }
 
-    model::HostModel {
+    let mut host_model = model::HostModel {
         meshes: Arc::new(meshes),
         materials: Arc::new(materials),
+        skeleton: None,
+        animations: Arc::new(Vec::new()),
+        warnings,
+    };
+
+    if options.optimize_meshes {
+        host_model = optimizer::optimize_host_model(&host_model);
+    }
+    if options.generate_lods {
+        host_model = simplifier::generate_host_model_lods(&host_model);
     }
+
+    Ok(host_model)
 }
 
-fn create_host_mesh_from_tobj_mesh(raw_model: &tobj::Model) -> mesh::HostMesh {
-    assert!(
-        !raw_model.mesh.positions.is_empty(),
-        "Model must have vertices."
-    );
-    assert!(
-        !raw_model.mesh.indices.is_empty(),
-        "Model must have indices."
-    );
-    assert!(
-        raw_model.mesh.positions.len() == raw_model.mesh.normals.len()
-            || raw_model.mesh.normals.is_empty(),
-        "Every vertex must have a normal if there are normals."
-    );
-    assert!(
-        raw_model.mesh.positions.len() / 3 == raw_model.mesh.texcoords.len() / 2
-            || raw_model.mesh.texcoords.is_empty(),
-        "Every vertex must have a uv if there are uvs."
-    );
+fn create_host_mesh_from_tobj_mesh(
+    raw_model: &tobj::Model,
+    material_count: usize,
+    normals_generation: helper::NormalsGeneration,
+) -> Result<mesh::HostMesh, LoaderError> {
+    let validation_error = |msg: &str| {
+        Err(LoaderError::Validation(format!(
+            "OBJ model '{}': {}",
+            raw_model.name, msg
+        )))
+    };
+
+    if raw_model.mesh.positions.is_empty() {
+        return validation_error("Model must have vertices.");
+    }
+    if raw_model.mesh.indices.is_empty() {
+        return validation_error("Model must have indices.");
+    }
+    if raw_model.mesh.indices.len() % 3 != 0 {
+        return validation_error("Index count must be a multiple of 3.");
+    }
+    if raw_model.mesh.positions.len() != raw_model.mesh.normals.len()
+        && !raw_model.mesh.normals.is_empty()
+    {
+        return validation_error("Every vertex must have a normal if there are normals.");
+    }
+    if raw_model.mesh.positions.len() / 3 != raw_model.mesh.texcoords.len() / 2
+        && !raw_model.mesh.texcoords.is_empty()
+    {
+        return validation_error("Every vertex must have a uv if there are uvs.");
+    }
+    let vertex_count = raw_model.mesh.positions.len() as u32 / 3;
+    if raw_model.mesh.indices.iter().any(|&i| i >= vertex_count) {
+        return validation_error("Index is out of vertex range.");
+    }
+    let material_index = match raw_model.mesh.material_id {
+        Some(material_index) if material_index < material_count => material_index,
+        Some(_) => return validation_error("Material index is out of range."),
+        None => return validation_error("Model does not have material index."),
+    };
 
     let mut vertices = mesh::Vertices::new();
     let mut normals = mesh::Normals::new();
@@ -203,27 +299,41 @@ fn create_host_mesh_from_tobj_mesh(raw_model: &tobj::Model) -> mesh::HostMesh {
         i += 1;
     }
 
-    let (tangents, bitangents) =
-        helper::calculate_tangents_and_bitangents(&indices, &vertices, &uvs);
+    if normals.is_empty() {
+        let (new_normals, new_indices, remap) =
+            helper::calculate_normals(&indices, &vertices, normals_generation);
+        vertices = helper::remap_vertex_attribute(&vertices, &remap);
+        uvs = helper::remap_vertex_attribute(&uvs, &remap);
+        normals = new_normals;
+        indices = new_indices;
+    }
+
+    let (tangents, bitangents, indices, remap) =
+        match helper::calculate_tangents_and_bitangents(&indices, &vertices, &normals, &uvs) {
+            Ok(result) => result,
+            Err(msg) => return validation_error(&msg),
+        };
+    let vertices = helper::remap_vertex_attribute(&vertices, &remap);
+    let normals = helper::remap_vertex_attribute(&normals, &remap);
+    let uvs = helper::remap_vertex_attribute(&uvs, &remap);
 
-    mesh::HostMesh::new(
+    Ok(mesh::HostMesh::new(
         raw_model.name.clone(),
-        raw_model.mesh.material_id.expect(&format!(
-            "Raw OBJ Model: {} Does not have material index",
-            raw_model.name,
-        )),
+        material_index,
         vertices,
         normals,
         tangents,
         bitangents,
         uvs,
         indices,
-    )
+    ))
 }
 
 fn create_host_material_from_tobj_material(
     folder_path: &Path,
     raw_material: &tobj::Material,
+    mip_filter: Option<mipmap::MipFilter>,
+    warnings: &mut Vec<LoaderError>,
 ) -> material::HostMaterial {
     struct TextureLoadInfo<'a> {
         path: PathBuf,
@@ -280,36 +390,152 @@ fn create_host_material_from_tobj_material(
             text_name: "uRoughnessSampler2D",
             bool_name: "uRoughnessAvailableUint",
         },
+        TextureLoadInfo {
+            path: folder_path.join(Path::new(
+                &raw_material
+                    .unknown_param
+                    .get(&"map_ao".to_string())
+                    .unwrap_or(&"".to_string())
+                    .to_string(),
+            )),
+            text_name: "uOcclusionMapSampler2D",
+            bool_name: "uOcclusionMapAvailableUint",
+        },
+        TextureLoadInfo {
+            path: folder_path.join(Path::new(
+                &raw_material
+                    .unknown_param
+                    .get(&"map_Ke".to_string())
+                    .unwrap_or(&"".to_string())
+                    .to_string(),
+            )),
+            text_name: "uEmissiveMapSampler2D",
+            bool_name: "uEmissiveMapAvailableUint",
+        },
+        TextureLoadInfo {
+            path: folder_path.join(Path::new(
+                &raw_material
+                    .unknown_param
+                    .get(&"map_Lm".to_string())
+                    .unwrap_or(&"".to_string())
+                    .to_string(),
+            )),
+            text_name: "uLightMapSampler2D",
+            bool_name: "uLightMapAvailableUint",
+        },
     ]
     .into_iter()
     .collect();
 
-    let pool_size = texture_load_infos.len();
+    // Textures that are not referenced by the material resolve to the folder
     let mut states = Vec::<(String, bool)>::new();
-    let mut textures = Vec::<tex::HostTexture>::new();
+    let (texture_load_infos, unreferenced_infos): (Vec<TextureLoadInfo>, Vec<TextureLoadInfo>) =
+        texture_load_infos
+            .into_iter()
+            .partition(|info| info.path != folder_path);
+    for info in unreferenced_infos {
+        states.push((info.bool_name.to_string(), false));
+    }
+
+    let pool_size = texture_load_infos.len();
+    let mut textures = Vec::<(String, material::HostSampler)>::new();
     if pool_size > 0 {
         let pool = ThreadPool::new(pool_size);
         let (sender, receiver) = channel();
         for info in texture_load_infos {
             let sender = sender.clone();
             pool.execute(move || {
+                let key = cache::create_texture_key(&info.path, "");
                 sender
                     .send((
-                        load_host_texture_from_file(&info.path, &info.text_name),
+                        cache::get_or_load_host_texture(&key, || {
+                            load_host_texture_from_file(&info.path, "")
+                        })
+                        .map(|texture| {
+                            create_host_sampler(info.text_name, texture, mip_filter, None)
+                        }),
+                        info.text_name,
                         info.bool_name,
                     ))
                     .unwrap();
             });
         }
         for texture_load_result in receiver.iter().take(pool_size) {
-            let (result, bool_name) = texture_load_result;
+            let (result, text_name, bool_name) = texture_load_result;
             states.push((bool_name.to_string(), result.is_ok()));
-            if let Ok(texture) = result {
-                textures.push(texture);
+            match result {
+                Ok(sampler) => textures.push((text_name.to_string(), sampler)),
+                Err(msg) => warnings.push(LoaderError::MissingTexture(format!(
+                    "Material '{}': {}",
+                    raw_material.name, msg
+                ))),
             }
         }
     }
 
+    // MTL to PBR conversion rules:
+    //  Kd      -> albedo, scales map_Kd. A black Kd next to map_Kd counts as
+    //             unset and is replaced by white.
+    //  Pr      -> roughness. Without Pr, the Blinn-Phong exponent Ns is mapped
+    //             to perceptual roughness as (2 / (Ns + 2))^(1/4). The GGX
+    //             alpha sqrt(2 / (Ns + 2)) is its square, taken by the shader.
+    //  Pm      -> metalness. Without Pm, a material with black Kd and non-black
+    //             Ks is treated as a metal with Ks as its albedo.
+    //  Ke      -> emissive, scales map_Ke. Without Ke, map_Ke is used as is.
+    //  d / Tr  -> opacity, where Tr = 1 - d. Only kept for the exporters, the
+    //             renderer draws every material opaque.
+    //  uv_ao   -> uv set of map_ao, 0 by default.
+    //  uv_Lm   -> uv set of the map_Lm lightmap, 1 by default.
+    let has_map = |bool_name: &str| states.iter().any(|x| x.0 == bool_name && x.1);
+    let diffuse = math::Vec3f::new(
+        raw_material.diffuse[0],
+        raw_material.diffuse[1],
+        raw_material.diffuse[2],
+    );
+    let diffuse = if has_map("uAlbedoMapAvailableUint")
+        && math::length_squared_vec3(diffuse) < f32::EPSILON
+    {
+        math::Vec3f::new(1., 1., 1.)
+    } else {
+        diffuse
+    };
+    let specular = math::Vec3f::new(
+        raw_material.specular[0],
+        raw_material.specular[1],
+        raw_material.specular[2],
+    );
+    let is_specular_metal = parse_mtl_scalar(raw_material, "Pm").is_none()
+        && math::length_squared_vec3(diffuse) < f32::EPSILON
+        && math::length_squared_vec3(specular) > f32::EPSILON;
+
+    let albedo = if is_specular_metal { specular } else { diffuse };
+    let roughness = parse_mtl_scalar(raw_material, "Pr")
+        .unwrap_or_else(|| (2. / (raw_material.shininess.max(0.) + 2.)).powf(0.25))
+        .clamp(0., 1.);
+    let metalness = parse_mtl_scalar(raw_material, "Pm")
+        .unwrap_or(if is_specular_metal { 1. } else { 0. })
+        .clamp(0., 1.);
+    let emissive =
+        parse_mtl_vec3(raw_material, "Ke").unwrap_or(if has_map("uEmissiveMapAvailableUint") {
+            math::Vec3f::new(1., 1., 1.)
+        } else {
+            math::zero_vec3()
+        });
+    let opacity = parse_mtl_scalar(raw_material, "Tr")
+        .map(|transparency| 1. - transparency)
+        .unwrap_or(raw_material.dissolve)
+        .clamp(0., 1.);
+    let uv_sets = [
+        (
+            "uOcclusionUVSetUint",
+            parse_mtl_scalar(raw_material, "uv_ao").unwrap_or(0.),
+        ),
+        (
+            "uLightMapUVSetUint",
+            parse_mtl_scalar(raw_material, "uv_Lm").unwrap_or(1.),
+        ),
+    ];
+
     material::HostMaterial {
         name: raw_material.name.clone(),
 
@@ -319,35 +545,1138 @@ fn create_host_material_from_tobj_material(
                 name: x.0.clone(),
                 value: math::Vec1u::new(x.1 as u32),
             })
+            .chain(uv_sets.iter().map(|x| material::Property::<math::Vec1u> {
+                name: x.0.to_string(),
+                value: math::Vec1u::new((x.1 >= 1.) as u32),
+            }))
             .collect(),
 
-        //ToDo: Those are default values, need to replace them with
-        //values from the actual raw materials
         properties_1f: vec![
             material::Property {
                 name: "uScalarRoughnessVec1f".to_string(),
-                value: math::Vec1f { x: 0.5 },
+                value: math::Vec1f::new(roughness),
             },
             material::Property {
                 name: "uScalarMetalnessVec1f".to_string(),
-                value: math::Vec1f { x: 0. },
+                value: math::Vec1f::new(metalness),
+            },
+            material::Property {
+                name: "uScalarOpacityVec1f".to_string(),
+                value: math::Vec1f::new(opacity),
             },
         ],
-        properties_3f: vec![material::Property {
-            name: "uScalarAlbedoVec3f".to_string(),
-            value: math::Vec3f {
-                x: 1.,
-                y: 1.,
-                z: 1.,
+        properties_3f: vec![
+            material::Property {
+                name: "uScalarAlbedoVec3f".to_string(),
+                value: albedo,
+            },
+            material::Property {
+                name: "uScalarEmissiveVec3f".to_string(),
+                value: emissive,
             },
-        }],
+        ],
 
         properties_samplers: textures
+            .into_iter()
+            .map(|(name, sampler)| material::Property {
+                name,
+                value: sampler,
+            })
+            .collect(),
+    }
+}
+
+fn parse_mtl_scalar(raw_material: &tobj::Material, key: &str) -> Option<f32> {
+    raw_material
+        .unknown_param
+        .get(key)
+        .and_then(|value| value.split_whitespace().next())
+        .and_then(|value| value.parse().ok())
+}
+
+fn parse_mtl_vec3(raw_material: &tobj::Material, key: &str) -> Option<math::Vec3f> {
+    let values: Vec<f32> = raw_material
+        .unknown_param
+        .get(key)?
+        .split_whitespace()
+        .map(|value| value.parse().ok())
+        .collect::<Option<Vec<f32>>>()?;
+
+    match values.len() {
+        1 => Some(math::Vec3f::new(values[0], values[0], values[0])),
+        3 => Some(math::Vec3f::new(values[0], values[1], values[2])),
+        _ => None,
+    }
+}
+
+pub fn load_host_model_from_gltf(
+    file_path: &Path,
+    options: &LoaderOptions,
+) -> Result<model::HostModel, LoaderError> {
+    let extension = file_path.extension().unwrap_or_default();
+    if extension != "gltf" && extension != "glb" {
+        return Err(LoaderError::Unsupported(format!(
+            "'{}' is not a glTF file",
+            file_path.to_str().unwrap_or_default()
+        )));
+    }
+    if !file_path.exists() {
+        return Err(LoaderError::Io(format!(
+            "File does not exist '{}'",
+            file_path.to_str().unwrap_or_default()
+        )));
+    }
+    let folder_path = file_path.parent().ok_or(LoaderError::Io(format!(
+        "Invalid folder path of '{}'",
+        file_path.to_str().unwrap_or_default()
+    )))?;
+
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(file_path).map_err(|error| {
+        LoaderError::Parse(format!(
+            "Failed to load gltf file '{}' '{}'",
+            file_path.to_str().unwrap_or_default(),
+            error
+        ))
+    })?;
+
+    let mut warnings: Vec<LoaderError> = Vec::new();
+    let buffers = load_gltf_buffers(&document, folder_path, blob)?;
+    let images = load_gltf_images(&document, file_path, &buffers, &mut warnings)?;
+
+    let mut materials: Vec<material::HostMaterial> = document
+        .materials()
+        .map(|raw_material| {
+            create_host_material_from_gltf_material(&raw_material, &images, options.mip_filter)
+        })
+        .collect();
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or(LoaderError::Validation(
+            "glTF file does not contain any scenes.".to_string(),
+        ))?;
+
+    let (skeleton, joint_indices) =
+        create_skeleton_from_gltf(&document, &scene, &buffers, &mut warnings);
+    let animations =
+        create_animation_clips_from_gltf(&document, &buffers, &joint_indices, &mut warnings);
+
+    let context = GltfContext {
+        buffers: &buffers,
+        images: &images,
+        joint_indices: &joint_indices,
+        normals_generation: options.normals_generation,
+    };
+    let mut meshes: Vec<mesh::HostMesh> = Vec::new();
+    let mut default_material_index: Option<usize> = None;
+    for node in scene.nodes() {
+        create_host_meshes_from_gltf_node(
+            &node,
+            &math::Mat4x4f::identity(),
+            "",
+            &context,
+            &mut materials,
+            &mut default_material_index,
+            &mut meshes,
+            &mut warnings,
+        )?;
+    }
+
+    let mut host_model = model::HostModel {
+        meshes: Arc::new(meshes),
+        materials: Arc::new(materials),
+        skeleton: skeleton.map(Arc::new),
+        animations: Arc::new(animations),
+        warnings,
+    };
+
+    if options.optimize_meshes {
+        host_model = optimizer::optimize_host_model(&host_model);
+    }
+    if options.generate_lods {
+        host_model = simplifier::generate_host_model_lods(&host_model);
+    }
+
+    Ok(host_model)
+}
+
+fn load_gltf_buffers(
+    document: &gltf::Document,
+    folder_path: &Path,
+    mut blob: Option<Vec<u8>>,
+) -> Result<Vec<Vec<u8>>, LoaderError> {
+    document
+        .buffers()
+        .map(|buffer| {
+            let mut data = match buffer.source() {
+                gltf::buffer::Source::Bin => blob.take().ok_or(LoaderError::Validation(
+                    "glTF buffer references a missing binary chunk.".to_string(),
+                ))?,
+                gltf::buffer::Source::Uri(uri) => {
+                    load_gltf_uri(folder_path, uri).map_err(|msg| {
+                        LoaderError::Io(format!("Failed to load glTF buffer: {}", msg))
+                    })?
+                }
+            };
+            if data.len() < buffer.length() {
+                return Err(LoaderError::Validation(format!(
+                    "glTF buffer #{} is shorter than declared.",
+                    buffer.index()
+                )));
+            }
+            while data.len() % 4 != 0 {
+                data.push(0);
+            }
+            Ok(data)
+        })
+        .collect()
+}
+
+fn load_gltf_uri(folder_path: &Path, uri: &str) -> Result<Vec<u8>, String> {
+    if uri.starts_with("data:") {
+        let encoded = uri
+            .splitn(2, ";base64,")
+            .nth(1)
+            .ok_or(format!("Unsupported data uri '{}'", uri))?;
+        base64::decode(encoded).map_err(|error| format!("Failed to decode data uri: {}", error))
+    } else {
+        let path = folder_path.join(uri);
+        fs::read(&path).map_err(|error| {
+            format!(
+                "Failed to read file '{}' '{}'",
+                path.to_str().unwrap_or_default(),
+                error
+            )
+        })
+    }
+}
+
+fn load_gltf_images(
+    document: &gltf::Document,
+    file_path: &Path,
+    buffers: &Vec<Vec<u8>>,
+    warnings: &mut Vec<LoaderError>,
+) -> Result<Vec<Option<Arc<tex::HostTexture>>>, LoaderError> {
+    enum ImageSource {
+        File(PathBuf),
+        Memory(String, Vec<u8>),
+    }
+    let folder_path = file_path.parent().unwrap_or(Path::new(""));
+
+    let image_sources = document
+        .images()
+        .map(|image| match image.source() {
+            gltf::image::Source::View { view, .. } => {
+                let begin = view.offset();
+                let data = begin
+                    .checked_add(view.length())
+                    .and_then(|end| buffers.get(view.buffer().index())?.get(begin..end))
+                    .ok_or(LoaderError::Validation(format!(
+                        "glTF image #{} references bytes outside of buffer #{}.",
+                        image.index(),
+                        view.buffer().index()
+                    )))?;
+                Ok(ImageSource::Memory(
+                    format!("glTF image #{}", image.index()),
+                    data.to_vec(),
+                ))
+            }
+            gltf::image::Source::Uri { uri, .. } => {
+                if uri.starts_with("data:") {
+                    Ok(ImageSource::Memory(
+                        format!("glTF image #{}", image.index()),
+                        load_gltf_uri(folder_path, uri).unwrap_or_default(),
+                    ))
+                } else {
+                    Ok(ImageSource::File(folder_path.join(uri)))
+                }
+            }
+        })
+        .collect::<Result<Vec<ImageSource>, LoaderError>>()?;
+
+    let pool_size = image_sources.len();
+    let mut images: Vec<Option<Arc<tex::HostTexture>>> = vec![None; pool_size];
+    if pool_size > 0 {
+        let pool = ThreadPool::new(pool_size.min(8));
+        let (sender, receiver) = channel();
+        for (index, image_source) in image_sources.into_iter().enumerate() {
+            let sender = sender.clone();
+            let file_path = file_path.to_path_buf();
+            pool.execute(move || {
+                let result = match image_source {
+                    ImageSource::File(path) => cache::get_or_load_host_texture(
+                        &cache::create_texture_key(&path, ""),
+                        || load_host_texture_from_file(&path, ""),
+                    ),
+                    ImageSource::Memory(source_name, data) => cache::get_or_load_host_texture(
+                        &cache::create_texture_key(&file_path, &format!("image={}", index)),
+                        || load_host_texture_from_memory(&data, &source_name, ""),
+                    ),
+                };
+                sender.send((index, result)).unwrap();
+            });
+        }
+        for (index, result) in receiver.iter().take(pool_size) {
+            match result {
+                Ok(texture) => images[index] = Some(texture),
+                Err(msg) => warnings.push(LoaderError::MissingTexture(msg)),
+            }
+        }
+    }
+
+    Ok(images)
+}
+
+struct GltfContext<'a> {
+    buffers: &'a Vec<Vec<u8>>,
+    images: &'a Vec<Option<Arc<tex::HostTexture>>>,
+    // Maps glTF node indices to skeleton joint indices
+    joint_indices: &'a HashMap<usize, usize>,
+    normals_generation: helper::NormalsGeneration,
+}
+
+fn create_host_meshes_from_gltf_node(
+    node: &gltf::Node,
+    parent_transform: &math::Mat4x4f,
+    parent_name: &str,
+    context: &GltfContext,
+    materials: &mut Vec<material::HostMaterial>,
+    default_material_index: &mut Option<usize>,
+    meshes: &mut Vec<mesh::HostMesh>,
+    warnings: &mut Vec<LoaderError>,
+) -> Result<(), LoaderError> {
+    let transform = *parent_transform * convert_gltf_matrix(node.transform().matrix());
+    let name = format!(
+        "{}{}",
+        parent_name,
+        node.name()
+            .map(|x| x.to_string())
+            .unwrap_or(format!("Node #{}", node.index()))
+    );
+
+    let skin = node.skin().filter(|_| !context.joint_indices.is_empty());
+
+    if let Some(raw_mesh) = node.mesh() {
+        for raw_primitive in raw_mesh.primitives() {
+            if raw_primitive.mode() != gltf::mesh::Mode::Triangles {
+                warnings.push(LoaderError::Unsupported(format!(
+                    "Skipping glTF primitive #{} of '{}', only triangles are supported.",
+                    raw_primitive.index(),
+                    name
+                )));
+                continue;
+            }
+
+            let material_index = match raw_primitive.material().index() {
+                Some(index) => index,
+                None => *default_material_index.get_or_insert_with(|| {
+                    materials.push(create_host_material_from_gltf_material(
+                        &raw_primitive.material(),
+                        context.images,
+                        None,
+                    ));
+                    materials.len() - 1
+                }),
+            };
+
+            let morph_target_count = raw_primitive.morph_targets().count();
+            if morph_target_count > mesh::MAX_MORPH_TARGET_COUNT {
+                warnings.push(LoaderError::Unsupported(format!(
+                    "glTF primitive #{} of '{}' has {} morph targets, only the first {} are used.",
+                    raw_primitive.index(),
+                    name,
+                    morph_target_count,
+                    mesh::MAX_MORPH_TARGET_COUNT
+                )));
+            }
+
+            meshes.push(create_host_mesh_from_gltf_primitive(
+                format!("{}#{}", name, raw_primitive.index()),
+                material_index,
+                &raw_primitive,
+                &transform,
+                skin.as_ref(),
+                raw_mesh.weights().unwrap_or_default(),
+                context,
+            )?);
+        }
+    }
+
+    for child in node.children() {
+        create_host_meshes_from_gltf_node(
+            &child,
+            &transform,
+            &format!("{}/", name),
+            context,
+            materials,
+            default_material_index,
+            meshes,
+            warnings,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn create_host_mesh_from_gltf_primitive(
+    name: String,
+    material_index: usize,
+    raw_primitive: &gltf::Primitive,
+    transform: &math::Mat4x4f,
+    skin: Option<&gltf::Skin>,
+    morph_weights: &[f32],
+    context: &GltfContext,
+) -> Result<mesh::HostMesh, LoaderError> {
+    let validation_error = |msg: &str| {
+        Err(LoaderError::Validation(format!(
+            "glTF primitive '{}': {}",
+            name, msg
+        )))
+    };
+
+    let reader = raw_primitive.reader(|buffer| Some(&context.buffers[buffer.index()]));
+    let skin = skin.filter(|_| reader.read_joints(0).is_some() && reader.read_weights(0).is_some());
+    // Skinned vertices are moved by the joint matrices, which already include the node hierarchy
+    let transform = if skin.is_some() {
+        math::Mat4x4f::identity()
+    } else {
+        *transform
+    };
+    let transform_3x3 = math::upper_left_mat3x3(transform);
+    let normal_transform = math::transpose_mat3x3(math::inverse_mat3x3(transform_3x3));
+
+    let mut vertices: mesh::Vertices = match reader.read_positions() {
+        Some(positions) => positions
+            .map(|p| {
+                let p = transform * math::Vec4f::new(p[0], p[1], p[2], 1.);
+                math::Vec3f::new(p.x, p.y, p.z)
+            })
+            .collect(),
+        None => return validation_error("Primitive must have positions."),
+    };
+    let mut normals: mesh::Normals = reader
+        .read_normals()
+        .map(|normals| {
+            normals
+                .map(|n| {
+                    math::normalize_vec3(normal_transform * math::Vec3f::new(n[0], n[1], n[2]))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut uvs: mesh::UVs = reader
+        .read_tex_coords(0)
+        .map(|uvs| {
+            uvs.into_f32()
+                .map(|uv| math::Vec2f::new(uv[0], 1. - uv[1]))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut uvs1: mesh::UVs = reader
+        .read_tex_coords(1)
+        .map(|uvs| {
+            uvs.into_f32()
+                .map(|uv| math::Vec2f::new(uv[0], 1. - uv[1]))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut colors: mesh::Colors = reader
+        .read_colors(0)
+        .map(|colors| {
+            colors
+                .into_rgba_f32()
+                .map(|c| math::Vec4f::new(c[0], c[1], c[2], c[3]))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut indices: mesh::Indices = reader
+        .read_indices()
+        .map(|indices| indices.into_u32().map(math::Vec1u::new).collect())
+        .unwrap_or((0..vertices.len() as u32).map(math::Vec1u::new).collect());
+
+    if vertices.is_empty() {
+        return validation_error("Primitive must have vertices.");
+    }
+    if indices.is_empty() || indices.len() % 3 != 0 {
+        return validation_error("Index count must be a non-zero multiple of 3.");
+    }
+    if indices.iter().any(|i| i.x as usize >= vertices.len()) {
+        return validation_error("Index is out of vertex range.");
+    }
+    if !normals.is_empty() && normals.len() != vertices.len() {
+        return validation_error("Every vertex must have a normal if there are normals.");
+    }
+    if !uvs.is_empty() && uvs.len() != vertices.len() {
+        return validation_error("Every vertex must have a uv if there are uvs.");
+    }
+    if !uvs1.is_empty() && uvs1.len() != vertices.len() {
+        return validation_error("Every vertex must have a second uv if there are second uvs.");
+    }
+    if !colors.is_empty() && colors.len() != vertices.len() {
+        return validation_error("Every vertex must have a color if there are colors.");
+    }
+
+    let mut joint_indices = mesh::JointIndices::new();
+    let mut joint_weights = mesh::JointWeights::new();
+    if let (Some(skin), Some(raw_joints), Some(raw_weights)) =
+        (skin, reader.read_joints(0), reader.read_weights(0))
+    {
+        let skin_joints: Vec<usize> = skin.joints().map(|x| x.index()).collect();
+        for j in raw_joints.into_u16() {
+            let mut joints = [0; 4];
+            for k in 0..4 {
+                match skin_joints
+                    .get(j[k] as usize)
+                    .and_then(|x| context.joint_indices.get(x))
+                {
+                    Some(&joint) => joints[k] = joint as u32,
+                    None => return validation_error("Joint index is out of skin range."),
+                }
+            }
+            joint_indices.push(math::Vec4u::new(joints[0], joints[1], joints[2], joints[3]));
+        }
+        joint_weights = raw_weights
+            .into_f32()
+            .map(|w| {
+                let sum = w[0] + w[1] + w[2] + w[3];
+                if sum > 0. {
+                    math::Vec4f::new(w[0] / sum, w[1] / sum, w[2] / sum, w[3] / sum)
+                } else {
+                    math::Vec4f::new(1., 0., 0., 0.)
+                }
+            })
+            .collect();
+
+        if joint_indices.len() != vertices.len() || joint_weights.len() != vertices.len() {
+            return validation_error("Every vertex must have joints and weights if skinned.");
+        }
+    }
+
+    let mut morph_targets: Vec<mesh::MorphTarget> = Vec::new();
+    for (i, (raw_positions, raw_normals, _)) in reader
+        .read_morph_targets()
+        .take(mesh::MAX_MORPH_TARGET_COUNT)
+        .enumerate()
+    {
+        let position_deltas: mesh::Vertices = match raw_positions {
+            Some(positions) => positions
+                .map(|p| transform_3x3 * math::Vec3f::new(p[0], p[1], p[2]))
+                .collect(),
+            None => vec![math::zero_vec3(); vertices.len()],
+        };
+        // Normal deltas stay unnormalized, the shader normalizes the morphed normal
+        let normal_deltas: mesh::Normals = raw_normals
+            .map(|normals| {
+                normals
+                    .map(|n| normal_transform * math::Vec3f::new(n[0], n[1], n[2]))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if position_deltas.len() != vertices.len()
+            || (!normal_deltas.is_empty() && normal_deltas.len() != vertices.len())
+        {
+            return validation_error("Every vertex must have a delta in every morph target.");
+        }
+        morph_targets.push(mesh::MorphTarget {
+            name: format!("Target #{}", i),
+            position_deltas,
+            normal_deltas,
+        });
+    }
+    let morph_weights: Vec<f32> = (0..morph_targets.len())
+        .map(|i| morph_weights.get(i).copied().unwrap_or(0.))
+        .collect();
+
+    // Mirroring transforms flip the triangle winding
+    if math::determinant_mat3x3(transform_3x3) < 0. {
+        for triangle in indices.chunks_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+
+    // Source tangents are only valid together with source normals
+    let source_normals_available = !normals.is_empty();
+    if !source_normals_available {
+        let (new_normals, new_indices, remap) =
+            helper::calculate_normals(&indices, &vertices, context.normals_generation);
+        vertices = helper::remap_vertex_attribute(&vertices, &remap);
+        uvs = helper::remap_vertex_attribute(&uvs, &remap);
+        uvs1 = helper::remap_vertex_attribute(&uvs1, &remap);
+        colors = helper::remap_vertex_attribute(&colors, &remap);
+        joint_indices = helper::remap_vertex_attribute(&joint_indices, &remap);
+        joint_weights = helper::remap_vertex_attribute(&joint_weights, &remap);
+        // Generated normals cannot follow the morph targets
+        for target in &mut morph_targets {
+            target.position_deltas =
+                helper::remap_vertex_attribute(&target.position_deltas, &remap);
+            target.normal_deltas.clear();
+        }
+        normals = new_normals;
+        indices = new_indices;
+    }
+
+    let (tangents, bitangents) = match reader.read_tangents() {
+        Some(raw_tangents) if source_normals_available => {
+            let mut tangents = mesh::Tangents::new();
+            let mut bitangents = mesh::Bitangents::new();
+            for (t, n) in raw_tangents.zip(normals.iter()) {
+                let tangent =
+                    math::normalize_vec3(transform_3x3 * math::Vec3f::new(t[0], t[1], t[2]));
+                // glTF UVs are flipped on load, which mirrors the bitangent
+                tangents.push(tangent);
+                bitangents.push(math::cross(*n, tangent) * -t[3]);
+            }
+            (tangents, bitangents)
+        }
+        _ if !uvs.is_empty() => {
+            let (tangents, bitangents, new_indices, remap) =
+                match helper::calculate_tangents_and_bitangents(&indices, &vertices, &normals, &uvs)
+                {
+                    Ok(result) => result,
+                    Err(msg) => return validation_error(&msg),
+                };
+            vertices = helper::remap_vertex_attribute(&vertices, &remap);
+            normals = helper::remap_vertex_attribute(&normals, &remap);
+            uvs = helper::remap_vertex_attribute(&uvs, &remap);
+            uvs1 = helper::remap_vertex_attribute(&uvs1, &remap);
+            colors = helper::remap_vertex_attribute(&colors, &remap);
+            joint_indices = helper::remap_vertex_attribute(&joint_indices, &remap);
+            joint_weights = helper::remap_vertex_attribute(&joint_weights, &remap);
+            for target in &mut morph_targets {
+                target.position_deltas =
+                    helper::remap_vertex_attribute(&target.position_deltas, &remap);
+                target.normal_deltas =
+                    helper::remap_vertex_attribute(&target.normal_deltas, &remap);
+            }
+            indices = new_indices;
+            (tangents, bitangents)
+        }
+        _ => (mesh::Tangents::new(), mesh::Bitangents::new()),
+    };
+    if !tangents.is_empty() && tangents.len() != vertices.len() {
+        return validation_error("Every vertex must have a tangent if there are tangents.");
+    }
+
+    Ok(mesh::HostMesh::new(
+        name,
+        material_index,
+        vertices,
+        normals,
+        tangents,
+        bitangents,
+        uvs,
+        indices,
+    )
+    .with_uvs1(uvs1)
+    .with_colors(colors)
+    .with_joints(joint_indices, joint_weights)
+    .with_morph_targets(morph_targets, morph_weights))
+}
+
+fn create_skeleton_from_gltf(
+    document: &gltf::Document,
+    scene: &gltf::Scene,
+    buffers: &Vec<Vec<u8>>,
+    warnings: &mut Vec<LoaderError>,
+) -> (Option<animation::Skeleton>, HashMap<usize, usize>) {
+    // Joints shared by several skins keep the inverse bind matrix of the first skin
+    let mut inverse_bind_matrices: HashMap<usize, math::Mat4x4f> = HashMap::new();
+    for skin in document.skins() {
+        let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+        let matrices: Vec<math::Mat4x4f> = reader
+            .read_inverse_bind_matrices()
+            .map(|matrices| matrices.map(convert_gltf_matrix).collect())
+            .unwrap_or_default();
+        for (i, joint) in skin.joints().enumerate() {
+            inverse_bind_matrices.entry(joint.index()).or_insert(
+                matrices
+                    .get(i)
+                    .copied()
+                    .unwrap_or(math::Mat4x4f::identity()),
+            );
+        }
+    }
+    if inverse_bind_matrices.is_empty() {
+        return (None, HashMap::new());
+    }
+
+    let mut joints: Vec<animation::Joint> = Vec::new();
+    let mut joint_indices: HashMap<usize, usize> = HashMap::new();
+    for node in scene.nodes() {
+        collect_gltf_joints(
+            &node,
+            None,
+            &math::Mat4x4f::identity(),
+            &inverse_bind_matrices,
+            &mut joints,
+            &mut joint_indices,
+        );
+    }
+
+    if joints.len() > animation::MAX_JOINT_COUNT {
+        warnings.push(LoaderError::Unsupported(format!(
+            "Skeleton has {} joints but at most {} are supported, meshes are loaded unskinned.",
+            joints.len(),
+            animation::MAX_JOINT_COUNT
+        )));
+        return (None, HashMap::new());
+    }
+
+    (Some(animation::Skeleton { joints }), joint_indices)
+}
+
+fn collect_gltf_joints(
+    node: &gltf::Node,
+    parent: Option<usize>,
+    parent_transform: &math::Mat4x4f,
+    inverse_bind_matrices: &HashMap<usize, math::Mat4x4f>,
+    joints: &mut Vec<animation::Joint>,
+    joint_indices: &mut HashMap<usize, usize>,
+) {
+    let (parent, parent_transform) = match inverse_bind_matrices.get(&node.index()) {
+        Some(inverse_bind_matrix) => {
+            let (t, r, s) = node.transform().decomposed();
+            joints.push(animation::Joint {
+                name: node
+                    .name()
+                    .map(|x| x.to_string())
+                    .unwrap_or(format!("Node #{}", node.index())),
+                parent,
+                parent_transform: *parent_transform,
+                inverse_bind_matrix: *inverse_bind_matrix,
+                translation: math::Vec3f::new(t[0], t[1], t[2]),
+                rotation: math::Vec4f::new(r[0], r[1], r[2], r[3]),
+                scale: math::Vec3f::new(s[0], s[1], s[2]),
+            });
+            joint_indices.insert(node.index(), joints.len() - 1);
+            (Some(joints.len() - 1), math::Mat4x4f::identity())
+        }
+        None => (
+            parent,
+            *parent_transform * convert_gltf_matrix(node.transform().matrix()),
+        ),
+    };
+
+    for child in node.children() {
+        collect_gltf_joints(
+            &child,
+            parent,
+            &parent_transform,
+            inverse_bind_matrices,
+            joints,
+            joint_indices,
+        );
+    }
+}
+
+fn create_animation_clips_from_gltf(
+    document: &gltf::Document,
+    buffers: &Vec<Vec<u8>>,
+    joint_indices: &HashMap<usize, usize>,
+    warnings: &mut Vec<LoaderError>,
+) -> Vec<animation::AnimationClip> {
+    let mut clips: Vec<animation::AnimationClip> = Vec::new();
+
+    for raw_animation in document.animations() {
+        let name = raw_animation
+            .name()
+            .map(|x| x.to_string())
+            .unwrap_or(format!("Animation #{}", raw_animation.index()));
+
+        let mut channels: Vec<animation::Channel> = Vec::new();
+        let mut skipped_channel_count = 0;
+        let mut skipped_morph_channel_count = 0;
+        for (channel_index, raw_channel) in raw_animation.channels().enumerate() {
+            let reader = raw_channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let joint_index = joint_indices.get(&raw_channel.target().node().index());
+            let times: Vec<f32> = reader
+                .read_inputs()
+                .map(|times| times.collect())
+                .unwrap_or_default();
+            let values = match reader.read_outputs() {
+                Some(gltf::animation::util::ReadOutputs::Translations(values)) => {
+                    animation::ChannelValues::Translations(
+                        values.map(|v| math::Vec3f::new(v[0], v[1], v[2])).collect(),
+                    )
+                }
+                Some(gltf::animation::util::ReadOutputs::Rotations(values)) => {
+                    animation::ChannelValues::Rotations(
+                        values
+                            .into_f32()
+                            .map(|v| math::Vec4f::new(v[0], v[1], v[2], v[3]))
+                            .collect(),
+                    )
+                }
+                Some(gltf::animation::util::ReadOutputs::Scales(values)) => {
+                    animation::ChannelValues::Scales(
+                        values.map(|v| math::Vec3f::new(v[0], v[1], v[2])).collect(),
+                    )
+                }
+                Some(gltf::animation::util::ReadOutputs::MorphTargetWeights(_)) => {
+                    skipped_morph_channel_count += 1;
+                    continue;
+                }
+                _ => {
+                    skipped_channel_count += 1;
+                    continue;
+                }
+            };
+            let interpolation = match raw_channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Step => animation::Interpolation::Step,
+                gltf::animation::Interpolation::Linear => animation::Interpolation::Linear,
+                gltf::animation::Interpolation::CubicSpline => {
+                    animation::Interpolation::CubicSpline
+                }
+            };
+
+            let value_count = match &values {
+                animation::ChannelValues::Translations(x) => x.len(),
+                animation::ChannelValues::Rotations(x) => x.len(),
+                animation::ChannelValues::Scales(x) => x.len(),
+            };
+            let expected_value_count = match interpolation {
+                animation::Interpolation::CubicSpline => times.len() * 3,
+                _ => times.len(),
+            };
+            if times.is_empty() || value_count != expected_value_count {
+                warnings.push(LoaderError::Validation(format!(
+                    "Skipping channel #{} of animation '{}', keyframe count does not match.",
+                    channel_index, name
+                )));
+                continue;
+            }
+
+            match joint_index {
+                Some(&joint_index) => channels.push(animation::Channel {
+                    joint_index,
+                    interpolation,
+                    times,
+                    values,
+                }),
+                None => skipped_channel_count += 1,
+            }
+        }
+
+        if skipped_channel_count > 0 {
+            warnings.push(LoaderError::Unsupported(format!(
+                "Skipping {} channels of animation '{}', only joint transforms are supported.",
+                skipped_channel_count, name
+            )));
+        }
+        if skipped_morph_channel_count > 0 {
+            warnings.push(LoaderError::Unsupported(format!(
+                "Skipping {} morph target weight channels of animation '{}', morph weights are not animated.",
+                skipped_morph_channel_count, name
+            )));
+        }
+        if channels.is_empty() {
+            continue;
+        }
+
+        let duration = channels
             .iter()
-            .map(|x| material::Property {
-                name: x.name.clone(),
-                value: x.clone(),
+            .map(|x| *x.times.last().unwrap())
+            .fold(0., f32::max);
+        clips.push(animation::AnimationClip {
+            name,
+            duration,
+            channels,
+        });
+    }
+
+    clips
+}
+
+// glTF matrices are column-major
+fn convert_gltf_matrix(m: [[f32; 4]; 4]) -> math::Mat4x4f {
+    math::Mat4x4f {
+        r1: math::Vec4f::new(m[0][0], m[1][0], m[2][0], m[3][0]),
+        r2: math::Vec4f::new(m[0][1], m[1][1], m[2][1], m[3][1]),
+        r3: math::Vec4f::new(m[0][2], m[1][2], m[2][2], m[3][2]),
+        r4: math::Vec4f::new(m[0][3], m[1][3], m[2][3], m[3][3]),
+    }
+}
+
+fn create_host_material_from_gltf_material(
+    raw_material: &gltf::Material,
+    images: &Vec<Option<Arc<tex::HostTexture>>>,
+    mip_filter: Option<mipmap::MipFilter>,
+) -> material::HostMaterial {
+    let get_image = |texture: gltf::Texture| -> Option<&Arc<tex::HostTexture>> {
+        images[texture.source().index()].as_ref()
+    };
+
+    let pbr = raw_material.pbr_metallic_roughness();
+    let base_color_factor = pbr.base_color_factor();
+    let emissive_factor = raw_material.emissive_factor();
+
+    let albedo = pbr
+        .base_color_texture()
+        .and_then(|info| get_image(info.texture()))
+        .cloned();
+    let normal = raw_material
+        .normal_texture()
+        .and_then(|info| get_image(info.texture()))
+        .cloned();
+    let metallic_roughness = pbr
+        .metallic_roughness_texture()
+        .and_then(|info| get_image(info.texture()));
+    let metallic = metallic_roughness
+        .map(|image| derive_host_texture(image, Some(2), &[pbr.metallic_factor()]));
+    let roughness = metallic_roughness
+        .map(|image| derive_host_texture(image, Some(1), &[pbr.roughness_factor()]));
+    let occlusion = raw_material
+        .occlusion_texture()
+        .and_then(|info| get_image(info.texture()))
+        .map(|image| derive_host_texture(image, Some(0), &[1.]));
+    let emissive = raw_material
+        .emissive_texture()
+        .and_then(|info| get_image(info.texture()))
+        .cloned();
+
+    let states = vec![
+        ("uAlbedoMapAvailableUint", albedo.is_some()),
+        ("uNormalMapAvailableUint", normal.is_some()),
+        ("uBumpMapAvailableUint", false),
+        ("uMetallicAvailableUint", metallic.is_some()),
+        ("uRoughnessAvailableUint", roughness.is_some()),
+        ("uOcclusionMapAvailableUint", occlusion.is_some()),
+        ("uEmissiveMapAvailableUint", emissive.is_some()),
+        ("uLightMapAvailableUint", false),
+    ];
+    let uv_sets = vec![
+        (
+            "uOcclusionUVSetUint",
+            raw_material
+                .occlusion_texture()
+                .map_or(0, |info| info.tex_coord()),
+        ),
+        ("uLightMapUVSetUint", 1),
+    ];
+    let textures: Vec<(&str, Arc<tex::HostTexture>)> = vec![
+        ("uAlbedoMapSampler2D", albedo),
+        ("uNormalMapSampler2D", normal),
+        ("uMetallicSampler2D", metallic),
+        ("uRoughnessSampler2D", roughness),
+        ("uOcclusionMapSampler2D", occlusion),
+        ("uEmissiveMapSampler2D", emissive),
+    ]
+    .into_iter()
+    .filter_map(|(name, texture)| texture.map(|texture| (name, texture)))
+    .collect();
+
+    material::HostMaterial {
+        name: raw_material
+            .name()
+            .map(|x| x.to_string())
+            .unwrap_or(format!(
+                "Material #{}",
+                raw_material.index().unwrap_or_default()
+            )),
+
+        properties_1u: states
+            .iter()
+            .map(|x| material::Property::<math::Vec1u> {
+                name: x.0.to_string(),
+                value: math::Vec1u::new(x.1 as u32),
+            })
+            .chain(uv_sets.iter().map(|x| material::Property::<math::Vec1u> {
+                name: x.0.to_string(),
+                value: math::Vec1u::new(x.1.min(1)),
+            }))
+            .collect(),
+
+        properties_1f: vec![
+            material::Property {
+                name: "uScalarRoughnessVec1f".to_string(),
+                value: math::Vec1f::new(pbr.roughness_factor()),
+            },
+            material::Property {
+                name: "uScalarMetalnessVec1f".to_string(),
+                value: math::Vec1f::new(pbr.metallic_factor()),
+            },
+            material::Property {
+                name: "uScalarOpacityVec1f".to_string(),
+                value: math::Vec1f::new(base_color_factor[3]),
+            },
+        ],
+        properties_3f: vec![
+            material::Property {
+                name: "uScalarAlbedoVec3f".to_string(),
+                value: math::Vec3f::new(
+                    base_color_factor[0],
+                    base_color_factor[1],
+                    base_color_factor[2],
+                ),
+            },
+            material::Property {
+                name: "uScalarEmissiveVec3f".to_string(),
+                value: math::Vec3f::new(emissive_factor[0], emissive_factor[1], emissive_factor[2]),
+            },
+        ],
+
+        properties_samplers: textures
+            .into_iter()
+            .map(|(name, texture)| material::Property {
+                name: name.to_string(),
+                value: create_host_sampler(
+                    name,
+                    texture,
+                    mip_filter,
+                    match raw_material.alpha_mode() {
+                        gltf::material::AlphaMode::Mask if name == "uAlbedoMapSampler2D" => {
+                            Some(raw_material.alpha_cutoff())
+                        }
+                        _ => None,
+                    },
+                ),
             })
             .collect(),
     }
 }
+
+fn create_host_sampler(
+    name: &str,
+    texture: Arc<tex::HostTexture>,
+    mip_filter: Option<mipmap::MipFilter>,
+    alpha_cutoff: Option<f32>,
+) -> material::HostSampler {
+    let mut sampler = material::HostSampler::new(name, texture);
+    let filter = match (mip_filter, &sampler.texture.data) {
+        (None, _) | (_, tex::HostTextureData::COMPRESSED(..)) => return sampler,
+        (Some(filter), _) => filter,
+    };
+
+    let options = mipmap::MipOptions {
+        alpha_cutoff,
+        ..mipmap::create_mip_options(name, sampler.format, filter)
+    };
+    sampler.texture = if sampler.texture.name.is_empty() {
+        Arc::new(mipmap::generate_host_texture_mips(
+            &sampler.texture,
+            &options,
+        ))
+    } else {
+        // Named by the derived key so device textures of the source and its mips stay apart
+        let key = format!("{}|mips={:?}", sampler.texture.name, options);
+        cache::get_or_load_host_texture(&key, || {
+            Ok(tex::HostTexture {
+                name: key.clone(),
+                ..mipmap::generate_host_texture_mips(&sampler.texture, &options)
+            })
+        })
+        .unwrap()
+    };
+    sampler
+}
+
+fn derive_host_texture(
+    texture: &Arc<tex::HostTexture>,
+    channel: Option<usize>,
+    scale: &[f32],
+) -> Arc<tex::HostTexture> {
+    if channel.is_none() && scale.iter().all(|&x| x == 1.) {
+        return texture.clone();
+    }
+
+    if let tex::HostTextureData::COMPRESSED(format, _) = &texture.data {
+        log::log_warning(format!(
+            "Channel remapping is not supported for {:?} texture: {}",
+            format, texture.name
+        ));
+        return texture.clone();
+    }
+
+    let key = format!("{}|channel={:?}|scale={:?}", texture.name, channel, scale);
+    cache::get_or_load_host_texture(&key, || {
+        let derived = match channel {
+            Some(channel) => extract_host_texture_channel(texture, channel),
+            None => (**texture).clone(),
+        };
+        Ok(scale_host_texture_channels(derived, scale))
+    })
+    .unwrap()
+}
+
+fn extract_host_texture_channel(texture: &tex::HostTexture, channel: usize) -> tex::HostTexture {
+    let channel = channel.min(texture.depth - 1);
+
+    tex::HostTexture {
+        name: String::new(),
+        width: texture.width,
+        height: texture.height,
+        depth: 1,
+        data: match &texture.data {
+            tex::HostTextureData::UINT8(data) => tex::HostTextureData::UINT8(
+                data.iter()
+                    .skip(channel)
+                    .step_by(texture.depth)
+                    .cloned()
+                    .collect(),
+            ),
+            tex::HostTextureData::FLOAT32(data) => tex::HostTextureData::FLOAT32(
+                data.iter()
+                    .skip(channel)
+                    .step_by(texture.depth)
+                    .cloned()
+                    .collect(),
+            ),
+            tex::HostTextureData::COMPRESSED(..) => texture.data.clone(),
+        },
+        mips: Vec::new(),
+        srgb: false,
+    }
+}
+
+fn scale_host_texture_channels(mut texture: tex::HostTexture, scale: &[f32]) -> tex::HostTexture {
+    if scale.iter().all(|&x| x == 1.) {
+        return texture;
+    }
+
+    let depth = texture.depth;
+    match &mut texture.data {
+        tex::HostTextureData::UINT8(data) => {
+            for (i, value) in data.iter_mut().enumerate() {
+                let factor = scale[(i % depth).min(scale.len() - 1)];
+                *value = (*value as f32 * factor).round().min(255.) as u8;
+            }
+        }
+        tex::HostTextureData::FLOAT32(data) => {
+            for (i, value) in data.iter_mut().enumerate() {
+                *value *= scale[(i % depth).min(scale.len() - 1)];
+            }
+        }
+        tex::HostTextureData::COMPRESSED(..) => {}
+    }
+
+    texture
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gltf_image_outside_of_buffer_is_an_error() {
+        let folder_path =
+            std::env::temp_dir().join(format!("loader_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&folder_path).unwrap();
+        let file_path = folder_path.join("model.gltf");
+        let gltf = serde_json::json!({
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 4, "uri": "data:application/octet-stream;base64,AAAAAA==" }],
+            "bufferViews": [{ "buffer": 0, "byteOffset": 2, "byteLength": 64 }],
+            "images": [{ "bufferView": 0, "mimeType": "image/png" }],
+            "scenes": [{ "nodes": [] }],
+        });
+        fs::write(&file_path, gltf.to_string()).unwrap();
+
+        match load_host_model_from_gltf(&file_path, &DEFAULT_LOADER_OPTIONS) {
+            Err(LoaderError::Validation(msg)) => assert!(msg.contains("outside of buffer")),
+            _ => panic!("Expected a validation error"),
+        }
+        fs::remove_dir_all(&folder_path).unwrap();
+    }
+}
@@ -0,0 +1,592 @@
+use crate::gl::tex;
+use crate::helpers::log;
+
+const KAISER_WIDTH: f32 = 3.;
+const KAISER_ALPHA: f32 = 4.;
+const LANCZOS_WIDTH: f32 = 3.;
+const COVERAGE_SEARCH_STEPS: usize = 16;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MipFilter {
+    Box,
+    Kaiser,
+    Lanczos,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct MipOptions {
+    pub filter: MipFilter,
+    pub color_space: tex::ColorSpace,
+    pub normal_map: bool,
+    // Alpha test threshold whose coverage is kept on every level
+    pub alpha_cutoff: Option<f32>,
+}
+
+// Interleaved channels in linear space
+struct MipLevel {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+pub fn create_mip_options(
+    sampler_name: &str,
+    sampler_format: tex::SamplerFormat,
+    filter: MipFilter,
+) -> MipOptions {
+    MipOptions {
+        filter,
+        color_space: sampler_format.color_space,
+        normal_map: sampler_name == "uNormalMapSampler2D",
+        alpha_cutoff: None,
+    }
+}
+
+pub fn generate_host_texture_mips(
+    texture: &tex::HostTexture,
+    options: &MipOptions,
+) -> tex::HostTexture {
+    let depth = texture.depth;
+    let data: Vec<f32> = match &texture.data {
+        tex::HostTextureData::UINT8(data) => data.iter().map(|&x| x as f32 / 255.).collect(),
+        tex::HostTextureData::FLOAT32(data) => data.clone(),
+        tex::HostTextureData::COMPRESSED(format, _) => {
+            log::log_warning(format!(
+                "Mips of {:?} texture '{}' come from its file only",
+                format, texture.name
+            ));
+            return texture.clone();
+        }
+    };
+    if data.len() < texture.width * texture.height * depth {
+        return texture.clone();
+    }
+
+    // Grey + alpha and RGBA keep alpha in the last channel, it is never gamma encoded
+    let alpha_channel = match depth {
+        2 | 4 => Some(depth - 1),
+        _ => None,
+    };
+    let srgb = (options.color_space == tex::ColorSpace::Srgb || texture.srgb)
+        && !options.normal_map
+        && matches!(texture.data, tex::HostTextureData::UINT8(_));
+    let color_channels: Vec<usize> = (0..depth)
+        .filter(|&channel| Some(channel) != alpha_channel)
+        .collect();
+
+    let mut level = MipLevel {
+        width: texture.width,
+        height: texture.height,
+        data,
+    };
+    if srgb {
+        convert_level(&mut level, depth, &color_channels, convert_srgb_to_linear);
+    }
+
+    let cutoff_coverage = match (options.alpha_cutoff, alpha_channel) {
+        (Some(cutoff), Some(alpha_channel)) => Some((
+            cutoff,
+            alpha_channel,
+            calculate_alpha_coverage(&level.data, depth, alpha_channel, cutoff, 1.),
+        )),
+        _ => None,
+    };
+
+    let mut mips: Vec<tex::HostTextureData> = Vec::new();
+    while level.width > 1 || level.height > 1 {
+        level = downsample_level(&level, depth, options.filter);
+        if options.normal_map {
+            normalize_level(&mut level, depth);
+        }
+
+        // Coverage and gamma only affect the stored level, the next one filters the linear data
+        let mut output = MipLevel {
+            width: level.width,
+            height: level.height,
+            data: level.data.clone(),
+        };
+        if let Some((cutoff, alpha_channel, coverage)) = cutoff_coverage {
+            let scale = find_alpha_scale(&output.data, depth, alpha_channel, cutoff, coverage);
+            for value in output.data.iter_mut().skip(alpha_channel).step_by(depth) {
+                *value = (*value * scale).min(1.);
+            }
+        }
+        if srgb {
+            convert_level(&mut output, depth, &color_channels, convert_linear_to_srgb);
+        }
+
+        mips.push(match &texture.data {
+            tex::HostTextureData::UINT8(_) => tex::HostTextureData::UINT8(
+                output
+                    .data
+                    .iter()
+                    .map(|&x| (x.clamp(0., 1.) * 255.).round() as u8)
+                    .collect(),
+            ),
+            _ => tex::HostTextureData::FLOAT32(output.data.iter().map(|&x| x.max(0.)).collect()),
+        });
+    }
+
+    log::log_info(format!(
+        "Generated {} mips with {:?} filter: {}",
+        mips.len(),
+        options.filter,
+        texture.name
+    ));
+
+    tex::HostTexture {
+        name: texture.name.clone(),
+        width: texture.width,
+        height: texture.height,
+        depth,
+        data: texture.data.clone(),
+        mips,
+        srgb: texture.srgb,
+    }
+}
+
+fn downsample_level(level: &MipLevel, depth: usize, filter: MipFilter) -> MipLevel {
+    let width = (level.width / 2).max(1);
+    let height = (level.height / 2).max(1);
+
+    // Separable filter, rows first
+    let weights = calculate_filter_weights(level.width, width, filter);
+    let mut rows = vec![0_f32; width * level.height * depth];
+    for y in 0..level.height {
+        for (x, taps) in weights.iter().enumerate() {
+            for &(source, weight) in taps {
+                for channel in 0..depth {
+                    rows[(y * width + x) * depth + channel] +=
+                        level.data[(y * level.width + source) * depth + channel] * weight;
+                }
+            }
+        }
+    }
+
+    let weights = calculate_filter_weights(level.height, height, filter);
+    let mut data = vec![0_f32; width * height * depth];
+    for (y, taps) in weights.iter().enumerate() {
+        for &(source, weight) in taps {
+            for x in 0..width {
+                for channel in 0..depth {
+                    data[(y * width + x) * depth + channel] +=
+                        rows[(source * width + x) * depth + channel] * weight;
+                }
+            }
+        }
+    }
+
+    MipLevel {
+        width,
+        height,
+        data,
+    }
+}
+
+// Taps per destination texel, clamped to the edge and normalized
+fn calculate_filter_weights(
+    source_size: usize,
+    destination_size: usize,
+    filter: MipFilter,
+) -> Vec<Vec<(usize, f32)>> {
+    let scale = source_size as f32 / destination_size as f32;
+    let support = get_filter_width(filter) * scale;
+
+    (0..destination_size)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * scale;
+            let first = (center - support).floor() as isize;
+            let last = (center + support).ceil() as isize;
+
+            let mut taps: Vec<(usize, f32)> = (first..last)
+                .map(|j| {
+                    let x = (j as f32 + 0.5 - center) / scale;
+                    let source = j.clamp(0, source_size as isize - 1) as usize;
+                    (source, evaluate_filter(filter, x))
+                })
+                .filter(|&(_, weight)| weight != 0.)
+                .collect();
+
+            let sum: f32 = taps.iter().map(|x| x.1).sum();
+            if sum.abs() < f32::EPSILON {
+                return vec![((center as usize).min(source_size - 1), 1.)];
+            }
+            for tap in &mut taps {
+                tap.1 /= sum;
+            }
+            taps
+        })
+        .collect()
+}
+
+fn get_filter_width(filter: MipFilter) -> f32 {
+    match filter {
+        MipFilter::Box => 0.5,
+        MipFilter::Kaiser => KAISER_WIDTH,
+        MipFilter::Lanczos => LANCZOS_WIDTH,
+    }
+}
+
+fn evaluate_filter(filter: MipFilter, x: f32) -> f32 {
+    let x = x.abs();
+    match filter {
+        MipFilter::Box => (x <= 0.5) as u32 as f32,
+        MipFilter::Kaiser if x < KAISER_WIDTH => {
+            let t = x / KAISER_WIDTH;
+            sinc(x) * bessel_i0(KAISER_ALPHA * (1. - t * t).sqrt()) / bessel_i0(KAISER_ALPHA)
+        }
+        MipFilter::Lanczos if x < LANCZOS_WIDTH => sinc(x) * sinc(x / LANCZOS_WIDTH),
+        _ => 0.,
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-4 {
+        1.
+    } else {
+        let x = x * std::f32::consts::PI;
+        x.sin() / x
+    }
+}
+
+// Modified Bessel function of the first kind, order zero
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.;
+    let mut term = 1.;
+    let mut k = 1.;
+    while term > sum * 1e-7 {
+        term *= (x / (2. * k)) * (x / (2. * k));
+        sum += term;
+        k += 1.;
+    }
+    sum
+}
+
+// Normals are stored remapped to [0, 1], two channel maps rebuild z
+fn normalize_level(level: &mut MipLevel, depth: usize) {
+    if depth < 2 {
+        return;
+    }
+
+    for texel in level.data.chunks_exact_mut(depth) {
+        let x = texel[0] * 2. - 1.;
+        let y = texel[1] * 2. - 1.;
+        let z = if depth > 2 {
+            texel[2] * 2. - 1.
+        } else {
+            (1. - x * x - y * y).max(0.).sqrt()
+        };
+        let length = (x * x + y * y + z * z).sqrt();
+        if length < f32::EPSILON {
+            continue;
+        }
+
+        texel[0] = x / length * 0.5 + 0.5;
+        texel[1] = y / length * 0.5 + 0.5;
+        if depth > 2 {
+            texel[2] = z / length * 0.5 + 0.5;
+        }
+    }
+}
+
+fn convert_level(level: &mut MipLevel, depth: usize, channels: &[usize], convert: fn(f32) -> f32) {
+    for texel in level.data.chunks_exact_mut(depth) {
+        for &channel in channels {
+            texel[channel] = convert(texel[channel]);
+        }
+    }
+}
+
+fn convert_srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn convert_linear_to_srgb(x: f32) -> f32 {
+    let x = x.clamp(0., 1.);
+    if x <= 0.0031308 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1. / 2.4) - 0.055
+    }
+}
+
+fn calculate_alpha_coverage(
+    data: &[f32],
+    depth: usize,
+    alpha_channel: usize,
+    cutoff: f32,
+    scale: f32,
+) -> f32 {
+    let texel_count = data.len() / depth;
+    let covered = data
+        .iter()
+        .skip(alpha_channel)
+        .step_by(depth)
+        .filter(|&&alpha| (alpha * scale).min(1.) >= cutoff)
+        .count();
+    covered as f32 / texel_count.max(1) as f32
+}
+
+// Coverage grows with the scale, so a bisection finds the smallest one reaching the base level
+fn find_alpha_scale(
+    data: &[f32],
+    depth: usize,
+    alpha_channel: usize,
+    cutoff: f32,
+    coverage: f32,
+) -> f32 {
+    let (mut low, mut high) = (0_f32, 4_f32);
+    for _ in 0..COVERAGE_SEARCH_STEPS {
+        let middle = (low + high) * 0.5;
+        if calculate_alpha_coverage(data, depth, alpha_channel, cutoff, middle) < coverage {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    high
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [MipFilter; 3] = [MipFilter::Box, MipFilter::Kaiser, MipFilter::Lanczos];
+
+    fn create_options(filter: MipFilter, color_space: tex::ColorSpace) -> MipOptions {
+        MipOptions {
+            filter,
+            color_space,
+            normal_map: false,
+            alpha_cutoff: None,
+        }
+    }
+
+    fn create_texture(
+        width: usize,
+        height: usize,
+        depth: usize,
+        data: tex::HostTextureData,
+    ) -> tex::HostTexture {
+        let mut texture = tex::HostTexture::empty("test".to_string(), width, height, depth);
+        texture.data = data;
+        texture
+    }
+
+    fn get_floats(data: &tex::HostTextureData) -> Vec<f32> {
+        match data {
+            tex::HostTextureData::UINT8(data) => data.iter().map(|&x| x as f32).collect(),
+            tex::HostTextureData::FLOAT32(data) => data.clone(),
+            tex::HostTextureData::COMPRESSED(..) => panic!("Compressed data has no texels"),
+        }
+    }
+
+    // Deterministic values in [0, 1)
+    fn create_noise(count: usize) -> Vec<f32> {
+        let mut state = 12345_u32;
+        (0..count)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                (state >> 8) as f32 / (1 << 24) as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn level_sizes_halve_down_to_one_texel() {
+        let texture = create_texture(8, 2, 4, tex::HostTextureData::UINT8(vec![0; 8 * 2 * 4]));
+        let texture = generate_host_texture_mips(
+            &texture,
+            &create_options(MipFilter::Box, tex::ColorSpace::Linear),
+        );
+
+        let sizes: Vec<usize> = texture.mips.iter().map(|x| get_floats(x).len()).collect();
+        assert_eq!(sizes, vec![4 * 1 * 4, 2 * 1 * 4, 1 * 1 * 4]);
+    }
+
+    #[test]
+    fn constant_images_stay_constant() {
+        for &filter in &FILTERS {
+            for &color_space in &[tex::ColorSpace::Linear, tex::ColorSpace::Srgb] {
+                let texture =
+                    create_texture(16, 8, 3, tex::HostTextureData::UINT8(vec![77; 16 * 8 * 3]));
+                let texture =
+                    generate_host_texture_mips(&texture, &create_options(filter, color_space));
+                for mip in &texture.mips {
+                    for value in get_floats(mip) {
+                        assert!((value - 77.).abs() <= 1., "{:?} {}", filter, value);
+                    }
+                }
+            }
+
+            let texture =
+                create_texture(16, 8, 1, tex::HostTextureData::FLOAT32(vec![3.5; 16 * 8]));
+            let texture = generate_host_texture_mips(
+                &texture,
+                &create_options(filter, tex::ColorSpace::Linear),
+            );
+            for mip in &texture.mips {
+                for value in get_floats(mip) {
+                    assert!((value - 3.5).abs() < 1e-4, "{:?} {}", filter, value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn box_filter_averages_texel_pairs() {
+        let texture = create_texture(4, 1, 1, tex::HostTextureData::FLOAT32(vec![0., 1., 2., 3.]));
+        let texture = generate_host_texture_mips(
+            &texture,
+            &create_options(MipFilter::Box, tex::ColorSpace::Linear),
+        );
+
+        assert_eq!(get_floats(&texture.mips[0]), vec![0.5, 2.5]);
+        assert_eq!(get_floats(&texture.mips[1]), vec![1.5]);
+    }
+
+    #[test]
+    fn filter_weights_are_normalized() {
+        for &filter in &FILTERS {
+            for taps in calculate_filter_weights(16, 8, filter) {
+                let sum: f32 = taps.iter().map(|x| x.1).sum();
+                assert!((sum - 1.).abs() < 1e-5, "{:?} {}", filter, sum);
+            }
+        }
+
+        // Windowed sinc filters sharpen with negative lobes, the box filter never does
+        let has_negative_lobes = |filter| {
+            calculate_filter_weights(16, 8, filter)
+                .iter()
+                .flatten()
+                .any(|x| x.1 < 0.)
+        };
+        assert!(!has_negative_lobes(MipFilter::Box));
+        assert!(has_negative_lobes(MipFilter::Kaiser));
+        assert!(has_negative_lobes(MipFilter::Lanczos));
+    }
+
+    #[test]
+    fn kaiser_and_lanczos_keep_more_detail_than_box() {
+        // A period of 8 texels becomes 4 texels on the first level, well below Nyquist
+        let data: Vec<f32> = (0..64)
+            .map(|x| 0.5 + 0.5 * (x as f32 * std::f32::consts::PI / 4.).sin())
+            .collect();
+        let texture = create_texture(64, 1, 1, tex::HostTextureData::FLOAT32(data));
+        let amplitude = |filter| {
+            let texture = generate_host_texture_mips(
+                &texture,
+                &create_options(filter, tex::ColorSpace::Linear),
+            );
+            // Texels near the clamped edges are skipped
+            let mip = &get_floats(&texture.mips[0])[8..24];
+            let max = mip.iter().cloned().fold(f32::MIN, f32::max);
+            let min = mip.iter().cloned().fold(f32::MAX, f32::min);
+            (max - min) * 0.5
+        };
+
+        let box_amplitude = amplitude(MipFilter::Box);
+        assert!(amplitude(MipFilter::Kaiser) > box_amplitude);
+        assert!(amplitude(MipFilter::Lanczos) > box_amplitude);
+    }
+
+    #[test]
+    fn srgb_textures_are_filtered_in_linear_space() {
+        let data = vec![0, 0, 0, 0, 255, 255, 255, 255];
+        let texture = create_texture(2, 1, 4, tex::HostTextureData::UINT8(data));
+
+        let srgb = generate_host_texture_mips(
+            &texture,
+            &create_options(MipFilter::Box, tex::ColorSpace::Srgb),
+        );
+        // Half the linear intensity in sRGB, alpha is never gamma encoded
+        assert_eq!(get_floats(&srgb.mips[0]), vec![188., 188., 188., 128.]);
+
+        let linear = generate_host_texture_mips(
+            &texture,
+            &create_options(MipFilter::Box, tex::ColorSpace::Linear),
+        );
+        assert_eq!(get_floats(&linear.mips[0]), vec![128., 128., 128., 128.]);
+
+        // Containers can declare sRGB data for slots that read it as linear
+        let texture = tex::HostTexture {
+            srgb: true,
+            ..texture
+        };
+        let declared = generate_host_texture_mips(
+            &texture,
+            &create_options(MipFilter::Box, tex::ColorSpace::Linear),
+        );
+        assert_eq!(get_floats(&declared.mips[0]), vec![188., 188., 188., 128.]);
+    }
+
+    #[test]
+    fn normal_maps_are_renormalized() {
+        // +X and +Y remapped to [0, 1], their average is shorter than one
+        let data = vec![1., 0.5, 0.5, 0.5, 1., 0.5];
+        let texture = create_texture(2, 1, 3, tex::HostTextureData::FLOAT32(data));
+        let texture = generate_host_texture_mips(
+            &texture,
+            &MipOptions {
+                normal_map: true,
+                ..create_options(MipFilter::Box, tex::ColorSpace::Linear)
+            },
+        );
+
+        let normal: Vec<f32> = get_floats(&texture.mips[0])
+            .iter()
+            .map(|x| x * 2. - 1.)
+            .collect();
+        let length = normal.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((length - 1.).abs() < 1e-5, "{}", length);
+        assert!((normal[0] - normal[1]).abs() < 1e-5);
+    }
+
+    #[test]
+    fn alpha_scale_bisection_matches_coverage() {
+        let data: Vec<f32> = (1..=10).map(|x| x as f32 * 0.1).collect();
+        for &coverage in &[0.2, 0.5, 0.8] {
+            let scale = find_alpha_scale(&data, 1, 0, 0.5, coverage);
+            let result = calculate_alpha_coverage(&data, 1, 0, 0.5, scale);
+            assert!((result - coverage).abs() < 1e-5, "{} {}", coverage, result);
+        }
+    }
+
+    #[test]
+    fn alpha_coverage_is_kept_on_every_level() {
+        let (width, height, cutoff) = (64, 64, 0.7);
+        let data: Vec<f32> = create_noise(width * height)
+            .iter()
+            .flat_map(|&alpha| vec![1., alpha])
+            .collect();
+        let base_coverage = calculate_alpha_coverage(&data, 2, 1, cutoff, 1.);
+        let texture = create_texture(width, height, 2, tex::HostTextureData::FLOAT32(data));
+
+        for &alpha_cutoff in &[None, Some(cutoff)] {
+            let texture = generate_host_texture_mips(
+                &texture,
+                &MipOptions {
+                    alpha_cutoff,
+                    ..create_options(MipFilter::Box, tex::ColorSpace::Linear)
+                },
+            );
+            // Levels of 8x8 texels and more, smaller ones cannot resolve the coverage
+            let errors: Vec<f32> = texture.mips[..3]
+                .iter()
+                .map(|mip| {
+                    let coverage = calculate_alpha_coverage(&get_floats(mip), 2, 1, cutoff, 1.);
+                    (coverage - base_coverage).abs()
+                })
+                .collect();
+
+            match alpha_cutoff {
+                Some(_) => assert!(errors.iter().all(|&x| x < 0.05), "{:?}", errors),
+                None => assert!(errors.iter().any(|&x| x > 0.1), "{:?}", errors),
+            }
+        }
+    }
+}
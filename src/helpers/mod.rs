@@ -1,3 +1,13 @@
+pub mod container;
+pub mod cooker;
+pub mod environment;
+pub mod exporter;
 pub mod helper;
 pub mod loader;
 pub mod log;
+pub mod mipmap;
+pub mod optimizer;
+pub mod primitives;
+pub mod scene;
+pub mod simplifier;
+pub mod writer;
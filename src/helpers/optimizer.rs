@@ -0,0 +1,316 @@
+use crate::asset::{mesh, model};
+use crate::helpers::{helper, log};
+use crate::math;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const VERTEX_CACHE_SIZE: usize = 16;
+
+#[derive(Copy, Clone, Default)]
+pub struct MeshOptimizationStatistics {
+    pub vertex_count_before: usize,
+    pub vertex_count_after: usize,
+    pub acmr_before: f32,
+    pub acmr_after: f32,
+}
+
+pub fn optimize_host_model(host_model: &model::HostModel) -> model::HostModel {
+    let mut total = MeshOptimizationStatistics::default();
+    let mut triangle_count = 0;
+
+    let meshes: Vec<mesh::HostMesh> = host_model
+        .meshes
+        .iter()
+        .map(|host_mesh| {
+            let (optimized_mesh, statistics) = optimize_host_mesh(host_mesh);
+            let mesh_triangle_count = (host_mesh.indices.len() / 3) as f32;
+
+            total.vertex_count_before += statistics.vertex_count_before;
+            total.vertex_count_after += statistics.vertex_count_after;
+            total.acmr_before += statistics.acmr_before * mesh_triangle_count;
+            total.acmr_after += statistics.acmr_after * mesh_triangle_count;
+            triangle_count += host_mesh.indices.len() / 3;
+
+            optimized_mesh
+        })
+        .collect();
+
+    if triangle_count > 0 {
+        log::log_info(format!(
+            "Mesh optimization: vertices {} -> {}, ACMR {:.3} -> {:.3}",
+            total.vertex_count_before,
+            total.vertex_count_after,
+            total.acmr_before / triangle_count as f32,
+            total.acmr_after / triangle_count as f32,
+        ));
+    }
+
+    model::HostModel {
+        meshes: Arc::new(meshes),
+        materials: host_model.materials.clone(),
+        skeleton: host_model.skeleton.clone(),
+        animations: host_model.animations.clone(),
+        warnings: host_model.warnings.clone(),
+    }
+}
+
+pub fn optimize_host_mesh(
+    host_mesh: &mesh::HostMesh,
+) -> (mesh::HostMesh, MeshOptimizationStatistics) {
+    let acmr_before = calculate_acmr(&host_mesh.indices, VERTEX_CACHE_SIZE);
+
+    let welded_mesh = weld_vertices(host_mesh);
+    let indices = optimize_vertex_cache(
+        &welded_mesh.indices,
+        welded_mesh.vertices.len(),
+        VERTEX_CACHE_SIZE,
+    );
+    let optimized_mesh = optimize_vertex_fetch(&welded_mesh, &indices);
+
+    let statistics = MeshOptimizationStatistics {
+        vertex_count_before: host_mesh.vertices.len(),
+        vertex_count_after: optimized_mesh.vertices.len(),
+        acmr_before,
+        acmr_after: calculate_acmr(&optimized_mesh.indices, VERTEX_CACHE_SIZE),
+    };
+
+    (optimized_mesh, statistics)
+}
+
+pub fn weld_vertices(host_mesh: &mesh::HostMesh) -> mesh::HostMesh {
+    let mut unique_vertices: HashMap<Vec<u32>, u32> = HashMap::new();
+    let mut remap: Vec<usize> = Vec::new();
+    let mut vertex_remap: Vec<u32> = Vec::with_capacity(host_mesh.vertices.len());
+
+    for i in 0..host_mesh.vertices.len() {
+        let new_index = *unique_vertices
+            .entry(create_vertex_key(host_mesh, i))
+            .or_insert_with(|| {
+                remap.push(i);
+                remap.len() as u32 - 1
+            });
+        vertex_remap.push(new_index);
+    }
+
+    let indices: mesh::Indices = host_mesh
+        .indices
+        .iter()
+        .map(|i| math::Vec1u::new(vertex_remap[i.x as usize]))
+        .collect();
+
+    remap_host_mesh(host_mesh, &remap, indices)
+}
+
+// Tipsify from "Fast Triangle Reordering for Vertex Locality and Reduced Overdraw"
+pub fn optimize_vertex_cache(
+    indices: &mesh::Indices,
+    vertex_count: usize,
+    cache_size: usize,
+) -> mesh::Indices {
+    let triangle_count = indices.len() / 3;
+
+    let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    for triangle in 0..triangle_count {
+        for k in 0..3 {
+            adjacency[indices[triangle * 3 + k].x as usize].push(triangle);
+        }
+    }
+
+    let mut live_triangles: Vec<usize> = adjacency.iter().map(|x| x.len()).collect();
+    let mut cache_time: Vec<usize> = vec![0; vertex_count];
+    let mut emitted: Vec<bool> = vec![false; triangle_count];
+    let mut dead_end: Vec<usize> = Vec::new();
+    let mut result = mesh::Indices::with_capacity(indices.len());
+
+    let mut time = cache_size + 1;
+    let mut cursor = 0;
+    let mut fanning_vertex = if vertex_count > 0 { Some(0) } else { None };
+
+    while let Some(vertex) = fanning_vertex {
+        let mut candidates: Vec<usize> = Vec::new();
+
+        for &triangle in &adjacency[vertex] {
+            if emitted[triangle] {
+                continue;
+            }
+
+            for k in 0..3 {
+                let v = indices[triangle * 3 + k].x as usize;
+                result.push(math::Vec1u::new(v as u32));
+                dead_end.push(v);
+                candidates.push(v);
+                live_triangles[v] -= 1;
+
+                if time - cache_time[v] > cache_size {
+                    cache_time[v] = time;
+                    time += 1;
+                }
+            }
+            emitted[triangle] = true;
+        }
+
+        let mut best_priority: Option<usize> = None;
+        fanning_vertex = None;
+        for &v in &candidates {
+            if live_triangles[v] == 0 {
+                continue;
+            }
+
+            let age = time - cache_time[v];
+            let priority = if age + 2 * live_triangles[v] <= cache_size {
+                age
+            } else {
+                0
+            };
+            if best_priority.map_or(true, |x| priority > x) {
+                best_priority = Some(priority);
+                fanning_vertex = Some(v);
+            }
+        }
+
+        if fanning_vertex.is_none() {
+            while let Some(v) = dead_end.pop() {
+                if live_triangles[v] > 0 {
+                    fanning_vertex = Some(v);
+                    break;
+                }
+            }
+        }
+        if fanning_vertex.is_none() {
+            while cursor < vertex_count {
+                if live_triangles[cursor] > 0 {
+                    fanning_vertex = Some(cursor);
+                    break;
+                }
+                cursor += 1;
+            }
+        }
+    }
+
+    result
+}
+
+pub fn optimize_vertex_fetch(
+    host_mesh: &mesh::HostMesh,
+    indices: &mesh::Indices,
+) -> mesh::HostMesh {
+    let mut vertex_remap: Vec<Option<u32>> = vec![None; host_mesh.vertices.len()];
+    let mut remap: Vec<usize> = Vec::new();
+
+    let indices: mesh::Indices = indices
+        .iter()
+        .map(|i| {
+            let new_index = vertex_remap[i.x as usize].get_or_insert_with(|| {
+                remap.push(i.x as usize);
+                remap.len() as u32 - 1
+            });
+            math::Vec1u::new(*new_index)
+        })
+        .collect();
+
+    remap_host_mesh(host_mesh, &remap, indices)
+}
+
+// Average cache miss ratio of a FIFO post-transform cache
+pub fn calculate_acmr(indices: &mesh::Indices, cache_size: usize) -> f32 {
+    if indices.len() < 3 {
+        return 0.;
+    }
+
+    let mut cache: Vec<u32> = Vec::with_capacity(cache_size + 1);
+    let mut misses = 0;
+    for index in indices {
+        if !cache.contains(&index.x) {
+            misses += 1;
+            cache.push(index.x);
+            if cache.len() > cache_size {
+                cache.remove(0);
+            }
+        }
+    }
+
+    misses as f32 / (indices.len() / 3) as f32
+}
+
+pub fn remap_host_mesh(
+    host_mesh: &mesh::HostMesh,
+    remap: &Vec<usize>,
+    indices: mesh::Indices,
+) -> mesh::HostMesh {
+    mesh::HostMesh::new(
+        host_mesh.name.clone(),
+        host_mesh.material_index,
+        helper::remap_vertex_attribute(&host_mesh.vertices, remap),
+        helper::remap_vertex_attribute(&host_mesh.normals, remap),
+        helper::remap_vertex_attribute(&host_mesh.tangents, remap),
+        helper::remap_vertex_attribute(&host_mesh.bitangents, remap),
+        helper::remap_vertex_attribute(&host_mesh.uvs, remap),
+        indices,
+    )
+    .with_uvs1(helper::remap_vertex_attribute(&host_mesh.uvs1, remap))
+    .with_colors(helper::remap_vertex_attribute(&host_mesh.colors, remap))
+    .with_joints(
+        helper::remap_vertex_attribute(&host_mesh.joint_indices, remap),
+        helper::remap_vertex_attribute(&host_mesh.joint_weights, remap),
+    )
+    .with_morph_targets(
+        host_mesh
+            .morph_targets
+            .iter()
+            .map(|target| mesh::MorphTarget {
+                name: target.name.clone(),
+                position_deltas: helper::remap_vertex_attribute(&target.position_deltas, remap),
+                normal_deltas: helper::remap_vertex_attribute(&target.normal_deltas, remap),
+            })
+            .collect(),
+        host_mesh.morph_weights.clone(),
+    )
+}
+
+fn create_vertex_key(host_mesh: &mesh::HostMesh, i: usize) -> Vec<u32> {
+    let mut key: Vec<u32> = Vec::with_capacity(28 + 6 * host_mesh.morph_targets.len());
+
+    for stream in &[
+        &host_mesh.vertices,
+        &host_mesh.normals,
+        &host_mesh.tangents,
+        &host_mesh.bitangents,
+    ] {
+        if let Some(value) = stream.get(i) {
+            key.extend_from_slice(&[value.x.to_bits(), value.y.to_bits(), value.z.to_bits()]);
+        }
+    }
+    for stream in &[&host_mesh.uvs, &host_mesh.uvs1] {
+        if let Some(value) = stream.get(i) {
+            key.extend_from_slice(&[value.x.to_bits(), value.y.to_bits()]);
+        }
+    }
+    if let Some(value) = host_mesh.colors.get(i) {
+        key.extend_from_slice(&[
+            value.x.to_bits(),
+            value.y.to_bits(),
+            value.z.to_bits(),
+            value.w.to_bits(),
+        ]);
+    }
+    for target in &host_mesh.morph_targets {
+        for stream in &[&target.position_deltas, &target.normal_deltas] {
+            if let Some(value) = stream.get(i) {
+                key.extend_from_slice(&[value.x.to_bits(), value.y.to_bits(), value.z.to_bits()]);
+            }
+        }
+    }
+    if let Some(value) = host_mesh.joint_indices.get(i) {
+        key.extend_from_slice(&[value.x, value.y, value.z, value.w]);
+    }
+    if let Some(value) = host_mesh.joint_weights.get(i) {
+        key.extend_from_slice(&[
+            value.x.to_bits(),
+            value.y.to_bits(),
+            value.z.to_bits(),
+            value.w.to_bits(),
+        ]);
+    }
+
+    key
+}
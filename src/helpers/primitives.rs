@@ -0,0 +1,775 @@
+use crate::asset::{material, mesh, model};
+use crate::helpers::{helper, log};
+use crate::math;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+pub const MIN_TESSELLATION: u32 = 3;
+pub const MAX_TESSELLATION: u32 = 256;
+// Every subdivision quadruples the triangle count, 6 gives 81920 triangles
+pub const MAX_ICOSPHERE_SUBDIVISIONS: u32 = 6;
+
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Primitive {
+    Cube,
+    UVSphere,
+    Icosphere,
+    Plane,
+    Cylinder,
+    Cone,
+    Torus,
+    Capsule,
+}
+
+pub const PRIMITIVES: [(Primitive, &str); 8] = [
+    (Primitive::Cube, "Cube"),
+    (Primitive::UVSphere, "UV sphere"),
+    (Primitive::Icosphere, "Icosphere"),
+    (Primitive::Plane, "Plane"),
+    (Primitive::Cylinder, "Cylinder"),
+    (Primitive::Cone, "Cone"),
+    (Primitive::Torus, "Torus"),
+    (Primitive::Capsule, "Capsule"),
+];
+
+struct MeshBuilder {
+    vertices: mesh::Vertices,
+    normals: mesh::Normals,
+    uvs: mesh::UVs,
+    indices: mesh::Indices,
+}
+
+impl MeshBuilder {
+    fn new() -> MeshBuilder {
+        MeshBuilder {
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    fn add_vertex(&mut self, position: math::Vec3f, normal: math::Vec3f, uv: math::Vec2f) -> u32 {
+        self.vertices.push(position);
+        self.normals.push(normal);
+        self.uvs.push(uv);
+        self.vertices.len() as u32 - 1
+    }
+
+    // Winding follows the vertex normals so generators don't have to care about it,
+    // triangles collapsed at poles and apexes are dropped
+    fn add_triangle(&mut self, a: u32, b: u32, c: u32) {
+        let (pa, pb, pc) = (
+            self.vertices[a as usize],
+            self.vertices[b as usize],
+            self.vertices[c as usize],
+        );
+        let face_normal = math::cross(pb - pa, pc - pa);
+        if math::length_squared_vec3(face_normal) == 0. {
+            return;
+        }
+
+        let normal = self.normals[a as usize] + self.normals[b as usize] + self.normals[c as usize];
+        let (b, c) = if math::dot_vec3(face_normal, normal) < 0. {
+            (c, b)
+        } else {
+            (b, c)
+        };
+
+        self.indices.extend_from_slice(&[
+            math::Vec1u::new(a),
+            math::Vec1u::new(b),
+            math::Vec1u::new(c),
+        ]);
+    }
+
+    // Adds (columns + 1) x (rows + 1) vertices and two triangles per cell
+    fn add_grid<F>(&mut self, columns: u32, rows: u32, vertex: F)
+    where
+        F: Fn(u32, u32) -> (math::Vec3f, math::Vec3f, math::Vec2f),
+    {
+        let first = self.vertices.len() as u32;
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let (position, normal, uv) = vertex(column, row);
+                self.add_vertex(position, normal, uv);
+            }
+        }
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let a = first + row * (columns + 1) + column;
+                let b = a + 1;
+                let c = a + columns + 1;
+                let d = c + 1;
+                self.add_triangle(a, b, d);
+                self.add_triangle(a, d, c);
+            }
+        }
+    }
+
+    // Revolves a profile of (radius, height, radial normal, vertical normal, v) around +Y
+    fn add_lathe(&mut self, segments: u32, profile: &[(f32, f32, f32, f32, f32)]) {
+        self.add_grid(segments, profile.len() as u32 - 1, |column, row| {
+            let (radius, height, normal_radius, normal_height, v) = profile[row as usize];
+            let (sin, cos) = calculate_segment_angle(column, segments).sin_cos();
+
+            (
+                math::Vec3f::new(radius * cos, height, radius * sin),
+                math::normalize_vec3(math::Vec3f::new(
+                    normal_radius * cos,
+                    normal_height,
+                    normal_radius * sin,
+                )),
+                math::Vec2f::new(column as f32 / segments as f32, v),
+            )
+        });
+    }
+
+    fn add_disc(&mut self, segments: u32, radius: f32, height: f32, normal: math::Vec3f) {
+        let center = self.add_vertex(
+            math::Vec3f::new(0., height, 0.),
+            normal,
+            math::Vec2f::new(0.5, 0.5),
+        );
+        for column in 0..=segments {
+            let (sin, cos) = calculate_segment_angle(column, segments).sin_cos();
+            self.add_vertex(
+                math::Vec3f::new(radius * cos, height, radius * sin),
+                normal,
+                math::Vec2f::new(0.5 + 0.5 * cos, 0.5 + 0.5 * sin),
+            );
+        }
+        for column in 0..segments {
+            self.add_triangle(center, center + column + 1, center + column + 2);
+        }
+    }
+
+    fn build(self, name: &str) -> mesh::HostMesh {
+        let (tangents, bitangents, indices, remap) = helper::calculate_tangents_and_bitangents(
+            &self.indices,
+            &self.vertices,
+            &self.normals,
+            &self.uvs,
+        )
+        .expect("Primitives have a uv and a normal for every vertex.");
+
+        mesh::HostMesh::new(
+            name.to_string(),
+            0,
+            helper::remap_vertex_attribute(&self.vertices, &remap),
+            helper::remap_vertex_attribute(&self.normals, &remap),
+            tangents,
+            bitangents,
+            helper::remap_vertex_attribute(&self.uvs, &remap),
+            indices,
+        )
+    }
+}
+
+pub fn create_cube_host_mesh(size: f32, subdivisions: u32) -> mesh::HostMesh {
+    let subdivisions = subdivisions.max(1);
+    let half_size = size * 0.5;
+
+    // (normal, u axis, v axis) with u x v = normal so no face has mirrored uvs
+    let faces = [
+        ((1., 0., 0.), (0., 0., -1.), (0., 1., 0.)),
+        ((-1., 0., 0.), (0., 0., 1.), (0., 1., 0.)),
+        ((0., 1., 0.), (1., 0., 0.), (0., 0., -1.)),
+        ((0., -1., 0.), (1., 0., 0.), (0., 0., 1.)),
+        ((0., 0., 1.), (1., 0., 0.), (0., 1., 0.)),
+        ((0., 0., -1.), (-1., 0., 0.), (0., 1., 0.)),
+    ];
+
+    let mut builder = MeshBuilder::new();
+    for &(normal, u_axis, v_axis) in &faces {
+        let normal = math::Vec3f::new(normal.0, normal.1, normal.2);
+        let u_axis = math::Vec3f::new(u_axis.0, u_axis.1, u_axis.2);
+        let v_axis = math::Vec3f::new(v_axis.0, v_axis.1, v_axis.2);
+
+        builder.add_grid(subdivisions, subdivisions, |column, row| {
+            let u = column as f32 / subdivisions as f32;
+            let v = row as f32 / subdivisions as f32;
+            (
+                (normal + u_axis * (u * 2. - 1.) + v_axis * (v * 2. - 1.)) * half_size,
+                normal,
+                math::Vec2f::new(u, v),
+            )
+        });
+    }
+
+    builder.build("Cube")
+}
+
+pub fn create_uv_sphere_host_mesh(radius: f32, segments: u32, rings: u32) -> mesh::HostMesh {
+    let segments = segments.max(MIN_TESSELLATION);
+    let rings = rings.max(2);
+
+    let profile: Vec<(f32, f32, f32, f32, f32)> = (0..=rings)
+        .map(|ring| {
+            let (sin, cos) = calculate_ring_angle(ring, rings);
+            (
+                radius * sin,
+                radius * cos,
+                sin,
+                cos,
+                1. - ring as f32 / rings as f32,
+            )
+        })
+        .collect();
+
+    let mut builder = MeshBuilder::new();
+    builder.add_lathe(segments, &profile);
+    builder.build("UV sphere")
+}
+
+pub fn create_icosphere_host_mesh(radius: f32, subdivisions: u32) -> mesh::HostMesh {
+    if subdivisions > MAX_ICOSPHERE_SUBDIVISIONS {
+        log::log_warning(format!(
+            "Icosphere with {} subdivisions is limited to {}.",
+            subdivisions, MAX_ICOSPHERE_SUBDIVISIONS
+        ));
+    }
+    // The first subdivision puts vertices at the poles, so no triangle has one inside it
+    let subdivisions = subdivisions.clamp(1, MAX_ICOSPHERE_SUBDIVISIONS);
+    let t = (1. + 5_f32.sqrt()) * 0.5;
+    let mut directions: Vec<math::Vec3f> = [
+        (-1., t, 0.),
+        (1., t, 0.),
+        (-1., -t, 0.),
+        (1., -t, 0.),
+        (0., -1., t),
+        (0., 1., t),
+        (0., -1., -t),
+        (0., 1., -t),
+        (t, 0., -1.),
+        (t, 0., 1.),
+        (-t, 0., -1.),
+        (-t, 0., 1.),
+    ]
+    .iter()
+    .map(|&(x, y, z)| math::normalize_vec3(math::Vec3f::new(x, y, z)))
+    .collect();
+
+    let mut triangles: Vec<[usize; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut midpoint = |a: usize, b: usize, directions: &mut Vec<math::Vec3f>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                directions.push(math::normalize_vec3((directions[a] + directions[b]) * 0.5));
+                directions.len() - 1
+            })
+        };
+
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let ab = midpoint(a, b, &mut directions);
+                let bc = midpoint(b, c, &mut directions);
+                let ca = midpoint(c, a, &mut directions);
+                vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let uvs: Vec<math::Vec2f> = directions
+        .iter()
+        .map(|x| {
+            math::Vec2f::new(
+                0.5 + x.z.atan2(x.x) / (2. * PI),
+                0.5 + x.y.clamp(-1., 1.).asin() / PI,
+            )
+        })
+        .collect();
+
+    let mut builder = MeshBuilder::new();
+    for (&direction, &uv) in directions.iter().zip(uvs.iter()) {
+        builder.add_vertex(direction * radius, direction, uv);
+    }
+
+    // Triangles crossing the u seam are cut along it, the part past the seam wraps to u = 0.
+    // Corners on the seam or at a pole lie on the cut and get a copy on each side.
+    let mut cut_vertices: HashMap<(usize, bool), u32> = HashMap::new();
+    let mut seam_vertices: HashMap<(usize, usize, bool), u32> = HashMap::new();
+    for triangle in &triangles {
+        let us: Vec<f32> = triangle.iter().map(|&x| uvs[x].x).collect();
+        let crosses_seam = us.iter().cloned().fold(f32::MIN, f32::max)
+            - us.iter().cloned().fold(f32::MAX, f32::min)
+            > 0.5;
+        if !crosses_seam {
+            builder.add_triangle(triangle[0] as u32, triangle[1] as u32, triangle[2] as u32);
+            continue;
+        }
+
+        let unwrapped: Vec<f32> = triangle
+            .iter()
+            .map(|&x| {
+                if directions[x].z == 0. && directions[x].x <= 0. {
+                    1.
+                } else if uvs[x].x < 0.5 {
+                    uvs[x].x + 1.
+                } else {
+                    uvs[x].x
+                }
+            })
+            .collect();
+
+        for &wrapped in &[false, true] {
+            let is_inside = |u: f32| if wrapped { u >= 1. } else { u <= 1. };
+            let seam_u = if wrapped { 0. } else { 1. };
+            let to_uv = |direction: math::Vec3f| {
+                math::Vec2f::new(seam_u, 0.5 + direction.y.clamp(-1., 1.).asin() / PI)
+            };
+
+            let mut polygon: Vec<u32> = Vec::new();
+            for corner in 0..3 {
+                let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+                let (unwrapped_a, unwrapped_b) = (unwrapped[corner], unwrapped[(corner + 1) % 3]);
+
+                if is_inside(unwrapped_a) {
+                    polygon.push(if unwrapped_a == 1. {
+                        *cut_vertices.entry((a, wrapped)).or_insert_with(|| {
+                            builder.add_vertex(
+                                directions[a] * radius,
+                                directions[a],
+                                to_uv(directions[a]),
+                            )
+                        })
+                    } else {
+                        a as u32
+                    });
+                }
+                if (unwrapped_a - 1.) * (unwrapped_b - 1.) < 0. {
+                    let key = (a.min(b), a.max(b), wrapped);
+                    polygon.push(*seam_vertices.entry(key).or_insert_with(|| {
+                        let (da, db) = (directions[key.0], directions[key.1]);
+                        let direction =
+                            math::normalize_vec3(da + (db - da) * (da.z / (da.z - db.z)));
+                        builder.add_vertex(direction * radius, direction, to_uv(direction))
+                    }));
+                }
+            }
+            for i in 1..polygon.len().saturating_sub(1) {
+                builder.add_triangle(polygon[0], polygon[i], polygon[i + 1]);
+            }
+        }
+    }
+
+    builder.build("Icosphere")
+}
+
+// Lies in the XZ plane facing +Y
+pub fn create_plane_host_mesh(size: f32, subdivisions: u32) -> mesh::HostMesh {
+    let subdivisions = subdivisions.max(1);
+
+    let mut builder = MeshBuilder::new();
+    builder.add_grid(subdivisions, subdivisions, |column, row| {
+        let u = column as f32 / subdivisions as f32;
+        let v = row as f32 / subdivisions as f32;
+        (
+            math::Vec3f::new(u - 0.5, 0., 0.5 - v) * size,
+            math::Vec3f::new(0., 1., 0.),
+            math::Vec2f::new(u, v),
+        )
+    });
+    builder.build("Plane")
+}
+
+pub fn create_cylinder_host_mesh(
+    radius: f32,
+    height: f32,
+    segments: u32,
+    height_segments: u32,
+) -> mesh::HostMesh {
+    let segments = segments.max(MIN_TESSELLATION);
+    let height_segments = height_segments.max(1);
+
+    let profile: Vec<(f32, f32, f32, f32, f32)> = (0..=height_segments)
+        .map(|row| {
+            let v = row as f32 / height_segments as f32;
+            (radius, (v - 0.5) * height, 1., 0., v)
+        })
+        .collect();
+
+    let mut builder = MeshBuilder::new();
+    builder.add_lathe(segments, &profile);
+    builder.add_disc(segments, radius, height * 0.5, math::Vec3f::new(0., 1., 0.));
+    builder.add_disc(
+        segments,
+        radius,
+        -height * 0.5,
+        math::Vec3f::new(0., -1., 0.),
+    );
+    builder.build("Cylinder")
+}
+
+pub fn create_cone_host_mesh(
+    radius: f32,
+    height: f32,
+    segments: u32,
+    height_segments: u32,
+) -> mesh::HostMesh {
+    let segments = segments.max(MIN_TESSELLATION);
+    let height_segments = height_segments.max(1);
+
+    // The apex row keeps the slope normal, collapsed triangles there are dropped
+    let profile: Vec<(f32, f32, f32, f32, f32)> = (0..=height_segments)
+        .map(|row| {
+            let v = row as f32 / height_segments as f32;
+            (radius * (1. - v), (v - 0.5) * height, height, radius, v)
+        })
+        .collect();
+
+    let mut builder = MeshBuilder::new();
+    builder.add_lathe(segments, &profile);
+    builder.add_disc(
+        segments,
+        radius,
+        -height * 0.5,
+        math::Vec3f::new(0., -1., 0.),
+    );
+    builder.build("Cone")
+}
+
+pub fn create_torus_host_mesh(
+    major_radius: f32,
+    minor_radius: f32,
+    segments: u32,
+    sides: u32,
+) -> mesh::HostMesh {
+    let segments = segments.max(MIN_TESSELLATION);
+    let sides = sides.max(MIN_TESSELLATION);
+
+    let profile: Vec<(f32, f32, f32, f32, f32)> = (0..=sides)
+        .map(|side| {
+            let (sin, cos) = calculate_segment_angle(side, sides).sin_cos();
+            (
+                major_radius + minor_radius * cos,
+                minor_radius * sin,
+                cos,
+                sin,
+                side as f32 / sides as f32,
+            )
+        })
+        .collect();
+
+    let mut builder = MeshBuilder::new();
+    builder.add_lathe(segments, &profile);
+    builder.build("Torus")
+}
+
+// The height excludes the hemispherical caps
+pub fn create_capsule_host_mesh(
+    radius: f32,
+    height: f32,
+    segments: u32,
+    rings: u32,
+) -> mesh::HostMesh {
+    let segments = segments.max(MIN_TESSELLATION);
+    let rings = rings.max(1);
+
+    // v follows the arc length of the profile so the texel density stays uniform
+    let length = PI * radius + height;
+    let cap_length = PI * radius * 0.5;
+
+    let mut profile: Vec<(f32, f32, f32, f32, f32)> = Vec::new();
+    for ring in 0..=rings {
+        let (sin, cos) = calculate_ring_angle(ring, rings * 2);
+        let arc = cap_length * ring as f32 / rings as f32;
+        profile.push((
+            radius * sin,
+            height * 0.5 + radius * cos,
+            sin,
+            cos,
+            1. - arc / length,
+        ));
+    }
+    for ring in rings..=rings * 2 {
+        let (sin, cos) = calculate_ring_angle(ring, rings * 2);
+        let arc = cap_length * ring as f32 / rings as f32 + height;
+        profile.push((
+            radius * sin,
+            -height * 0.5 + radius * cos,
+            sin,
+            cos,
+            1. - arc / length,
+        ));
+    }
+
+    let mut builder = MeshBuilder::new();
+    builder.add_lathe(segments, &profile);
+    builder.build("Capsule")
+}
+
+pub fn create_full_screen_triangle_host_mesh() -> mesh::HostMesh {
+    let mut builder = MeshBuilder::new();
+    let normal = math::Vec3f::new(0., 0., 1.);
+    let a = builder.add_vertex(
+        math::Vec3f::new(-1., -1., 0.5),
+        normal,
+        math::Vec2f::new(0., 0.),
+    );
+    let b = builder.add_vertex(
+        math::Vec3f::new(3., -1., 0.5),
+        normal,
+        math::Vec2f::new(2., 0.),
+    );
+    let c = builder.add_vertex(
+        math::Vec3f::new(-1., 3., 0.5),
+        normal,
+        math::Vec2f::new(0., 2.),
+    );
+    builder.add_triangle(a, b, c);
+    builder.build("Triangle")
+}
+
+// Unit sized primitive, the tessellation is the segment count around the widest circle
+pub fn create_primitive_host_mesh(primitive: Primitive, tessellation: u32) -> mesh::HostMesh {
+    let tessellation = tessellation.clamp(MIN_TESSELLATION, MAX_TESSELLATION);
+
+    match primitive {
+        Primitive::Cube => create_cube_host_mesh(1., (tessellation / 8).max(1)),
+        Primitive::UVSphere => create_uv_sphere_host_mesh(0.5, tessellation, tessellation / 2),
+        Primitive::Icosphere => create_icosphere_host_mesh(
+            0.5,
+            (32 - (tessellation / 5).max(1).leading_zeros()).min(MAX_ICOSPHERE_SUBDIVISIONS),
+        ),
+        Primitive::Plane => create_plane_host_mesh(1., (tessellation / 8).max(1)),
+        Primitive::Cylinder => create_cylinder_host_mesh(0.5, 1., tessellation, 1),
+        Primitive::Cone => create_cone_host_mesh(0.5, 1., tessellation, 1),
+        Primitive::Torus => create_torus_host_mesh(0.375, 0.125, tessellation, tessellation / 2),
+        Primitive::Capsule => create_capsule_host_mesh(0.25, 0.5, tessellation, tessellation / 4),
+    }
+}
+
+pub fn create_primitive_host_model(primitive: Primitive, tessellation: u32) -> model::HostModel {
+    model::HostModel {
+        meshes: Arc::new(vec![create_primitive_host_mesh(primitive, tessellation)]),
+        materials: Arc::new(vec![create_lookdev_host_material(
+            math::Vec3f::new(1., 1., 1.),
+            0.5,
+            0.,
+        )]),
+        skeleton: None,
+        animations: Arc::new(Vec::new()),
+        warnings: Vec::new(),
+    }
+}
+
+// Untextured material that provides every uniform of the lighting technique
+pub fn create_lookdev_host_material(
+    albedo: math::Vec3f,
+    roughness: f32,
+    metalness: f32,
+) -> material::HostMaterial {
+    let properties_1u = [
+        ("uAlbedoMapAvailableUint", 0),
+        ("uNormalMapAvailableUint", 0),
+        ("uBumpMapAvailableUint", 0),
+        ("uMetallicAvailableUint", 0),
+        ("uRoughnessAvailableUint", 0),
+        ("uOcclusionMapAvailableUint", 0),
+        ("uEmissiveMapAvailableUint", 0),
+        ("uLightMapAvailableUint", 0),
+        ("uOcclusionUVSetUint", 0),
+        ("uLightMapUVSetUint", 1),
+    ];
+    let properties_1f = [
+        ("uScalarRoughnessVec1f", roughness),
+        ("uScalarMetalnessVec1f", metalness),
+        ("uScalarOpacityVec1f", 1.),
+    ];
+    let properties_3f = [
+        ("uScalarAlbedoVec3f", albedo),
+        ("uScalarEmissiveVec3f", math::zero_vec3()),
+    ];
+
+    material::HostMaterial {
+        name: "Lookdev".to_string(),
+        properties_1u: properties_1u
+            .iter()
+            .map(|&(name, value)| material::Property {
+                name: name.to_string(),
+                value: math::Vec1u::new(value),
+            })
+            .collect(),
+        properties_1f: properties_1f
+            .iter()
+            .map(|&(name, value)| material::Property {
+                name: name.to_string(),
+                value: math::Vec1f::new(value),
+            })
+            .collect(),
+        properties_3f: properties_3f
+            .iter()
+            .map(|&(name, value)| material::Property {
+                name: name.to_string(),
+                value,
+            })
+            .collect(),
+        properties_samplers: Vec::new(),
+    }
+}
+
+fn calculate_segment_angle(segment: u32, segments: u32) -> f32 {
+    2. * PI * segment as f32 / segments as f32
+}
+
+// Returns (sin, cos) of the polar angle, exact at the poles so pole triangles collapse
+fn calculate_ring_angle(ring: u32, rings: u32) -> (f32, f32) {
+    if ring == 0 {
+        (0., 1.)
+    } else if ring == rings {
+        (0., -1.)
+    } else {
+        (PI * ring as f32 / rings as f32).sin_cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Closest point of the surface the primitive is offset from, the torus is built around its tube
+    fn calculate_center(primitive: Primitive, position: math::Vec3f) -> math::Vec3f {
+        match primitive {
+            Primitive::Torus => {
+                math::normalize_vec3(math::Vec3f::new(position.x, 0., position.z)) * 0.375
+            }
+            _ => math::zero_vec3(),
+        }
+    }
+
+    #[test]
+    fn primitives_are_well_formed() {
+        for &(primitive, name) in &PRIMITIVES {
+            for &tessellation in &[MIN_TESSELLATION, 16, 64] {
+                let host_mesh = create_primitive_host_mesh(primitive, tessellation);
+                let vertex_count = host_mesh.vertices.len();
+                assert!(!host_mesh.indices.is_empty(), "{}", name);
+                assert_eq!(host_mesh.indices.len() % 3, 0, "{}", name);
+                assert!(
+                    host_mesh
+                        .indices
+                        .iter()
+                        .all(|i| (i.x as usize) < vertex_count),
+                    "{}: index out of bounds",
+                    name
+                );
+                for stream_length in &[
+                    host_mesh.normals.len(),
+                    host_mesh.tangents.len(),
+                    host_mesh.bitangents.len(),
+                    host_mesh.uvs.len(),
+                ] {
+                    assert_eq!(*stream_length, vertex_count, "{}", name);
+                }
+
+                for i in 0..vertex_count {
+                    let (position, normal, tangent, uv) = (
+                        host_mesh.vertices[i],
+                        host_mesh.normals[i],
+                        host_mesh.tangents[i],
+                        host_mesh.uvs[i],
+                    );
+                    assert!(
+                        (math::length_vec3(normal) - 1.).abs() < 1e-4,
+                        "{}: normal {} is not unit length",
+                        name,
+                        i
+                    );
+                    assert!(
+                        math::dot_vec3(tangent, normal).abs() < 1e-3,
+                        "{}: tangent {} is not orthogonal to its normal",
+                        name,
+                        i
+                    );
+                    assert!(
+                        (0. ..=1.).contains(&uv.x) && (0. ..=1.).contains(&uv.y),
+                        "{}: uv {} ({}, {}) is outside [0, 1]",
+                        name,
+                        i,
+                        uv.x,
+                        uv.y
+                    );
+
+                    let outward = match primitive {
+                        Primitive::Plane => normal.y > 0.99,
+                        _ => {
+                            math::dot_vec3(normal, position - calculate_center(primitive, position))
+                                > 0.
+                        }
+                    };
+                    assert!(outward, "{}: normal {} points inward", name, i);
+                }
+
+                // Windings agree with the vertex normals
+                for triangle in host_mesh.indices.chunks_exact(3) {
+                    let [a, b, c] = [
+                        triangle[0].x as usize,
+                        triangle[1].x as usize,
+                        triangle[2].x as usize,
+                    ];
+                    let (pa, pb, pc) = (
+                        host_mesh.vertices[a],
+                        host_mesh.vertices[b],
+                        host_mesh.vertices[c],
+                    );
+                    let face_normal = math::cross(pb - pa, pc - pa);
+                    let normal = host_mesh.normals[a] + host_mesh.normals[b] + host_mesh.normals[c];
+                    assert!(
+                        math::dot_vec3(face_normal, normal) > 0.,
+                        "{}: inverted triangle",
+                        name
+                    );
+                }
+            }
+        }
+    }
+
+    // Cutting along the uv seam must not open the surface, every edge has two triangles
+    #[test]
+    fn icosphere_seam_is_closed() {
+        for subdivisions in 1..=4 {
+            let host_mesh = create_icosphere_host_mesh(1., subdivisions);
+            let quantize = |i: &math::Vec1u| {
+                let p = host_mesh.vertices[i.x as usize] * 1e4;
+                (p.x.round() as i32, p.y.round() as i32, p.z.round() as i32)
+            };
+
+            let mut edge_counts: HashMap<_, u32> = HashMap::new();
+            for triangle in host_mesh.indices.chunks_exact(3) {
+                for corner in 0..3 {
+                    let a = quantize(&triangle[corner]);
+                    let b = quantize(&triangle[(corner + 1) % 3]);
+                    *edge_counts.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+                }
+            }
+            assert!(edge_counts.values().all(|&x| x == 2));
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use crate::asset::error::LoaderError;
+use crate::asset::{material, model};
+use crate::core::camera;
+use crate::gl::tex;
+use crate::helpers::loader::LoaderOptions;
+use crate::helpers::{cooker, loader, primitives};
+use crate::math;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+// Must match the light array sizes in lighting.frag
+pub const MAX_DIRECT_LIGHT_COUNT: usize = 4;
+pub const MAX_POINT_LIGHT_COUNT: usize = 8;
+// Must match the probe sampler count in lighting.frag
+pub const MAX_REFLECTION_PROBE_COUNT: usize = 4;
+
+pub const DEFAULT_SCENE_PATH: &str = "scenes/default.json";
+
+// File paths are relative to the working directory, like the rest of the data folder
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SceneDescription {
+    pub models: Vec<ModelDescription>,
+    pub hdri: String,
+    #[serde(default)]
+    pub camera: CameraDescription,
+    #[serde(default = "create_default_lights")]
+    pub lights: Vec<Light>,
+    #[serde(default)]
+    pub diffuse_irradiance: DiffuseIrradiance,
+    // Radians around the vertical axis
+    #[serde(default)]
+    pub environment_rotation: f32,
+    #[serde(default = "create_default_environment_intensity")]
+    pub environment_intensity: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reflection_probes: Vec<ReflectionProbeDescription>,
+}
+
+// Captured from the position, reflections inside the box are projected onto its faces
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct ReflectionProbeDescription {
+    pub position: [f32; 3],
+    pub box_min: [f32; 3],
+    pub box_max: [f32; 3],
+    // Fade width inside the box faces, zero gives a hard edge
+    #[serde(default)]
+    pub blend_distance: f32,
+}
+
+// Source of the diffuse image based lighting
+#[derive(Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffuseIrradiance {
+    #[default]
+    SphericalHarmonics,
+    CubeMap,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ModelDescription {
+    pub source: ModelSource,
+    #[serde(default)]
+    pub transform: TransformDescription,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub material_overrides: Vec<MaterialOverride>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelSource {
+    File(String),
+    Primitive {
+        primitive: primitives::Primitive,
+        tessellation: u32,
+    },
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransformDescription {
+    pub translation: [f32; 3],
+    // Euler angles in degrees, applied around x, then y, then z
+    pub rotation: [f32; 3],
+    pub scale: [f32; 3],
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MaterialOverride {
+    // Every material of the model is overridden when the name is missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>,
+    pub properties: BTreeMap<String, PropertyOverride>,
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PropertyOverride {
+    Scalar(f32),
+    Vec3([f32; 3]),
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraDescription {
+    pub position: [f32; 3],
+    // Angles in degrees
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Light {
+    Directional {
+        direction: [f32; 3],
+        color: [f32; 3],
+        radiance: f32,
+    },
+    Point {
+        position: [f32; 3],
+        color: [f32; 3],
+        radiance: f32,
+    },
+}
+
+// Stands in for a scene that cannot be loaded, there is no HDRI to fall back to
+impl Default for SceneDescription {
+    fn default() -> SceneDescription {
+        SceneDescription {
+            models: Vec::new(),
+            hdri: String::new(),
+            camera: CameraDescription::default(),
+            lights: create_default_lights(),
+            diffuse_irradiance: DiffuseIrradiance::default(),
+            environment_rotation: 0.,
+            environment_intensity: create_default_environment_intensity(),
+            reflection_probes: Vec::new(),
+        }
+    }
+}
+
+impl Default for TransformDescription {
+    fn default() -> TransformDescription {
+        TransformDescription {
+            translation: [0., 0., 0.],
+            rotation: [0., 0., 0.],
+            scale: [1., 1., 1.],
+        }
+    }
+}
+
+impl Default for CameraDescription {
+    fn default() -> CameraDescription {
+        create_camera_description(&camera::create_default_camera(1, 1))
+    }
+}
+
+pub fn create_default_environment_intensity() -> f32 {
+    1.
+}
+
+pub fn create_default_lights() -> Vec<Light> {
+    let white = [1., 1., 1.];
+    let mut lights = vec![Light::Directional {
+        direction: [0., 1., 0.],
+        color: white,
+        radiance: 1.,
+    }];
+    for &position in &[
+        [-50., 50., -20.],
+        [50., 50., -20.],
+        [-50., -50., -20.],
+        [50., -50., -20.],
+        [0., 0., -20.],
+    ] {
+        lights.push(Light::Point {
+            position,
+            color: white,
+            radiance: 1000.,
+        });
+    }
+
+    lights
+}
+
+pub fn load_scene_description(file_path: &Path) -> Result<SceneDescription, LoaderError> {
+    let data = fs::read_to_string(file_path).map_err(|error| {
+        LoaderError::Io(format!(
+            "Failed to read '{}' '{}'",
+            file_path.to_str().unwrap_or_default(),
+            error
+        ))
+    })?;
+
+    serde_json::from_str(&data).map_err(|error| {
+        LoaderError::Parse(format!(
+            "Failed to parse scene '{}' '{}'",
+            file_path.to_str().unwrap_or_default(),
+            error
+        ))
+    })
+}
+
+pub fn save_scene_description(
+    file_path: &Path,
+    scene: &SceneDescription,
+) -> Result<(), LoaderError> {
+    let data = serde_json::to_string_pretty(scene)
+        .map_err(|error| LoaderError::Parse(format!("Failed to serialize scene '{}'", error)))?;
+
+    if let Some(folder_path) = file_path.parent() {
+        if !folder_path.as_os_str().is_empty() {
+            fs::create_dir_all(folder_path).map_err(|error| {
+                LoaderError::Io(format!(
+                    "Failed to create folder '{}' '{}'",
+                    folder_path.to_str().unwrap_or_default(),
+                    error
+                ))
+            })?;
+        }
+    }
+
+    fs::write(file_path, data + "\n").map_err(|error| {
+        LoaderError::Io(format!(
+            "Failed to write '{}' '{}'",
+            file_path.to_str().unwrap_or_default(),
+            error
+        ))
+    })
+}
+
+pub fn load_hdri_host_texture(scene: &SceneDescription) -> Result<tex::HostTexture, LoaderError> {
+    loader::load_host_texture_from_file(Path::new(&scene.hdri), "uHdriSampler2D")
+        .map_err(LoaderError::MissingTexture)
+}
+
+// Material overrides are applied here, the transform stays with the model's entity
+pub fn load_host_model_from_description(
+    description: &ModelDescription,
+    options: &LoaderOptions,
+) -> Result<model::HostModel, LoaderError> {
+    let mut host_model = load_host_model_from_source(&description.source, options)?;
+    if description.material_overrides.is_empty() {
+        return Ok(host_model);
+    }
+
+    let mut warnings = Vec::new();
+    let materials = host_model
+        .materials
+        .iter()
+        .map(|host_material| {
+            let mut host_material = host_material.clone();
+            for material_override in &description.material_overrides {
+                let matches = material_override
+                    .material
+                    .as_ref()
+                    .is_none_or(|name| *name == host_material.name);
+                if matches {
+                    apply_material_override(&mut host_material, material_override, &mut warnings);
+                }
+            }
+            host_material
+        })
+        .collect();
+    for material_override in &description.material_overrides {
+        if let Some(name) = &material_override.material {
+            if !host_model.materials.iter().any(|x| x.name == *name) {
+                warnings.push(LoaderError::Validation(format!(
+                    "Material override targets missing material '{}'",
+                    name
+                )));
+            }
+        }
+    }
+
+    host_model.materials = Arc::new(materials);
+    host_model.warnings.extend(warnings);
+    Ok(host_model)
+}
+
+pub fn load_host_model_from_source(
+    source: &ModelSource,
+    options: &LoaderOptions,
+) -> Result<model::HostModel, LoaderError> {
+    match source {
+        ModelSource::File(file_path) => {
+            let file_path = Path::new(file_path);
+            match file_path.extension().and_then(|x| x.to_str()) {
+                Some("obj") => cooker::load_host_model_from_obj_cached(file_path, options),
+                Some("gltf") | Some("glb") => loader::load_host_model_from_gltf(file_path, options),
+                _ => Err(LoaderError::Unsupported(format!(
+                    "'{}' is not an OBJ or glTF file",
+                    file_path.to_str().unwrap_or_default()
+                ))),
+            }
+        }
+        ModelSource::Primitive {
+            primitive,
+            tessellation,
+        } => Ok(primitives::create_primitive_host_model(
+            *primitive,
+            *tessellation,
+        )),
+    }
+}
+
+pub fn create_transform_mat4x4(transform: &TransformDescription) -> math::Mat4x4f {
+    let [x, y, z] = transform.rotation;
+
+    math::tranlation_mat4x4(create_vec3f(transform.translation))
+        * math::z_rotation_mat4x4(z.to_radians())
+        * math::y_rotation_mat4x4(y.to_radians())
+        * math::x_rotation_mat4x4(x.to_radians())
+        * math::scale_mat4x4(create_vec3f(transform.scale))
+}
+
+pub fn create_camera(description: &CameraDescription, width: u32, height: u32) -> camera::Camera {
+    camera::Camera {
+        pos: create_vec3f(description.position),
+        pitch: description.pitch.to_radians(),
+        yaw: description.yaw.to_radians(),
+        near: description.near,
+        far: description.far,
+        fov: description.fov.to_radians(),
+        ..camera::create_default_camera(width, height)
+    }
+}
+
+pub fn create_camera_description(camera: &camera::Camera) -> CameraDescription {
+    CameraDescription {
+        position: [camera.pos.x, camera.pos.y, camera.pos.z],
+        yaw: camera.yaw.to_degrees(),
+        pitch: camera.pitch.to_degrees(),
+        fov: camera.fov.to_degrees(),
+        near: camera.near,
+        far: camera.far,
+    }
+}
+
+fn apply_material_override(
+    host_material: &mut material::HostMaterial,
+    material_override: &MaterialOverride,
+    warnings: &mut Vec<LoaderError>,
+) {
+    for (name, value) in &material_override.properties {
+        let found = match *value {
+            // Flags and uv set indices are unsigned, every other scalar is a float
+            PropertyOverride::Scalar(value) => {
+                match host_material
+                    .properties_1u
+                    .iter_mut()
+                    .find(|x| x.name == *name)
+                {
+                    Some(property) => {
+                        property.value = math::Vec1u::new(value.max(0.) as u32);
+                        true
+                    }
+                    None => match host_material
+                        .properties_1f
+                        .iter_mut()
+                        .find(|x| x.name == *name)
+                    {
+                        Some(property) => {
+                            property.value = math::Vec1f::new(value);
+                            true
+                        }
+                        None => false,
+                    },
+                }
+            }
+            PropertyOverride::Vec3(value) => match host_material
+                .properties_3f
+                .iter_mut()
+                .find(|x| x.name == *name)
+            {
+                Some(property) => {
+                    property.value = create_vec3f(value);
+                    true
+                }
+                None => false,
+            },
+        };
+
+        if !found {
+            warnings.push(LoaderError::Validation(format!(
+                "Material '{}' has no property '{}' to override",
+                host_material.name, name
+            )));
+        }
+    }
+}
+
+fn create_vec3f(value: [f32; 3]) -> math::Vec3f {
+    math::Vec3f::new(value[0], value[1], value[2])
+}
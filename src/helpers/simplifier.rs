@@ -0,0 +1,456 @@
+use crate::asset::{mesh, model};
+use crate::helpers::{helper, log, optimizer};
+use crate::math;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+pub const MAX_LOD_COUNT: usize = 4;
+pub const LOD_TRIANGLE_RATIO: f32 = 0.5;
+pub const MIN_LOD_TRIANGLE_COUNT: usize = 32;
+// Relative to the mesh bounding radius
+pub const MAX_LOD_ERROR: f32 = 0.1;
+
+#[derive(Copy, Clone, Default)]
+struct Quadric {
+    a: [f64; 10],
+}
+
+impl Quadric {
+    fn from_plane(normal: math::Vec3f, distance: f32) -> Quadric {
+        let (x, y, z, w) = (
+            normal.x as f64,
+            normal.y as f64,
+            normal.z as f64,
+            distance as f64,
+        );
+        Quadric {
+            a: [
+                x * x,
+                x * y,
+                x * z,
+                x * w,
+                y * y,
+                y * z,
+                y * w,
+                z * z,
+                z * w,
+                w * w,
+            ],
+        }
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (a, b) in self.a.iter_mut().zip(other.a.iter()) {
+            *a += b;
+        }
+    }
+
+    fn evaluate(&self, position: math::Vec3f) -> f64 {
+        let (x, y, z) = (position.x as f64, position.y as f64, position.z as f64);
+        let a = &self.a;
+
+        let error = a[0] * x * x
+            + 2. * a[1] * x * y
+            + 2. * a[2] * x * z
+            + 2. * a[3] * x
+            + a[4] * y * y
+            + 2. * a[5] * y * z
+            + 2. * a[6] * y
+            + a[7] * z * z
+            + 2. * a[8] * z
+            + a[9];
+
+        error.max(0.)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum VertexKind {
+    Manifold,
+    // Shares its position with other vertices and lies on exactly one attribute seam
+    Seam,
+    Locked,
+}
+
+struct Collapse {
+    from: usize,
+    to: usize,
+    error: f64,
+}
+
+pub fn generate_host_model_lods(host_model: &model::HostModel) -> model::HostModel {
+    let meshes: Vec<mesh::HostMesh> = host_model
+        .meshes
+        .iter()
+        .map(|host_mesh| {
+            let mut host_mesh = host_mesh.clone();
+            host_mesh.lods = generate_host_mesh_lods(&host_mesh);
+
+            log::log_info(format!(
+                "Mesh '{}' LOD triangles: {}",
+                host_mesh.name,
+                std::iter::once(&host_mesh.indices)
+                    .chain(host_mesh.lods.iter().map(|x| &x.indices))
+                    .map(|x| (x.len() / 3).to_string())
+                    .collect::<Vec<String>>()
+                    .join(" -> ")
+            ));
+
+            host_mesh
+        })
+        .collect();
+
+    model::HostModel {
+        meshes: Arc::new(meshes),
+        materials: host_model.materials.clone(),
+        skeleton: host_model.skeleton.clone(),
+        animations: host_model.animations.clone(),
+        warnings: host_model.warnings.clone(),
+    }
+}
+
+pub fn generate_host_mesh_lods(host_mesh: &mesh::HostMesh) -> Vec<mesh::MeshLod> {
+    let max_error = calculate_bounding_radius(&host_mesh.vertices) * MAX_LOD_ERROR;
+
+    let mut lods: Vec<mesh::MeshLod> = Vec::new();
+    let mut indices = host_mesh.indices.clone();
+    let mut error = 0.;
+
+    while lods.len() < MAX_LOD_COUNT {
+        let triangle_count = indices.len() / 3;
+        let target_triangle_count = (triangle_count as f32 * LOD_TRIANGLE_RATIO) as usize;
+        if target_triangle_count < MIN_LOD_TRIANGLE_COUNT {
+            break;
+        }
+
+        let (lod_indices, lod_error) = simplify_indices(
+            &indices,
+            &host_mesh.vertices,
+            target_triangle_count,
+            max_error,
+        );
+
+        // Stop once the simplifier cannot make meaningful progress
+        if lod_indices.len() / 3 > triangle_count * 7 / 8 {
+            break;
+        }
+
+        error = lod_error.max(error);
+        indices = lod_indices;
+        lods.push(mesh::MeshLod {
+            indices: optimizer::optimize_vertex_cache(
+                &indices,
+                host_mesh.vertices.len(),
+                optimizer::VERTEX_CACHE_SIZE,
+            ),
+            error,
+        });
+    }
+
+    lods
+}
+
+// Edge collapse with quadric error metrics from "Surface Simplification Using Quadric Error
+// Metrics". Vertices only collapse onto existing vertices so every LOD shares the vertex buffer.
+// Border vertices are locked to keep the silhouette. Seam vertices only slide along their seam and
+// all copies at one position collapse together, so the attributes on both sides stay intact.
+pub fn simplify_indices(
+    indices: &mesh::Indices,
+    vertices: &mesh::Vertices,
+    target_triangle_count: usize,
+    max_error: f32,
+) -> (mesh::Indices, f32) {
+    let mut indices: Vec<usize> = indices.iter().map(|i| i.x as usize).collect();
+
+    let positions = create_position_groups(vertices);
+    let mut groups: Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+    for (i, &position) in positions.iter().enumerate() {
+        groups[position].push(i);
+    }
+    let (kinds, seam_edges) = classify_vertices(&indices, &positions);
+    let mut quadrics = calculate_vertex_quadrics(&indices, vertices, &positions);
+
+    let max_error = max_error as f64 * max_error as f64;
+    let mut result_error: f64 = 0.;
+
+    while indices.len() / 3 > target_triangle_count {
+        let adjacency = calculate_vertex_triangles(&indices, vertices.len());
+
+        let mut collapses: Vec<Collapse> = Vec::new();
+        for triangle in indices.chunks(3) {
+            for k in 0..3 {
+                let (from, to) = (triangle[k], triangle[(k + 1) % 3]);
+                for &(from, to) in &[(from, to), (to, from)] {
+                    let edge = (
+                        positions[from].min(positions[to]),
+                        positions[from].max(positions[to]),
+                    );
+                    if kinds[from] == VertexKind::Locked
+                        || (kinds[from] == VertexKind::Seam && !seam_edges.contains(&edge))
+                    {
+                        continue;
+                    }
+
+                    let mut quadric = quadrics[positions[from]];
+                    quadric.add(&quadrics[positions[to]]);
+                    let error = quadric.evaluate(vertices[to]);
+                    if error <= max_error {
+                        collapses.push(Collapse { from, to, error });
+                    }
+                }
+            }
+        }
+        if collapses.is_empty() {
+            break;
+        }
+        collapses.sort_by(|a, b| a.error.partial_cmp(&b.error).unwrap());
+
+        let mut remap: Vec<usize> = (0..vertices.len()).collect();
+        let mut touched: Vec<bool> = vec![false; vertices.len()];
+        let mut triangle_count = indices.len() / 3;
+
+        for collapse in &collapses {
+            if triangle_count <= target_triangle_count {
+                break;
+            }
+            let pairs = match find_collapse_pairs(
+                collapse, &indices, &adjacency, &positions, &groups, &kinds,
+            ) {
+                Some(pairs) => pairs,
+                None => continue,
+            };
+            if pairs.iter().any(|&(from, to)| {
+                touched[from]
+                    || touched[to]
+                    || is_collapse_flipping(from, to, &indices, vertices, &adjacency[from])
+            }) {
+                continue;
+            }
+
+            for &(from, to) in &pairs {
+                for &triangle in &adjacency[from] {
+                    let triangle = &indices[triangle * 3..triangle * 3 + 3];
+                    if triangle.contains(&to) {
+                        triangle_count -= 1;
+                    }
+                    for &v in triangle {
+                        touched[v] = true;
+                    }
+                }
+                remap[from] = to;
+            }
+
+            let quadric = quadrics[positions[collapse.from]];
+            quadrics[positions[collapse.to]].add(&quadric);
+            result_error = result_error.max(collapse.error);
+        }
+
+        let index_count = indices.len();
+        indices = indices
+            .chunks(3)
+            .map(|x| [remap[x[0]], remap[x[1]], remap[x[2]]])
+            .filter(|x| x[0] != x[1] && x[1] != x[2] && x[2] != x[0])
+            .flat_map(|x| x.to_vec())
+            .collect();
+        if indices.len() == index_count {
+            break;
+        }
+    }
+
+    (
+        indices
+            .iter()
+            .map(|&i| math::Vec1u::new(i as u32))
+            .collect(),
+        result_error.sqrt() as f32,
+    )
+}
+
+pub fn calculate_bounding_radius(vertices: &mesh::Vertices) -> f32 {
+    math::calculate_bounding_sphere(vertices).1
+}
+
+// Maps every vertex to the first vertex sharing its position
+fn create_position_groups(vertices: &mesh::Vertices) -> Vec<usize> {
+    let mut first_vertex: HashMap<[u32; 3], usize> = HashMap::new();
+    vertices
+        .iter()
+        .enumerate()
+        .map(|(i, &vertex)| {
+            *first_vertex
+                .entry(helper::position_key(vertex))
+                .or_insert(i)
+        })
+        .collect()
+}
+
+// Vertices on border or non-manifold edges and seam corners are locked
+fn classify_vertices(
+    indices: &[usize],
+    positions: &[usize],
+) -> (Vec<VertexKind>, HashSet<(usize, usize)>) {
+    let mut group_sizes: Vec<usize> = vec![0; positions.len()];
+    for &position in positions {
+        group_sizes[position] += 1;
+    }
+
+    // Vertex pairs of every position edge, different pairs on both sides mark a seam
+    let mut edges: HashMap<(usize, usize), Vec<(usize, usize)>> = HashMap::new();
+    for triangle in indices.chunks(3) {
+        for k in 0..3 {
+            let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+            let (a, b) = if positions[a] < positions[b] {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            edges
+                .entry((positions[a], positions[b]))
+                .or_default()
+                .push((a, b));
+        }
+    }
+
+    let mut locked_groups: Vec<bool> = vec![false; positions.len()];
+    let mut seam_edge_counts: Vec<usize> = vec![0; positions.len()];
+    let mut seam_edges: HashSet<(usize, usize)> = HashSet::new();
+    for (&(a, b), pairs) in &edges {
+        if pairs.len() != 2 {
+            locked_groups[a] = true;
+            locked_groups[b] = true;
+        } else if pairs[0] != pairs[1] {
+            seam_edge_counts[a] += 1;
+            seam_edge_counts[b] += 1;
+            seam_edges.insert((a, b));
+        }
+    }
+
+    let kinds = positions
+        .iter()
+        .map(|&x| {
+            if locked_groups[x] {
+                VertexKind::Locked
+            } else if group_sizes[x] == 1 {
+                VertexKind::Manifold
+            } else if seam_edge_counts[x] == 2 {
+                VertexKind::Seam
+            } else {
+                VertexKind::Locked
+            }
+        })
+        .collect();
+
+    (kinds, seam_edges)
+}
+
+// Vertex pairs that move together, every copy of a seam vertex goes to the copy across its edge
+fn find_collapse_pairs(
+    collapse: &Collapse,
+    indices: &[usize],
+    adjacency: &[Vec<usize>],
+    positions: &[usize],
+    groups: &[Vec<usize>],
+    kinds: &[VertexKind],
+) -> Option<Vec<(usize, usize)>> {
+    let target = positions[collapse.to];
+    let neighbours = |v: usize| {
+        adjacency[v]
+            .iter()
+            .flat_map(move |&t| indices[t * 3..t * 3 + 3].iter().copied())
+    };
+
+    match kinds[collapse.from] {
+        VertexKind::Locked => None,
+        VertexKind::Manifold => {
+            // Another copy of the target would leave a zero area triangle behind
+            if neighbours(collapse.from).any(|v| positions[v] == target && v != collapse.to) {
+                None
+            } else {
+                Some(vec![(collapse.from, collapse.to)])
+            }
+        }
+        VertexKind::Seam => groups[positions[collapse.from]]
+            .iter()
+            .map(|&from| {
+                neighbours(from)
+                    .find(|&v| positions[v] == target)
+                    .map(|to| (from, to))
+            })
+            .collect(),
+    }
+}
+
+fn calculate_vertex_quadrics(
+    indices: &[usize],
+    vertices: &mesh::Vertices,
+    positions: &[usize],
+) -> Vec<Quadric> {
+    let mut quadrics: Vec<Quadric> = vec![Quadric::default(); vertices.len()];
+
+    for triangle in indices.chunks(3) {
+        let (p0, p1, p2) = (
+            vertices[triangle[0]],
+            vertices[triangle[1]],
+            vertices[triangle[2]],
+        );
+        let normal = math::cross(p1 - p0, p2 - p0);
+        if math::length_squared_vec3(normal) == 0. {
+            continue;
+        }
+        let normal = math::normalize_vec3(normal);
+
+        let quadric = Quadric::from_plane(normal, -math::dot_vec3(normal, p0));
+        for &v in triangle {
+            quadrics[positions[v]].add(&quadric);
+        }
+    }
+
+    quadrics
+}
+
+fn calculate_vertex_triangles(indices: &[usize], vertex_count: usize) -> Vec<Vec<usize>> {
+    let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    for (triangle, vertices) in indices.chunks(3).enumerate() {
+        for &v in vertices {
+            adjacency[v].push(triangle);
+        }
+    }
+    adjacency
+}
+
+fn is_collapse_flipping(
+    from: usize,
+    to: usize,
+    indices: &[usize],
+    vertices: &mesh::Vertices,
+    triangles: &[usize],
+) -> bool {
+    for &triangle in triangles {
+        let triangle = &indices[triangle * 3..triangle * 3 + 3];
+        if triangle.contains(&to) {
+            continue;
+        }
+
+        let position = |v: usize| {
+            if v == from {
+                vertices[to]
+            } else {
+                vertices[v]
+            }
+        };
+        let before = math::cross(
+            vertices[triangle[1]] - vertices[triangle[0]],
+            vertices[triangle[2]] - vertices[triangle[0]],
+        );
+        let after = math::cross(
+            position(triangle[1]) - position(triangle[0]),
+            position(triangle[2]) - position(triangle[0]),
+        );
+
+        if math::dot_vec3(before, after) <= 0. {
+            return true;
+        }
+    }
+
+    false
+}
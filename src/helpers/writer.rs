@@ -0,0 +1,374 @@
+use crate::gl::tex;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Picks the format from the extension: png, hdr or exr
+pub fn write_host_texture(texture: &tex::HostTexture, path: &Path) -> Result<(), String> {
+    let extension = path
+        .extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let data = match extension.as_str() {
+        "png" => encode_png(texture),
+        "hdr" => encode_hdr(texture),
+        "exr" => encode_exr(texture),
+        _ => Err(format!("Unsupported image extension '{}'", extension)),
+    }
+    .map_err(|msg| {
+        format!(
+            "Failed to write image '{}': {}",
+            path.to_str().unwrap_or_default(),
+            msg
+        )
+    })?;
+
+    if let Some(folder_path) = path.parent() {
+        fs::create_dir_all(folder_path).map_err(|error| error.to_string())?;
+    }
+    fs::write(path, data).map_err(|error| {
+        format!(
+            "Failed to write image '{}': {}",
+            path.to_str().unwrap_or_default(),
+            error
+        )
+    })
+}
+
+// 8 bits per channel, float data is clamped to [0, 1] without any encoding
+pub fn encode_png(texture: &tex::HostTexture) -> Result<Vec<u8>, String> {
+    let color_type = match texture.depth {
+        1 => png::ColorType::Grayscale,
+        2 => png::ColorType::GrayscaleAlpha,
+        3 => png::ColorType::Rgb,
+        4 => png::ColorType::Rgba,
+        _ => return Err(format!("Unsupported channel count {}", texture.depth)),
+    };
+    let pixels = convert_to_u8(texture)?;
+
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, texture.width as u32, texture.height as u32);
+    encoder.set_color(color_type);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| {
+            writer.write_image_data(&pixels)?;
+            writer.finish()
+        })
+        .map_err(|error| error.to_string())?;
+    Ok(data)
+}
+
+// Shared exponent RGB with run-length encoded scanlines
+pub fn encode_hdr(texture: &tex::HostTexture) -> Result<Vec<u8>, String> {
+    let pixels: Vec<image::Rgb<f32>> = convert_to_rgb_f32(texture)?
+        .into_iter()
+        .map(image::Rgb)
+        .collect();
+
+    let mut data = Vec::new();
+    image::codecs::hdr::HdrEncoder::new(&mut data)
+        .encode(&pixels, texture.width, texture.height)
+        .map_err(|error| error.to_string())?;
+    Ok(data)
+}
+
+// 32-bit float channels, grey data is written as luminance
+pub fn encode_exr(texture: &tex::HostTexture) -> Result<Vec<u8>, String> {
+    let depth = texture.depth;
+    let names: &[&str] = match depth {
+        1 => &["Y"],
+        2 => &["Y", "A"],
+        3 => &["R", "G", "B"],
+        4 => &["R", "G", "B", "A"],
+        _ => return Err(format!("Unsupported channel count {}", depth)),
+    };
+    let pixels = convert_to_f32(texture)?;
+
+    let channels = names
+        .iter()
+        .enumerate()
+        .map(|(channel, &name)| {
+            exr::prelude::AnyChannel::new(
+                name,
+                exr::prelude::FlatSamples::F32(
+                    pixels
+                        .iter()
+                        .skip(channel)
+                        .step_by(depth)
+                        .cloned()
+                        .collect(),
+                ),
+            )
+        })
+        .collect();
+    let image = exr::prelude::Image::from_channels(
+        (texture.width, texture.height),
+        exr::prelude::AnyChannels::sort(channels),
+    );
+
+    let mut data = std::io::Cursor::new(Vec::new());
+    exr::prelude::WritableImage::write(&image)
+        .to_buffered(&mut data)
+        .map_err(|error| error.to_string())?;
+    Ok(data.into_inner())
+}
+
+// Keeps the color channels of RGBA and grey + alpha data
+pub fn remove_alpha_channel(texture: &tex::HostTexture) -> tex::HostTexture {
+    let depth = texture.depth;
+    if depth != 2 && depth != 4 {
+        return texture.clone();
+    }
+
+    let mut result = tex::HostTexture::empty(
+        texture.name.clone(),
+        texture.width,
+        texture.height,
+        depth - 1,
+    );
+    result.data = match &texture.data {
+        tex::HostTextureData::UINT8(data) => tex::HostTextureData::UINT8(
+            data.chunks_exact(depth)
+                .flat_map(|x| x[..depth - 1].to_vec())
+                .collect(),
+        ),
+        tex::HostTextureData::FLOAT32(data) => tex::HostTextureData::FLOAT32(
+            data.chunks_exact(depth)
+                .flat_map(|x| x[..depth - 1].to_vec())
+                .collect(),
+        ),
+        tex::HostTextureData::COMPRESSED(..) => return texture.clone(),
+    };
+    result
+}
+
+// UTC date and time, sortable and safe for file names
+pub fn create_timestamp() -> String {
+    let duration = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = duration.as_secs() as i64;
+    let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+    // Days to civil date, from Howard Hinnant's date algorithms
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}{:02}{:02}_{:02}{:02}{:02}_{:03}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        duration.subsec_millis()
+    )
+}
+
+fn convert_to_u8(texture: &tex::HostTexture) -> Result<Vec<u8>, String> {
+    let count = texture.width * texture.height * texture.depth;
+    match &texture.data {
+        tex::HostTextureData::UINT8(data) if data.len() >= count => Ok(data[..count].to_vec()),
+        tex::HostTextureData::FLOAT32(data) if data.len() >= count => Ok(data[..count]
+            .iter()
+            .map(|&x| (x.clamp(0., 1.) * 255.).round() as u8)
+            .collect()),
+        tex::HostTextureData::COMPRESSED(..) => {
+            Err("block compressed data cannot be written".to_string())
+        }
+        _ => Err("texture has no data".to_string()),
+    }
+}
+
+fn convert_to_f32(texture: &tex::HostTexture) -> Result<Vec<f32>, String> {
+    let count = texture.width * texture.height * texture.depth;
+    match &texture.data {
+        tex::HostTextureData::UINT8(data) if data.len() >= count => {
+            Ok(data[..count].iter().map(|&x| x as f32 / 255.).collect())
+        }
+        tex::HostTextureData::FLOAT32(data) if data.len() >= count => Ok(data[..count].to_vec()),
+        tex::HostTextureData::COMPRESSED(..) => {
+            Err("block compressed data cannot be written".to_string())
+        }
+        _ => Err("texture has no data".to_string()),
+    }
+}
+
+fn convert_to_rgb_f32(texture: &tex::HostTexture) -> Result<Vec<[f32; 3]>, String> {
+    let depth = texture.depth;
+    let pixels = convert_to_f32(texture)?;
+    Ok(pixels
+        .chunks_exact(depth)
+        .map(|x| match depth {
+            1 | 2 => [x[0], x[0], x[0]],
+            _ => [x[0], x[1], x[2]],
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stb_image::image;
+
+    fn create_texture(
+        width: usize,
+        height: usize,
+        depth: usize,
+        data: tex::HostTextureData,
+    ) -> tex::HostTexture {
+        let mut texture = tex::HostTexture::empty("test".to_string(), width, height, depth);
+        texture.data = data;
+        texture
+    }
+
+    fn decode_u8(data: &[u8]) -> image::Image<u8> {
+        match image::load_from_memory(data) {
+            image::LoadResult::ImageU8(image) => image,
+            image::LoadResult::ImageF32(..) => panic!("Expected 8-bit data"),
+            image::LoadResult::Error(msg) => panic!("{}", msg),
+        }
+    }
+
+    fn decode_f32(data: &[u8]) -> image::Image<f32> {
+        match image::load_from_memory(data) {
+            image::LoadResult::ImageF32(image) => image,
+            image::LoadResult::ImageU8(..) => panic!("Expected float data"),
+            image::LoadResult::Error(msg) => panic!("{}", msg),
+        }
+    }
+
+    #[test]
+    fn png_round_trips_u8() {
+        // Repeated rows and a gradient exercise both matches and the scanline filters
+        for depth in 1..=4 {
+            let (width, height) = (37, 19);
+            let data: Vec<u8> = (0..width * height * depth)
+                .map(|i| ((i % (width * depth)) * 7 + (i / (width * depth * 4)) * 3) as u8)
+                .collect();
+            let texture = create_texture(width, height, depth, tex::HostTextureData::UINT8(data));
+
+            let image = decode_u8(&encode_png(&texture).unwrap());
+            assert_eq!(
+                (image.width, image.height, image.depth),
+                (width, height, depth)
+            );
+            match &texture.data {
+                tex::HostTextureData::UINT8(data) => assert_eq!(image.data, *data),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn png_round_trips_f32() {
+        let data: Vec<f32> = (0..16 * 8 * 3)
+            .map(|i| (i % 11) as f32 * 0.15 - 0.25)
+            .collect();
+        let texture = create_texture(16, 8, 3, tex::HostTextureData::FLOAT32(data.clone()));
+
+        let image = decode_u8(&encode_png(&texture).unwrap());
+        assert_eq!((image.width, image.height, image.depth), (16, 8, 3));
+        let expected: Vec<u8> = data
+            .iter()
+            .map(|&x| (x.clamp(0., 1.) * 255.).round() as u8)
+            .collect();
+        assert_eq!(image.data, expected);
+    }
+
+    #[test]
+    fn hdr_round_trips_f32() {
+        // The constant first row is longer than the 127 texels a single run can hold
+        let width = 300;
+        let mut data = vec![0.75; width * 3];
+        data.extend((0..width * 3).map(|i| [0.5, 2., 0.125, 6.][i % 4] * (1 + i % 3) as f32));
+        data.extend(vec![0.; width * 3]);
+        let texture = create_texture(width, 3, 3, tex::HostTextureData::FLOAT32(data.clone()));
+
+        let image = decode_f32(&encode_hdr(&texture).unwrap());
+        assert_eq!((image.width, image.height, image.depth), (width, 3, 3));
+        for (i, (&actual, &expected)) in image.data.iter().zip(&data).enumerate() {
+            // Shared exponents keep 8 bits of mantissa for the largest channel
+            let max = data[i / 3 * 3..i / 3 * 3 + 3]
+                .iter()
+                .cloned()
+                .fold(0., f32::max);
+            assert!((actual - expected).abs() <= max / 128., "texel {}", i);
+        }
+    }
+
+    #[test]
+    fn hdr_round_trips_u8() {
+        // Narrow images are written without run-length encoding
+        let data: Vec<u8> = (0..4 * 2).map(|i| (i * 30) as u8).collect();
+        let texture = create_texture(4, 2, 1, tex::HostTextureData::UINT8(data.clone()));
+
+        let image = decode_f32(&encode_hdr(&texture).unwrap());
+        assert_eq!((image.width, image.height, image.depth), (4, 2, 3));
+        for (texel, &expected) in image.data.chunks_exact(3).zip(&data) {
+            let expected = expected as f32 / 255.;
+            for &actual in texel {
+                assert!((actual - expected).abs() <= expected / 128.);
+            }
+        }
+    }
+
+    #[test]
+    fn exr_round_trips() {
+        use exr::prelude::*;
+
+        for depth in 1..=4 {
+            let data: Vec<f32> = (0..5 * 3 * depth).map(|i| i as f32 * 0.5 - 2.).collect();
+            let texture = create_texture(5, 3, depth, tex::HostTextureData::FLOAT32(data.clone()));
+
+            let image = read()
+                .no_deep_data()
+                .largest_resolution_level()
+                .all_channels()
+                .first_valid_layer()
+                .all_attributes()
+                .from_buffered(std::io::Cursor::new(encode_exr(&texture).unwrap()))
+                .unwrap();
+            assert_eq!(image.layer_data.size, Vec2(5, 3));
+
+            // Channels are sorted by name in the file
+            let names: &[&str] = match depth {
+                1 => &["Y"],
+                2 => &["Y", "A"],
+                3 => &["R", "G", "B"],
+                _ => &["R", "G", "B", "A"],
+            };
+            let channels = &image.layer_data.channel_data.list;
+            assert_eq!(channels.len(), depth);
+            for (channel, name) in names.iter().enumerate() {
+                let samples = channels
+                    .iter()
+                    .find(|x| x.name == **name)
+                    .unwrap()
+                    .sample_data
+                    .values_as_f32()
+                    .collect::<Vec<f32>>();
+                let expected: Vec<f32> =
+                    data.iter().skip(channel).step_by(depth).cloned().collect();
+                assert_eq!(samples, expected, "channel {}", name);
+            }
+        }
+    }
+}
@@ -1,28 +1,168 @@
 use crate::asset::model;
-use crate::core::{pass, tech};
+use crate::core::{camera, pass, tech};
 use crate::gl::{shader, tex};
-use crate::helpers::helper;
+use crate::helpers::environment::{self, EnvironmentSettings, HostEnvironment};
+use crate::helpers::{cooker, helper, log, scene};
 use crate::math;
 use crate::techniques;
 use std::f32;
-use std::ffi::c_void;
-use std::ptr::null;
+use std::path::Path;
 use std::rc::Rc;
 
-pub fn create_cube_map_texture(host_texture: &tex::HostTexture) -> Rc<tex::DeviceTexture> {
-    let width: u32 = 2048;
-    let height: u32 = 2048;
+pub const REFLECTION_PROBE_SIZE: usize = 128;
+
+pub struct DeviceEnvironment {
+    pub cube_map: Rc<tex::DeviceTexture>,
+    pub irradiance: Rc<tex::DeviceTexture>,
+    pub prefiltered: Rc<tex::DeviceTexture>,
+    pub brdf_lut: Rc<tex::DeviceTexture>,
+    pub irradiance_sh: Vec<math::Vec3f>,
+}
+
+pub struct ReflectionProbe {
+    pub description: scene::ReflectionProbeDescription,
+    // Laid out like DeviceEnvironment::prefiltered, None until the first capture
+    pub prefiltered: Option<Rc<tex::DeviceTexture>>,
+}
+
+impl ReflectionProbe {
+    pub fn new(description: scene::ReflectionProbeDescription) -> ReflectionProbe {
+        ReflectionProbe {
+            description,
+            prefiltered: None,
+        }
+    }
+}
+
+// Loads the bake cooked next to the HDRI, a missing or stale one is baked on the GPU and cooked
+pub fn create_environment(
+    hdri: &tex::HostTexture,
+    settings: &EnvironmentSettings,
+) -> DeviceEnvironment {
+    let hdri_path = Path::new(&hdri.name);
+    let source_hash = cooker::calculate_environment_hash(hdri, settings);
+    let cooked_path = cooker::get_cooked_environment_path(hdri_path);
+
+    if cooked_path.exists() {
+        match cooker::load_host_environment_from_cooked(&cooked_path, Some(source_hash)) {
+            Ok(host_environment) => return create_device_environment(&host_environment),
+            Err(error) => log::log_info(format!("Rebaking '{}': {}", hdri.name, error)),
+        }
+    }
+
+    let device_environment = bake_device_environment(hdri, settings);
+    if hdri_path.is_file() {
+        let host_environment = download_device_environment(&device_environment, settings);
+        match cooker::write_cooked_environment(&cooked_path, &host_environment, source_hash) {
+            Ok(()) => log::log_info(format!(
+                "Cooked '{}'",
+                cooked_path.to_str().unwrap_or_default()
+            )),
+            Err(error) => log::log_warning(error.to_string()),
+        }
+    }
+
+    device_environment
+}
+
+pub fn bake_device_environment(
+    hdri: &tex::HostTexture,
+    settings: &EnvironmentSettings,
+) -> DeviceEnvironment {
     let mut box_model = helper::load_skybox();
+    let cube_map = create_cube_map_texture(hdri, settings.cube_map_size);
+
+    DeviceEnvironment {
+        irradiance: create_diffuse_cube_map_texture(
+            cube_map.clone(),
+            &mut box_model,
+            settings.irradiance_size,
+        ),
+        prefiltered: create_prefiltered_environment_map(
+            cube_map.clone(),
+            &mut box_model,
+            settings.prefiltered_size,
+        ),
+        brdf_lut: create_brdf_lut(settings.brdf_lut_size),
+        irradiance_sh: environment::project_irradiance_sh(hdri),
+        cube_map,
+    }
+}
+
+// Cooked and CPU baked environments get the same formats as the GPU bake
+pub fn create_device_environment(host_environment: &HostEnvironment) -> DeviceEnvironment {
+    let attachment_desc = tex::Descriptor::new(tex::DescriptorType::ColorAttachment);
+
+    DeviceEnvironment {
+        cube_map: tex::DeviceTexture::new_cube_map(
+            &host_environment.cube_map,
+            &tex::Descriptor::new(tex::DescriptorType::SphericalHdri(
+                &host_environment.cube_map.px,
+            )),
+        ),
+        irradiance: tex::DeviceTexture::new_cube_map(
+            &host_environment.irradiance,
+            &attachment_desc,
+        ),
+        prefiltered: tex::DeviceTexture::new_cube_map(
+            &host_environment.prefiltered,
+            &tex::Descriptor::new(tex::DescriptorType::PrefilteredEnvMap),
+        ),
+        brdf_lut: tex::DeviceTexture::new(&host_environment.brdf_lut, &attachment_desc),
+        irradiance_sh: host_environment.irradiance_sh.clone(),
+    }
+}
+
+pub fn download_device_environment(
+    device_environment: &DeviceEnvironment,
+    settings: &EnvironmentSettings,
+) -> HostEnvironment {
+    let download_cube_map = |texture: &tex::DeviceTexture, level_count: usize| {
+        environment::create_host_cube_map_texture(
+            (0..6)
+                .map(|face| {
+                    let mut host_texture = texture.download_level(face, 0);
+                    host_texture.mips = (1..level_count)
+                        .map(|level| texture.download_level(face, level).data)
+                        .collect();
+                    host_texture
+                })
+                .collect(),
+        )
+    };
 
-    let (mut techs, pass) =
-        create_hdri_2_cube_map_pass(host_texture, &mut box_model, width, height);
+    HostEnvironment {
+        cube_map: download_cube_map(&device_environment.cube_map, 1),
+        irradiance: download_cube_map(&device_environment.irradiance, 1),
+        prefiltered: download_cube_map(
+            &device_environment.prefiltered,
+            environment::calculate_prefiltered_level_count(settings.prefiltered_size),
+        ),
+        brdf_lut: device_environment.brdf_lut.download_level(0, 0),
+        irradiance_sh: device_environment.irradiance_sh.clone(),
+    }
+}
 
-    let (fbos, pass) = draw_cubemap(&mut techs, pass, &box_model);
+pub fn create_cube_map_texture(
+    host_texture: &tex::HostTexture,
+    size: usize,
+) -> Rc<tex::DeviceTexture> {
+    let width = size as u32;
+    let height = size as u32;
+    let mut box_model = helper::load_skybox();
 
     let desc = tex::Descriptor::new(tex::DescriptorType::SphericalHdri(&host_texture));
-    let cubemap = create_cubemap(&desc, width, height, &fbos);
+    let cubemap = create_empty_cubemap(&desc, width, height);
 
-    cleanup_cubemap_fbos(&mut techs, &mut box_model, &pass);
+    let (mut techs, mut pass) =
+        create_hdri_2_cube_map_pass(host_texture, cubemap.clone(), &mut box_model, width, height);
+
+    draw_cube_map(&mut techs, &mut pass, &cubemap, &box_model);
+    if desc.use_mipmaps {
+        cubemap.generate_mipmaps();
+    }
+
+    cleanup_cube_map_pass(&mut techs, &mut box_model, &pass);
 
     cubemap
 }
@@ -30,19 +170,28 @@ pub fn create_cube_map_texture(host_texture: &tex::HostTexture) -> Rc<tex::Devic
 pub fn create_diffuse_cube_map_texture(
     cube_map: Rc<tex::DeviceTexture>,
     box_model: &mut model::DeviceModel,
+    size: usize,
 ) -> Rc<tex::DeviceTexture> {
-    let width: u32 = 32;
-    let height: u32 = 32;
+    let width = size as u32;
+    let height = size as u32;
 
-    let (mut techs, pass) =
-        create_diffuse_cubemap_convolution_pass(cube_map, box_model, width, height);
+    let desc = tex::Descriptor::new(tex::DescriptorType::ColorAttachment);
+    let cubemap = create_empty_cubemap(&desc, width, height);
 
-    let (fbos, pass) = draw_cubemap(&mut techs, pass, &box_model);
+    let (mut techs, mut pass) = create_diffuse_cubemap_convolution_pass(
+        cube_map,
+        cubemap.clone(),
+        box_model,
+        width,
+        height,
+    );
 
-    let desc = tex::Descriptor::new(tex::DescriptorType::ColorAttachment);
-    let cubemap = create_cubemap(&desc, width, height, &fbos);
+    draw_cube_map(&mut techs, &mut pass, &cubemap, &box_model);
+    if desc.use_mipmaps {
+        cubemap.generate_mipmaps();
+    }
 
-    cleanup_cubemap_fbos(&mut techs, box_model, &pass);
+    cleanup_cube_map_pass(&mut techs, box_model, &pass);
 
     cubemap
 }
@@ -50,9 +199,10 @@ pub fn create_diffuse_cube_map_texture(
 pub fn create_prefiltered_environment_map(
     cube_map: Rc<tex::DeviceTexture>,
     box_model: &mut model::DeviceModel,
+    size: usize,
 ) -> Rc<tex::DeviceTexture> {
-    let width = 128;
-    let height = 128;
+    let width = size as u32;
+    let height = size as u32;
 
     let desc = tex::Descriptor::new(tex::DescriptorType::PrefilteredEnvMap);
     let map = create_empty_cubemap(&desc, width, height);
@@ -60,22 +210,13 @@ pub fn create_prefiltered_environment_map(
     let (mut techs, mut pass) =
         create_prefiltered_environment_map_pass(cube_map, map.clone(), box_model, width, height);
 
-    let views = [
-        math::y_rotation_mat4x4(-f32::consts::PI),
-        math::Mat4x4f::identity(),
-        math::x_rotation_mat4x4(f32::consts::PI / 2.)
-            * math::y_rotation_mat4x4(f32::consts::PI / 2.),
-        math::x_rotation_mat4x4(-f32::consts::PI / 2.)
-            * math::y_rotation_mat4x4(f32::consts::PI / 2.),
-        math::y_rotation_mat4x4(f32::consts::PI / 2.),
-        math::y_rotation_mat4x4(-f32::consts::PI / 2.),
-    ];
+    let views = create_cube_map_face_views();
 
-    for mip_level in 0..8 {
-        let roughness = mip_level as f32 / 7_f32;
+    for mip_level in 0..environment::calculate_prefiltered_level_count(size) as i32 {
+        let roughness = mip_level as f32 / (environment::PREFILTERED_LEVEL_COUNT - 1) as f32;
 
-        let width = (width as f32 * 0.5_f32.powf(mip_level as f32)) as u32;
-        let height = (height as f32 * 0.5_f32.powf(mip_level as f32)) as u32;
+        let width = (width >> mip_level).max(1);
+        let height = (height >> mip_level).max(1);
 
         for face_index in 0..6 {
             pass.recreate_attachments(&create_env_map_attachment_descriptors(
@@ -102,252 +243,183 @@ pub fn create_prefiltered_environment_map(
     map
 }
 
-pub fn create_brdf_lut() -> Rc<tex::DeviceTexture> {
-    let width: u32 = 128;
-    let height: u32 = 128;
-
-    let (mut techs, mut pass, mut model) = create_brdf_integration_map_pass(width, height);
-    pass.execute(&techs, &model);
-
-    let result = pass.fbo.attachments[0].texture.clone();
-    pass.fbo.attachments.clear();
-
-    techs.unbind_render_pass(pass.program.handle);
-    model.unbind_pass(pass.program.handle);
-
-    result
-}
+// Renders the model and the skybox from the probe with the face views of the environment bake and
+// prefilters the result, so lighting.frag samples probes like the prefiltered environment
+pub fn capture_reflection_probe(
+    description: &scene::ReflectionProbeDescription,
+    camera: &camera::Camera,
+    techniques: &mut tech::TechniqueContainer,
+    device_models: &mut [model::DeviceModel],
+    transforms: &[math::Mat4x4f],
+    environment_rotation: f32,
+    environment_intensity: f32,
+) -> Rc<tex::DeviceTexture> {
+    let size = REFLECTION_PROBE_SIZE as u32;
+    let cube_map = create_empty_cubemap(
+        &tex::Descriptor::new(tex::DescriptorType::ColorAttachment),
+        size,
+        size,
+    );
+    let mut skybox_model = helper::load_skybox();
 
-struct PassFbo {
-    fbo: pass::Framebuffer,
-}
+    // Captures only see the distant environment
+    techniques::lighting::update_reflection_probes(
+        techniques.map.get_mut(&tech::Techniques::Lighting).unwrap(),
+        &[],
+    );
+    let mut passes = create_reflection_probe_passes(cube_map.clone(), size);
+    for pass in &passes {
+        techniques.bind_render_pass(pass);
+    }
+    for device_model in device_models.iter_mut() {
+        device_model.bind_pass(&passes[0]);
+        device_model.bind_pass(&passes[1]);
+    }
+    skybox_model.bind_pass(&passes[2]);
+    let mesh_transforms = model::create_mesh_transforms(device_models, transforms);
 
-struct CubeMapFbos {
-    nz: PassFbo,
-    pz: PassFbo,
-    py: PassFbo,
-    ny: PassFbo,
-    nx: PassFbo,
-    px: PassFbo,
-}
+    let position = math::Vec3f::new(
+        description.position[0],
+        description.position[1],
+        description.position[2],
+    );
+    let mut probe_camera = camera::Camera {
+        pos: position,
+        aspect: 1.,
+        fov: f32::consts::PI / 2.,
+        ..camera.clone()
+    };
 
-fn create_cubemap(
-    desc: &tex::Descriptor,
-    width: u32,
-    height: u32,
-    fbos: &CubeMapFbos,
-) -> Rc<tex::DeviceTexture> {
-    let mut handle: u32 = 0;
-
-    unsafe {
-        gl::GenTextures(1, &mut handle as *mut u32);
-        assert!(handle != 0, "Failed to generate texture");
-        gl::BindTexture(gl::TEXTURE_CUBE_MAP, handle);
-
-        for i in 0..6 {
-            gl::TexImage2D(
-                (gl::TEXTURE_CUBE_MAP_POSITIVE_X as usize + i) as gl::types::GLenum,
-                0,
-                desc.internal_format as i32,
-                width as i32,
-                height as i32,
-                0,
-                desc.format,
-                desc.data_type,
-                null() as *const c_void,
-            );
+    for (face_index, view) in create_cube_map_face_views().iter().enumerate() {
+        let textarget =
+            (gl::TEXTURE_CUBE_MAP_POSITIVE_X as usize + face_index) as gl::types::GLenum;
+        let depth_texture = passes[0].fbo.attachments[0].texture.clone();
+        for (pass, lighting) in passes[1..].iter_mut().zip([true, false].iter()) {
+            pass.recreate_attachments(&create_reflection_probe_attachment_descriptors(
+                depth_texture.clone(),
+                cube_map.clone(),
+                textarget,
+                size,
+                *lighting,
+            ))
+            .unwrap();
         }
 
-        pass::copy_framebuffer_to_texture(
-            fbos.nz.fbo.handle,
-            width,
-            height,
-            gl::TEXTURE_CUBE_MAP_NEGATIVE_Z,
+        probe_camera.view = *view * math::tranlation_mat4x4(-position);
+        techniques::mvp::update(
+            techniques.map.get_mut(&tech::Techniques::MVP).unwrap(),
+            &probe_camera,
+            &mesh_transforms,
         );
-        pass::copy_framebuffer_to_texture(
-            fbos.pz.fbo.handle,
-            width,
-            height,
-            gl::TEXTURE_CUBE_MAP_POSITIVE_Z,
+        techniques::lighting::update(
+            techniques.map.get_mut(&tech::Techniques::Lighting).unwrap(),
+            &probe_camera,
+            environment_rotation,
+            environment_intensity,
         );
-        pass::copy_framebuffer_to_texture(
-            fbos.nx.fbo.handle,
-            width,
-            height,
-            gl::TEXTURE_CUBE_MAP_NEGATIVE_X,
-        );
-        pass::copy_framebuffer_to_texture(
-            fbos.px.fbo.handle,
-            width,
-            height,
-            gl::TEXTURE_CUBE_MAP_POSITIVE_X,
-        );
-        pass::copy_framebuffer_to_texture(
-            fbos.ny.fbo.handle,
-            width,
-            height,
-            gl::TEXTURE_CUBE_MAP_NEGATIVE_Y,
-        );
-        pass::copy_framebuffer_to_texture(
-            fbos.py.fbo.handle,
-            width,
-            height,
-            gl::TEXTURE_CUBE_MAP_POSITIVE_Y,
+        techniques::skybox::update(
+            techniques.map.get_mut(&tech::Techniques::Skybox).unwrap(),
+            &probe_camera,
+            environment_rotation,
+            environment_intensity,
         );
 
-        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, desc.s_wrap as i32);
-        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, desc.t_wrap as i32);
-        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, desc.r_wrap as i32);
-        gl::TexParameteri(
-            gl::TEXTURE_CUBE_MAP,
-            gl::TEXTURE_MAG_FILTER,
-            desc.mag_filter as i32,
-        );
-        gl::TexParameteri(
-            gl::TEXTURE_CUBE_MAP,
-            gl::TEXTURE_MIN_FILTER,
-            desc.min_filter as i32,
-        );
-
-        if desc.use_mipmaps {
-            gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
-        }
+        passes[0].execute_models(techniques, device_models, &[]);
+        passes[1].execute_models(techniques, device_models, &[]);
+        passes[2].execute(techniques, &skybox_model);
+    }
 
-        gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+    for pass in &passes {
+        techniques.unbind_render_pass(pass.program.handle);
+    }
+    for device_model in device_models.iter_mut() {
+        device_model.unbind_pass(passes[0].program.handle);
+        device_model.unbind_pass(passes[1].program.handle);
     }
+    skybox_model.unbind_pass(passes[2].program.handle);
 
-    Rc::new(tex::DeviceTexture {
-        handle,
-        target: gl::TEXTURE_CUBE_MAP,
-    })
+    create_prefiltered_environment_map(cube_map, &mut skybox_model, REFLECTION_PROBE_SIZE)
 }
 
-fn create_empty_cubemap(desc: &tex::Descriptor, width: u32, height: u32) -> Rc<tex::DeviceTexture> {
-    let mut handle: u32 = 0;
-
-    unsafe {
-        gl::GenTextures(1, &mut handle as *mut u32);
-        assert!(handle != 0, "Failed to generate texture");
-        gl::BindTexture(gl::TEXTURE_CUBE_MAP, handle);
-
-        for i in 0..6 {
-            gl::TexImage2D(
-                (gl::TEXTURE_CUBE_MAP_POSITIVE_X as usize + i) as gl::types::GLenum,
-                0,
-                desc.internal_format as i32,
-                width as i32,
-                height as i32,
-                0,
-                desc.format,
-                desc.data_type,
-                null() as *const c_void,
-            );
-        }
-
-        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, desc.s_wrap as i32);
-        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, desc.t_wrap as i32);
-        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, desc.r_wrap as i32);
-        gl::TexParameteri(
-            gl::TEXTURE_CUBE_MAP,
-            gl::TEXTURE_MAG_FILTER,
-            desc.mag_filter as i32,
-        );
-        gl::TexParameteri(
-            gl::TEXTURE_CUBE_MAP,
-            gl::TEXTURE_MIN_FILTER,
-            desc.min_filter as i32,
-        );
-
-        if desc.use_mipmaps {
-            gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
-        }
+pub fn create_brdf_lut(size: usize) -> Rc<tex::DeviceTexture> {
+    let width = size as u32;
+    let height = size as u32;
 
-        gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
-    }
+    let (mut techs, mut pass, mut model) = create_brdf_integration_map_pass(width, height);
+    pass.execute(&techs, &model);
 
-    Rc::new(tex::DeviceTexture {
-        handle,
-        target: gl::TEXTURE_CUBE_MAP,
-    })
-}
+    let result = pass.fbo.attachments[0].texture.clone();
+    pass.fbo.attachments.clear();
 
-fn cleanup_cubemap_fbos(
-    techs: &mut tech::TechniqueContainer,
-    box_model: &mut model::DeviceModel,
-    pass: &pass::Pass,
-) {
     techs.unbind_render_pass(pass.program.handle);
-    box_model.unbind_pass(pass.program.handle);
+    model.unbind_pass(pass.program.handle);
+
+    result
 }
 
-fn draw_cubemap(
-    techs: &mut tech::TechniqueContainer,
-    pass: pass::Pass,
-    box_model: &model::DeviceModel,
-) -> (CubeMapFbos, pass::Pass) {
-    let (nz, pass) = draw_cubemap_face(
-        techs,
-        math::y_rotation_mat4x4(-f32::consts::PI / 2.),
-        pass,
-        &box_model,
-    );
-    let (pz, pass) = draw_cubemap_face(
-        techs,
-        math::y_rotation_mat4x4(f32::consts::PI / 2.),
-        pass,
-        &box_model,
-    );
-    let (py, pass) = draw_cubemap_face(
-        techs,
+// GL face order, the texel at lookup direction d sees convert_cube_map_to_hdri_direction(d)
+fn create_cube_map_face_views() -> [math::Mat4x4f; 6] {
+    [
+        math::y_rotation_mat4x4(-f32::consts::PI),
+        math::Mat4x4f::identity(),
         math::x_rotation_mat4x4(f32::consts::PI / 2.)
             * math::y_rotation_mat4x4(f32::consts::PI / 2.),
-        pass,
-        &box_model,
-    );
-    let (ny, pass) = draw_cubemap_face(
-        techs,
         math::x_rotation_mat4x4(-f32::consts::PI / 2.)
             * math::y_rotation_mat4x4(f32::consts::PI / 2.),
-        pass,
-        &box_model,
-    );
-    let (nx, pass) = draw_cubemap_face(techs, math::Mat4x4f::identity(), pass, &box_model);
-    let (px, pass) = draw_cubemap_face(
-        techs,
-        math::y_rotation_mat4x4(-f32::consts::PI),
-        pass,
-        &box_model,
-    );
+        math::y_rotation_mat4x4(f32::consts::PI / 2.),
+        math::y_rotation_mat4x4(-f32::consts::PI / 2.),
+    ]
+}
 
-    (
-        CubeMapFbos {
-            nz,
-            pz,
-            py,
-            ny,
-            nx,
-            px,
+fn create_empty_cubemap(desc: &tex::Descriptor, width: u32, height: u32) -> Rc<tex::DeviceTexture> {
+    let cubemap = tex::DeviceTexture::new_empty(
+        &tex::Descriptor {
+            target: gl::TEXTURE_CUBE_MAP,
+            ..desc.clone()
         },
-        pass,
-    )
+        width as usize,
+        height as usize,
+        1,
+    );
+    unsafe { gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0) };
+
+    cubemap
 }
 
-fn draw_cubemap_face(
+fn cleanup_cube_map_pass(
     techs: &mut tech::TechniqueContainer,
-    view: math::Mat4x4f,
-    mut pass: pass::Pass,
+    box_model: &mut model::DeviceModel,
+    pass: &pass::Pass,
+) {
+    techs.unbind_render_pass(pass.program.handle);
+    box_model.unbind_pass(pass.program.handle);
+}
+
+// Renders every face straight into the cube map with the shared face views
+fn draw_cube_map(
+    techs: &mut tech::TechniqueContainer,
+    pass: &mut pass::Pass,
+    cube_map: &Rc<tex::DeviceTexture>,
     box_model: &model::DeviceModel,
-) -> (PassFbo, pass::Pass) {
-    techniques::ibl::update(techs.map.get_mut(&tech::Techniques::IBL).unwrap(), view);
-    pass.execute(&techs, &box_model);
-    let fbo = pass.fbo;
-    pass.fbo = pass::Framebuffer::new(create_attachments(pass.width, pass.height)).unwrap();
+) {
+    for (face_index, view) in create_cube_map_face_views().iter().enumerate() {
+        pass.recreate_attachments(&create_env_map_attachment_descriptors(
+            cube_map.clone(),
+            (gl::TEXTURE_CUBE_MAP_POSITIVE_X as usize + face_index) as gl::types::GLenum,
+            pass.width,
+            pass.height,
+            0,
+        ))
+        .unwrap();
 
-    (PassFbo { fbo }, pass)
+        techniques::ibl::update(techs.map.get_mut(&tech::Techniques::IBL).unwrap(), *view);
+        pass.execute(techs, box_model);
+    }
 }
 
 fn create_hdri_2_cube_map_pass(
     host_texture: &tex::HostTexture,
+    target_cube_map: Rc<tex::DeviceTexture>,
     box_model: &mut model::DeviceModel,
     width: u32,
     height: u32,
@@ -373,17 +445,13 @@ fn create_hdri_2_cube_map_pass(
         },
         techniques: vec![tech::Techniques::IBL],
 
-        attachments: vec![pass::PassAttachmentDescriptor {
-            texture_desc: tex::Descriptor::new(tex::DescriptorType::ColorAttachment),
-            flavor: pass::PassAttachmentType::Color(math::zero_vec4()),
-            source: pass::PassTextureSource::ThisPass,
-            textarget: gl::TEXTURE_2D,
-            write: true,
-            clear: true,
+        attachments: create_env_map_attachment_descriptors(
+            target_cube_map,
+            gl::TEXTURE_CUBE_MAP_POSITIVE_X,
             width,
             height,
-            mip_level: 0,
-        }],
+            0,
+        ),
         dependencies: Vec::new(),
 
         width,
@@ -403,6 +471,7 @@ fn create_hdri_2_cube_map_pass(
 
 fn create_diffuse_cubemap_convolution_pass(
     cube_map: Rc<tex::DeviceTexture>,
+    target_cube_map: Rc<tex::DeviceTexture>,
     box_model: &mut model::DeviceModel,
     width: u32,
     height: u32,
@@ -425,17 +494,13 @@ fn create_diffuse_cubemap_convolution_pass(
         },
         techniques: vec![tech::Techniques::IBL],
 
-        attachments: vec![pass::PassAttachmentDescriptor {
-            texture_desc: tex::Descriptor::new(tex::DescriptorType::ColorAttachment),
-            flavor: pass::PassAttachmentType::Color(math::zero_vec4()),
-            source: pass::PassTextureSource::ThisPass,
-            textarget: gl::TEXTURE_2D,
-            write: true,
-            clear: true,
+        attachments: create_env_map_attachment_descriptors(
+            target_cube_map,
+            gl::TEXTURE_CUBE_MAP_POSITIVE_X,
             width,
             height,
-            mip_level: 0,
-        }],
+            0,
+        ),
         dependencies: Vec::new(),
 
         width,
@@ -482,6 +547,7 @@ fn create_brdf_integration_map_pass(
             width,
             height,
             mip_level: 0,
+            layer: 0,
         }],
         dependencies: Vec::new(),
 
@@ -550,6 +616,139 @@ fn create_prefiltered_environment_map_pass(
     (techs, pass)
 }
 
+// Depth pre-pass, lighting and skybox like the main pipeline, both color passes draw into a face
+fn create_reflection_probe_passes(cube_map: Rc<tex::DeviceTexture>, size: u32) -> Vec<pass::Pass> {
+    let depth_pass = pass::Pass::new(pass::PassDescriptor {
+        name: "Reflection Probe Depth Pre-Pass".to_string(),
+        program: shader::HostShaderProgramDescriptor {
+            name: "reflection probe depth pre-pass".to_string(),
+            vert_shader_file_path: "shaders/depth_pre_pass.vert".to_string(),
+            frag_shader_file_path: "shaders/depth_pre_pass.frag".to_string(),
+        },
+        techniques: vec![
+            tech::Techniques::MVP,
+            tech::Techniques::Skinning,
+            tech::Techniques::MorphTargets,
+        ],
+        attachments: vec![pass::PassAttachmentDescriptor {
+            texture_desc: tex::Descriptor::new(tex::DescriptorType::Depth),
+            flavor: pass::PassAttachmentType::Depth(1., gl::LESS),
+            source: pass::PassTextureSource::ThisPass,
+            textarget: gl::TEXTURE_2D,
+            write: true,
+            clear: true,
+            width: size,
+            height: size,
+            mip_level: 0,
+            layer: 0,
+        }],
+        dependencies: Vec::new(),
+        width: size,
+        height: size,
+    })
+    .expect("Failed to create reflection probe depth pass.");
+    let depth_texture = depth_pass.fbo.attachments[0].texture.clone();
+
+    let lighting_pass = pass::Pass::new(pass::PassDescriptor {
+        name: "Reflection Probe Lighting Pass".to_string(),
+        program: shader::HostShaderProgramDescriptor {
+            name: "reflection probe lighting".to_string(),
+            vert_shader_file_path: "shaders/lighting.vert".to_string(),
+            frag_shader_file_path: "shaders/lighting.frag".to_string(),
+        },
+        techniques: vec![
+            tech::Techniques::MVP,
+            tech::Techniques::Skinning,
+            tech::Techniques::MorphTargets,
+            tech::Techniques::Lighting,
+        ],
+        attachments: create_reflection_probe_attachment_descriptors(
+            depth_texture.clone(),
+            cube_map.clone(),
+            gl::TEXTURE_CUBE_MAP_POSITIVE_X,
+            size,
+            true,
+        ),
+        dependencies: vec![pass::PassDependencyDescriptor {
+            name: "uDepthMapSampler2D".to_string(),
+            source: pass::OtherPassTextureSource {
+                pipeline_index: 0,
+                attachment_index: 0,
+                device_texture: depth_texture.clone(),
+            },
+        }],
+        width: size,
+        height: size,
+    })
+    .expect("Failed to create reflection probe lighting pass.");
+
+    let skybox_pass = pass::Pass::new(pass::PassDescriptor {
+        name: "Reflection Probe Skybox Pass".to_string(),
+        program: shader::HostShaderProgramDescriptor {
+            name: "reflection probe skybox".to_string(),
+            vert_shader_file_path: "shaders/skybox.vert".to_string(),
+            frag_shader_file_path: "shaders/skybox.frag".to_string(),
+        },
+        techniques: vec![tech::Techniques::Skybox],
+        attachments: create_reflection_probe_attachment_descriptors(
+            depth_texture,
+            cube_map,
+            gl::TEXTURE_CUBE_MAP_POSITIVE_X,
+            size,
+            false,
+        ),
+        dependencies: Vec::new(),
+        width: size,
+        height: size,
+    })
+    .expect("Failed to create reflection probe skybox pass.");
+
+    vec![depth_pass, lighting_pass, skybox_pass]
+}
+
+// The lighting pass tests depth for equality and clears the face, the skybox pass draws behind it
+fn create_reflection_probe_attachment_descriptors(
+    depth_texture: Rc<tex::DeviceTexture>,
+    cube_map: Rc<tex::DeviceTexture>,
+    textarget: gl::types::GLenum,
+    size: u32,
+    lighting: bool,
+) -> Vec<pass::PassAttachmentDescriptor> {
+    vec![
+        pass::PassAttachmentDescriptor {
+            texture_desc: tex::Descriptor::new(tex::DescriptorType::Depth),
+            flavor: pass::PassAttachmentType::Depth(
+                1.,
+                if lighting { gl::EQUAL } else { gl::LESS },
+            ),
+            source: pass::PassTextureSource::OtherPass(pass::OtherPassTextureSource {
+                pipeline_index: 0,
+                attachment_index: 0,
+                device_texture: depth_texture,
+            }),
+            textarget: gl::TEXTURE_2D,
+            write: false,
+            clear: false,
+            width: size,
+            height: size,
+            mip_level: 0,
+            layer: 0,
+        },
+        pass::PassAttachmentDescriptor {
+            texture_desc: tex::Descriptor::new(tex::DescriptorType::ColorAttachment),
+            flavor: pass::PassAttachmentType::Color(math::zero_vec4()),
+            source: pass::PassTextureSource::FreeTexture(cube_map),
+            textarget,
+            write: true,
+            clear: lighting,
+            width: size,
+            height: size,
+            mip_level: 0,
+            layer: 0,
+        },
+    ]
+}
+
 fn create_env_map_attachment_descriptors(
     cube_map: Rc<tex::DeviceTexture>,
     textarget: gl::types::GLenum,
@@ -567,19 +766,6 @@ fn create_env_map_attachment_descriptors(
         width,
         height,
         mip_level,
+        layer: 0,
     }]
 }
-
-fn create_attachments(width: u32, height: u32) -> Vec<pass::PassAttachment> {
-    pass::create_pass_attachments(&vec![pass::PassAttachmentDescriptor {
-        texture_desc: tex::Descriptor::new(tex::DescriptorType::ColorAttachment),
-        flavor: pass::PassAttachmentType::Color(math::zero_vec4()),
-        source: pass::PassTextureSource::ThisPass,
-        textarget: gl::TEXTURE_2D,
-        write: true,
-        clear: true,
-        width,
-        height,
-        mip_level: 0,
-    }])
-}
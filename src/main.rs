@@ -14,30 +14,77 @@ use glfw::Context;
 
 pub fn create_ecs(
     app: core::app::App,
+    scene_path: &std::path::Path,
 ) -> (
     core::ecs::ArchetypeStorage,
     core::ecs::SharedArchetypeStorage,
 ) {
     let mut archetype_storage = core::ecs::ArchetypeStorage::new();
 
-    let mut model = helpers::helper::load_wall();
-    let transform = math::scale_uniform_mat4x4(100.) * math::x_rotation_mat4x4(3.14);
-    let camera = core::camera::create_default_camera(app.width, app.height);
+    let scene = helpers::scene::load_scene_description(scene_path)
+        .or_else(|error| {
+            helpers::log::log_error(error.to_string());
+            helpers::scene::load_scene_description(std::path::Path::new(
+                helpers::scene::DEFAULT_SCENE_PATH,
+            ))
+        })
+        .unwrap_or_else(|error| {
+            helpers::log::log_error(error.to_string());
+            helpers::scene::SceneDescription::default()
+        });
+    let camera = helpers::scene::create_camera(&scene.camera, app.width, app.height);
+
+    // Models that fail to load are skipped but stay in the scene description
+    let mut host_model = None;
+    let asset: &mut core::ecs::Asset = archetype_storage.get_archetype_mut();
+    for description in &scene.models {
+        match helpers::scene::load_host_model_from_description(
+            description,
+            &helpers::loader::DEFAULT_LOADER_OPTIONS,
+        ) {
+            Ok(model) => {
+                for warning in &model.warnings {
+                    helpers::log::log_warning(warning.to_string());
+                }
+                let device_model =
+                    asset::model::DeviceModel::new(&model, asset::mesh::VertexLayout::Separate);
+                let animation_state = asset::animation::AnimationState::new(&device_model);
+                asset.add(
+                    device_model,
+                    helpers::scene::create_transform_mat4x4(&description.transform),
+                    animation_state,
+                );
+                host_model = Some(model);
+            }
+            Err(error) => helpers::log::log_error(error.to_string()),
+        }
+    }
 
     let mut techniques = {
-        let skybox_texture = ibl::create_cube_map_texture(&helpers::helper::load_hdri_texture());
+        let hdri = helpers::scene::load_hdri_host_texture(&scene).unwrap_or_else(|error| {
+            helpers::log::log_error(error.to_string());
+            helpers::environment::create_constant_hdri("", math::Vec3f::new(0.5, 0.5, 0.5))
+        });
+        let environment =
+            ibl::create_environment(&hdri, &helpers::environment::DEFAULT_ENVIRONMENT_SETTINGS);
         let mut techniques = core::tech::TechniqueContainer::new();
-        techniques.map.insert(
-            core::tech::Techniques::MVP,
-            techniques::mvp::create(&camera, &vec![transform; model.meshes.len()]),
-        );
+        insert_model_techniques(&mut techniques, &camera, &asset.models, &asset.transforms);
         techniques.map.insert(
             core::tech::Techniques::Lighting,
-            techniques::lighting::create(&camera, &skybox_texture),
+            techniques::lighting::create(
+                &camera,
+                &environment,
+                scene.diffuse_irradiance,
+                &scene.lights,
+            ),
         );
         techniques.map.insert(
             core::tech::Techniques::Skybox,
-            techniques::skybox::create(&camera, skybox_texture, math::scale_uniform_mat4x4(500.)),
+            techniques::skybox::create(
+                &camera,
+                environment.cube_map.clone(),
+                math::scale_uniform_mat4x4(500.),
+            ),
         );
         techniques.map.insert(
             core::tech::Techniques::ToneMapping,
@@ -52,14 +99,21 @@ pub fn create_ecs(
     };
 
     let pipeline = core::pipeline::Pipeline::new(&app).unwrap();
-    pipeline.bind_model(&mut model);
     techniques.bind_pipeline(&pipeline);
-    if let Err(msg) = core::pipeline::is_render_pipeline_valid(&pipeline, &techniques, &model) {
-        helpers::log::log_error(msg);
+    for model in asset.models.iter_mut() {
+        pipeline.bind_model(model);
+        if let Err(msg) = core::pipeline::is_render_pipeline_valid(&pipeline, &techniques, model) {
+            helpers::log::log_error(msg);
+        }
     }
 
-    let asset: &mut core::ecs::Asset = archetype_storage.get_archetype_mut();
-    asset.add(model, transform);
+    let mut ui_editor = ui::editor::Editor::new(scene_path, &scene);
+    ui_editor.load_file_window.host_model = host_model;
+
+    let probes: &mut core::ecs::ReflectionProbes = archetype_storage.get_archetype_mut();
+    for description in &scene.reflection_probes {
+        probes.add(ibl::ReflectionProbe::new(*description));
+    }
 
     (
         archetype_storage,
@@ -68,62 +122,282 @@ pub fn create_ecs(
             input: core::input::Data::new(),
             pipeline,
             techniques,
-            ui_editor: ui::editor::Editor::new(),
+            ui_editor,
             camera,
+            scene,
         },
     )
 }
 
+// Per model uniforms of these techniques cover the meshes of every model, in entity order
+fn insert_model_techniques(
+    techniques: &mut core::tech::TechniqueContainer,
+    camera: &core::camera::Camera,
+    models: &[asset::model::DeviceModel],
+    transforms: &[math::Mat4x4f],
+) {
+    techniques.map.insert(
+        core::tech::Techniques::MVP,
+        techniques::mvp::create(
+            camera,
+            &asset::model::create_mesh_transforms(models, transforms),
+            models,
+        ),
+    );
+    techniques.map.insert(
+        core::tech::Techniques::Skinning,
+        techniques::skinning::create(models),
+    );
+    techniques.map.insert(
+        core::tech::Techniques::MorphTargets,
+        techniques::morph_targets::create(models),
+    );
+}
+
+// Loaded models are added to the scene next to the existing ones
 pub fn model_loading_system(
-    entities: &mut Vec<(&mut Vec<asset::model::DeviceModel>, &mut Vec<math::Mat4x4f>)>,
+    archetype_storage: &mut core::ecs::ArchetypeStorage,
     shared_entities: &mut core::ecs::SharedArchetypeStorage,
 ) {
-    assert_eq!(entities.len(), 1);
-
-    let (model, transform) = &mut entities[0];
-    let model = &mut model[0];
-    let transform = &mut transform[0];
-
     let model_load_result = shared_entities
         .ui_editor
         .load_file_window
         .receiver
         .try_recv();
-    if let Ok(host_model) = model_load_result {
-        shared_entities.pipeline.unbind_model(model);
+    if let Ok((_, Err(error))) = &model_load_result {
+        helpers::log::log_error(error.to_string());
+        shared_entities
+            .ui_editor
+            .load_file_window
+            .set_messages(vec![error.to_string()]);
+    }
+    if let Ok((model_source, Ok(host_model))) = model_load_result {
+        let messages: Vec<String> = host_model.warnings.iter().map(|x| x.to_string()).collect();
+        for message in &messages {
+            helpers::log::log_warning(message.clone());
+        }
+        shared_entities
+            .ui_editor
+            .load_file_window
+            .set_messages(messages);
+
+        let asset: &mut core::ecs::Asset = archetype_storage.get_archetype_mut();
+        for model in asset.models.iter_mut() {
+            shared_entities.pipeline.unbind_model(model);
+        }
         shared_entities
             .techniques
             .unbind_pipeline(&shared_entities.pipeline);
 
-        *model = asset::model::DeviceModel::new(&host_model);
-        *transform = math::Mat4x4f::identity();
-        shared_entities.techniques.map.insert(
-            core::tech::Techniques::MVP,
-            techniques::mvp::create(
-                &shared_entities.camera,
-                &vec![*transform; model.meshes.len()],
-            ),
+        let model = asset::model::DeviceModel::new(
+            &host_model,
+            shared_entities.ui_editor.load_file_window.vertex_layout,
+        );
+        shared_entities.ui_editor.load_file_window.host_model = Some(host_model);
+        let animation_state = asset::animation::AnimationState::new(&model);
+        asset.add(model, math::Mat4x4f::identity(), animation_state);
+        // The previously loaded host model may have held the last references to its textures
+        asset::cache::evict_unused_textures();
+        asset::cache::log_texture_statistics();
+        shared_entities
+            .scene
+            .models
+            .push(helpers::scene::ModelDescription {
+                source: model_source,
+                transform: helpers::scene::TransformDescription::default(),
+                material_overrides: Vec::new(),
+            });
+        insert_model_techniques(
+            &mut shared_entities.techniques,
+            &shared_entities.camera,
+            &asset.models,
+            &asset.transforms,
         );
 
         shared_entities
             .techniques
             .bind_pipeline(&shared_entities.pipeline);
-        shared_entities.pipeline.bind_model(model);
+        for model in asset.models.iter_mut() {
+            shared_entities.pipeline.bind_model(model);
+        }
+    }
+}
+
+pub fn environment_loading_system(shared_entities: &mut core::ecs::SharedArchetypeStorage) {
+    let window = &mut shared_entities.ui_editor.environment_window;
+    shared_entities.scene.environment_rotation = window.rotation;
+    shared_entities.scene.environment_intensity = window.intensity;
+
+    let (hdri_path, host_environment) = match window.receiver.try_recv() {
+        Ok(result) => result,
+        Err(_) => return,
+    };
+    window.loading = false;
+    let host_environment = match host_environment {
+        Ok(host_environment) => host_environment,
+        Err(error) => {
+            helpers::log::log_error(error.to_string());
+            window.set_messages(vec![error.to_string()]);
+            return;
+        }
+    };
+    window.set_messages(vec![format!(
+        "Loaded '{}'",
+        hdri_path.to_str().unwrap_or_default()
+    )]);
+
+    let environment = ibl::create_device_environment(&host_environment);
+    shared_entities.scene.hdri = hdri_path.to_str().unwrap_or_default().to_string();
+
+    shared_entities
+        .techniques
+        .unbind_pipeline(&shared_entities.pipeline);
+    shared_entities.techniques.map.insert(
+        core::tech::Techniques::Lighting,
+        techniques::lighting::create(
+            &shared_entities.camera,
+            &environment,
+            shared_entities.scene.diffuse_irradiance,
+            &shared_entities.scene.lights,
+        ),
+    );
+    shared_entities.techniques.map.insert(
+        core::tech::Techniques::Skybox,
+        techniques::skybox::create(
+            &shared_entities.camera,
+            environment.cube_map.clone(),
+            math::scale_uniform_mat4x4(500.),
+        ),
+    );
+    shared_entities
+        .techniques
+        .bind_pipeline(&shared_entities.pipeline);
+}
+
+// Models, lights and the environment are written to the scene description as they change,
+// only the camera has to be captured here
+pub fn scene_saving_system(shared_entities: &mut core::ecs::SharedArchetypeStorage) {
+    let window = &mut shared_entities.ui_editor.load_file_window;
+    if let Some(path) = window.scene_save_path.take() {
+        shared_entities.scene.camera =
+            helpers::scene::create_camera_description(&shared_entities.camera);
+
+        match helpers::scene::save_scene_description(&path, &shared_entities.scene) {
+            Ok(()) => window.set_messages(vec![format!(
+                "Saved '{}'",
+                path.to_str().unwrap_or_default()
+            )]),
+            Err(error) => {
+                helpers::log::log_error(error.to_string());
+                window.set_messages(vec![error.to_string()]);
+            }
+        }
+    }
+}
+
+pub fn screenshot_system(shared_entities: &mut core::ecs::SharedArchetypeStorage) {
+    let window = &mut shared_entities.ui_editor.load_file_window;
+    let requested = std::mem::replace(&mut window.screenshot_requested, false);
+    if !requested && !core::input::screenshot_requested(&shared_entities.input) {
+        return;
+    }
+
+    match shared_entities
+        .pipeline
+        .save_screenshot(std::path::Path::new("screenshots"))
+    {
+        Ok(paths) => {
+            let messages: Vec<String> = paths
+                .iter()
+                .map(|x| format!("Saved '{}'", x.to_str().unwrap_or_default()))
+                .collect();
+            messages
+                .iter()
+                .for_each(|x| helpers::log::log_info(x.to_string()));
+            window.set_messages(messages);
+        }
+        Err(error) => {
+            helpers::log::log_error(error.to_string());
+            window.set_messages(vec![error]);
+        }
     }
 }
 
+// Captures probes that were never baked or all of them on request, then hands them to lighting
+pub fn reflection_probe_system(
+    archetype_storage: &mut core::ecs::ArchetypeStorage,
+    shared_entities: &mut core::ecs::SharedArchetypeStorage,
+) {
+    let window = &mut shared_entities.ui_editor.environment_window;
+    let rebake = std::mem::replace(&mut window.rebake_probes_requested, false);
+
+    let probes: &mut core::ecs::ReflectionProbes = archetype_storage.get_archetype_mut();
+    if rebake {
+        probes
+            .reflection_probes
+            .iter_mut()
+            .for_each(|x| x.prefiltered = None);
+    }
+    let pending: Vec<(usize, helpers::scene::ReflectionProbeDescription)> = probes
+        .reflection_probes
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.prefiltered.is_none())
+        .map(|(i, x)| (i, x.description))
+        .collect();
+
+    if !pending.is_empty() {
+        let mut captures = Vec::new();
+        {
+            let asset: &mut core::ecs::Asset = archetype_storage.get_archetype_mut();
+            for (_, description) in &pending {
+                captures.push(ibl::capture_reflection_probe(
+                    description,
+                    &shared_entities.camera,
+                    &mut shared_entities.techniques,
+                    &mut asset.models,
+                    &asset.transforms,
+                    shared_entities.scene.environment_rotation,
+                    shared_entities.scene.environment_intensity,
+                ));
+            }
+        }
+        let probes: &mut core::ecs::ReflectionProbes = archetype_storage.get_archetype_mut();
+        for ((i, _), capture) in pending.iter().zip(captures.into_iter()) {
+            probes.reflection_probes[*i].prefiltered = Some(capture);
+        }
+        shared_entities
+            .ui_editor
+            .environment_window
+            .set_messages(vec![format!("Baked {} reflection probes", pending.len())]);
+    }
+
+    let probes: &core::ecs::ReflectionProbes = archetype_storage.get_archetype();
+    techniques::lighting::update_reflection_probes(
+        shared_entities
+            .techniques
+            .map
+            .get_mut(&core::tech::Techniques::Lighting)
+            .unwrap(),
+        &probes.reflection_probes,
+    );
+}
+
 pub fn update_input_system(shared_entities: &mut core::ecs::SharedArchetypeStorage) {
     core::input::update_input(&mut shared_entities.app, &mut shared_entities.input);
 }
 
 pub fn handle_input_system(
-    entities: &mut Vec<(&mut Vec<asset::model::DeviceModel>, &mut Vec<math::Mat4x4f>)>,
+    entities: &mut Vec<(
+        &mut Vec<asset::model::DeviceModel>,
+        &mut Vec<math::Mat4x4f>,
+        &mut Vec<asset::animation::AnimationState>,
+    )>,
     shared_entities: &mut core::ecs::SharedArchetypeStorage,
 ) {
     assert_eq!(entities.len(), 1);
 
-    let (model, _) = &mut entities[0];
-    let model = &mut model[0];
+    let (models, _, _) = &mut entities[0];
 
     core::input::update_window_size(&mut shared_entities.app);
     core::input::update_cursor_mode(&mut shared_entities.app, &mut shared_entities.input);
@@ -136,26 +410,65 @@ pub fn handle_input_system(
     core::input::hot_reload(
         &mut shared_entities.pipeline,
         &mut shared_entities.techniques,
-        model,
+        models,
         &shared_entities.input,
     );
     core::input::resize(
         &mut shared_entities.pipeline,
         &mut shared_entities.techniques,
-        model,
+        models,
         &shared_entities.app,
     );
 }
 
+pub fn animation_system(
+    entities: &mut Vec<(
+        &mut Vec<asset::model::DeviceModel>,
+        &mut Vec<math::Mat4x4f>,
+        &mut Vec<asset::animation::AnimationState>,
+    )>,
+    shared_entities: &mut core::ecs::SharedArchetypeStorage,
+) {
+    assert_eq!(entities.len(), 1);
+
+    let (models, _, animation_states) = &mut entities[0];
+
+    for (model, animation_state) in models.iter_mut().zip(animation_states.iter_mut()) {
+        let skeleton = match &model.skeleton {
+            Some(skeleton) => skeleton,
+            None => continue,
+        };
+        let clip = match model.animations.get(animation_state.clip_index) {
+            Some(clip) => clip,
+            None => continue,
+        };
+
+        animation_state.advance(clip, shared_entities.app.delta_time);
+        let pose = asset::animation::sample_animation_clip(skeleton, clip, animation_state.time);
+        model.joint_matrices = asset::animation::calculate_joint_matrices(skeleton, &pose);
+    }
+
+    techniques::skinning::update(
+        &mut shared_entities
+            .techniques
+            .map
+            .get_mut(&core::tech::Techniques::Skinning)
+            .unwrap(),
+        models,
+    );
+}
+
 pub fn update_techniques_system(
-    entities: &mut Vec<(&mut Vec<asset::model::DeviceModel>, &mut Vec<math::Mat4x4f>)>,
+    entities: &mut Vec<(
+        &mut Vec<asset::model::DeviceModel>,
+        &mut Vec<math::Mat4x4f>,
+        &mut Vec<asset::animation::AnimationState>,
+    )>,
     shared_entities: &mut core::ecs::SharedArchetypeStorage,
 ) {
     assert_eq!(entities.len(), 1);
 
-    let (model, transform) = &mut entities[0];
-    let model = &mut model[0];
-    let transform = &mut transform[0];
+    let (models, transforms, animation_states) = &mut entities[0];
 
     techniques::mvp::update(
         &mut shared_entities
@@ -164,7 +477,19 @@ pub fn update_techniques_system(
             .get_mut(&core::tech::Techniques::MVP)
             .unwrap(),
         &shared_entities.camera,
-        &vec![*transform; model.meshes.len()],
+        &asset::model::create_mesh_transforms(models, transforms),
+    );
+
+    techniques::morph_targets::update(
+        &mut shared_entities
+            .techniques
+            .map
+            .get_mut(&core::tech::Techniques::MorphTargets)
+            .unwrap(),
+        &animation_states
+            .iter()
+            .flat_map(|x| x.morph_weights.iter().cloned())
+            .collect::<Vec<_>>(),
     );
 
     techniques::lighting::update(
@@ -174,6 +499,8 @@ pub fn update_techniques_system(
             .get_mut(&core::tech::Techniques::Lighting)
             .unwrap(),
         &shared_entities.camera,
+        shared_entities.scene.environment_rotation,
+        shared_entities.scene.environment_intensity,
     );
 
     techniques::skybox::update(
@@ -183,72 +510,123 @@ pub fn update_techniques_system(
             .get_mut(&core::tech::Techniques::Skybox)
             .unwrap(),
         &shared_entities.camera,
+        shared_entities.scene.environment_rotation,
+        shared_entities.scene.environment_intensity,
     );
 }
 
 pub fn model_render_system(
-    entities: &mut Vec<(&mut Vec<asset::model::DeviceModel>, &mut Vec<math::Mat4x4f>)>,
+    entities: &mut Vec<(
+        &mut Vec<asset::model::DeviceModel>,
+        &mut Vec<math::Mat4x4f>,
+        &mut Vec<asset::animation::AnimationState>,
+    )>,
     shared_entities: &mut core::ecs::SharedArchetypeStorage,
 ) {
     assert_eq!(entities.len(), 1);
 
-    let (model, _) = &mut entities[0];
-    let model = &mut model[0];
+    let (models, transforms, _) = &mut entities[0];
+    let lod_indices: Vec<Vec<usize>> = models
+        .iter()
+        .zip(transforms.iter())
+        .map(|(model, transform)| {
+            model.select_lods(
+                &shared_entities.camera,
+                transform,
+                shared_entities.app.height,
+            )
+        })
+        .collect();
 
     shared_entities
         .pipeline
-        .draw(&mut shared_entities.techniques, model);
+        .draw(&mut shared_entities.techniques, models, &lod_indices);
 }
 
 pub fn ui_render_system(
-    entities: &mut Vec<(&mut Vec<asset::model::DeviceModel>, &mut Vec<math::Mat4x4f>)>,
+    entities: &mut Vec<(
+        &mut Vec<asset::model::DeviceModel>,
+        &mut Vec<math::Mat4x4f>,
+        &mut Vec<asset::animation::AnimationState>,
+    )>,
     shared_entities: &mut core::ecs::SharedArchetypeStorage,
 ) {
     assert_eq!(entities.len(), 1);
 
-    let (model, transform) = &mut entities[0];
-    let model = &mut model[0];
-    let transform = &mut transform[0];
+    let (models, transforms, animation_states) = &mut entities[0];
+    let model_labels: Vec<String> = (0..models.len()).map(|i| format!("Model #{}", i)).collect();
 
-    let children_outliner_items: Vec<ui::editor::OutlinerItem> = model
-        .meshes
+    let children_outliner_items: Vec<Vec<ui::editor::OutlinerItem>> = models
         .iter()
-        .map(|m| ui::editor::OutlinerItem {
-            label: m.name.clone(),
-            children: Vec::new(),
+        .map(|model| {
+            model
+                .meshes
+                .iter()
+                .map(|m| ui::editor::OutlinerItem {
+                    label: m.name.clone(),
+                    children: Vec::new(),
+                })
+                .collect()
         })
         .collect();
 
-    let outliner_items = vec![ui::editor::OutlinerItem {
-        label: "Model".to_string(),
-        children: children_outliner_items.iter().map(|x| x).collect(),
-    }];
+    let outliner_items: Vec<ui::editor::OutlinerItem> = model_labels
+        .iter()
+        .zip(children_outliner_items.iter())
+        .map(|(label, children)| ui::editor::OutlinerItem {
+            label: label.clone(),
+            children: children.iter().collect(),
+        })
+        .collect();
 
     let mut inspector_items = {
         let mut inspector_items = Vec::<ui::editor::InsepctorItem>::new();
-        inspector_items.push(ui::editor::InsepctorItem {
-            label: "Transform".to_string(),
-            access: ui::editor::PropertyAccess::ReadOnly,
-            property: ui::editor::PropertyValue::Mat4x4f(transform),
-        });
-        let material = &mut model.materials[model.meshes[0].material_index];
-
-        for property in material.properties_1f.iter_mut() {
-            for (i, data) in property.value.data_location.data.iter_mut().enumerate() {
-                inspector_items.push(ui::editor::InsepctorItem {
-                    label: format!("{0}[{1}]", property.name, i),
-                    access: ui::editor::PropertyAccess::ReadWrite,
-                    property: ui::editor::PropertyValue::Vec1f(data),
-                });
+        let entities = models
+            .iter_mut()
+            .zip(transforms.iter_mut())
+            .zip(animation_states.iter_mut());
+        for (((model, transform), animation_state), model_label) in entities.zip(&model_labels) {
+            inspector_items.push(ui::editor::InsepctorItem {
+                label: format!("{}/Transform", model_label),
+                access: ui::editor::PropertyAccess::ReadOnly,
+                property: ui::editor::PropertyValue::Mat4x4f(transform),
+            });
+
+            for (mesh, weights) in model
+                .meshes
+                .iter()
+                .zip(animation_state.morph_weights.iter_mut())
+            {
+                for (name, weight) in mesh.morph_target_names.iter().zip(weights.iter_mut()) {
+                    inspector_items.push(ui::editor::InsepctorItem {
+                        label: format!("{0}/{1}/{2}", model_label, mesh.name, name),
+                        access: ui::editor::PropertyAccess::ReadWrite,
+                        property: ui::editor::PropertyValue::Vec1f(weight),
+                    });
+                }
             }
-        }
-        for property in material.properties_3f.iter_mut() {
-            for (i, data) in property.value.data_location.data.iter_mut().enumerate() {
-                inspector_items.push(ui::editor::InsepctorItem {
-                    label: format!("{0}[{1}]", property.name, i),
-                    access: ui::editor::PropertyAccess::ReadWrite,
-                    property: ui::editor::PropertyValue::Vec3f(data),
-                });
+
+            let material = match model.meshes.first() {
+                Some(mesh) => &mut model.materials[mesh.material_index],
+                None => continue,
+            };
+            for property in material.properties_1f.iter_mut() {
+                for (i, data) in property.value.data_location.data.iter_mut().enumerate() {
+                    inspector_items.push(ui::editor::InsepctorItem {
+                        label: format!("{0}/{1}[{2}]", model_label, property.name, i),
+                        access: ui::editor::PropertyAccess::ReadWrite,
+                        property: ui::editor::PropertyValue::Vec1f(data),
+                    });
+                }
+            }
+            for property in material.properties_3f.iter_mut() {
+                for (i, data) in property.value.data_location.data.iter_mut().enumerate() {
+                    inspector_items.push(ui::editor::InsepctorItem {
+                        label: format!("{0}/{1}[{2}]", model_label, property.name, i),
+                        access: ui::editor::PropertyAccess::ReadWrite,
+                        property: ui::editor::PropertyValue::Vec3f(data),
+                    });
+                }
             }
         }
 
@@ -272,33 +650,41 @@ pub fn ui_render_system(
 }
 
 pub fn shutdown_system(
-    entities: &mut Vec<(&mut Vec<asset::model::DeviceModel>, &mut Vec<math::Mat4x4f>)>,
+    entities: &mut Vec<(
+        &mut Vec<asset::model::DeviceModel>,
+        &mut Vec<math::Mat4x4f>,
+        &mut Vec<asset::animation::AnimationState>,
+    )>,
     shared_entities: &mut core::ecs::SharedArchetypeStorage,
 ) {
     assert_eq!(entities.len(), 1);
 
-    let (model, _) = &mut entities[0];
-    let model = &mut model[0];
+    let (models, _, _) = &mut entities[0];
 
-    shared_entities.pipeline.unbind_model(model);
+    for model in models.iter_mut() {
+        shared_entities.pipeline.unbind_model(model);
+    }
     shared_entities
         .techniques
         .unbind_pipeline(&shared_entities.pipeline);
 }
 
-pub fn ecs_loop(app: core::app::App) {
-    let (mut archetype_storage, mut shared_archetype_storage) = create_ecs(app);
+pub fn ecs_loop(app: core::app::App, scene_path: &std::path::Path) {
+    let (mut archetype_storage, mut shared_archetype_storage) = create_ecs(app, scene_path);
 
     while !shared_archetype_storage.app.window.should_close() {
-        model_loading_system(
+        model_loading_system(&mut archetype_storage, &mut shared_archetype_storage);
+        environment_loading_system(&mut shared_archetype_storage);
+        update_input_system(&mut shared_archetype_storage);
+        handle_input_system(
             &mut archetype_storage.get_chunks_mut(),
             &mut shared_archetype_storage,
         );
-        update_input_system(&mut shared_archetype_storage);
-        handle_input_system(
+        animation_system(
             &mut archetype_storage.get_chunks_mut(),
             &mut shared_archetype_storage,
         );
+        reflection_probe_system(&mut archetype_storage, &mut shared_archetype_storage);
         update_techniques_system(
             &mut archetype_storage.get_chunks_mut(),
             &mut shared_archetype_storage,
@@ -311,6 +697,8 @@ pub fn ecs_loop(app: core::app::App) {
             &mut archetype_storage.get_chunks_mut(),
             &mut shared_archetype_storage,
         );
+        scene_saving_system(&mut shared_archetype_storage);
+        screenshot_system(&mut shared_archetype_storage);
     }
 
     shutdown_system(
@@ -320,5 +708,37 @@ pub fn ecs_loop(app: core::app::App) {
 }
 
 fn main() {
-    ecs_loop(core::app::App::new());
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|x| x == "--cook") {
+        let folder_path = args.get(index + 1).expect("Usage: --cook <folder>");
+        if let Err(error) = helpers::cooker::cook_folder(
+            std::path::Path::new(folder_path),
+            &helpers::loader::DEFAULT_LOADER_OPTIONS,
+        ) {
+            helpers::log::log_error(error.to_string());
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(index) = args.iter().position(|x| x == "--bake-environment") {
+        let hdri_path = args
+            .get(index + 1)
+            .expect("Usage: --bake-environment <hdri>");
+        if let Err(error) = helpers::cooker::cook_environment(
+            std::path::Path::new(hdri_path),
+            &helpers::environment::DEFAULT_ENVIRONMENT_SETTINGS,
+        ) {
+            helpers::log::log_error(error.to_string());
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let scene_path = match args.iter().position(|x| x == "--scene") {
+        Some(index) => args.get(index + 1).expect("Usage: --scene <file>").as_str(),
+        None => helpers::scene::DEFAULT_SCENE_PATH,
+    };
+
+    ecs_loop(core::app::App::new(), std::path::Path::new(scene_path));
 }
@@ -567,6 +567,62 @@ pub fn normalize_vec4(a: Vec4f) -> Vec4f {
     a / length_vec4(a)
 }
 
+// Maps a direction onto the octahedron unfolded into [-1, 1]^2
+#[allow(dead_code)]
+pub fn encode_octahedral_vec3(a: Vec3f) -> Vec2f {
+    let length = a.x.abs() + a.y.abs() + a.z.abs();
+    if length == 0. {
+        return Vec2f::new(0., 0.);
+    }
+
+    let (x, y) = (a.x / length, a.y / length);
+    if a.z >= 0. {
+        Vec2f::new(x, y)
+    } else {
+        Vec2f::new(
+            (1. - y.abs()) * if x >= 0. { 1. } else { -1. },
+            (1. - x.abs()) * if y >= 0. { 1. } else { -1. },
+        )
+    }
+}
+
+#[allow(dead_code)]
+pub fn convert_f32_to_snorm16(value: f32) -> i16 {
+    (value.max(-1.).min(1.) * i16::MAX as f32).round() as i16
+}
+
+// IEEE 754 binary16 with round to nearest
+#[allow(dead_code)]
+pub fn convert_f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = (mantissa | 0x80_0000) >> (1 - exponent);
+        return sign | ((mantissa + 0x1000) >> 13) as u16;
+    }
+
+    let half = sign | ((exponent as u16) << 10) | (mantissa >> 13) as u16;
+    if mantissa & 0x1000 != 0 {
+        half + 1
+    } else {
+        half
+    }
+}
+
 #[allow(dead_code)]
 pub fn zero_vec1<T>() -> Vec1<T>
 where
@@ -1026,3 +1082,150 @@ pub fn create_camera_mat4x4(pos: Vec3f, yaw: f32, pitch: f32) -> Mat4x4f {
 pub fn create_view_mat4x4(pos: Vec3f, yaw: f32, pitch: f32) -> Mat4x4f {
     x_rotation_mat4x4(-pitch) * y_rotation_mat4x4(-yaw) * tranlation_mat4x4(-pos)
 }
+
+#[allow(dead_code)]
+pub fn upper_left_mat3x3(mat: Mat4x4f) -> Mat3x3f {
+    Mat3x3f {
+        r1: Vec3f::new(mat.r1.x, mat.r1.y, mat.r1.z),
+        r2: Vec3f::new(mat.r2.x, mat.r2.y, mat.r2.z),
+        r3: Vec3f::new(mat.r3.x, mat.r3.y, mat.r3.z),
+    }
+}
+
+#[allow(dead_code)]
+pub fn calculate_max_scale_mat4x4(mat: &Mat4x4f) -> f32 {
+    let columns = transpose_mat3x3(upper_left_mat3x3(*mat));
+    length_vec3(columns.r1)
+        .max(length_vec3(columns.r2))
+        .max(length_vec3(columns.r3))
+}
+
+// Sphere around the center of the bounding box
+pub fn calculate_bounding_sphere(vertices: &[Vec3f]) -> (Vec3f, f32) {
+    if vertices.is_empty() {
+        return (zero_vec3(), 0.);
+    }
+
+    let mut min = vertices[0];
+    let mut max = vertices[0];
+    for vertex in vertices {
+        min = Vec3f::new(
+            min.x.min(vertex.x),
+            min.y.min(vertex.y),
+            min.z.min(vertex.z),
+        );
+        max = Vec3f::new(
+            max.x.max(vertex.x),
+            max.y.max(vertex.y),
+            max.z.max(vertex.z),
+        );
+    }
+
+    let center = (min + max) * 0.5;
+    let radius = vertices
+        .iter()
+        .map(|&x| length_squared_vec3(x - center))
+        .fold(0., f32::max)
+        .sqrt();
+
+    (center, radius)
+}
+
+#[allow(dead_code)]
+pub fn transpose_mat3x3<T>(mat: Mat3x3<T>) -> Mat3x3<T>
+where
+    T: Copy,
+{
+    Mat3x3::<T> {
+        r1: Vec3::<T> {
+            x: mat.r1.x,
+            y: mat.r2.x,
+            z: mat.r3.x,
+        },
+        r2: Vec3::<T> {
+            x: mat.r1.y,
+            y: mat.r2.y,
+            z: mat.r3.y,
+        },
+        r3: Vec3::<T> {
+            x: mat.r1.z,
+            y: mat.r2.z,
+            z: mat.r3.z,
+        },
+    }
+}
+
+#[allow(dead_code)]
+pub fn determinant_mat3x3(mat: Mat3x3f) -> f32 {
+    dot_vec3(mat.r1, cross(mat.r2, mat.r3))
+}
+
+#[allow(dead_code)]
+pub fn inverse_mat3x3(mat: Mat3x3f) -> Mat3x3f {
+    let inv_det = 1. / determinant_mat3x3(mat);
+
+    transpose_mat3x3(Mat3x3f {
+        r1: cross(mat.r2, mat.r3) * inv_det,
+        r2: cross(mat.r3, mat.r1) * inv_det,
+        r3: cross(mat.r1, mat.r2) * inv_det,
+    })
+}
+
+// Quaternions are stored as Vec4f with xyz as the vector part and w as the scalar part
+#[allow(dead_code)]
+pub fn identity_quat() -> Vec4f {
+    Vec4f::new(0., 0., 0., 1.)
+}
+
+#[allow(dead_code)]
+pub fn slerp_quat(a: Vec4f, b: Vec4f, t: f32) -> Vec4f {
+    // Take the shorter arc
+    let mut cos_theta = dot_vec4(a, b);
+    let b = if cos_theta < 0. {
+        cos_theta = -cos_theta;
+        -b
+    } else {
+        b
+    };
+
+    // Nearly parallel quaternions fall back to normalized lerp
+    if cos_theta > 0.9995 {
+        return normalize_vec4(a * (1. - t) + b * t);
+    }
+
+    let theta = cos_theta.acos();
+    let sin_theta = theta.sin();
+    a * (((1. - t) * theta).sin() / sin_theta) + b * ((t * theta).sin() / sin_theta)
+}
+
+#[allow(dead_code)]
+pub fn rotation_mat4x4_from_quat(q: Vec4f) -> Mat4x4f {
+    let (x, y, z, w) = (q.x, q.y, q.z, q.w);
+
+    Mat4x4f {
+        r1: Vec4f::new(
+            1. - 2. * (y * y + z * z),
+            2. * (x * y - z * w),
+            2. * (x * z + y * w),
+            0.,
+        ),
+        r2: Vec4f::new(
+            2. * (x * y + z * w),
+            1. - 2. * (x * x + z * z),
+            2. * (y * z - x * w),
+            0.,
+        ),
+        r3: Vec4f::new(
+            2. * (x * z - y * w),
+            2. * (y * z + x * w),
+            1. - 2. * (x * x + y * y),
+            0.,
+        ),
+        r4: Vec4f::new(0., 0., 0., 1.),
+    }
+}
+
+#[allow(dead_code)]
+pub fn create_trs_mat4x4(translation: Vec3f, rotation: Vec4f, scale: Vec3f) -> Mat4x4f {
+    tranlation_mat4x4(translation) * rotation_mat4x4_from_quat(rotation) * scale_mat4x4(scale)
+}
@@ -1,9 +1,14 @@
 pub mod mvp {
+    use crate::asset::{mesh, model};
     use crate::core::{camera, tech};
     use crate::gl::uniform::{PerModelUnifrom, Uniform};
     use crate::math;
 
-    pub fn create(cam: &camera::Camera, transforms: &Vec<math::Mat4x4f>) -> tech::Technique {
+    pub fn create(
+        cam: &camera::Camera,
+        transforms: &Vec<math::Mat4x4f>,
+        models: &[model::DeviceModel],
+    ) -> tech::Technique {
         let mut technique = tech::Technique::new("MVP");
         technique.per_frame_uniforms.mat4x4f = vec![
             Uniform::<math::Mat4x4f>::new(
@@ -21,6 +26,27 @@ pub mod mvp {
             "uModelMat4",
             transforms.iter().map(|&x| vec![x]).collect(),
         )];
+        let per_mesh_flag = |name: &str, f: &dyn Fn(&mesh::DeviceMesh) -> bool| {
+            PerModelUnifrom::<math::Vec1u>::new(
+                name,
+                models
+                    .iter()
+                    .flat_map(|x| x.meshes.iter())
+                    .map(|x| vec![math::Vec1u::new(f(x) as u32)])
+                    .collect(),
+            )
+        };
+        technique.per_model_uniforms.vec1u = vec![
+            per_mesh_flag("uOctahedralDirectionsUint", &|x| {
+                x.layout == mesh::VertexLayout::Compressed
+            }),
+            per_mesh_flag("uUV1AvailableUint", &|x| {
+                x.has_attribute(mesh::UV1_ATTRIBUTE_NAME)
+            }),
+            per_mesh_flag("uVertexColorAvailableUint", &|x| {
+                x.has_attribute(mesh::COLOR_ATTRIBUTE_NAME)
+            }),
+        ];
 
         technique
     }
@@ -67,42 +93,319 @@ pub mod mvp {
     }
 }
 
+pub mod skinning {
+    use crate::asset::model;
+    use crate::core::tech;
+    use crate::gl::uniform::PerModelUnifrom;
+    use crate::math;
+
+    pub fn create(models: &[model::DeviceModel]) -> tech::Technique {
+        let mut technique = tech::Technique::new("Skinning");
+        technique.per_model_uniforms.mat4x4f = vec![PerModelUnifrom::<math::Mat4x4f>::new(
+            "uJointMat4",
+            create_joint_matrices(models),
+        )];
+        technique.per_model_uniforms.vec1u = vec![PerModelUnifrom::<math::Vec1u>::new(
+            "uSkinnedMeshUint",
+            models
+                .iter()
+                .flat_map(|x| x.meshes.iter())
+                .map(|x| vec![math::Vec1u::new(x.skinned as u32)])
+                .collect(),
+        )];
+
+        technique
+    }
+
+    pub fn update(tech: &mut tech::Technique, models: &[model::DeviceModel]) {
+        let joint_mat_index = tech
+            .per_model_uniforms
+            .mat4x4f
+            .iter()
+            .position(|x| x.name == "uJointMat4")
+            .expect("Skinning technique must have uJointMat4");
+        let joint_mats = &mut tech.per_model_uniforms.mat4x4f[joint_mat_index].data_locations;
+        let joint_matrices = create_joint_matrices(models);
+        assert_eq!(joint_mats.len(), joint_matrices.len());
+        for (joint_mat, mesh_joint_matrices) in joint_mats.iter_mut().zip(joint_matrices) {
+            joint_mat.data = mesh_joint_matrices;
+        }
+    }
+
+    // Skinned meshes upload the palette of their model, the others a single unused matrix
+    fn create_joint_matrices(models: &[model::DeviceModel]) -> Vec<Vec<math::Mat4x4f>> {
+        models
+            .iter()
+            .flat_map(|model| {
+                model.meshes.iter().map(move |mesh| {
+                    if mesh.skinned && !model.joint_matrices.is_empty() {
+                        model.joint_matrices.clone()
+                    } else {
+                        vec![math::Mat4x4f::identity()]
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+pub mod morph_targets {
+    use crate::asset::model;
+    use crate::core::tech;
+    use crate::gl::uniform::PerModelUnifrom;
+    use crate::math;
+
+    pub fn create(models: &[model::DeviceModel]) -> tech::Technique {
+        let mut technique = tech::Technique::new("Morph Targets");
+        technique.per_model_uniforms.vec1f = vec![PerModelUnifrom::<math::Vec1f>::new(
+            "uMorphWeightsVec1f",
+            models
+                .iter()
+                .flat_map(|x| x.meshes.iter())
+                .map(|x| x.default_morph_weights.clone())
+                .collect(),
+        )];
+
+        technique
+    }
+
+    pub fn update(tech: &mut tech::Technique, morph_weights: &[Vec<math::Vec1f>]) {
+        let weights_index = tech
+            .per_model_uniforms
+            .vec1f
+            .iter()
+            .position(|x| x.name == "uMorphWeightsVec1f")
+            .expect("Morph targets technique must have uMorphWeightsVec1f");
+        let weights = &mut tech.per_model_uniforms.vec1f[weights_index].data_locations;
+        assert_eq!(weights.len(), morph_weights.len());
+        for (weight, mesh_weights) in weights.iter_mut().zip(morph_weights) {
+            weight.data.copy_from_slice(mesh_weights);
+        }
+    }
+}
+
 pub mod lighting {
     use crate::core::{camera, tech};
     use crate::gl::tex;
     use crate::gl::uniform::{TextureSampler, Uniform};
-    use crate::helpers::helper;
+    use crate::helpers::{log, scene};
     use crate::ibl;
     use crate::math;
     use std::rc::Rc;
 
     pub fn create(
         camera: &camera::Camera,
-        skybox_texture: &Rc<tex::DeviceTexture>,
+        environment: &ibl::DeviceEnvironment,
+        diffuse_irradiance: scene::DiffuseIrradiance,
+        lights: &[scene::Light],
     ) -> tech::Technique {
         let mut technique = tech::Technique::new("Lighting");
-        let mut box_model = helper::load_skybox();
 
-        technique.per_frame_uniforms.vec3f = vec![Uniform::<math::Vec3f>::new(
-            "uCameraPosVec3",
-            vec![camera.pos],
-        )];
-        technique.textures = vec![
-            TextureSampler::new(
-                "uDiffuseSamplerCube",
-                ibl::create_diffuse_cube_map_texture(skybox_texture.clone(), &mut box_model),
+        let mut direct_light_directions = Vec::new();
+        let mut direct_light_colors = Vec::new();
+        let mut direct_light_radiance = Vec::new();
+        let mut point_light_positions = Vec::new();
+        let mut point_light_colors = Vec::new();
+        let mut point_light_radiance = Vec::new();
+        for light in lights {
+            match *light {
+                scene::Light::Directional {
+                    direction,
+                    color,
+                    radiance,
+                } => {
+                    direct_light_directions.push(math::Vec3f::new(
+                        direction[0],
+                        direction[1],
+                        direction[2],
+                    ));
+                    direct_light_colors.push(math::Vec3f::new(color[0], color[1], color[2]));
+                    direct_light_radiance.push(math::Vec1f::new(radiance));
+                }
+                scene::Light::Point {
+                    position,
+                    color,
+                    radiance,
+                } => {
+                    point_light_positions.push(math::Vec3f::new(
+                        position[0],
+                        position[1],
+                        position[2],
+                    ));
+                    point_light_colors.push(math::Vec3f::new(color[0], color[1], color[2]));
+                    point_light_radiance.push(math::Vec1f::new(radiance));
+                }
+            }
+        }
+        if direct_light_directions.len() > scene::MAX_DIRECT_LIGHT_COUNT
+            || point_light_positions.len() > scene::MAX_POINT_LIGHT_COUNT
+        {
+            log::log_warning(format!(
+                "At most {} directional and {} point lights are supported, the rest are ignored.",
+                scene::MAX_DIRECT_LIGHT_COUNT,
+                scene::MAX_POINT_LIGHT_COUNT
+            ));
+        }
+        let direct_light_count = direct_light_directions
+            .len()
+            .min(scene::MAX_DIRECT_LIGHT_COUNT);
+        let point_light_count = point_light_positions
+            .len()
+            .min(scene::MAX_POINT_LIGHT_COUNT);
+
+        // Arrays are padded to the sizes declared in the shader
+        direct_light_directions.resize(scene::MAX_DIRECT_LIGHT_COUNT, math::zero_vec3());
+        direct_light_colors.resize(scene::MAX_DIRECT_LIGHT_COUNT, math::zero_vec3());
+        direct_light_radiance.resize(scene::MAX_DIRECT_LIGHT_COUNT, math::zero_vec1());
+        point_light_positions.resize(scene::MAX_POINT_LIGHT_COUNT, math::zero_vec3());
+        point_light_colors.resize(scene::MAX_POINT_LIGHT_COUNT, math::zero_vec3());
+        point_light_radiance.resize(scene::MAX_POINT_LIGHT_COUNT, math::zero_vec1());
+
+        technique.per_frame_uniforms.vec1u = vec![
+            Uniform::<math::Vec1u>::new(
+                "uDirectLightCountUint",
+                vec![math::Vec1u::new(direct_light_count as u32)],
+            ),
+            Uniform::<math::Vec1u>::new(
+                "uPointLightCountUint",
+                vec![math::Vec1u::new(point_light_count as u32)],
             ),
-            TextureSampler::new("uBrdfLUTSampler2D", ibl::create_brdf_lut()),
-            TextureSampler::new(
-                "uEnvMapSamplerCube",
-                ibl::create_prefiltered_environment_map(skybox_texture.clone(), &mut box_model),
+            Uniform::<math::Vec1u>::new("uReflectionProbeCountUint", vec![math::Vec1u::new(0)]),
+            Uniform::<math::Vec1u>::new(
+                "uShIrradianceUint",
+                vec![math::Vec1u::new(
+                    (diffuse_irradiance == scene::DiffuseIrradiance::SphericalHarmonics) as u32,
+                )],
+            ),
+        ];
+        technique.per_frame_uniforms.vec1f = vec![
+            Uniform::<math::Vec1f>::new("uDirectLightRadianceVec1f", direct_light_radiance),
+            Uniform::<math::Vec1f>::new("uPointLightRadianceVec1f", point_light_radiance),
+            Uniform::<math::Vec1f>::new("uEnvironmentRotationVec1f", vec![math::zero_vec1()]),
+            Uniform::<math::Vec1f>::new("uEnvironmentIntensityVec1f", vec![math::Vec1f::new(1.)]),
+            Uniform::<math::Vec1f>::new(
+                "uReflectionProbeBlendDistanceVec1f",
+                vec![math::zero_vec1(); scene::MAX_REFLECTION_PROBE_COUNT],
             ),
         ];
+        technique.per_frame_uniforms.vec3f = vec![
+            Uniform::<math::Vec3f>::new("uCameraPosVec3", vec![camera.pos]),
+            Uniform::<math::Vec3f>::new("uDirectLightDirectionsVec3f", direct_light_directions),
+            Uniform::<math::Vec3f>::new("uDirectLightColorsVec3f", direct_light_colors),
+            Uniform::<math::Vec3f>::new("uPointLightPositionsVec3f", point_light_positions),
+            Uniform::<math::Vec3f>::new("uPointLightColorsVec3f", point_light_colors),
+            Uniform::<math::Vec3f>::new("uIrradianceShVec3f", environment.irradiance_sh.clone()),
+        ];
+        for name in &[
+            "uReflectionProbePositionsVec3f",
+            "uReflectionProbeBoxMinVec3f",
+            "uReflectionProbeBoxMaxVec3f",
+        ] {
+            technique
+                .per_frame_uniforms
+                .vec3f
+                .push(Uniform::<math::Vec3f>::new(
+                    name,
+                    vec![math::zero_vec3(); scene::MAX_REFLECTION_PROBE_COUNT],
+                ));
+        }
+        technique.textures = vec![
+            TextureSampler::new("uDiffuseSamplerCube", environment.irradiance.clone()),
+            TextureSampler::new("uBrdfLUTSampler2D", environment.brdf_lut.clone()),
+            TextureSampler::new("uEnvMapSamplerCube", environment.prefiltered.clone()),
+        ];
+        // Every probe sampler needs a texture, unused ones keep the environment
+        for i in 0..scene::MAX_REFLECTION_PROBE_COUNT {
+            technique.textures.push(TextureSampler::new(
+                &format!("uReflectionProbe{}SamplerCube", i),
+                environment.prefiltered.clone(),
+            ));
+        }
 
         technique
     }
 
-    pub fn update(tech: &mut tech::Technique, camera: &camera::Camera) {
+    // Probes without a capture are skipped, the ones past the shader limit too
+    pub fn update_reflection_probes(tech: &mut tech::Technique, probes: &[ibl::ReflectionProbe]) {
+        let probes: Vec<(&scene::ReflectionProbeDescription, &Rc<tex::DeviceTexture>)> = probes
+            .iter()
+            .filter_map(|x| x.prefiltered.as_ref().map(|y| (&x.description, y)))
+            .take(scene::MAX_REFLECTION_PROBE_COUNT)
+            .collect();
+
+        let to_vec3 = |x: [f32; 3]| math::Vec3f::new(x[0], x[1], x[2]);
+        let padded_vec3 = |f: &dyn Fn(&scene::ReflectionProbeDescription) -> [f32; 3]| {
+            let mut data: Vec<math::Vec3f> = probes.iter().map(|x| to_vec3(f(x.0))).collect();
+            data.resize(scene::MAX_REFLECTION_PROBE_COUNT, math::zero_vec3());
+            data
+        };
+        let mut blend_distances: Vec<math::Vec1f> = probes
+            .iter()
+            .map(|x| math::Vec1f::new(x.0.blend_distance))
+            .collect();
+        blend_distances.resize(scene::MAX_REFLECTION_PROBE_COUNT, math::zero_vec1());
+
+        set_uniform(
+            &mut tech.per_frame_uniforms.vec1u,
+            "uReflectionProbeCountUint",
+            vec![math::Vec1u::new(probes.len() as u32)],
+        );
+        set_uniform(
+            &mut tech.per_frame_uniforms.vec1f,
+            "uReflectionProbeBlendDistanceVec1f",
+            blend_distances,
+        );
+        set_uniform(
+            &mut tech.per_frame_uniforms.vec3f,
+            "uReflectionProbePositionsVec3f",
+            padded_vec3(&|x| x.position),
+        );
+        set_uniform(
+            &mut tech.per_frame_uniforms.vec3f,
+            "uReflectionProbeBoxMinVec3f",
+            padded_vec3(&|x| x.box_min),
+        );
+        set_uniform(
+            &mut tech.per_frame_uniforms.vec3f,
+            "uReflectionProbeBoxMaxVec3f",
+            padded_vec3(&|x| x.box_max),
+        );
+
+        let environment = tech
+            .textures
+            .iter()
+            .find(|x| x.name == "uEnvMapSamplerCube")
+            .expect("Lighting technique must have uEnvMapSamplerCube")
+            .texture
+            .clone();
+        for i in 0..scene::MAX_REFLECTION_PROBE_COUNT {
+            let name = format!("uReflectionProbe{}SamplerCube", i);
+            let sampler = tech
+                .textures
+                .iter_mut()
+                .find(|x| x.name == name)
+                .unwrap_or_else(|| panic!("Lighting technique must have {}", name));
+            sampler.texture = probes
+                .get(i)
+                .map(|x| x.1.clone())
+                .unwrap_or_else(|| environment.clone());
+        }
+    }
+
+    fn set_uniform<T>(uniforms: &mut Vec<Uniform<T>>, name: &str, data: Vec<T>) {
+        let index = uniforms
+            .iter()
+            .position(|x| x.name == name)
+            .unwrap_or_else(|| panic!("Lighting technique must have {}", name));
+        uniforms[index].data_location.data = data;
+    }
+
+    pub fn update(
+        tech: &mut tech::Technique,
+        camera: &camera::Camera,
+        environment_rotation: f32,
+        environment_intensity: f32,
+    ) {
         let camera_pos_index = tech
             .per_frame_uniforms
             .vec3f
@@ -113,6 +416,8 @@ pub mod lighting {
             .data_location
             .data[0];
         *camera_pos_vec = camera.pos;
+
+        super::skybox::update_environment(tech, environment_rotation, environment_intensity);
     }
 }
 
@@ -145,12 +450,23 @@ pub mod skybox {
             ),
             Uniform::<math::Mat4x4f>::new("uModelMat4", vec![skybox_model]),
         ];
+        technique.per_frame_uniforms.vec1f = vec![
+            Uniform::<math::Vec1f>::new("uEnvironmentRotationVec1f", vec![math::zero_vec1()]),
+            Uniform::<math::Vec1f>::new("uEnvironmentIntensityVec1f", vec![math::Vec1f::new(1.)]),
+        ];
         technique.textures = vec![TextureSampler::new("uSkyboxSamplerCube", skybox)];
 
         technique
     }
 
-    pub fn update(tech: &mut Technique, camera: &camera::Camera) {
+    pub fn update(
+        tech: &mut Technique,
+        camera: &camera::Camera,
+        environment_rotation: f32,
+        environment_intensity: f32,
+    ) {
+        update_environment(tech, environment_rotation, environment_intensity);
+
         let view_mat_index = tech
             .per_frame_uniforms
             .mat4x4f
@@ -173,6 +489,22 @@ pub mod skybox {
         *proj_mat =
             math::perspective_projection_mat4x4(camera.fov, camera.aspect, camera.near, camera.far);
     }
+
+    // Shared with the lighting technique, both sample the same environment
+    pub fn update_environment(tech: &mut Technique, rotation: f32, intensity: f32) {
+        for (name, value) in &[
+            ("uEnvironmentRotationVec1f", rotation),
+            ("uEnvironmentIntensityVec1f", intensity),
+        ] {
+            let index = tech
+                .per_frame_uniforms
+                .vec1f
+                .iter()
+                .position(|x| x.name == *name)
+                .unwrap_or_else(|| panic!("{} technique must have {}", tech.name, name));
+            tech.per_frame_uniforms.vec1f[index].data_location.data[0] = math::Vec1f::new(*value);
+        }
+    }
 }
 
 pub mod tone_mapping {
@@ -86,8 +86,10 @@ impl imgui::ClipboardBackend for GlfwClipboardBackend {
 }
 
 pub mod editor {
-    use crate::asset::model;
-    use crate::helpers::loader;
+    use crate::asset::{error, mesh, model};
+    use crate::helpers::{
+        cooker, environment, exporter, helper, loader, log, mipmap, primitives, scene,
+    };
     use crate::math;
     use imgui::{im_str, Condition, ImStr, ImString, Window};
     use std::fs;
@@ -96,10 +98,19 @@ pub mod editor {
     use std::thread;
     use std::vec::Vec;
 
+    // Matches the "Mip filter" combo entries
+    const MIP_FILTERS: [Option<mipmap::MipFilter>; 4] = [
+        None,
+        Some(mipmap::MipFilter::Box),
+        Some(mipmap::MipFilter::Kaiser),
+        Some(mipmap::MipFilter::Lanczos),
+    ];
+
     pub struct Editor {
         pub outliner: Outliner,
         pub inspector: Insepctor,
         pub load_file_window: LoadFileWindow,
+        pub environment_window: EnvironmentWindow,
     }
 
     pub struct LoadFileWindow {
@@ -109,8 +120,56 @@ pub mod editor {
         inner_dir_names: Vec<ImString>,
         current_dir_index: usize,
         selected_dir_index: usize,
-        sender: Sender<model::HostModel>,
-        pub receiver: Receiver<model::HostModel>,
+        crease_angle: f32,
+        flat_normals: bool,
+        optimize_meshes: bool,
+        generate_lods: bool,
+        mip_filter_index: usize,
+        vertex_layout_index: usize,
+        pub vertex_layout: mesh::VertexLayout,
+        primitive_index: usize,
+        tessellation: u32,
+        // Last loaded model, used by "Save As"
+        pub host_model: Option<model::HostModel>,
+        save_path: ImString,
+        scene_path: ImString,
+        // Consumed by the scene saving system, which knows the live camera
+        pub scene_save_path: Option<PathBuf>,
+        // Consumed by the screenshot system, F12 sets it too
+        pub screenshot_requested: bool,
+        messages: Vec<String>,
+        message_sender: Sender<Vec<String>>,
+        message_receiver: Receiver<Vec<String>>,
+        sender: Sender<(
+            scene::ModelSource,
+            Result<model::HostModel, error::LoaderError>,
+        )>,
+        pub receiver: Receiver<(
+            scene::ModelSource,
+            Result<model::HostModel, error::LoaderError>,
+        )>,
+    }
+
+    pub struct EnvironmentWindow {
+        hdri_paths: Vec<PathBuf>,
+        hdri_names: Vec<ImString>,
+        selected_hdri_index: usize,
+        // Read by the environment loading system every frame
+        pub rotation: f32,
+        pub intensity: f32,
+        // Set while a bake runs, cleared by the environment loading system
+        pub loading: bool,
+        // Consumed by the reflection probe system
+        pub rebake_probes_requested: bool,
+        messages: Vec<String>,
+        sender: Sender<(
+            PathBuf,
+            Result<environment::HostEnvironment, error::LoaderError>,
+        )>,
+        pub receiver: Receiver<(
+            PathBuf,
+            Result<environment::HostEnvironment, error::LoaderError>,
+        )>,
     }
 
     #[allow(dead_code)]
@@ -143,11 +202,12 @@ pub mod editor {
     pub struct Insepctor {}
 
     impl Editor {
-        pub fn new() -> Editor {
+        pub fn new(scene_path: &Path, scene: &scene::SceneDescription) -> Editor {
             Editor {
                 outliner: Outliner::new(),
                 inspector: Insepctor::new(),
-                load_file_window: LoadFileWindow::new(),
+                load_file_window: LoadFileWindow::new(scene_path),
+                environment_window: EnvironmentWindow::new(scene),
             }
         }
 
@@ -158,6 +218,7 @@ pub mod editor {
             inspector_items: &mut Vec<InsepctorItem<'a>>,
         ) {
             self.load_file_window.draw_ui(ui);
+            self.environment_window.draw_ui(ui);
             self.outliner.draw_ui(ui, outliner_items);
             self.inspector.draw_ui(ui, inspector_items);
         }
@@ -176,11 +237,15 @@ pub mod editor {
     }
 
     impl LoadFileWindow {
-        pub fn new() -> LoadFileWindow {
+        pub fn new(scene_path: &Path) -> LoadFileWindow {
             let (root_dir_paths, root_dir_names) = read_directory(&Path::new("./data/models"));
 
-            let (inner_dir_paths, inner_dir_names) = read_directory(&root_dir_paths[0]);
+            let (inner_dir_paths, inner_dir_names) = match root_dir_paths.first() {
+                Some(path) => read_directory(path),
+                None => (Vec::new(), Vec::new()),
+            };
             let (sender, receiver) = channel();
+            let (message_sender, message_receiver) = channel();
 
             LoadFileWindow {
                 root_dir_paths,
@@ -189,11 +254,35 @@ pub mod editor {
                 inner_dir_names,
                 current_dir_index: 0,
                 selected_dir_index: 0,
+                crease_angle: std::f32::consts::FRAC_PI_3,
+                flat_normals: false,
+                optimize_meshes: loader::DEFAULT_LOADER_OPTIONS.optimize_meshes,
+                generate_lods: loader::DEFAULT_LOADER_OPTIONS.generate_lods,
+                mip_filter_index: MIP_FILTERS
+                    .iter()
+                    .position(|&x| x == loader::DEFAULT_LOADER_OPTIONS.mip_filter)
+                    .unwrap_or_default(),
+                vertex_layout_index: 0,
+                vertex_layout: mesh::VertexLayout::Separate,
+                primitive_index: 0,
+                tessellation: 32,
+                host_model: None,
+                save_path: ImString::new("./data/models/export/model.gltf"),
+                scene_path: ImString::new(scene_path.to_str().unwrap_or_default()),
+                scene_save_path: None,
+                screenshot_requested: false,
+                messages: Vec::new(),
+                message_sender,
+                message_receiver,
                 sender,
                 receiver,
             }
         }
 
+        pub fn set_messages(&mut self, messages: Vec<String>) {
+            self.messages = messages;
+        }
+
         pub fn draw_ui(&mut self, ui: &mut imgui::Ui) {
             Window::new(im_str!("Load model"))
                 .size([600., 100.], Condition::FirstUseEver)
@@ -210,6 +299,7 @@ pub mod editor {
                                 read_directory(&self.root_dir_paths[self.current_dir_index]);
                             self.inner_dir_paths = inner_dir_paths;
                             self.inner_dir_names = inner_dir_names;
+                            self.selected_dir_index = 0;
                         }
                     }
 
@@ -226,16 +316,268 @@ pub mod editor {
 
                     ui.next_column();
 
-                    if ui.button(im_str!("Load OBJ"), [80., 20.]) {
-                        let path = self.inner_dir_paths[self.selected_dir_index].clone();
+                    let options = loader::LoaderOptions {
+                        normals_generation: if self.flat_normals {
+                            helper::NormalsGeneration::Flat
+                        } else {
+                            helper::NormalsGeneration::Smooth(self.crease_angle)
+                        },
+                        optimize_meshes: self.optimize_meshes,
+                        generate_lods: self.generate_lods,
+                        mip_filter: MIP_FILTERS[self.mip_filter_index],
+                    };
+
+                    // The data folder may be missing or empty
+                    let selected_path = self.inner_dir_paths.get(self.selected_dir_index).cloned();
+
+                    if ui.button(im_str!("Load OBJ"), [80., 20.]) && selected_path.is_some() {
+                        let path = selected_path.clone().unwrap();
+                        let sender = self.sender.clone();
+
+                        thread::spawn(move || {
+                            let host_model =
+                                cooker::load_host_model_from_obj_cached(&path, &options);
+                            sender
+                                .send((create_file_model_source(&path), host_model))
+                                .unwrap();
+                        });
+                    }
+
+                    if ui.button(im_str!("Load glTF"), [80., 20.]) && selected_path.is_some() {
+                        let path = selected_path.clone().unwrap();
                         let sender = self.sender.clone();
 
                         thread::spawn(move || {
+                            let host_model = loader::load_host_model_from_gltf(&path, &options);
                             sender
-                                .send(loader::load_host_model_from_obj(&path))
+                                .send((create_file_model_source(&path), host_model))
                                 .unwrap();
                         });
                     }
+
+                    ui.checkbox(im_str!("Flat normals"), &mut self.flat_normals);
+                    imgui::AngleSlider::new(im_str!("Crease angle"))
+                        .min_degrees(0.)
+                        .max_degrees(180.)
+                        .build(ui, &mut self.crease_angle);
+                    ui.checkbox(im_str!("Optimize meshes"), &mut self.optimize_meshes);
+                    ui.checkbox(im_str!("Generate LODs"), &mut self.generate_lods);
+                    imgui::ComboBox::new(im_str!("Mip filter")).build_simple_string(
+                        ui,
+                        &mut self.mip_filter_index,
+                        &[
+                            im_str!("Driver"),
+                            im_str!("Box"),
+                            im_str!("Kaiser"),
+                            im_str!("Lanczos"),
+                        ],
+                    );
+                    if imgui::ComboBox::new(im_str!("Vertex layout")).build_simple_string(
+                        ui,
+                        &mut self.vertex_layout_index,
+                        &[
+                            im_str!("Separate"),
+                            im_str!("Interleaved"),
+                            im_str!("Compressed"),
+                        ],
+                    ) {
+                        self.vertex_layout = match self.vertex_layout_index {
+                            1 => mesh::VertexLayout::Interleaved,
+                            2 => mesh::VertexLayout::Compressed,
+                            _ => mesh::VertexLayout::Separate,
+                        };
+                    }
+
+                    ui.separator();
+
+                    let primitive_names: Vec<ImString> = primitives::PRIMITIVES
+                        .iter()
+                        .map(|x| ImString::new(x.1))
+                        .collect();
+                    let primitive_name_strs: Vec<&ImStr> =
+                        primitive_names.iter().map(|x| x as &ImStr).collect();
+
+                    imgui::ComboBox::new(im_str!("Primitive")).build_simple_string(
+                        ui,
+                        &mut self.primitive_index,
+                        &primitive_name_strs,
+                    );
+                    imgui::Slider::new(
+                        im_str!("Tessellation"),
+                        primitives::MIN_TESSELLATION..=primitives::MAX_TESSELLATION,
+                    )
+                    .build(ui, &mut self.tessellation);
+
+                    if ui.button(im_str!("Spawn"), [80., 20.]) {
+                        let primitive = primitives::PRIMITIVES[self.primitive_index].0;
+                        self.sender
+                            .send((
+                                scene::ModelSource::Primitive {
+                                    primitive,
+                                    tessellation: self.tessellation,
+                                },
+                                Ok(primitives::create_primitive_host_model(
+                                    primitive,
+                                    self.tessellation,
+                                )),
+                            ))
+                            .unwrap();
+                    }
+
+                    ui.separator();
+
+                    ui.input_text(im_str!("Path"), &mut self.save_path)
+                        .resize_buffer(true)
+                        .build();
+                    if ui.button(im_str!("Save As"), [80., 20.]) {
+                        match &self.host_model {
+                            Some(host_model) => {
+                                let host_model = host_model.clone();
+                                let path = PathBuf::from(self.save_path.to_str());
+                                let message_sender = self.message_sender.clone();
+
+                                thread::spawn(move || {
+                                    let messages =
+                                        match exporter::export_host_model(&path, &host_model) {
+                                            Ok(warnings) => {
+                                                for warning in &warnings {
+                                                    log::log_warning(warning.to_string());
+                                                }
+                                                std::iter::once(format!(
+                                                    "Saved '{}'",
+                                                    path.to_str().unwrap_or_default()
+                                                ))
+                                                .chain(warnings.iter().map(|x| x.to_string()))
+                                                .collect()
+                                            }
+                                            Err(error) => {
+                                                log::log_error(error.to_string());
+                                                vec![error.to_string()]
+                                            }
+                                        };
+                                    message_sender.send(messages).unwrap();
+                                });
+                            }
+                            None => self.messages = vec!["No model to save.".to_string()],
+                        }
+                    }
+
+                    ui.separator();
+
+                    ui.input_text(im_str!("Scene"), &mut self.scene_path)
+                        .resize_buffer(true)
+                        .build();
+                    if ui.button(im_str!("Save scene"), [80., 20.]) {
+                        self.scene_save_path = Some(PathBuf::from(self.scene_path.to_str()));
+                    }
+                    ui.same_line(0.);
+                    if ui.button(im_str!("Screenshot"), [80., 20.]) {
+                        self.screenshot_requested = true;
+                    }
+
+                    if let Ok(messages) = self.message_receiver.try_recv() {
+                        self.messages = messages;
+                    }
+                    for message in &self.messages {
+                        ui.text_wrapped(&ImString::new(message.clone()));
+                    }
+                });
+        }
+    }
+
+    impl EnvironmentWindow {
+        pub fn new(scene: &scene::SceneDescription) -> EnvironmentWindow {
+            let (sender, receiver) = channel();
+            let mut window = EnvironmentWindow {
+                hdri_paths: Vec::new(),
+                hdri_names: Vec::new(),
+                selected_hdri_index: 0,
+                rotation: scene.environment_rotation,
+                intensity: scene.environment_intensity,
+                loading: false,
+                rebake_probes_requested: false,
+                messages: Vec::new(),
+                sender,
+                receiver,
+            };
+            window.refresh(Path::new(&scene.hdri));
+            window
+        }
+
+        pub fn set_messages(&mut self, messages: Vec<String>) {
+            self.messages = messages;
+        }
+
+        // Keeps the given HDRI selected when it is still listed
+        fn refresh(&mut self, selected_path: &Path) {
+            self.hdri_paths = environment::find_hdri_files(Path::new(environment::HDRI_DIRECTORY));
+            self.hdri_names = self
+                .hdri_paths
+                .iter()
+                .map(|x| ImString::new(x.file_name().unwrap().to_str().unwrap()))
+                .collect();
+            self.selected_hdri_index = self
+                .hdri_paths
+                .iter()
+                .position(|x| x == selected_path)
+                .unwrap_or_default();
+        }
+
+        pub fn draw_ui(&mut self, ui: &mut imgui::Ui) {
+            Window::new(im_str!("Environment"))
+                .size([300., 300.], Condition::FirstUseEver)
+                .build(&ui, || {
+                    for (i, name) in self.hdri_names.iter().enumerate() {
+                        if imgui::Selectable::new(name)
+                            .selected(i == self.selected_hdri_index)
+                            .build(ui)
+                        {
+                            self.selected_hdri_index = i;
+                        }
+                    }
+
+                    let selected_path = self.hdri_paths.get(self.selected_hdri_index).cloned();
+                    if ui.button(im_str!("Load"), [80., 20.])
+                        && selected_path.is_some()
+                        && !self.loading
+                    {
+                        let path = selected_path.clone().unwrap();
+                        let sender = self.sender.clone();
+                        self.loading = true;
+                        self.messages =
+                            vec![format!("Loading '{}'", path.to_str().unwrap_or_default())];
+
+                        thread::spawn(move || {
+                            let host_environment = cooker::load_host_environment_cached(
+                                &path,
+                                &environment::DEFAULT_ENVIRONMENT_SETTINGS,
+                            );
+                            sender.send((path, host_environment)).unwrap();
+                        });
+                    }
+                    ui.same_line(0.);
+                    if ui.button(im_str!("Refresh"), [80., 20.]) {
+                        let selected_path = selected_path.unwrap_or_default();
+                        self.refresh(&selected_path);
+                    }
+
+                    imgui::AngleSlider::new(im_str!("Rotation"))
+                        .min_degrees(-180.)
+                        .max_degrees(180.)
+                        .build(ui, &mut self.rotation);
+                    imgui::Slider::new(im_str!("Intensity"), 0. ..=4.)
+                        .build(ui, &mut self.intensity);
+
+                    ui.separator();
+
+                    // Probes keep their capture until rebaked, also after switching environments
+                    if ui.button(im_str!("Rebake probes"), [120., 20.]) {
+                        self.rebake_probes_requested = true;
+                    }
+
+                    for message in &self.messages {
+                        ui.text_wrapped(&ImString::new(message.clone()));
+                    }
                 });
         }
     }
@@ -396,10 +738,10 @@ pub mod editor {
     }
 
     fn read_directory(dir_path: &Path) -> (Vec<PathBuf>, Vec<imgui::ImString>) {
-        let file_paths: Vec<PathBuf> = fs::read_dir(dir_path)
-            .unwrap()
-            .map(|x| x.unwrap().path())
-            .collect();
+        let file_paths: Vec<PathBuf> = match fs::read_dir(dir_path) {
+            Ok(entries) => entries.filter_map(|x| x.ok()).map(|x| x.path()).collect(),
+            Err(_) => Vec::new(),
+        };
         let file_names: Vec<imgui::ImString> = file_paths
             .iter()
             .map(|x| imgui::ImString::new(x.file_name().unwrap().to_str().unwrap()))
@@ -407,4 +749,8 @@ pub mod editor {
 
         (file_paths, file_names)
     }
+
+    fn create_file_model_source(path: &Path) -> scene::ModelSource {
+        scene::ModelSource::File(path.to_str().unwrap_or_default().to_string())
+    }
 }